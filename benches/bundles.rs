@@ -0,0 +1,53 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Serial and parallel verification of a synthetic consignment with 10k state
+//! transitions, run with `cargo bench --features rayon`. Transitions form
+//! independent chains spending different genesis outputs, so each
+//! topological level of the consignment has a bundle from each chain.
+
+mod common;
+
+use bitcoin::secp256k1::rand::rngs::StdRng;
+use bitcoin::secp256k1::rand::SeedableRng;
+use criterion::{criterion_group, criterion_main, Criterion};
+use rgb::{Node, StateTransfer};
+
+const TRANSITIONS: usize = 10_000;
+const CHAINS: u16 = 100;
+
+fn consignment() -> StateTransfer {
+    let mut rng = StdRng::seed_from_u64(0);
+    let genesis = common::genesis(CHAINS, &mut rng);
+    let mut parents = (0..CHAINS)
+        .map(|output_no| (genesis.node_id(), output_no))
+        .collect::<Vec<_>>();
+    let mut transitions = Vec::with_capacity(TRANSITIONS);
+    for no in 0..TRANSITIONS {
+        let (parent, output_no) = parents[no % parents.len()];
+        let transition = common::transition(parent, output_no, &mut rng);
+        parents[no % parents.len()] = (transition.node_id(), 0);
+        transitions.push(transition);
+    }
+    common::consignment(genesis, transitions)
+}
+
+fn verify_bundles(c: &mut Criterion) {
+    let consignment = consignment();
+    let mut group = c.benchmark_group("verify_bundles");
+    group.sample_size(10);
+    group.bench_function("serial", |b| b.iter(|| consignment.verify_bundles()));
+    group.bench_function("parallel", |b| b.iter(|| consignment.par_verify_bundles()));
+    group.finish();
+}
+
+criterion_group!(benches, verify_bundles);
+criterion_main!(benches);
@@ -0,0 +1,122 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Synthetic contract fixtures shared by the benchmarks
+
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+
+use amplify::Wrapper;
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::rand::rngs::StdRng;
+use bitcoin::{OutPoint, Txid};
+use bp::dbc::Proof;
+use commit_verify::lnpbp4::{self, MerkleBlock, MerkleTree, MultiSource};
+use commit_verify::CommitVerify;
+use lnpbp::chain::Chain;
+use rgb::schema::{Occurrences, TransitionSchema};
+use rgb::{
+    seal, value, Anchor, Assignment, AssignmentVec, Genesis, NodeId, OwnedRights,
+    ParentOwnedRights, Schema, SchemaId, StateTransfer, Transition, TransitionBundle,
+};
+
+pub const TRANSFER: u16 = 1;
+pub const ASSETS: u16 = 1;
+
+/// Constructs `count` asset assignments to the same seal
+pub fn assignments(count: u16, rng: &mut StdRng) -> OwnedRights {
+    let seal = seal::Revealed::from(OutPoint::new(Txid::from_inner([1u8; 32]), 0));
+    let assignments = (0..count)
+        .map(|_| Assignment::Revealed {
+            seal_definition: seal,
+            assigned_state: value::Revealed::with_amount(1, rng),
+        })
+        .collect();
+    let mut owned_rights = BTreeMap::new();
+    owned_rights.insert(ASSETS, AssignmentVec::Fungible(assignments));
+    OwnedRights::from_inner(owned_rights)
+}
+
+/// Constructs genesis with `count` asset assignments
+pub fn genesis(count: u16, rng: &mut StdRng) -> Genesis {
+    Genesis::with(
+        SchemaId::default(),
+        Chain::Testnet3,
+        Default::default(),
+        assignments(count, rng),
+        Default::default(),
+    )
+}
+
+/// Constructs transfer spending the asset output of the `parent` and
+/// assigning a single output
+pub fn transition(parent: NodeId, output_no: u16, rng: &mut StdRng) -> Transition {
+    let mut spent = BTreeMap::new();
+    spent.insert(ASSETS, vec![output_no]);
+    let mut parent_owned_rights = BTreeMap::new();
+    parent_owned_rights.insert(parent, spent);
+    Transition::with(
+        TRANSFER,
+        Default::default(),
+        Default::default(),
+        assignments(1, rng),
+        Default::default(),
+        ParentOwnedRights::from_inner(parent_owned_rights),
+    )
+}
+
+/// Constructs consignment putting each of the `transitions` into its own
+/// bundle. All bundles share the same anchor.
+pub fn consignment(
+    genesis: Genesis,
+    transitions: impl IntoIterator<Item = Transition>,
+) -> StateTransfer {
+    let mut transfer = TransitionSchema::default();
+    transfer.closes.insert(ASSETS, Occurrences::NoneOrMore);
+    transfer
+        .owned_rights
+        .insert(ASSETS, Occurrences::NoneOrMore);
+    let mut schema = Schema::default();
+    schema.transitions.insert(TRANSFER, transfer);
+
+    let protocol_id = lnpbp4::ProtocolId::from(genesis.contract_id());
+    let mut messages = BTreeMap::new();
+    messages.insert(protocol_id, lnpbp4::Message::from_inner([0u8; 32]));
+    let source = MultiSource {
+        min_depth: 3,
+        messages,
+        static_entropy: None,
+    };
+    let anchor = Anchor {
+        txid: Txid::from_inner([2u8; 32]),
+        lnpbp4_proof: MerkleBlock::from(MerkleTree::commit(&source)),
+        dbc_proof: Proof::OpretFirst,
+    }
+    .to_merkle_proof(protocol_id)
+    .unwrap();
+
+    let anchored_bundles = transitions
+        .into_iter()
+        .map(|transition| {
+            let mut bundle = BTreeMap::new();
+            bundle.insert(transition, vec![0u16].into_iter().collect());
+            (anchor.clone(), TransitionBundle::from(bundle))
+        })
+        .collect::<Vec<_>>();
+    StateTransfer::with(
+        schema,
+        None,
+        genesis,
+        Default::default(),
+        anchored_bundles.try_into().unwrap(),
+        Default::default(),
+    )
+}
@@ -0,0 +1,100 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Iteration over a synthetic consignment with 20k state transitions, comparing
+//! indexed chain iteration with lookups scanning all the bundles for each of
+//! the chain steps. Run with `cargo bench --bench iter`.
+
+mod common;
+
+use bitcoin::secp256k1::rand::rngs::StdRng;
+use bitcoin::secp256k1::rand::SeedableRng;
+use common::{ASSETS, TRANSFER};
+use criterion::{criterion_group, criterion_main, Criterion};
+use rgb::{GraphApi, Node, NodeId, StateTransfer};
+
+const TRANSITIONS: usize = 20_000;
+/// Depth of the chain walked by the iteration benchmarks; walks by scanning
+/// lookups are quadratic, so the whole chain would take too long
+const CHAIN_DEPTH: usize = 2_000;
+
+/// Returns consignment with a chain of transitions, each in its own bundle,
+/// and id of the transition at the [`CHAIN_DEPTH`]
+fn consignment() -> (StateTransfer, NodeId) {
+    let mut rng = StdRng::seed_from_u64(0);
+    let genesis = common::genesis(1, &mut rng);
+    let mut parent = genesis.node_id();
+    let mut start = parent;
+    let mut transitions = Vec::with_capacity(TRANSITIONS);
+    for no in 0..TRANSITIONS {
+        let transition = common::transition(parent, 0, &mut rng);
+        parent = transition.node_id();
+        if no + 1 == CHAIN_DEPTH {
+            start = parent;
+        }
+        transitions.push(transition);
+    }
+    (common::consignment(genesis, transitions), start)
+}
+
+/// Walks the chain looking up each of the parents in all the bundles
+fn scanning_walk(consignment: &StateTransfer, start_with: NodeId) -> usize {
+    let mut count = 0;
+    let mut next = consignment.transition_witness_by_id(start_with).ok();
+    while let Some((transition, _)) = next {
+        let output = match transition.parent_outputs_by_type(ASSETS).first() {
+            Some(output) => *output,
+            None => break,
+        };
+        count += 1;
+        next = consignment.transition_witness_by_id(output.node_id).ok();
+    }
+    count
+}
+
+fn iterate(c: &mut Criterion) {
+    let (consignment, start) = consignment();
+    let index = consignment.transition_index();
+    assert_eq!(
+        consignment.chain_iter(start, ASSETS).count(),
+        scanning_walk(&consignment, start)
+    );
+
+    let mut group = c.benchmark_group("chain_iter");
+    group.sample_size(10);
+    group.bench_function("scanning", |b| {
+        b.iter(|| scanning_walk(&consignment, start))
+    });
+    group.bench_function("indexed", |b| {
+        b.iter(|| consignment.chain_iter(start, ASSETS).count())
+    });
+    group.bench_function("shared_index", |b| {
+        b.iter(|| index.chain_iter(&consignment, start, ASSETS).count())
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("mesh_iter");
+    group.bench_function("borrowed", |b| {
+        b.iter(|| consignment.transition_witness_iter(&[TRANSFER]).count())
+    });
+    group.bench_function("cloned", |b| {
+        b.iter(|| {
+            consignment
+                .transition_witness_iter(&[TRANSFER])
+                .cloned()
+                .count()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, iterate);
+criterion_main!(benches);
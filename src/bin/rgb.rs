@@ -270,7 +270,7 @@ where T: StrictDecode + for<'de> serde::Deserialize<'de> {
                 .map_err(hex::Error::to_string)?,
         )?,
         Format::Binary => T::strict_deserialize(&data)?,
-        _ => panic!("Can't read data from {} format", format),
+        _ => return Err(format!("Can't read data from {} format", format)),
     })
 }
 
@@ -303,7 +303,7 @@ where
         Format::Commitment => {
             println!("{}", data.consensus_commit())
         }
-        format => panic!("Can't read data in {} format", format),
+        format => return Err(format!("Can't write data in {} format", format)),
     }
     Ok(())
 }
@@ -0,0 +1,362 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Source of the witness transactions using Bitcoin Core JSON-RPC.
+//!
+//! Transactions are retrieved with `getrawtransaction`, which requires the
+//! node to run with `txindex` for the transactions which are neither in the
+//! mempool nor in the node wallet. Without the index mining heights are still
+//! found with `gettxout`, as long as the first transaction output is unspent.
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::time::Duration;
+
+use bitcoin::{Transaction, Txid};
+use bitcoincore_rpc::jsonrpc::{self, simple_http};
+use bitcoincore_rpc::{Auth, Client, Error, RpcApi};
+use serde_json::Value;
+
+use crate::{CachingResolver, WitnessSource};
+
+/// Default timeout of the JSON-RPC requests
+pub const BITCOIND_DEFAULT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Error code returned by Bitcoin Core for the unknown transactions ("No such
+/// mempool or blockchain transaction")
+const RPC_INVALID_ADDRESS_OR_KEY: i32 = -5;
+
+/// Resolver of the witness transactions with Bitcoin Core node
+pub type BitcoindResolver<R = Client> = CachingResolver<BitcoindSource<R>>;
+
+impl BitcoindResolver<Client> {
+    /// Connects to the node JSON-RPC according to the `config`
+    pub fn with_config(config: &BitcoindConfig) -> Result<Self, Error> {
+        Ok(CachingResolver::with(BitcoindSource::with_config(config)?))
+    }
+}
+
+/// Parameters of the connection to Bitcoin Core JSON-RPC
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct BitcoindConfig {
+    /// URL of the RPC server, like `http://localhost:8332`
+    pub url: String,
+
+    /// RPC credentials
+    pub auth: Auth,
+
+    /// Timeout of each of the requests
+    pub timeout: Duration,
+}
+
+impl BitcoindConfig {
+    /// Constructs config with the [`BITCOIND_DEFAULT_TIMEOUT`]
+    pub fn with(url: impl ToString, auth: Auth) -> BitcoindConfig {
+        BitcoindConfig {
+            url: url.to_string(),
+            auth,
+            timeout: BITCOIND_DEFAULT_TIMEOUT,
+        }
+    }
+}
+
+fn is_unknown_tx(err: &Error) -> bool {
+    matches!(err, Error::JsonRpc(jsonrpc::Error::Rpc(err)) if err.code == RPC_INVALID_ADDRESS_OR_KEY)
+}
+
+/// Witness source requesting Bitcoin Core node
+#[derive(Debug)]
+pub struct BitcoindSource<R = Client>
+where R: RpcApi
+{
+    rpc: R,
+}
+
+impl BitcoindSource<Client> {
+    /// Connects to the node JSON-RPC according to the `config`
+    pub fn with_config(config: &BitcoindConfig) -> Result<Self, Error> {
+        let mut builder = simple_http::Builder::new()
+            .url(&config.url)
+            .map_err(jsonrpc::Error::from)?
+            .timeout(config.timeout);
+        if let (Some(user), pass) = config.auth.clone().get_user_pass()? {
+            builder = builder.auth(user, pass);
+        }
+        let client = jsonrpc::Client::with_transport(builder.build());
+        Ok(BitcoindSource::with(Client::from_jsonrpc(client)))
+    }
+}
+
+impl<R> BitcoindSource<R>
+where R: RpcApi
+{
+    /// Constructs source using the `rpc` client
+    #[inline]
+    pub fn with(rpc: R) -> Self { BitcoindSource { rpc } }
+
+    /// Returns RPC client used by the source
+    #[inline]
+    pub fn rpc(&self) -> &R { &self.rpc }
+
+    fn block_height(&self, block_hash: &str) -> Result<u32, Error> {
+        let header = self
+            .rpc
+            .call::<Value>("getblockheader", &[block_hash.into(), true.into()])?;
+        header
+            .get("height")
+            .and_then(Value::as_u64)
+            .and_then(|height| u32::try_from(height).ok())
+            .ok_or(Error::UnexpectedStructure)
+    }
+
+    /// Finds transaction height from its first output, if it is unspent.
+    /// Fails if the node reports more confirmations than there are blocks.
+    fn utxo_height(&self, txid: Txid) -> Result<Option<u32>, Error> {
+        let txout = self.rpc.call::<Value>(
+            "gettxout",
+            &[txid.to_string().into(), 0.into(), false.into()],
+        )?;
+        let confirmations = txout
+            .get("confirmations")
+            .and_then(Value::as_u64)
+            .unwrap_or_default();
+        match txout.get("bestblock").and_then(Value::as_str) {
+            Some(tip) if confirmations > 0 => {
+                let blocks = u64::from(self.block_height(tip)?) + 1;
+                blocks
+                    .checked_sub(confirmations)
+                    .and_then(|height| u32::try_from(height).ok())
+                    .map(Some)
+                    .ok_or(Error::UnexpectedStructure)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn height(&self, txid: Txid) -> Result<Option<u32>, Error> {
+        let info = match self
+            .rpc
+            .call::<Value>("getrawtransaction", &[txid.to_string().into(), true.into()])
+        {
+            Ok(info) => info,
+            Err(err) if is_unknown_tx(&err) => return self.utxo_height(txid),
+            Err(err) => return Err(err),
+        };
+        let confirmations = info
+            .get("confirmations")
+            .and_then(Value::as_u64)
+            .unwrap_or_default();
+        match info.get("blockhash").and_then(Value::as_str) {
+            Some(block_hash) if confirmations > 0 => self.block_height(block_hash).map(Some),
+            _ => Ok(None),
+        }
+    }
+}
+
+impl<R> WitnessSource for BitcoindSource<R>
+where R: RpcApi
+{
+    type Error = Error;
+
+    fn fetch_txs(&self, txids: &[Txid]) -> Result<Vec<Transaction>, Self::Error> {
+        let mut txs = Vec::with_capacity(txids.len());
+        for txid in txids {
+            match self.rpc.get_raw_transaction(txid, None) {
+                Ok(tx) => txs.push(tx),
+                Err(err) if is_unknown_tx(&err) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(txs)
+    }
+
+    fn fetch_heights(
+        &self,
+        txids: &[Txid],
+        _: &BTreeMap<Txid, Transaction>,
+    ) -> Result<BTreeMap<Txid, u32>, Self::Error> {
+        let mut heights = BTreeMap::new();
+        for txid in txids {
+            if let Some(height) = self.height(*txid)? {
+                heights.insert(*txid, height);
+            }
+        }
+        Ok(heights)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+    use std::str::FromStr;
+
+    use bitcoin::consensus::encode::serialize_hex;
+    use bitcoincore_rpc::jsonrpc::error::RpcError;
+    use serde_json::json;
+
+    use super::*;
+    use crate::resolver::test::{resolver_conformance, tx};
+    use crate::ResolveWitness;
+    use rgb_core::validation::ResolveTx;
+
+    const TIP: u64 = 100;
+
+    /// Error code returned by Bitcoin Core for the unknown RPC methods
+    const RPC_METHOD_NOT_FOUND: i32 = -32601;
+
+    /// RPC server with a fixed set of transactions, each of them mined at the
+    /// height equal to its number of inputs. Without `txindex` only mempool
+    /// transactions are returned by `getrawtransaction`. Unspent outputs
+    /// report `extra_confirmations` above their actual number.
+    struct MockRpc {
+        txs: Vec<Transaction>,
+        txindex: bool,
+        extra_confirmations: u64,
+        offline: Cell<bool>,
+    }
+
+    fn block_hash(height: u64) -> String { format!("{:064x}", height) }
+
+    impl MockRpc {
+        fn tx(&self, txid: &Value) -> Option<&Transaction> {
+            let txid = Txid::from_str(txid.as_str()?).ok()?;
+            self.txs.iter().find(|tx| tx.txid() == txid)
+        }
+
+        fn respond(&self, cmd: &str, args: &[Value]) -> Result<Value, Error> {
+            let rpc_error = |code: i32, message: &str| {
+                Error::JsonRpc(jsonrpc::Error::Rpc(RpcError {
+                    code,
+                    message: message.to_owned(),
+                    data: None,
+                }))
+            };
+            let unknown = || {
+                rpc_error(
+                    RPC_INVALID_ADDRESS_OR_KEY,
+                    "No such mempool or blockchain transaction",
+                )
+            };
+            Ok(match cmd {
+                "getrawtransaction" => {
+                    let tx = self.tx(&args[0]).ok_or_else(unknown)?;
+                    let height = tx.input.len() as u64;
+                    if !self.txindex && height > 0 {
+                        return Err(unknown());
+                    }
+                    if args[1] != json!(true) {
+                        return Ok(json!(serialize_hex(tx)));
+                    }
+                    match height {
+                        0 => json!({ "hex": serialize_hex(tx) }),
+                        _ => json!({
+                            "hex": serialize_hex(tx),
+                            "blockhash": block_hash(height),
+                            "confirmations": TIP - height + 1,
+                        }),
+                    }
+                }
+                "getblockheader" => {
+                    let height = u64::from_str_radix(args[0].as_str().unwrap(), 16).unwrap();
+                    json!({ "height": height })
+                }
+                "gettxout" => match self.tx(&args[0]).map(|tx| tx.input.len() as u64) {
+                    Some(height) if height > 0 => json!({
+                        "bestblock": block_hash(TIP),
+                        "confirmations": TIP - height + 1 + self.extra_confirmations,
+                    }),
+                    _ => Value::Null,
+                },
+                _ => return Err(rpc_error(RPC_METHOD_NOT_FOUND, "Method not found")),
+            })
+        }
+    }
+
+    impl RpcApi for MockRpc {
+        fn call<T: for<'a> serde_crate::Deserialize<'a>>(
+            &self,
+            cmd: &str,
+            args: &[Value],
+        ) -> bitcoincore_rpc::Result<T> {
+            if self.offline.get() {
+                return Err(Error::JsonRpc(jsonrpc::Error::Transport(
+                    "connection refused".into(),
+                )));
+            }
+            serde_json::from_value(self.respond(cmd, args)?).map_err(Error::Json)
+        }
+    }
+
+    fn source(txindex: bool) -> BitcoindSource<MockRpc> {
+        BitcoindSource::with(MockRpc {
+            txs: (0..4).map(tx).collect(),
+            txindex,
+            extra_confirmations: 0,
+            offline: Cell::new(false),
+        })
+    }
+
+    fn resolver(txindex: bool) -> BitcoindResolver<MockRpc> {
+        CachingResolver::with(source(txindex))
+    }
+
+    #[test]
+    fn test_bitcoind_conformance() {
+        resolver_conformance(resolver(true), |source, offline| {
+            source.rpc().offline.set(offline)
+        });
+    }
+
+    #[test]
+    fn test_no_txindex() {
+        let mut resolver = resolver(false);
+        let mined = tx(2).txid();
+        let unconfirmed = tx(0).txid();
+        let err = resolver.resolve_tx(mined).unwrap_err();
+        assert_eq!(err.txid, mined);
+        assert!(err.err.is_none());
+        assert_eq!(resolver.resolve_height(mined).unwrap(), Some(2));
+
+        assert_eq!(
+            resolver.resolve_tx(unconfirmed).unwrap().txid(),
+            unconfirmed
+        );
+        assert_eq!(resolver.resolve_height(unconfirmed).unwrap(), None);
+    }
+
+    #[test]
+    fn test_invalid_confirmations() {
+        let mut source = source(false);
+        source.rpc.extra_confirmations = TIP;
+        assert!(matches!(
+            source.utxo_height(tx(2).txid()),
+            Err(Error::UnexpectedStructure)
+        ));
+        source.rpc.extra_confirmations = 1;
+        assert_eq!(source.utxo_height(tx(2).txid()).unwrap(), Some(1));
+    }
+
+    #[test]
+    fn test_unsupported_method() {
+        let source = source(true);
+        let err = source
+            .rpc()
+            .call::<Value>("getblockcount", &[])
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::JsonRpc(jsonrpc::Error::Rpc(RpcError {
+                code: RPC_METHOD_NOT_FOUND,
+                ..
+            }))
+        ));
+    }
+}
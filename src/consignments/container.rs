@@ -10,7 +10,8 @@
 // If not, see <https://opensource.org/licenses/MIT>.
 
 use std::collections::{BTreeMap, BTreeSet};
-use std::io;
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
 use std::marker::PhantomData;
 
 use bitcoin::Txid;
@@ -19,12 +20,65 @@ use rgb_core::{
     schema, AttachmentId, BundleId, ConsistencyError, ContractId, Extension, Genesis, GraphApi,
     Node, NodeId, Schema, Transition, TransitionBundle,
 };
-use strict_encoding::{LargeVec, StrictDecode};
+use strict_encoding::{LargeVec, StrictDecode, StrictEncode};
 
 use super::{AnchoredBundles, ConsignmentEndpoints, ConsignmentType, ExtensionList};
-use crate::ConsignmentId;
+use crate::{AnchorCloseMethod, CloseMethod, ConsignmentId, TlvError, TlvMap};
 
-pub const RGB_INMEM_CONSIGNMENT_VERSION: u8 = 0;
+/// Current version of the consignment encoding. Version 1 adds
+/// [`InmemConsignment::tlv`] extension area; version 2 prefixes each of the
+/// anchored bundles with its id and the length of its data, allowing to skip
+/// bundles without decoding them. Consignments of the previous versions are
+/// still decoded and re-encoded in their original layout.
+pub const RGB_INMEM_CONSIGNMENT_VERSION: u8 = 2;
+
+/// Encodes anchored bundles in the layout of the consignment version 2, with
+/// each bundle prefixed by its id and the 32-bit length of its data
+fn encode_prefixed_bundles(
+    bundles: &AnchoredBundles,
+    mut e: impl io::Write,
+) -> Result<usize, strict_encoding::Error> {
+    let mut len = (bundles.len() as u32).strict_encode(&mut e)?;
+    for (anchor, bundle) in bundles.iter() {
+        let mut data = anchor.strict_serialize()?;
+        bundle.strict_encode(&mut data)?;
+        let data_len = u32::try_from(data.len())
+            .map_err(|_| strict_encoding::Error::ExceedMaxItems(data.len()))?;
+        len += bundle.bundle_id().strict_encode(&mut e)?;
+        len += data_len.strict_encode(&mut e)?;
+        e.write_all(&data)?;
+        len += data.len();
+    }
+    Ok(len)
+}
+
+/// Decodes anchored bundles encoded with [`encode_prefixed_bundles`], checking
+/// that each bundle takes exactly the declared length and matches its id
+fn decode_prefixed_bundles(
+    mut d: impl io::Read,
+) -> Result<AnchoredBundles, strict_encoding::Error> {
+    let count = u32::strict_decode(&mut d)?;
+    let mut bundles = vec![];
+    for _ in 0..count {
+        let bundle_id = BundleId::strict_decode(&mut d)?;
+        let len = u32::strict_decode(&mut d)?;
+        let mut data = (&mut d).take(len as u64);
+        let (anchor, bundle) = StrictDecode::strict_decode(&mut data)?;
+        if data.limit() > 0 {
+            return Err(strict_encoding::Error::DataIntegrityError(s!(
+                "anchored bundle data are shorter than their declared length"
+            )));
+        }
+        if TransitionBundle::bundle_id(&bundle) != bundle_id {
+            return Err(strict_encoding::Error::DataIntegrityError(format!(
+                "anchored bundle does not match its id {}",
+                bundle_id
+            )));
+        }
+        bundles.push((anchor, bundle));
+    }
+    Ok(LargeVec::try_from(bundles).expect("bundle count is read from u32 value"))
+}
 
 /// Consignment represents contract-specific data, always starting with genesis,
 /// which must be valid under client-side-validation rules (i.e. internally
@@ -42,12 +96,12 @@ pub const RGB_INMEM_CONSIGNMENT_VERSION: u8 = 0;
     derive(Serialize, Deserialize),
     serde(crate = "serde_crate")
 )]
-#[derive(Clone, PartialEq, Eq, Debug, StrictEncode)]
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub struct InmemConsignment<T>
 where T: ConsignmentType
 {
     /// Version, used internally
-    version: u8,
+    pub(super) version: u8,
 
     pub schema: Schema,
 
@@ -78,7 +132,12 @@ where T: ConsignmentType
     /// 24 bit value (RGB allows containers up to 32-bit values in size).
     pub data_containers: BTreeMap<AttachmentId, LargeVec<u8>>,
 
-    #[strict_encoding(skip)]
+    /// Extension records, present in the consignments of version 1 and
+    /// above. Unknown odd records are kept and re-encoded as is. Records
+    /// should be added with [`InmemConsignment::insert_tlv_record`]:
+    /// consignments of version 0 with records fail to encode.
+    pub tlv: TlvMap,
+
     _phantom: PhantomData<T>,
 }
 
@@ -95,27 +154,60 @@ where T: ConsignmentType
     type Commitment = ConsignmentId;
 }
 
+impl<T> StrictEncode for InmemConsignment<T>
+where T: ConsignmentType
+{
+    fn strict_encode<E: io::Write>(&self, mut e: E) -> Result<usize, strict_encoding::Error> {
+        let mut len = strict_encode_list!(e;
+            self.version,
+            self.schema,
+            self.root_schema,
+            self.genesis,
+            self.endpoints
+        );
+        if self.version > 1 {
+            len += encode_prefixed_bundles(&self.anchored_bundles, &mut e)?;
+        } else {
+            len += self.anchored_bundles.strict_encode(&mut e)?;
+        }
+        len += strict_encode_list!(e; self.state_extensions, self.data_containers);
+        if self.version > 0 {
+            len += self.tlv.strict_encode(e)?;
+        } else if !self.tlv.is_empty() {
+            return Err(strict_encoding::Error::UnsupportedDataStructure(
+                "State transfers of version 0 can't contain extension records",
+            ));
+        }
+        Ok(len)
+    }
+}
+
 impl<T> StrictDecode for InmemConsignment<T>
 where T: ConsignmentType
 {
     fn strict_decode<D: io::Read>(mut d: D) -> Result<Self, strict_encoding::Error> {
-        let consignment = Self {
-            version: StrictDecode::strict_decode(&mut d)?,
+        let version = u8::strict_decode(&mut d)?;
+        if version > RGB_INMEM_CONSIGNMENT_VERSION {
+            return Err(strict_encoding::Error::UnsupportedDataStructure(
+                "State transfer versions above 2 are not supported",
+            ));
+        }
+        Ok(Self {
+            version,
             schema: StrictDecode::strict_decode(&mut d)?,
             root_schema: StrictDecode::strict_decode(&mut d)?,
             genesis: StrictDecode::strict_decode(&mut d)?,
             endpoints: StrictDecode::strict_decode(&mut d)?,
-            anchored_bundles: StrictDecode::strict_decode(&mut d)?,
+            anchored_bundles: if version > 1 {
+                decode_prefixed_bundles(&mut d)?
+            } else {
+                StrictDecode::strict_decode(&mut d)?
+            },
             state_extensions: StrictDecode::strict_decode(&mut d)?,
             data_containers: StrictDecode::strict_decode(&mut d)?,
+            tlv: if version > 0 { StrictDecode::strict_decode(&mut d)? } else { none!() },
             _phantom: none!(),
-        };
-        if consignment.version != RGB_INMEM_CONSIGNMENT_VERSION {
-            return Err(strict_encoding::Error::UnsupportedDataStructure(
-                "State transfer versions above 0 are not supported",
-            ));
-        }
-        Ok(consignment)
+        })
     }
 }
 
@@ -140,6 +232,7 @@ where T: ConsignmentType
             state_extensions,
             anchored_bundles,
             data_containers: none!(),
+            tlv: none!(),
             _phantom: none!(),
         }
     }
@@ -152,6 +245,21 @@ where T: ConsignmentType
     #[inline]
     pub fn version(&self) -> u8 { self.version }
 
+    /// Adds extension record, returning the previous value of the record with
+    /// the same type. Fails for the unknown even record types.
+    ///
+    /// Consignments of version 0 can't carry extension records, so they are
+    /// upgraded to version 1, keeping the layout of their bundles.
+    pub fn insert_tlv_record(
+        &mut self,
+        ty: u16,
+        value: Vec<u8>,
+    ) -> Result<Option<Vec<u8>>, TlvError> {
+        let prev = self.tlv.insert(ty, value)?;
+        self.version = self.version.max(1);
+        Ok(prev)
+    }
+
     #[inline]
     pub fn txids(&self) -> BTreeSet<Txid> {
         self.anchored_bundles
@@ -160,6 +268,32 @@ where T: ConsignmentType
             .collect()
     }
 
+    /// Returns set of the methods the consignment anchors commit with to
+    /// their witness transactions
+    #[inline]
+    pub fn close_methods(&self) -> BTreeSet<CloseMethod> {
+        self.anchored_bundles
+            .iter()
+            .map(|(anchor, _)| anchor.close_method())
+            .collect()
+    }
+
+    /// Detects whether the consignment anchors use more than one close
+    /// method. Such consignments are valid, but require the verifier to
+    /// support all of the methods.
+    #[inline]
+    pub fn has_mixed_close_methods(&self) -> bool { self.close_methods().len() > 1 }
+
+    /// Returns set of the methods used by the anchors of the endpoint bundles
+    pub fn endpoint_close_methods(&self) -> BTreeSet<CloseMethod> {
+        let bundle_ids = self.endpoint_bundle_ids();
+        self.anchored_bundles
+            .iter()
+            .filter(|(_, bundle)| bundle_ids.contains(&bundle.bundle_id()))
+            .map(|(anchor, _)| anchor.close_method())
+            .collect()
+    }
+
     #[inline]
     pub fn node_ids(&self) -> BTreeSet<NodeId> {
         let mut set = bset![self.genesis.node_id()];
@@ -228,3 +362,90 @@ where T: ConsignmentType
             .collect()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use strict_encoding::StrictDecode;
+
+    use super::*;
+    use crate::verify::test::consignment;
+    use crate::TransferConsignment;
+
+    #[test]
+    fn test_tlv_preserved() {
+        let mut consignment = consignment(5);
+        consignment.tlv.insert(5, vec![0xAB; 4]).unwrap();
+        let id = consignment.id();
+        let data = consignment.strict_serialize().unwrap();
+
+        let mut decoded = TransferConsignment::strict_deserialize(&data).unwrap();
+        assert_eq!(decoded.tlv.get(5), Some(&[0xABu8; 4][..]));
+        assert_eq!(decoded.id(), id);
+        decoded.endpoints = empty!();
+        let decoded =
+            TransferConsignment::strict_deserialize(decoded.strict_serialize().unwrap()).unwrap();
+        assert_eq!(decoded.tlv, consignment.tlv);
+    }
+
+    #[test]
+    fn test_tlv_unknown_even() {
+        let consignment = consignment(5);
+        let mut data = consignment.strict_serialize().unwrap();
+        data.truncate(data.len() - 2);
+        data.extend([1, 0, 4, 0, 0, 0]);
+        assert!(TransferConsignment::strict_deserialize(&data).is_err());
+    }
+
+    #[test]
+    fn test_tlv_version_0() {
+        let mut consignment = consignment(2);
+        consignment.version = 0;
+        let data = consignment.strict_serialize().unwrap();
+        assert_eq!(
+            TransferConsignment::strict_deserialize(&data).unwrap(),
+            consignment
+        );
+
+        let mut invalid = consignment.clone();
+        invalid.tlv.insert(5, vec![1]).unwrap();
+        assert!(invalid.strict_serialize().is_err());
+
+        consignment.insert_tlv_record(5, vec![1]).unwrap();
+        assert_eq!(consignment.version(), 1);
+        let decoded =
+            TransferConsignment::strict_deserialize(consignment.strict_serialize().unwrap())
+                .unwrap();
+        assert_eq!(decoded, consignment);
+    }
+
+    #[test]
+    fn test_bundle_prefixes() {
+        let mut consignment = consignment(3);
+        let data = consignment.strict_serialize().unwrap();
+        assert_eq!(
+            TransferConsignment::strict_deserialize(&data).unwrap(),
+            consignment
+        );
+
+        consignment.version = 1;
+        let legacy = consignment.strict_serialize().unwrap();
+        // Each of the bundles takes 32 bytes of id and 4 bytes of length
+        assert_eq!(legacy.len() + 3 * 36, data.len());
+        assert_eq!(
+            TransferConsignment::strict_deserialize(&legacy).unwrap(),
+            consignment
+        );
+
+        let offset = 1
+            + consignment.schema.strict_serialize().unwrap().len()
+            + consignment.root_schema.strict_serialize().unwrap().len()
+            + consignment.genesis.strict_serialize().unwrap().len()
+            + consignment.endpoints.strict_serialize().unwrap().len();
+        let mut corrupted = data.clone();
+        corrupted[offset + 4] ^= 0xFF;
+        assert!(TransferConsignment::strict_deserialize(&corrupted).is_err());
+        let mut corrupted = data;
+        corrupted[offset + 4 + 32] ^= 1;
+        assert!(TransferConsignment::strict_deserialize(&corrupted).is_err());
+    }
+}
@@ -9,7 +9,8 @@
 // You should have received a copy of the MIT License along with this software.
 // If not, see <https://opensource.org/licenses/MIT>.
 
-use std::collections::{btree_map, BTreeSet};
+use std::borrow::Cow;
+use std::collections::{btree_map, BTreeMap, BTreeSet};
 use std::slice;
 
 use bitcoin::Txid;
@@ -18,30 +19,158 @@ use commit_verify::lnpbp4;
 use crate::consignments::InmemConsignment;
 use crate::schema::{OwnedRightType, TransitionType};
 use crate::{
-    Anchor, ConsignmentType, ConsistencyError, GraphApi, Node, NodeId, Transition, TransitionBundle,
+    Anchor, ConsignmentType, ConsistencyError, Node, NodeId, Transition, TransitionBundle,
 };
 
+/// State transition borrowed from the consignment together with the bundle
+/// containing it and the id of the witness transaction
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct WitnessedTransition<'c> {
+    /// State transition
+    pub transition: &'c Transition,
+
+    /// Bundle containing the transition
+    pub bundle: &'c TransitionBundle,
+
+    /// Witness transaction of the bundle
+    pub txid: Txid,
+}
+
+impl<'c> WitnessedTransition<'c> {
+    /// Clones the transition, returning it together with the witness
+    /// transaction id
+    #[inline]
+    pub fn into_owned(self) -> (Transition, Txid) { (self.transition.clone(), self.txid) }
+}
+
+/// Index of the consignment state transitions by their node ids, together
+/// with the ids of the transitions spending their outputs. The index is built
+/// once in a single pass over the consignment bundles, computing id of each
+/// of the transitions only once.
+#[derive(Clone, Debug)]
+pub struct TransitionIndex<'c> {
+    transitions: BTreeMap<NodeId, WitnessedTransition<'c>>,
+    children: BTreeMap<NodeId, Vec<NodeId>>,
+}
+
+impl<'c> TransitionIndex<'c> {
+    /// Indexes all known state transitions of the `consignment`. If the same
+    /// transition is present in several bundles, the first of them is used.
+    pub fn with<T>(consignment: &'c InmemConsignment<T>) -> Self
+    where T: ConsignmentType {
+        let mut transitions = BTreeMap::new();
+        let mut children = BTreeMap::<NodeId, Vec<NodeId>>::new();
+        for (anchor, bundle) in consignment.anchored_bundles.iter() {
+            for transition in bundle.known_transitions() {
+                let node_id = transition.node_id();
+                if transitions.contains_key(&node_id) {
+                    continue;
+                }
+                transitions.insert(node_id, WitnessedTransition {
+                    transition,
+                    bundle,
+                    txid: anchor.txid,
+                });
+                let parents = transition
+                    .parent_outputs()
+                    .into_iter()
+                    .map(|output| output.node_id)
+                    .collect::<BTreeSet<_>>();
+                for parent in parents {
+                    children.entry(parent).or_default().push(node_id);
+                }
+            }
+        }
+        TransitionIndex {
+            transitions,
+            children,
+        }
+    }
+
+    /// Returns transition with the `node_id` and its witness
+    #[inline]
+    pub fn transition_witness_by_id(
+        &self,
+        node_id: NodeId,
+    ) -> Result<WitnessedTransition<'c>, ConsistencyError> {
+        self.transitions
+            .get(&node_id)
+            .copied()
+            .ok_or(ConsistencyError::TransitionAbsent(node_id))
+    }
+
+    /// Returns ids of the transitions spending outputs of the node with
+    /// `node_id`, in the order of the consignment bundles
+    #[inline]
+    pub fn children(&self, node_id: NodeId) -> &[NodeId] {
+        self.children
+            .get(&node_id)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Returns number of the indexed transitions
+    #[inline]
+    pub fn len(&self) -> usize { self.transitions.len() }
+
+    /// Detects whether the index contains no transitions
+    #[inline]
+    pub fn is_empty(&self) -> bool { self.transitions.is_empty() }
+
+    /// Creates iterator over a single chain of state transitions using the
+    /// index; see [`InmemConsignment::chain_iter`]
+    pub fn chain_iter<'i, T>(
+        &'i self,
+        consignment: &'c InmemConsignment<T>,
+        start_with: NodeId,
+        connected_by: OwnedRightType,
+    ) -> ChainIter<'i, 'c>
+    where
+        T: ConsignmentType,
+    {
+        ChainIter::with(Cow::Borrowed(self), consignment, start_with, connected_by)
+    }
+}
+
 /// Iterator over transitions and corresponding witness transaction ids which
 /// can be created out of consignment data. Transitions of this type must be
 /// organized into a chain connecting 1-to-1 via the provided `connected_by`
 /// during iterator creation.
 ///
-/// Iterator is created with [`Consignment::chain_iter`]
+/// Iterator is created with [`InmemConsignment::chain_iter`] or
+/// [`TransitionIndex::chain_iter`]
 #[derive(Debug)]
-pub struct ChainIter<'iter, T>
-where T: ConsignmentType
-{
-    consignment: &'iter InmemConsignment<T>,
+pub struct ChainIter<'i, 'c> {
+    index: Cow<'i, TransitionIndex<'c>>,
     connected_by: OwnedRightType,
-    next_item: Option<(&'iter Transition, Txid)>,
+    next_item: Option<WitnessedTransition<'c>>,
     error: Option<ConsistencyError>,
 }
 
-impl<'iter, T> ChainIter<'iter, T>
-where T: ConsignmentType
-{
+impl<'i, 'c> ChainIter<'i, 'c> {
+    fn with<T>(
+        index: Cow<'i, TransitionIndex<'c>>,
+        consignment: &'c InmemConsignment<T>,
+        start_with: NodeId,
+        connected_by: OwnedRightType,
+    ) -> Self
+    where
+        T: ConsignmentType,
+    {
+        let next_item = consignment
+            .endpoint_transition_by_id(start_with)
+            .ok()
+            .and_then(|_| index.transition_witness_by_id(start_with).ok());
+        ChainIter {
+            index,
+            connected_by,
+            next_item,
+            error: None,
+        }
+    }
+
     /// Detects whether iterator was stopped by a error
-    pub fn is_err(&'iter self) -> bool { self.error.is_some() }
+    pub fn is_err(&self) -> bool { self.error.is_some() }
 
     /// Converts iterator into a result type prividing information about the
     /// error (if any) which terminated execution of the iterator
@@ -52,18 +181,20 @@ where T: ConsignmentType
             Ok(())
         }
     }
+
+    /// Converts iterator into the one yielding cloned transitions
+    #[inline]
+    pub fn cloned(self) -> OwnedIter<Self> { OwnedIter(self) }
 }
 
-impl<'iter, T> Iterator for ChainIter<'iter, T>
-where T: ConsignmentType
-{
-    type Item = (&'iter Transition, Txid);
+impl<'i, 'c> Iterator for ChainIter<'i, 'c> {
+    type Item = WitnessedTransition<'c>;
 
     fn next(&mut self) -> Option<Self::Item> {
         let item = self.next_item?;
 
         let output = if let Some(output) = item
-            .0
+            .transition
             .parent_outputs_by_type(self.connected_by)
             .first()
             .copied()
@@ -75,7 +206,7 @@ where T: ConsignmentType
         };
 
         self.next_item = self
-            .consignment
+            .index
             .transition_witness_by_id(output.node_id)
             .map_err(|err| self.error = Some(err))
             .ok();
@@ -87,33 +218,32 @@ where T: ConsignmentType
 impl<T> InmemConsignment<T>
 where T: ConsignmentType
 {
+    /// Builds index of the consignment state transitions, which may be reused
+    /// by multiple iterators
+    #[inline]
+    pub fn transition_index(&self) -> TransitionIndex { TransitionIndex::with(self) }
+
     /// Creates iterator over a single chain of state transition starting from
     /// `node_id` which must be one of the consignment endpoints, and
     /// corresponding witness transaction ids. Transitions must be organized
     /// into a chain connecting 1-to-1 via the provided `connected_by` owned
     /// rights (one or none of them must be present for each state transition).
-    pub fn chain_iter(&self, start_with: NodeId, connected_by: OwnedRightType) -> ChainIter<T> {
-        let next_item = self
-            .endpoint_transition_by_id(start_with)
-            .into_iter()
-            .next()
-            .and_then(|_| self.transition_witness_by_id(start_with).ok());
-
-        ChainIter {
-            consignment: self,
-            connected_by,
-            next_item,
-            error: None,
-        }
+    ///
+    /// The iterator indexes consignment transitions on its creation; use
+    /// [`TransitionIndex::chain_iter`] to share the index among iterators.
+    pub fn chain_iter(&self, start_with: NodeId, connected_by: OwnedRightType) -> ChainIter {
+        let index = Cow::Owned(self.transition_index());
+        ChainIter::with(index, self, start_with, connected_by)
     }
 
-    pub fn transition_witness_iter<'iter>(
-        &'iter self,
-        transition_types: &'iter [TransitionType],
-    ) -> MeshIter<'iter> {
+    /// Creates iterator over all known transitions of the given types in the
+    /// order of the consignment bundles
+    pub fn transition_witness_iter<'c>(
+        &'c self,
+        transition_types: &'c [TransitionType],
+    ) -> MeshIter<'c> {
         let mut bundles = self.anchored_bundles.iter();
-        let next = bundles.next();
-        let transitions = next.map(|(anchor, bundle)| (anchor.txid, bundle.known_transitions()));
+        let transitions = bundles.next().map(MeshIter::bundle_transitions);
         MeshIter {
             bundles,
             transitions,
@@ -122,34 +252,172 @@ where T: ConsignmentType
     }
 }
 
+type BundleTransitions<'c> = (
+    Txid,
+    &'c TransitionBundle,
+    btree_map::Keys<'c, Transition, BTreeSet<u16>>,
+);
+
+/// Iterator over the consignment transitions of specific types and their
+/// witnesses, created with [`InmemConsignment::transition_witness_iter`]
 #[derive(Debug)]
-pub struct MeshIter<'iter> {
-    bundles: slice::Iter<'iter, (Anchor<lnpbp4::MerkleProof>, TransitionBundle)>,
-    transitions: Option<(Txid, btree_map::Keys<'iter, Transition, BTreeSet<u16>>)>,
-    transition_types: &'iter [TransitionType],
+pub struct MeshIter<'c> {
+    bundles: slice::Iter<'c, (Anchor<lnpbp4::MerkleProof>, TransitionBundle)>,
+    transitions: Option<BundleTransitions<'c>>,
+    transition_types: &'c [TransitionType],
 }
 
-impl<'iter> Iterator for MeshIter<'iter> {
-    type Item = (&'iter Transition, Txid);
+impl<'c> MeshIter<'c> {
+    fn bundle_transitions(
+        (anchor, bundle): &'c (Anchor<lnpbp4::MerkleProof>, TransitionBundle),
+    ) -> BundleTransitions<'c> {
+        (anchor.txid, bundle, bundle.known_transitions())
+    }
+
+    /// Converts iterator into the one yielding cloned transitions
+    #[inline]
+    pub fn cloned(self) -> OwnedIter<Self> { OwnedIter(self) }
+}
+
+impl<'c> Iterator for MeshIter<'c> {
+    type Item = WitnessedTransition<'c>;
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
-            while let Some((txid, transition)) = self
-                .transitions
-                .as_mut()
-                .and_then(|(txid, iter)| iter.next().map(|transition| (txid, transition)))
-            {
+            let (txid, bundle, transitions) = self.transitions.as_mut()?;
+            for transition in transitions {
                 if self
                     .transition_types
                     .contains(&transition.transition_type())
                 {
-                    return Some((transition, *txid));
+                    return Some(WitnessedTransition {
+                        transition,
+                        bundle: *bundle,
+                        txid: *txid,
+                    });
                 }
             }
-            let next = self.bundles.next();
-            self.transitions =
-                next.map(|(anchor, bundle)| (anchor.txid, bundle.known_transitions()));
-            self.transitions.as_ref()?;
+            self.transitions = self.bundles.next().map(MeshIter::bundle_transitions);
+        }
+    }
+}
+
+/// Adapter of [`ChainIter`] and [`MeshIter`] yielding owned copies of the
+/// transitions with their witness transaction ids
+#[derive(Debug)]
+pub struct OwnedIter<I>(I);
+
+impl<'c, I> Iterator for OwnedIter<I>
+where I: Iterator<Item = WitnessedTransition<'c>>
+{
+    type Item = (Transition, Txid);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> { self.0.next().map(WitnessedTransition::into_owned) }
+}
+
+#[cfg(test)]
+mod test {
+    use rgb_core::GraphApi;
+
+    use super::*;
+    use crate::verify::test::consignment;
+    use crate::StateTransfer;
+
+    const TRANSFER: TransitionType = 1;
+    const ASSETS: OwnedRightType = 1;
+
+    /// Chain iteration as it was done before the index, looking up each of
+    /// the transitions in all consignment bundles
+    fn scanning_chain(
+        consignment: &StateTransfer,
+        start_with: NodeId,
+    ) -> (Vec<(Transition, Txid)>, Result<(), ConsistencyError>) {
+        let mut chain = vec![];
+        let mut next = consignment
+            .endpoint_transition_by_id(start_with)
+            .ok()
+            .and_then(|_| consignment.transition_witness_by_id(start_with).ok());
+        while let Some((transition, txid)) = next {
+            let output = match transition.parent_outputs_by_type(ASSETS).first() {
+                Some(output) => *output,
+                None => return (chain, Ok(())),
+            };
+            chain.push((transition.clone(), txid));
+            match consignment.transition_witness_by_id(output.node_id) {
+                Ok(item) => next = Some(item),
+                Err(err) => return (chain, Err(err)),
+            }
+        }
+        (chain, Ok(()))
+    }
+
+    /// Transitions of the consignment in the order of the bundles
+    fn scanning_mesh(consignment: &StateTransfer) -> Vec<(Transition, Txid)> {
+        consignment
+            .anchored_bundles
+            .iter()
+            .flat_map(|(anchor, bundle)| {
+                bundle
+                    .known_transitions()
+                    .filter(|transition| transition.transition_type() == TRANSFER)
+                    .map(move |transition| (transition.clone(), anchor.txid))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_chain_order() {
+        let transfer = consignment(5);
+        let index = transfer.transition_index();
+        assert_eq!(index.len(), 5);
+
+        for (_, bundle) in transfer.anchored_bundles.iter() {
+            let start = bundle.known_transitions().next().unwrap().node_id();
+            let (expected, expected_result) = scanning_chain(&transfer, start);
+
+            let mut iter = transfer.chain_iter(start, ASSETS);
+            let chain = iter
+                .by_ref()
+                .map(WitnessedTransition::into_owned)
+                .collect::<Vec<_>>();
+            assert_eq!(chain, expected);
+            assert_eq!(iter.is_err(), expected_result.is_err());
+            assert_eq!(iter.into_result(), expected_result);
+
+            let shared = index.chain_iter(&transfer, start, ASSETS).cloned();
+            assert_eq!(shared.collect::<Vec<_>>(), expected);
+        }
+    }
+
+    #[test]
+    fn test_mesh_order() {
+        let transfer = consignment(5);
+        let mesh = transfer.transition_witness_iter(&[TRANSFER]);
+        assert_eq!(mesh.cloned().collect::<Vec<_>>(), scanning_mesh(&transfer));
+        for item in transfer.transition_witness_iter(&[TRANSFER]) {
+            assert!(item
+                .bundle
+                .known_transitions()
+                .any(|t| t == item.transition));
+        }
+        assert_eq!(transfer.transition_witness_iter(&[TRANSFER + 1]).count(), 0);
+    }
+
+    #[test]
+    fn test_children() {
+        let transfer = consignment(3);
+        let index = transfer.transition_index();
+        let mut ids = vec![transfer.genesis.node_id()];
+        ids.extend(
+            transfer
+                .anchored_bundles
+                .iter()
+                .flat_map(|(_, bundle)| bundle.known_node_ids()),
+        );
+        for pair in ids.windows(2) {
+            assert_eq!(index.children(pair[0]), &pair[1..]);
         }
+        assert!(index.children(ids[3]).is_empty());
     }
 }
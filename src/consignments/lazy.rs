@@ -0,0 +1,398 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Lazy decoding of strict-encoded consignments, allowing to inspect
+//! endpoints and individual bundles of large consignments before decoding
+//! them completely.
+
+use std::ops::Range;
+
+use commit_verify::lnpbp4;
+use rgb_core::{
+    Anchor, BundleId, ContractId, Genesis, Node, Schema, SealEndpoint, TransitionBundle,
+};
+use strict_encoding::{LargeVec, StrictDecode};
+
+use super::{ConsignmentEndpoints, ConsignmentType, InmemConsignment};
+use crate::RGB_INMEM_CONSIGNMENT_VERSION;
+
+/// Transition bundle together with its anchor, as stored in the consignment
+pub type AnchoredBundle = (Anchor<lnpbp4::MerkleProof>, TransitionBundle);
+
+/// Errors decoding consignment with [`LazyConsignment`]
+#[derive(Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum LazyError {
+    /// consignment version {0} is not supported
+    UnsupportedVersion(u8),
+
+    /// consignment declares list of {declared} items, while only {remaining}
+    /// bytes of data are left
+    Oversized { declared: u32, remaining: usize },
+
+    /// consignment has no bundle with index {0}
+    NoBundle(usize),
+
+    /// offset {0} is outside of the consignment data
+    OutOfRange(usize),
+
+    /// data of the bundle with index {0} do not match its id or length
+    BundleMismatch(usize),
+
+    /// consignment data can't be decoded: {0}
+    #[from]
+    Encoding(strict_encoding::Error),
+}
+
+/// Decodes value starting at the `offset`, advancing the offset past it
+fn decode_at<T>(data: &[u8], offset: &mut usize) -> Result<T, LazyError>
+where T: StrictDecode {
+    let mut cursor = data.get(*offset..).ok_or(LazyError::OutOfRange(*offset))?;
+    let len = cursor.len();
+    let value = T::strict_decode(&mut cursor)?;
+    *offset += len - cursor.len();
+    Ok(value)
+}
+
+/// Decodes 32-bit length prefix of a large vector at the `offset`. Each item
+/// takes at least a byte, which bounds the lengths accepted from the
+/// malformed data.
+fn decode_len(data: &[u8], offset: &mut usize) -> Result<usize, LazyError> {
+    let declared = decode_at::<u32>(data, offset)?;
+    let remaining = data.len().saturating_sub(*offset);
+    if declared as usize > remaining {
+        return Err(LazyError::Oversized {
+            declared,
+            remaining,
+        });
+    }
+    Ok(declared as usize)
+}
+
+/// Consignment borrowing its strict-encoded data, which decodes upfront only
+/// the header (schemata and genesis) and endpoints, and indexes positions of
+/// the anchored bundles.
+///
+/// Starting from the consignment version 2 each of the bundles is prefixed
+/// with its id and length, so the indexing skips the bundle data without
+/// decoding them. Consignments of the previous versions do not have these
+/// prefixes, and the indexing has to decode each of their bundles once; the
+/// decoded data are dropped immediately, keeping only the bundle ids and
+/// positions. Bundles are decoded from the borrowed data with [`LazyConsignment::bundle_at`]
+/// and [`LazyConsignment::bundles_for_endpoint`], while state extensions and
+/// data containers are decoded only by [`LazyConsignment::into_consignment`].
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct LazyConsignment<'a> {
+    data: &'a [u8],
+    version: u8,
+    schema: Schema,
+    root_schema: Option<Schema>,
+    genesis: Genesis,
+    endpoints: ConsignmentEndpoints,
+    /// Ids of the anchored bundles and their positions in the data
+    bundles: Vec<(BundleId, Range<usize>)>,
+    /// Position of the state extensions following the anchored bundles
+    extensions_offset: usize,
+}
+
+impl<'a> LazyConsignment<'a> {
+    /// Parses the consignment header and endpoints and indexes its bundles
+    pub fn with(data: &'a [u8]) -> Result<LazyConsignment<'a>, LazyError> {
+        let mut offset = 0usize;
+        let version = decode_at::<u8>(data, &mut offset)?;
+        if version > RGB_INMEM_CONSIGNMENT_VERSION {
+            return Err(LazyError::UnsupportedVersion(version));
+        }
+        let schema = decode_at(data, &mut offset)?;
+        let root_schema = decode_at(data, &mut offset)?;
+        let genesis = decode_at(data, &mut offset)?;
+        let endpoints = decode_at(data, &mut offset)?;
+
+        let count = decode_len(data, &mut offset)?;
+        let mut bundles = Vec::with_capacity(count);
+        for _ in 0..count {
+            if version > 1 {
+                let bundle_id = decode_at::<BundleId>(data, &mut offset)?;
+                let len = decode_len(data, &mut offset)?;
+                bundles.push((bundle_id, offset..offset + len));
+                offset += len;
+            } else {
+                let start = offset;
+                let (_, bundle) = decode_at::<AnchoredBundle>(data, &mut offset)?;
+                bundles.push((bundle.bundle_id(), start..offset));
+            }
+        }
+
+        Ok(LazyConsignment {
+            data,
+            version,
+            schema,
+            root_schema,
+            genesis,
+            endpoints,
+            bundles,
+            extensions_offset: offset,
+        })
+    }
+
+    /// Returns consignment schema
+    #[inline]
+    pub fn schema(&self) -> &Schema { &self.schema }
+
+    /// Returns root schema of the consignment schema, if any
+    #[inline]
+    pub fn root_schema(&self) -> Option<&Schema> { self.root_schema.as_ref() }
+
+    /// Returns contract genesis
+    #[inline]
+    pub fn genesis(&self) -> &Genesis { &self.genesis }
+
+    /// Returns consignment endpoints
+    #[inline]
+    pub fn endpoints(&self) -> &ConsignmentEndpoints { &self.endpoints }
+
+    /// Returns id of the consignment contract
+    #[inline]
+    pub fn contract_id(&self) -> ContractId { self.genesis.contract_id() }
+
+    /// Returns number of the anchored bundles in the consignment
+    #[inline]
+    pub fn bundle_count(&self) -> usize { self.bundles.len() }
+
+    /// Returns ids of the anchored bundles in the consignment order
+    pub fn bundle_ids(&self) -> Vec<BundleId> {
+        self.bundles
+            .iter()
+            .map(|(bundle_id, _)| *bundle_id)
+            .collect()
+    }
+
+    /// Decodes anchored bundle with the `index` in the consignment order,
+    /// checking that it takes all of its indexed data and matches its id
+    pub fn bundle_at(&self, index: usize) -> Result<AnchoredBundle, LazyError> {
+        let (bundle_id, range) = self.bundles.get(index).ok_or(LazyError::NoBundle(index))?;
+        let mut data = self
+            .data
+            .get(range.clone())
+            .ok_or(LazyError::OutOfRange(range.end))?;
+        let (anchor, bundle) = AnchoredBundle::strict_decode(&mut data)?;
+        if !data.is_empty() || bundle.bundle_id() != *bundle_id {
+            return Err(LazyError::BundleMismatch(index));
+        }
+        Ok((anchor, bundle))
+    }
+
+    /// Decodes all anchored bundles which the `endpoint` belongs to
+    pub fn bundles_for_endpoint(
+        &self,
+        endpoint: &SealEndpoint,
+    ) -> Result<Vec<AnchoredBundle>, LazyError> {
+        let bundle_ids = self
+            .endpoints
+            .iter()
+            .filter(|(_, seal)| seal == endpoint)
+            .map(|(bundle_id, _)| *bundle_id)
+            .collect::<Vec<_>>();
+        self.bundles
+            .iter()
+            .enumerate()
+            .filter(|(_, (bundle_id, _))| bundle_ids.contains(bundle_id))
+            .map(|(index, _)| self.bundle_at(index))
+            .collect()
+    }
+
+    /// Decodes the rest of the consignment data, converting it into the full
+    /// in-memory consignment
+    pub fn into_consignment<T>(self) -> Result<InmemConsignment<T>, LazyError>
+    where T: ConsignmentType {
+        let anchored_bundles = (0..self.bundles.len())
+            .map(|index| self.bundle_at(index))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut offset = self.extensions_offset;
+        let count = decode_len(self.data, &mut offset)?;
+        let state_extensions = (0..count)
+            .map(|_| decode_at(self.data, &mut offset))
+            .collect::<Result<Vec<_>, _>>()?;
+        let data_containers = decode_at(self.data, &mut offset)?;
+        let tlv = if self.version > 0 { decode_at(self.data, &mut offset)? } else { none!() };
+
+        let mut consignment = InmemConsignment::with(
+            self.schema,
+            self.root_schema,
+            self.genesis,
+            self.endpoints,
+            LargeVec::try_from(anchored_bundles)
+                .expect("number of bundles is read from 32-bit length prefix"),
+            LargeVec::try_from(state_extensions)
+                .expect("number of extensions is read from 32-bit length prefix"),
+        );
+        consignment.version = self.version;
+        consignment.data_containers = data_containers;
+        consignment.tlv = tlv;
+        Ok(consignment)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoin::hashes::Hash;
+    use bitcoin::secp256k1::rand::rngs::StdRng;
+    use bitcoin::secp256k1::rand::{Rng, SeedableRng};
+    use bitcoin::{OutPoint, Txid};
+    use commit_verify::CommitConceal;
+    use rgb_core::seal;
+    use strict_encoding::{StrictDecode, StrictEncode};
+
+    use super::*;
+    use crate::verify::test::consignment;
+    use crate::{StateTransfer, TransferConsignment};
+
+    fn transfer() -> (StateTransfer, SealEndpoint) {
+        let mut consignment = consignment(5);
+        let endpoint =
+            SealEndpoint::ConcealedUtxo(seal::Revealed::from(OutPoint::default()).commit_conceal());
+        let (_, last) = consignment.anchored_bundles.iter().last().unwrap();
+        consignment.endpoints = vec![(last.bundle_id(), endpoint)];
+        (consignment, endpoint)
+    }
+
+    #[test]
+    fn test_lazy_decoding() {
+        let (consignment, endpoint) = transfer();
+        let data = consignment.strict_serialize().unwrap();
+        let lazy = LazyConsignment::with(&data).unwrap();
+        assert_eq!(lazy.contract_id(), consignment.contract_id());
+        assert_eq!(lazy.endpoints(), &consignment.endpoints);
+        assert_eq!(lazy.bundle_count(), 5);
+
+        let anchored_bundles = consignment
+            .anchored_bundles
+            .iter()
+            .cloned()
+            .collect::<Vec<_>>();
+        for (index, anchored_bundle) in anchored_bundles.iter().enumerate() {
+            assert_eq!(&lazy.bundle_at(index).unwrap(), anchored_bundle);
+        }
+        assert!(matches!(lazy.bundle_at(5), Err(LazyError::NoBundle(5))));
+        assert_eq!(
+            lazy.bundles_for_endpoint(&endpoint).unwrap(),
+            vec![anchored_bundles[4].clone()]
+        );
+        let other = OutPoint::new(Txid::from_inner([5u8; 32]), 1);
+        let other = SealEndpoint::ConcealedUtxo(seal::Revealed::from(other).commit_conceal());
+        assert!(lazy.bundles_for_endpoint(&other).unwrap().is_empty());
+
+        assert_eq!(
+            lazy.into_consignment::<TransferConsignment>().unwrap(),
+            consignment
+        );
+    }
+
+    #[test]
+    fn test_lazy_tlv() {
+        let (mut consignment, _) = transfer();
+        consignment.tlv.insert(5, vec![0xAB; 4]).unwrap();
+        let data = consignment.strict_serialize().unwrap();
+        let lazy = LazyConsignment::with(&data).unwrap();
+        assert_eq!(
+            lazy.into_consignment::<TransferConsignment>().unwrap(),
+            consignment
+        );
+
+        let mut data = data;
+        data.truncate(data.len() - 10);
+        data.extend([1, 0, 4, 0, 0, 0]);
+        let lazy = LazyConsignment::with(&data).unwrap();
+        assert!(lazy.into_consignment::<TransferConsignment>().is_err());
+    }
+
+    #[test]
+    fn test_legacy_version() {
+        let (mut consignment, _) = transfer();
+        consignment.version = 1;
+        let data = consignment.strict_serialize().unwrap();
+        let lazy = LazyConsignment::with(&data).unwrap();
+        for (index, anchored_bundle) in consignment.anchored_bundles.iter().enumerate() {
+            assert_eq!(&lazy.bundle_at(index).unwrap(), anchored_bundle);
+        }
+        assert_eq!(
+            lazy.into_consignment::<TransferConsignment>().unwrap(),
+            consignment
+        );
+    }
+
+    #[test]
+    fn test_bundle_mismatch() {
+        let (consignment, _) = transfer();
+        let mut data = consignment.strict_serialize().unwrap();
+        let lazy = LazyConsignment::with(&data).unwrap();
+        let (_, range) = lazy.bundles[2].clone();
+        // Corrupting the bundle id prefix preceding its length
+        data[range.start - 5] ^= 0xFF;
+        let lazy = LazyConsignment::with(&data).unwrap();
+        assert!(matches!(
+            lazy.bundle_at(2),
+            Err(LazyError::BundleMismatch(2))
+        ));
+        assert!(lazy.bundle_at(1).is_ok());
+    }
+
+    #[test]
+    fn test_bundle_count() {
+        let (consignment, _) = transfer();
+        let mut data = consignment.strict_serialize().unwrap();
+        let offset = 1
+            + consignment.schema.strict_serialize().unwrap().len()
+            + consignment.root_schema.strict_serialize().unwrap().len()
+            + consignment.genesis.strict_serialize().unwrap().len()
+            + consignment.endpoints.strict_serialize().unwrap().len();
+        data[offset..offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(
+            LazyConsignment::with(&data),
+            Err(LazyError::Oversized {
+                declared: u32::MAX,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_truncated() {
+        let (consignment, _) = transfer();
+        let data = consignment.strict_serialize().unwrap();
+        for len in 0..data.len() {
+            if let Ok(lazy) = LazyConsignment::with(&data[..len]) {
+                for index in 0..lazy.bundle_count() {
+                    assert!(lazy.bundle_at(index).is_ok());
+                }
+                assert!(lazy.into_consignment::<TransferConsignment>().is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn test_corrupted() {
+        let (consignment, _) = transfer();
+        let data = consignment.strict_serialize().unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..1000 {
+            let mut corrupted = data.clone();
+            let pos = rng.gen_range(0..corrupted.len());
+            corrupted[pos] = rng.gen();
+            if let Ok(lazy) = LazyConsignment::with(&corrupted) {
+                for index in 0..=lazy.bundle_count() {
+                    let _ = lazy.bundle_at(index);
+                }
+                let _ = lazy.into_consignment::<TransferConsignment>();
+            }
+        }
+    }
+}
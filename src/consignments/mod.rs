@@ -15,6 +15,10 @@ mod contract;
 mod container;
 mod graph;
 mod iter;
+mod lazy;
+mod sealed;
+mod summary;
+mod uri;
 
 use commit_verify::lnpbp4;
 use rgb_core::{Anchor, BundleId, Extension, SealEndpoint, TransitionBundle};
@@ -22,7 +26,11 @@ use strict_encoding::LargeVec;
 
 pub use self::container::{InmemConsignment, RGB_INMEM_CONSIGNMENT_VERSION};
 pub use self::id::ConsignmentId;
-pub use self::iter::{ChainIter, MeshIter};
+pub use self::iter::{ChainIter, MeshIter, OwnedIter, TransitionIndex, WitnessedTransition};
+pub use self::lazy::{AnchoredBundle, LazyConsignment, LazyError};
+pub use self::sealed::{SealError, SealedConsignment, RGB_SEALED_CONSIGNMENT_VERSION};
+pub use self::summary::ConsignmentSummary;
+pub use self::uri::{qr_capacity, InvoiceUri, TransferPart, UriError};
 
 pub type AnchoredBundles = LargeVec<(Anchor<lnpbp4::MerkleProof>, TransitionBundle)>;
 pub type ExtensionList = LargeVec<Extension>;
@@ -0,0 +1,415 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Encrypted container for the consignments stored at relay servers and proxy
+//! mailboxes, which must not learn contract ids or any other consignment data.
+//!
+//! The consignment is encrypted with ChaCha20-Poly1305 under a random content
+//! key. The content key is wrapped for each of the recipients with a key
+//! derived from ECDH between an ephemeral key and the recipient public key.
+//! Since each of the keys is used only for a single message, all of them use
+//! the same random nonce. The container reveals only the nonce, the ephemeral
+//! key, the number of recipients and the size of the consignment.
+
+use std::io::{self, Read};
+
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::secp256k1::ecdh::SharedSecret;
+use bitcoin::secp256k1::rand::{thread_rng, CryptoRng, RngCore};
+use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+use chacha20poly1305::aead::{Aead, NewAead, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use strict_encoding::{StrictDecode, StrictEncode};
+
+use super::{ConsignmentType, InmemConsignment};
+use crate::ConsignmentId;
+
+/// Current version of the sealed consignment encoding
+pub const RGB_SEALED_CONSIGNMENT_VERSION: u8 = 1;
+
+const NONCE_LEN: usize = 12;
+const CONTENT_KEY_LEN: usize = 32;
+const TAG_LEN: usize = 16;
+const WRAPPED_KEY_LEN: usize = CONTENT_KEY_LEN + TAG_LEN;
+
+/// Domain separation tag of the key wrapping key derivation
+const WRAPPING_KEY_TAG: &[u8] = b"rgb:consignment:sealed";
+
+/// Errors sealing and unsealing consignments
+#[derive(Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum SealError {
+    /// consignment must be sealed to at least one recipient
+    NoRecipients,
+
+    /// consignment can't be sealed to {0} recipients; the maximum is 65535
+    TooManyRecipients(usize),
+
+    /// sealed consignment is not addressed to the provided key, or its key
+    /// data were modified
+    WrongKey,
+
+    /// sealed consignment data were modified
+    Tampered,
+
+    /// unsealed consignment has id {actual}, while {expected} was sealed
+    ChecksumMismatch {
+        expected: ConsignmentId,
+        actual: ConsignmentId,
+    },
+
+    /// consignment can't be encoded or decoded: {0}
+    #[from]
+    Encoding(strict_encoding::Error),
+}
+
+/// Consignment encrypted to one or more recipients
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct SealedConsignment {
+    version: u8,
+    nonce: [u8; NONCE_LEN],
+    ephemeral_key: PublicKey,
+    wrapped_keys: Vec<[u8; WRAPPED_KEY_LEN]>,
+    ciphertext: Vec<u8>,
+}
+
+fn wrapping_key(shared: SharedSecret, ephemeral_key: &PublicKey, recipient: &PublicKey) -> Key {
+    let mut engine = sha256::Hash::engine();
+    engine.input(WRAPPING_KEY_TAG);
+    engine.input(&shared.secret_bytes());
+    engine.input(&ephemeral_key.serialize());
+    engine.input(&recipient.serialize());
+    Key::clone_from_slice(&sha256::Hash::from_engine(engine)[..])
+}
+
+impl SealedConsignment {
+    /// Encrypts the `consignment` to a single `recipient`
+    #[inline]
+    pub fn seal<T>(
+        consignment: &InmemConsignment<T>,
+        recipient: &PublicKey,
+    ) -> Result<SealedConsignment, SealError>
+    where
+        T: ConsignmentType,
+    {
+        SealedConsignment::seal_to(consignment, &[*recipient])
+    }
+
+    /// Encrypts the `consignment` such that any of the `recipients` can
+    /// unseal it
+    #[inline]
+    pub fn seal_to<T>(
+        consignment: &InmemConsignment<T>,
+        recipients: &[PublicKey],
+    ) -> Result<SealedConsignment, SealError>
+    where
+        T: ConsignmentType,
+    {
+        SealedConsignment::seal_to_with_rng(consignment, recipients, &mut thread_rng())
+    }
+
+    /// Encrypts the `consignment` like [`Self::seal_to`], using the `rng` for
+    /// generating the ephemeral key, the content key and the nonce
+    pub fn seal_to_with_rng<T>(
+        consignment: &InmemConsignment<T>,
+        recipients: &[PublicKey],
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Result<SealedConsignment, SealError>
+    where
+        T: ConsignmentType,
+    {
+        let mut plaintext = consignment.id().strict_serialize()?;
+        consignment.strict_encode(&mut plaintext)?;
+        SealedConsignment::encrypt(&plaintext, recipients, rng)
+    }
+
+    fn encrypt(
+        plaintext: &[u8],
+        recipients: &[PublicKey],
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Result<SealedConsignment, SealError> {
+        if recipients.is_empty() {
+            return Err(SealError::NoRecipients);
+        }
+        if recipients.len() > u16::MAX as usize {
+            return Err(SealError::TooManyRecipients(recipients.len()));
+        }
+
+        let secp = Secp256k1::new();
+        let ephemeral_secret = SecretKey::new(rng);
+        let mut content_key = [0u8; CONTENT_KEY_LEN];
+        rng.fill_bytes(&mut content_key);
+        let mut sealed = SealedConsignment {
+            version: RGB_SEALED_CONSIGNMENT_VERSION,
+            nonce: [0u8; NONCE_LEN],
+            ephemeral_key: PublicKey::from_secret_key(&secp, &ephemeral_secret),
+            wrapped_keys: Vec::with_capacity(recipients.len()),
+            ciphertext: vec![],
+        };
+        rng.fill_bytes(&mut sealed.nonce);
+        let nonce = Nonce::from_slice(&sealed.nonce);
+        let aad = sealed.header();
+
+        for recipient in recipients {
+            let shared = SharedSecret::new(recipient, &ephemeral_secret);
+            let key = wrapping_key(shared, &sealed.ephemeral_key, recipient);
+            let wrapped = ChaCha20Poly1305::new(&key)
+                .encrypt(nonce, Payload {
+                    msg: &content_key,
+                    aad: &aad,
+                })
+                .expect("ChaCha20-Poly1305 encrypts 32-byte keys");
+            let mut wrapped_key = [0u8; WRAPPED_KEY_LEN];
+            wrapped_key.copy_from_slice(&wrapped);
+            sealed.wrapped_keys.push(wrapped_key);
+        }
+
+        sealed.ciphertext = ChaCha20Poly1305::new(Key::from_slice(&content_key))
+            .encrypt(nonce, Payload {
+                msg: plaintext,
+                aad: &aad,
+            })
+            .expect("consignment size is below ChaCha20-Poly1305 limit of 256 GB");
+        Ok(sealed)
+    }
+
+    /// Decrypts consignment with the recipient `secret` key, checking its
+    /// integrity
+    pub fn unseal<T>(&self, secret: &SecretKey) -> Result<InmemConsignment<T>, SealError>
+    where T: ConsignmentType {
+        let plaintext = self.decrypt(secret)?;
+        let mut cursor = io::Cursor::new(&plaintext);
+        let expected = ConsignmentId::strict_decode(&mut cursor)?;
+        let consignment =
+            InmemConsignment::<T>::strict_deserialize(&plaintext[cursor.position() as usize..])?;
+        let actual = consignment.id();
+        if actual != expected {
+            return Err(SealError::ChecksumMismatch { expected, actual });
+        }
+        Ok(consignment)
+    }
+
+    fn decrypt(&self, secret: &SecretKey) -> Result<Vec<u8>, SealError> {
+        let secp = Secp256k1::new();
+        let recipient = PublicKey::from_secret_key(&secp, secret);
+        let shared = SharedSecret::new(&self.ephemeral_key, secret);
+        let cipher = ChaCha20Poly1305::new(&wrapping_key(shared, &self.ephemeral_key, &recipient));
+        let nonce = Nonce::from_slice(&self.nonce);
+        let aad = self.header();
+
+        let content_key = self
+            .wrapped_keys
+            .iter()
+            .find_map(|wrapped_key| {
+                cipher
+                    .decrypt(nonce, Payload {
+                        msg: wrapped_key,
+                        aad: &aad,
+                    })
+                    .ok()
+            })
+            .ok_or(SealError::WrongKey)?;
+        ChaCha20Poly1305::new(Key::from_slice(&content_key))
+            .decrypt(nonce, Payload {
+                msg: &self.ciphertext,
+                aad: &aad,
+            })
+            .map_err(|_| SealError::Tampered)
+    }
+
+    /// Data authenticated together with each of the encrypted messages
+    fn header(&self) -> Vec<u8> {
+        let mut header = vec![self.version];
+        header.extend(self.nonce);
+        header.extend(self.ephemeral_key.serialize());
+        header
+    }
+
+    /// Returns version of the container encoding
+    #[inline]
+    pub fn version(&self) -> u8 { self.version }
+
+    /// Returns number of the recipients the consignment is sealed to
+    #[inline]
+    pub fn recipient_count(&self) -> usize { self.wrapped_keys.len() }
+}
+
+impl StrictEncode for SealedConsignment {
+    fn strict_encode<E: io::Write>(&self, mut e: E) -> Result<usize, strict_encoding::Error> {
+        let mut len = self.version.strict_encode(&mut e)?;
+        e.write_all(&self.nonce)?;
+        len += NONCE_LEN + self.ephemeral_key.strict_encode(&mut e)?;
+        len += (self.wrapped_keys.len() as u16).strict_encode(&mut e)?;
+        for wrapped_key in &self.wrapped_keys {
+            e.write_all(wrapped_key)?;
+            len += WRAPPED_KEY_LEN;
+        }
+        let ciphertext_len = u32::try_from(self.ciphertext.len()).map_err(|_| {
+            strict_encoding::Error::ExceedMaxItems(self.ciphertext.len())
+        })?;
+        len += ciphertext_len.strict_encode(&mut e)?;
+        e.write_all(&self.ciphertext)?;
+        Ok(len + self.ciphertext.len())
+    }
+}
+
+impl StrictDecode for SealedConsignment {
+    fn strict_decode<D: io::Read>(mut d: D) -> Result<Self, strict_encoding::Error> {
+        let version = u8::strict_decode(&mut d)?;
+        if version != RGB_SEALED_CONSIGNMENT_VERSION {
+            return Err(strict_encoding::Error::UnsupportedDataStructure(
+                "Only version 1 of sealed consignments is supported",
+            ));
+        }
+        let mut nonce = [0u8; NONCE_LEN];
+        d.read_exact(&mut nonce)?;
+        let ephemeral_key = PublicKey::strict_decode(&mut d)?;
+        let count = u16::strict_decode(&mut d)?;
+        let mut wrapped_keys = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let mut wrapped_key = [0u8; WRAPPED_KEY_LEN];
+            d.read_exact(&mut wrapped_key)?;
+            wrapped_keys.push(wrapped_key);
+        }
+        // Ciphertext is read up to its declared length without allocating it
+        // upfront, since the length is not authenticated
+        let ciphertext_len = u32::strict_decode(&mut d)? as usize;
+        let mut ciphertext = vec![];
+        (&mut d).take(ciphertext_len as u64).read_to_end(&mut ciphertext)?;
+        if ciphertext.len() != ciphertext_len {
+            return Err(strict_encoding::Error::DataIntegrityError(format!(
+                "sealed consignment declares {} bytes of ciphertext, while only {} are present",
+                ciphertext_len,
+                ciphertext.len()
+            )));
+        }
+        Ok(SealedConsignment {
+            version,
+            nonce,
+            ephemeral_key,
+            wrapped_keys,
+            ciphertext,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoin::secp256k1::rand::rngs::StdRng;
+    use bitcoin::secp256k1::rand::SeedableRng;
+
+    use super::*;
+    use crate::verify::test::consignment;
+    use crate::{StateTransfer, TransferConsignment};
+
+    fn keypair(no: u8) -> (SecretKey, PublicKey) {
+        let secret = SecretKey::from_slice(&[no; 32]).unwrap();
+        (secret, PublicKey::from_secret_key(&Secp256k1::new(), &secret))
+    }
+
+    fn contains(data: &[u8], needle: &[u8]) -> bool {
+        data.windows(needle.len()).any(|window| window == needle)
+    }
+
+    #[test]
+    fn test_seal_unseal() {
+        let transfer = consignment(2);
+        let (secret, public) = keypair(1);
+        let sealed = SealedConsignment::seal(&transfer, &public).unwrap();
+        let data = sealed.strict_serialize().unwrap();
+        assert!(!contains(&data, &transfer.id().strict_serialize().unwrap()));
+        assert!(!contains(&data, &transfer.contract_id().strict_serialize().unwrap()));
+
+        let sealed = SealedConsignment::strict_deserialize(&data).unwrap();
+        let unsealed: StateTransfer = sealed.unseal(&secret).unwrap();
+        assert_eq!(unsealed, transfer);
+    }
+
+    #[test]
+    fn test_seal_with_rng() {
+        let transfer = consignment(1);
+        let (secret, public) = keypair(1);
+        let seal = |seed: u64| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            SealedConsignment::seal_to_with_rng(&transfer, &[public], &mut rng).unwrap()
+        };
+        assert_eq!(seal(7), seal(7));
+        assert_ne!(seal(7), seal(8));
+        let unsealed: StateTransfer = seal(7).unseal(&secret).unwrap();
+        assert_eq!(unsealed, transfer);
+    }
+
+    #[test]
+    fn test_multiple_recipients() {
+        let transfer = consignment(1);
+        let keys = (1..=3).map(keypair).collect::<Vec<_>>();
+        let recipients = keys.iter().map(|(_, public)| *public).collect::<Vec<_>>();
+        let sealed = SealedConsignment::seal_to(&transfer, &recipients).unwrap();
+        assert_eq!(sealed.recipient_count(), 3);
+        for (secret, _) in &keys {
+            assert_eq!(sealed.unseal::<TransferConsignment>(secret).unwrap(), transfer);
+        }
+        assert!(matches!(
+            SealedConsignment::seal_to(&transfer, &[]),
+            Err(SealError::NoRecipients)
+        ));
+    }
+
+    #[test]
+    fn test_wrong_key() {
+        let sealed = SealedConsignment::seal(&consignment(1), &keypair(1).1).unwrap();
+        assert!(matches!(
+            sealed.unseal::<TransferConsignment>(&keypair(2).0),
+            Err(SealError::WrongKey)
+        ));
+    }
+
+    #[test]
+    fn test_tamper() {
+        let (secret, public) = keypair(1);
+        let data = SealedConsignment::seal(&consignment(1), &public)
+            .unwrap()
+            .strict_serialize()
+            .unwrap();
+
+        let mut tampered = data.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        let sealed = SealedConsignment::strict_deserialize(&tampered).unwrap();
+        assert!(matches!(
+            sealed.unseal::<TransferConsignment>(&secret),
+            Err(SealError::Tampered)
+        ));
+
+        // Modified nonce invalidates wrapped keys as well
+        let mut tampered = data.clone();
+        tampered[1] ^= 1;
+        let sealed = SealedConsignment::strict_deserialize(&tampered).unwrap();
+        assert!(matches!(
+            sealed.unseal::<TransferConsignment>(&secret),
+            Err(SealError::WrongKey)
+        ));
+
+        assert!(SealedConsignment::strict_deserialize(&data[..data.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_checksum_mismatch() {
+        let (secret, public) = keypair(1);
+        let transfer = consignment(1);
+        let mut plaintext = consignment(2).id().strict_serialize().unwrap();
+        transfer.strict_encode(&mut plaintext).unwrap();
+        let sealed = SealedConsignment::encrypt(&plaintext, &[public], &mut thread_rng()).unwrap();
+        assert!(matches!(
+            sealed.unseal::<TransferConsignment>(&secret),
+            Err(SealError::ChecksumMismatch { actual, .. }) if actual == transfer.id()
+        ));
+    }
+}
@@ -0,0 +1,91 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+use std::fmt::{self, Display, Formatter};
+
+use rgb_core::ContractId;
+
+use super::{ConsignmentType, InmemConsignment};
+use crate::{ConsignmentId, ToMnemonic};
+
+/// Short description of a consignment for presenting it to the users before
+/// the consignment is accepted. Display of the summary includes checksum words
+/// of the ids, see [`ToMnemonic`].
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct ConsignmentSummary {
+    /// Id of the contract the consignment belongs to
+    pub contract_id: ContractId,
+
+    /// Id of the consignment
+    pub consignment_id: ConsignmentId,
+
+    /// Number of the anchored bundles of state transitions
+    pub bundles: usize,
+
+    /// Number of the consignment endpoints
+    pub endpoints: usize,
+}
+
+impl Display for ConsignmentSummary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "contract: {} ({})",
+            self.contract_id,
+            self.contract_id.to_mnemonic()
+        )?;
+        writeln!(
+            f,
+            "consignment: {} ({})",
+            self.consignment_id,
+            self.consignment_id.to_mnemonic()
+        )?;
+        writeln!(f, "bundles: {}", self.bundles)?;
+        writeln!(f, "endpoints: {}", self.endpoints)
+    }
+}
+
+impl<T> InmemConsignment<T>
+where T: ConsignmentType
+{
+    /// Returns summary of the consignment
+    pub fn summary(&self) -> ConsignmentSummary {
+        ConsignmentSummary {
+            contract_id: self.contract_id(),
+            consignment_id: self.id(),
+            bundles: self.anchored_bundles.len(),
+            endpoints: self.endpoints.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::verify::test::consignment;
+
+    #[test]
+    fn test_summary() {
+        let consignment = consignment(2);
+        let summary = consignment.summary();
+        assert_eq!(summary, ConsignmentSummary {
+            contract_id: consignment.contract_id(),
+            consignment_id: consignment.id(),
+            bundles: 2,
+            endpoints: 0,
+        });
+
+        let display = summary.to_string();
+        assert!(display.contains(&summary.contract_id.to_mnemonic()));
+        assert!(display.contains(&summary.consignment_id.to_mnemonic()));
+        assert!(display.ends_with("bundles: 2\nendpoints: 0\n"));
+    }
+}
@@ -16,9 +16,21 @@ use std::collections::{BTreeMap, BTreeSet};
 use commit_verify::CommitConceal;
 use rgb_core::{seal, ConcealSeals, ConcealState, Node, SealEndpoint, TransitionBundle};
 
-use super::StateTransfer;
+use super::{ConsignmentType, InmemConsignment, StateTransfer};
 
 impl StateTransfer {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(
+                contract_id = %self.contract_id(),
+                bundles = self.anchored_bundles.len(),
+                concealed = tracing::field::Empty,
+            )
+        )
+    )]
     pub fn finalize(&mut self, expose: &BTreeSet<SealEndpoint>) -> usize {
         let concealed_endpoints = expose
             .iter()
@@ -71,9 +83,15 @@ impl StateTransfer {
                 count + extension.conceal_state_except(&concealed_endpoints)
             });
 
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("concealed", &count);
         count
     }
+}
 
+impl<T> InmemConsignment<T>
+where T: ConsignmentType
+{
     /// Reveals previously known seal information (replacing blind UTXOs with
     /// unblind ones). Function is used when a peer receives consignments
     /// containing concealed seals for the outputs owned by the peer
@@ -0,0 +1,447 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! `rgb:` URIs for invoices and state transfers, compact enough for QR codes.
+//!
+//! Invoices are represented as `rgb:invoice?inv=<bech32>` URIs, with `amount`
+//! and `contract` query params giving hints to the wallets which show them
+//! before decoding the invoice. State transfers are split into numbered parts
+//! `rgb:transfer/<n>of<total>/<base64url>`, each of which may be put into a
+//! separate QR code and scanned in any order.
+
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use percent_encoding::{percent_decode_str, utf8_percent_encode, NON_ALPHANUMERIC};
+use strict_encoding::{StrictDecode, StrictEncode};
+
+use crate::fungible::{Amount, Invoice};
+use crate::{ConsignmentId, ContractId, StateTransfer};
+
+const INVOICE_PREFIX: &str = "rgb:invoice?";
+const TRANSFER_PREFIX: &str = "rgb:transfer/";
+
+/// Number of the consignment id bytes repeated in each of the transfer parts
+const TRANSFER_TAG_LEN: usize = 4;
+
+/// Number of bytes which can be stored in QR codes of versions 1 to 40 in byte
+/// mode with the low (L) error correction level
+const QR_BYTE_CAPACITY: [usize; 40] = [
+    17, 32, 53, 78, 106, 134, 154, 192, 230, 271, 321, 367, 425, 458, 520, 586, 644, 718, 792, 858,
+    929, 1003, 1091, 1171, 1273, 1367, 1465, 1528, 1628, 1732, 1840, 1952, 2068, 2188, 2303, 2431,
+    2563, 2699, 2809, 2953,
+];
+
+/// Returns number of bytes which can be stored in a QR code of the given
+/// `version` with the low error correction level, or `None` for versions
+/// outside of 1..=40 range
+pub fn qr_capacity(version: u8) -> Option<usize> {
+    QR_BYTE_CAPACITY
+        .get(version.checked_sub(1)? as usize)
+        .copied()
+}
+
+/// Errors parsing `rgb:` URIs and reassembling state transfers
+#[derive(Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum UriError {
+    /// string is not an RGB invoice or transfer URI
+    UnknownUri,
+
+    /// invalid URI query parameter `{0}`
+    InvalidQuery(String),
+
+    /// invoice URI does not contain the invoice
+    NoInvoice,
+
+    /// `{0}` hint of the URI does not match the invoice
+    HintMismatch(&'static str),
+
+    /// invalid bech32 encoding: {0}
+    #[from]
+    Bech32(lnpbp_bech32::Error),
+
+    /// invalid transfer part number `{0}`
+    InvalidPartNumber(String),
+
+    /// invalid base64url encoding of the transfer part: {0}
+    #[from]
+    Base64(base64::DecodeError),
+
+    /// transfer part does not contain the transfer tag
+    NoTransferTag,
+
+    /// QR code version {0} is too small for the transfer parts
+    QrTooSmall(u8),
+
+    /// state transfer requires {0} parts, while at most 65535 are supported
+    TooManyParts(usize),
+
+    /// no transfer parts are provided
+    NoParts,
+
+    /// transfer parts belong to different state transfers
+    MixedTransfers,
+
+    /// {missing} of {total} transfer parts are missing
+    Incomplete { missing: usize, total: u16 },
+
+    /// reassembled state transfer does not match its id
+    ChecksumMismatch,
+
+    /// state transfer can't be encoded or decoded: {0}
+    #[from]
+    Encoding(strict_encoding::Error),
+}
+
+/// Invoice in the form of `rgb:invoice` URI
+#[derive(Clone, PartialEq, Eq, Hash, Debug, From)]
+pub struct InvoiceUri(Invoice);
+
+impl InvoiceUri {
+    /// Returns invoice represented by the URI
+    #[inline]
+    pub fn invoice(&self) -> &Invoice { &self.0 }
+
+    /// Converts URI into the invoice
+    #[inline]
+    pub fn into_invoice(self) -> Invoice { self.0 }
+
+    /// Checks whether the URI fits into a single QR code of the `version`
+    pub fn fits_in_qr(&self, version: u8) -> bool {
+        qr_capacity(version).map_or(false, |capacity| self.to_string().len() <= capacity)
+    }
+}
+
+impl Display for InvoiceUri {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let invoice = self.0.to_string();
+        write!(
+            f,
+            "{}inv={}",
+            INVOICE_PREFIX,
+            utf8_percent_encode(&invoice, NON_ALPHANUMERIC)
+        )?;
+        if let Some(amount) = self.0.amount {
+            write!(f, "&amount={}", amount)?;
+        }
+        let contract_id = self.0.contract_id.to_string();
+        write!(
+            f,
+            "&contract={}",
+            utf8_percent_encode(&contract_id, NON_ALPHANUMERIC)
+        )
+    }
+}
+
+/// Parses invoice URI, checking the hints against the invoice. Unknown query
+/// params are ignored.
+impl FromStr for InvoiceUri {
+    type Err = UriError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let query = s.strip_prefix(INVOICE_PREFIX).ok_or(UriError::UnknownUri)?;
+        let mut invoice = None;
+        let mut amount = None;
+        let mut contract_id = None;
+        for param in query.split('&') {
+            let invalid = || UriError::InvalidQuery(param.to_owned());
+            let (key, value) = param.split_once('=').ok_or_else(invalid)?;
+            let value = percent_decode_str(value)
+                .decode_utf8()
+                .map_err(|_| invalid())?;
+            match key {
+                "inv" => invoice = Some(Invoice::from_str(&value)?),
+                "amount" => amount = Some(Amount::from_str(&value).map_err(|_| invalid())?),
+                "contract" => contract_id = Some(ContractId::from_str(&value)?),
+                _ => {}
+            }
+        }
+
+        let invoice = invoice.ok_or(UriError::NoInvoice)?;
+        if amount.is_some() && amount != invoice.amount {
+            return Err(UriError::HintMismatch("amount"));
+        }
+        if matches!(contract_id, Some(id) if id != invoice.contract_id) {
+            return Err(UriError::HintMismatch("contract"));
+        }
+        Ok(InvoiceUri(invoice))
+    }
+}
+
+/// Numbered part of a state transfer in the form of `rgb:transfer` URI.
+///
+/// State transfer is encoded together with its id, and each of the parts
+/// starts with the first bytes of the id, such that the parts of different
+/// transfers can't be mixed up.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct TransferPart {
+    index: u16,
+    total: u16,
+    tag: [u8; TRANSFER_TAG_LEN],
+    chunk: Vec<u8>,
+}
+
+impl TransferPart {
+    /// Splits `transfer` into parts carrying up to `chunk_size` bytes of the
+    /// transfer data each
+    pub fn split(
+        transfer: &StateTransfer,
+        chunk_size: usize,
+    ) -> Result<Vec<TransferPart>, UriError> {
+        let mut data = transfer.id().strict_serialize()?;
+        transfer.strict_encode(&mut data)?;
+        let chunk_size = chunk_size.max(1);
+        let count = (data.len() + chunk_size - 1) / chunk_size;
+        let total = u16::try_from(count).map_err(|_| UriError::TooManyParts(count))?;
+        let mut tag = [0u8; TRANSFER_TAG_LEN];
+        tag.copy_from_slice(&data[..TRANSFER_TAG_LEN]);
+        Ok(data
+            .chunks(chunk_size)
+            .zip(1..)
+            .map(|(chunk, index)| TransferPart {
+                index,
+                total,
+                tag,
+                chunk: chunk.to_vec(),
+            })
+            .collect())
+    }
+
+    /// Splits `transfer` into parts each of which fits into a QR code of the
+    /// `version`
+    pub fn split_for_qr(
+        transfer: &StateTransfer,
+        version: u8,
+    ) -> Result<Vec<TransferPart>, UriError> {
+        let capacity = qr_capacity(version).ok_or(UriError::QrTooSmall(version))?;
+        // Reserve space for the largest part numbers
+        let header_len = TRANSFER_PREFIX.len() + "65535of65535/".len();
+        let base64_len = capacity.saturating_sub(header_len);
+        let chunk_size = (base64_len * 3 / 4).saturating_sub(TRANSFER_TAG_LEN);
+        if chunk_size == 0 {
+            return Err(UriError::QrTooSmall(version));
+        }
+        TransferPart::split(transfer, chunk_size)
+    }
+
+    /// Reassembles state transfer from its parts, provided in any order,
+    /// checking that all parts are present and belong to the same transfer
+    pub fn reassemble(parts: &[TransferPart]) -> Result<StateTransfer, UriError> {
+        let first = parts.first().ok_or(UriError::NoParts)?;
+        let mut chunks = vec![None; first.total as usize];
+        for part in parts {
+            if part.total != first.total || part.tag != first.tag {
+                return Err(UriError::MixedTransfers);
+            }
+            match &mut chunks[part.index as usize - 1] {
+                Some(chunk) if *chunk != &part.chunk => return Err(UriError::MixedTransfers),
+                slot => *slot = Some(&part.chunk),
+            }
+        }
+        let missing = chunks.iter().filter(|chunk| chunk.is_none()).count();
+        if missing > 0 {
+            return Err(UriError::Incomplete {
+                missing,
+                total: first.total,
+            });
+        }
+
+        let data = chunks
+            .into_iter()
+            .flatten()
+            .flatten()
+            .copied()
+            .collect::<Vec<_>>();
+        let mut cursor = data.as_slice();
+        let id = ConsignmentId::strict_decode(&mut cursor)?;
+        let transfer = StateTransfer::strict_deserialize(cursor)?;
+        if transfer.id() != id || data[..TRANSFER_TAG_LEN] != first.tag {
+            return Err(UriError::ChecksumMismatch);
+        }
+        Ok(transfer)
+    }
+
+    /// Returns one-based number of the part
+    #[inline]
+    pub fn index(&self) -> u16 { self.index }
+
+    /// Returns total number of the transfer parts
+    #[inline]
+    pub fn total(&self) -> u16 { self.total }
+
+    /// Checks whether the part URI fits into a single QR code of the `version`
+    pub fn fits_in_qr(&self, version: u8) -> bool {
+        qr_capacity(version).map_or(false, |capacity| self.to_string().len() <= capacity)
+    }
+}
+
+impl Display for TransferPart {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut data = self.tag.to_vec();
+        data.extend(&self.chunk);
+        write!(
+            f,
+            "{}{}of{}/{}",
+            TRANSFER_PREFIX,
+            self.index,
+            self.total,
+            base64::encode_config(data, base64::URL_SAFE_NO_PAD)
+        )
+    }
+}
+
+impl FromStr for TransferPart {
+    type Err = UriError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let path = s
+            .strip_prefix(TRANSFER_PREFIX)
+            .ok_or(UriError::UnknownUri)?;
+        let (number, data) = path.split_once('/').ok_or(UriError::UnknownUri)?;
+        let invalid = || UriError::InvalidPartNumber(number.to_owned());
+        let (index, total) = number.split_once("of").ok_or_else(invalid)?;
+        let index = index.parse::<u16>().map_err(|_| invalid())?;
+        let total = total.parse::<u16>().map_err(|_| invalid())?;
+        if index == 0 || index > total {
+            return Err(invalid());
+        }
+
+        let data = base64::decode_config(data, base64::URL_SAFE_NO_PAD)?;
+        if data.len() <= TRANSFER_TAG_LEN {
+            return Err(UriError::NoTransferTag);
+        }
+        let mut tag = [0u8; TRANSFER_TAG_LEN];
+        tag.copy_from_slice(&data[..TRANSFER_TAG_LEN]);
+        Ok(TransferPart {
+            index,
+            total,
+            tag,
+            chunk: data[TRANSFER_TAG_LEN..].to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoin::hashes::Hash;
+    use bitcoin::{OutPoint, Txid};
+
+    use super::*;
+    use crate::seal;
+    use crate::verify::test::consignment;
+
+    fn invoice_uri() -> InvoiceUri {
+        let outpoint = OutPoint::new(Txid::from_inner([1u8; 32]), 2);
+        let seal = seal::Revealed::from(outpoint);
+        let amount = Some(Amount::from(1000));
+        InvoiceUri::from(Invoice::with_seal(ContractId::default(), amount, seal))
+    }
+
+    fn parse(parts: &[TransferPart]) -> Vec<TransferPart> {
+        parts
+            .iter()
+            .map(|part| part.to_string().parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_invoice_uri() {
+        let uri = invoice_uri();
+        let s = uri.to_string();
+        assert!(s.starts_with("rgb:invoice?inv=rgbinv1"));
+        assert!(s.contains("&amount=1000&contract="));
+        assert_eq!(InvoiceUri::from_str(&s).unwrap(), uri);
+        assert!(uri.fits_in_qr(40));
+        assert!(!uri.fits_in_qr(1));
+        assert!(!uri.fits_in_qr(41));
+
+        let inv = format!("rgb:invoice?inv={}", uri.invoice());
+        assert_eq!(InvoiceUri::from_str(&inv).unwrap(), uri);
+        let extended = format!("{}&label=shop", s);
+        assert_eq!(InvoiceUri::from_str(&extended).unwrap(), uri);
+    }
+
+    #[test]
+    fn test_invoice_uri_invalid() {
+        let s = invoice_uri().to_string();
+        assert!(matches!(
+            InvoiceUri::from_str(&s.replace("amount=1000", "amount=999")),
+            Err(UriError::HintMismatch("amount"))
+        ));
+        assert!(matches!(
+            InvoiceUri::from_str("rgb:invoice?amount=1000"),
+            Err(UriError::NoInvoice)
+        ));
+        assert!(matches!(
+            InvoiceUri::from_str(&s.replace("rgb:", "bitcoin:")),
+            Err(UriError::UnknownUri)
+        ));
+        assert!(matches!(
+            InvoiceUri::from_str(&s.replace("&amount=", "&amount")),
+            Err(UriError::InvalidQuery(_))
+        ));
+    }
+
+    #[test]
+    fn test_transfer_parts() {
+        let transfer = consignment(2);
+        let parts = TransferPart::split_for_qr(&transfer, 5).unwrap();
+        assert!(parts.len() > 1);
+        assert!(parts.iter().all(|part| part.fits_in_qr(5)));
+        assert!(parts[0]
+            .to_string()
+            .starts_with(&format!("rgb:transfer/1of{}/", parts.len())));
+
+        let mut parsed = parse(&parts);
+        assert_eq!(parsed, parts);
+        parsed.reverse();
+        parsed.push(parsed[0].clone());
+        assert_eq!(TransferPart::reassemble(&parsed).unwrap(), transfer);
+
+        assert!(matches!(
+            TransferPart::split_for_qr(&transfer, 1),
+            Err(UriError::QrTooSmall(1))
+        ));
+    }
+
+    #[test]
+    fn test_transfer_parts_invalid() {
+        let parts = parse(&TransferPart::split(&consignment(2), 50).unwrap());
+        let other = TransferPart::split(&consignment(1), 50).unwrap();
+
+        assert!(matches!(
+            TransferPart::reassemble(&parts[1..]),
+            Err(UriError::Incomplete { missing: 1, total }) if total as usize == parts.len()
+        ));
+        let mut mixed = parts.clone();
+        mixed[0] = other[0].clone();
+        assert!(matches!(
+            TransferPart::reassemble(&mixed),
+            Err(UriError::MixedTransfers)
+        ));
+        assert!(matches!(
+            TransferPart::reassemble(&[]),
+            Err(UriError::NoParts)
+        ));
+
+        for s in ["rgb:transfer/0of2/AAAAAAA", "rgb:transfer/3of2/AAAAAAA", "rgb:transfer/1/AAAA"] {
+            assert!(matches!(
+                TransferPart::from_str(s),
+                Err(UriError::InvalidPartNumber(_))
+            ));
+        }
+        assert!(matches!(
+            TransferPart::from_str("rgb:transfer/1of1/AAAA"),
+            Err(UriError::NoTransferTag)
+        ));
+    }
+}
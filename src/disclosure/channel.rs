@@ -0,0 +1,440 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Disclosures of the lightning channel state updates. Each channel update
+//! produces a new commitment transaction together with a disclosure of the
+//! state transitions it anchors; the disclosure has to be merged into the
+//! stash only once the commitment transaction gets mined on channel closing.
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+
+use bitcoin::hashes::hex::ToHex;
+use bitcoin::Txid;
+use strict_encoding::{StrictDecode, StrictEncode};
+
+use super::Disclosure;
+
+/// Lightning channel identifier
+#[derive(Wrapper, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default, From)]
+#[derive(StrictEncode, StrictDecode)]
+#[wrapper(BorrowSlice)]
+pub struct ChannelId([u8; 32]);
+
+impl Display for ChannelId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result { f.write_str(&self.0.to_hex()) }
+}
+
+/// Errors updating [`ChannelDisclosureQueue`]
+#[derive(Clone, PartialEq, Eq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum ChannelError {
+    /// commitment {commitment_no} of channel {channel_id} is older than the
+    /// latest known commitment {latest}
+    Outdated {
+        channel_id: ChannelId,
+        commitment_no: u64,
+        latest: u64,
+    },
+
+    /// commitment transaction {0} is already used by another channel update
+    TxidReused(Txid),
+}
+
+/// Disclosure of the state transitions anchored to a commitment transaction
+/// of a lightning channel
+#[derive(Clone, PartialEq, Debug)]
+#[derive(StrictEncode, StrictDecode)]
+pub struct ChannelDisclosure {
+    pub channel_id: ChannelId,
+
+    /// Number of the channel state update
+    pub commitment_no: u64,
+
+    /// Commitment transaction of the update, which becomes the witness
+    /// transaction of the disclosed state transitions once mined
+    pub commitment_txid: Txid,
+
+    pub disclosure: Disclosure,
+}
+
+/// Queue of the disclosures for the channel updates pending the channel
+/// closing.
+///
+/// The queue keeps a single disclosure per channel commitment number. Adding
+/// disclosure of a newer commitment supersedes the disclosures of the older
+/// ones, which are retained as revoked: a counterparty may still publish one
+/// of the revoked commitment transactions. Revoked disclosures are kept until
+/// the channel gets closed or they are removed with
+/// [`ChannelDisclosureQueue::prune_revoked`].
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct ChannelDisclosureQueue {
+    channels: BTreeMap<ChannelId, BTreeMap<u64, ChannelDisclosure>>,
+    /// Channel ids and commitment numbers of all the commitment transactions
+    /// in the queue; not encoded and reconstructed on decoding
+    txids: BTreeMap<Txid, (ChannelId, u64)>,
+}
+
+impl StrictEncode for ChannelDisclosureQueue {
+    fn strict_encode<E: io::Write>(&self, e: E) -> Result<usize, strict_encoding::Error> {
+        self.channels.strict_encode(e)
+    }
+}
+
+impl StrictDecode for ChannelDisclosureQueue {
+    fn strict_decode<D: io::Read>(d: D) -> Result<Self, strict_encoding::Error> {
+        let channels = BTreeMap::<ChannelId, BTreeMap<u64, ChannelDisclosure>>::strict_decode(d)?;
+        let mut txids = BTreeMap::new();
+        for (channel_id, updates) in &channels {
+            if updates.is_empty() {
+                return Err(strict_encoding::Error::DataIntegrityError(format!(
+                    "channel {} has no commitments",
+                    channel_id
+                )));
+            }
+            for (commitment_no, update) in updates {
+                if (update.channel_id, update.commitment_no) != (*channel_id, *commitment_no) {
+                    return Err(strict_encoding::Error::DataIntegrityError(format!(
+                        "disclosure of commitment {} of channel {} is stored as commitment {} of \
+                         channel {}",
+                        update.commitment_no, update.channel_id, commitment_no, channel_id
+                    )));
+                }
+                let txid = update.commitment_txid;
+                if txids.insert(txid, (*channel_id, *commitment_no)).is_some() {
+                    return Err(strict_encoding::Error::DataIntegrityError(format!(
+                        "commitment transaction {} is used by more than one channel update",
+                        txid
+                    )));
+                }
+            }
+        }
+        Ok(ChannelDisclosureQueue { channels, txids })
+    }
+}
+
+impl ChannelDisclosureQueue {
+    /// Constructs empty queue
+    #[inline]
+    pub fn new() -> ChannelDisclosureQueue { ChannelDisclosureQueue::default() }
+
+    /// Returns number of channels with pending disclosures
+    #[inline]
+    pub fn len(&self) -> usize { self.channels.len() }
+
+    /// Detects whether the queue has no pending disclosures
+    #[inline]
+    pub fn is_empty(&self) -> bool { self.channels.is_empty() }
+
+    /// Adds disclosure of a channel update, returning the disclosure of the
+    /// same commitment number which it replaces, if any. Fails if the channel
+    /// already has a newer commitment, or if the commitment transaction is
+    /// used by other update.
+    pub fn insert(
+        &mut self,
+        disclosure: ChannelDisclosure,
+    ) -> Result<Option<ChannelDisclosure>, ChannelError> {
+        let txid = disclosure.commitment_txid;
+        if let Some((channel_id, commitment_no, _)) = self.find_commitment(txid) {
+            if (channel_id, commitment_no) != (disclosure.channel_id, disclosure.commitment_no) {
+                return Err(ChannelError::TxidReused(txid));
+            }
+        }
+
+        let updates = self.channels.entry(disclosure.channel_id).or_default();
+        if let Some(latest) = updates.keys().next_back().copied() {
+            if disclosure.commitment_no < latest {
+                return Err(ChannelError::Outdated {
+                    channel_id: disclosure.channel_id,
+                    commitment_no: disclosure.commitment_no,
+                    latest,
+                });
+            }
+        }
+        let key = (disclosure.channel_id, disclosure.commitment_no);
+        let prev = updates.insert(disclosure.commitment_no, disclosure);
+        if let Some(ref prev) = prev {
+            self.txids.remove(&prev.commitment_txid);
+        }
+        self.txids.insert(txid, key);
+        Ok(prev)
+    }
+
+    /// Returns disclosure of the latest commitment of the channel
+    pub fn pending(&self, channel_id: ChannelId) -> Option<&ChannelDisclosure> {
+        self.channels
+            .get(&channel_id)
+            .and_then(|updates| updates.values().next_back())
+    }
+
+    /// Returns disclosures of the revoked commitments of the channel, ordered
+    /// by their commitment number
+    pub fn revoked(&self, channel_id: ChannelId) -> impl Iterator<Item = &ChannelDisclosure> {
+        let updates = self.channels.get(&channel_id);
+        let count = updates.map(BTreeMap::len).unwrap_or_default();
+        updates
+            .into_iter()
+            .flat_map(BTreeMap::values)
+            .take(count.saturating_sub(1))
+    }
+
+    /// Removes disclosures of the revoked commitments of the channel, except
+    /// for the `keep` most recent of them, and returns the removed
+    /// disclosures ordered by their commitment number.
+    ///
+    /// Once a revoked disclosure is removed, confirmation of its commitment
+    /// transaction can't be processed by the queue anymore.
+    pub fn prune_revoked(&mut self, channel_id: ChannelId, keep: usize) -> Vec<ChannelDisclosure> {
+        let updates = match self.channels.get_mut(&channel_id) {
+            Some(updates) => updates,
+            None => return vec![],
+        };
+        let pruned = updates.len().saturating_sub(keep + 1);
+        let commitment_nos = updates.keys().take(pruned).copied().collect::<Vec<_>>();
+        let mut removed = Vec::with_capacity(pruned);
+        for commitment_no in commitment_nos {
+            if let Some(update) = updates.remove(&commitment_no) {
+                self.txids.remove(&update.commitment_txid);
+                removed.push(update);
+            }
+        }
+        removed
+    }
+
+    /// Returns channel id and number of the commitment transaction `txid`,
+    /// together with a flag whether the commitment is revoked
+    fn find_commitment(&self, txid: Txid) -> Option<(ChannelId, u64, bool)> {
+        let (channel_id, commitment_no) = *self.txids.get(&txid)?;
+        let latest = *self.channels.get(&channel_id)?.keys().next_back()?;
+        Some((channel_id, commitment_no, commitment_no < latest))
+    }
+
+    /// Detects whether `txid` is a revoked commitment transaction of some
+    /// channel
+    #[inline]
+    pub fn is_revoked(&self, txid: Txid) -> bool {
+        matches!(self.find_commitment(txid), Some((_, _, true)))
+    }
+
+    /// Processes closing of the channel with the latest commitment
+    /// transaction `txid`, returning its disclosure and discarding the
+    /// disclosures of the revoked commitments of the channel.
+    ///
+    /// Returns `None` and keeps the queue unchanged if `txid` is not known or
+    /// is a revoked commitment; the latter case is processed by
+    /// [`ChannelDisclosureQueue::commitment_confirmed`].
+    pub fn channel_closed(&mut self, txid: Txid) -> Option<ChannelDisclosure> {
+        if self.is_revoked(txid) {
+            return None;
+        }
+        let (disclosure, _) = self.commitment_confirmed(txid)?;
+        Some(disclosure)
+    }
+
+    /// Processes closing of the channel with any of its commitment
+    /// transactions, returning disclosure of the commitment `txid` together
+    /// with a flag whether the commitment was revoked, and discarding all
+    /// other disclosures of the channel.
+    ///
+    /// Confirmation of the revoked commitment means that the counterparty
+    /// tries to close the channel with the outdated state, and the
+    /// disclosure describes the state which has to be punished.
+    pub fn commitment_confirmed(&mut self, txid: Txid) -> Option<(ChannelDisclosure, bool)> {
+        let (channel_id, commitment_no, revoked) = self.find_commitment(txid)?;
+        let mut updates = self.channels.remove(&channel_id)?;
+        for update in updates.values() {
+            self.txids.remove(&update.commitment_txid);
+        }
+        let disclosure = updates.remove(&commitment_no)?;
+        Some((disclosure, revoked))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use amplify::Wrapper;
+    use bitcoin::hashes::Hash;
+
+    use super::*;
+
+    fn channel_id(no: u8) -> ChannelId { ChannelId::from_inner([no; 32]) }
+
+    fn update(channel: u8, commitment_no: u64) -> ChannelDisclosure {
+        let mut disclosure = Disclosure::default();
+        disclosure.change_comment(format!("channel {} update {}", channel, commitment_no));
+        let mut txid = [channel; 32];
+        txid[..8].copy_from_slice(&commitment_no.to_be_bytes());
+        ChannelDisclosure {
+            channel_id: channel_id(channel),
+            commitment_no,
+            commitment_txid: Txid::from_inner(txid),
+            disclosure,
+        }
+    }
+
+    fn queue(rounds: u64) -> ChannelDisclosureQueue {
+        let mut queue = ChannelDisclosureQueue::new();
+        for no in 0..rounds {
+            assert_eq!(queue.insert(update(1, no)), Ok(None));
+            assert_eq!(queue.insert(update(2, no * 2)), Ok(None));
+            assert_eq!(queue.pending(channel_id(1)), Some(&update(1, no)));
+            assert_eq!(queue.pending(channel_id(2)), Some(&update(2, no * 2)));
+        }
+        queue
+    }
+
+    #[test]
+    fn test_updates() {
+        let mut queue = queue(5);
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.pending(channel_id(3)), None);
+        assert_eq!(queue.revoked(channel_id(1)).count(), 4);
+        assert_eq!(queue.revoked(channel_id(3)).count(), 0);
+        assert!(queue
+            .revoked(channel_id(1))
+            .all(|update| update.commitment_no < 4));
+
+        assert_eq!(
+            queue.insert(update(1, 3)),
+            Err(ChannelError::Outdated {
+                channel_id: channel_id(1),
+                commitment_no: 3,
+                latest: 4
+            })
+        );
+        let mut reused = update(2, 9);
+        reused.commitment_txid = update(1, 2).commitment_txid;
+        assert_eq!(
+            queue.insert(reused),
+            Err(ChannelError::TxidReused(update(1, 2).commitment_txid))
+        );
+
+        let mut replacement = update(1, 4);
+        replacement.disclosure.change_comment(s!("replacement"));
+        assert_eq!(queue.insert(replacement.clone()), Ok(Some(update(1, 4))));
+        assert_eq!(queue.pending(channel_id(1)), Some(&replacement));
+    }
+
+    #[test]
+    fn test_channel_closed() {
+        let mut queue = queue(5);
+        assert_eq!(queue.channel_closed(Txid::from_inner([0xFF; 32])), None);
+        assert!(queue.is_revoked(update(1, 2).commitment_txid));
+        assert!(!queue.is_revoked(update(1, 4).commitment_txid));
+        assert_eq!(queue.channel_closed(update(1, 2).commitment_txid), None);
+        assert_eq!(queue.len(), 2);
+
+        assert_eq!(
+            queue.channel_closed(update(1, 4).commitment_txid),
+            Some(update(1, 4))
+        );
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.pending(channel_id(1)), None);
+        assert_eq!(queue.channel_closed(update(1, 4).commitment_txid), None);
+        assert_eq!(
+            queue.commitment_confirmed(update(1, 2).commitment_txid),
+            None
+        );
+        assert_eq!(queue.pending(channel_id(2)), Some(&update(2, 8)));
+    }
+
+    #[test]
+    fn test_revoked_confirmed() {
+        let mut queue = queue(5);
+        assert_eq!(
+            queue.commitment_confirmed(update(2, 4).commitment_txid),
+            Some((update(2, 4), true))
+        );
+        assert_eq!(queue.pending(channel_id(2)), None);
+        assert_eq!(queue.revoked(channel_id(2)).count(), 0);
+        assert_eq!(
+            queue.commitment_confirmed(update(1, 4).commitment_txid),
+            Some((update(1, 4), false))
+        );
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_persistence() {
+        let mut queue = queue(3);
+        let data = queue.strict_serialize().unwrap();
+        let mut restored = ChannelDisclosureQueue::strict_deserialize(&data).unwrap();
+        assert_eq!(restored, queue);
+
+        restored.insert(update(1, 3)).unwrap();
+        let mut restored =
+            ChannelDisclosureQueue::strict_deserialize(restored.strict_serialize().unwrap())
+                .unwrap();
+        assert_eq!(
+            restored.channel_closed(update(1, 3).commitment_txid),
+            Some(update(1, 3))
+        );
+        assert_eq!(
+            queue.channel_closed(update(1, 2).commitment_txid),
+            Some(update(1, 2))
+        );
+    }
+
+    #[test]
+    fn test_prune_revoked() {
+        let mut queue = queue(5);
+        assert!(queue.prune_revoked(channel_id(3), 0).is_empty());
+        assert!(queue.prune_revoked(channel_id(1), 4).is_empty());
+        assert_eq!(
+            queue.prune_revoked(channel_id(1), 1),
+            vec![update(1, 0), update(1, 1), update(1, 2)]
+        );
+        assert_eq!(
+            queue.revoked(channel_id(1)).collect::<Vec<_>>(),
+            vec![&update(1, 3)]
+        );
+        assert!(!queue.is_revoked(update(1, 2).commitment_txid));
+        assert_eq!(
+            queue.commitment_confirmed(update(1, 2).commitment_txid),
+            None
+        );
+        assert_eq!(queue.prune_revoked(channel_id(1), 0), vec![update(1, 3)]);
+        assert_eq!(queue.pending(channel_id(1)), Some(&update(1, 4)));
+
+        // Pruned commitment transactions may be reused by other updates
+        let mut reused = update(2, 9);
+        reused.commitment_txid = update(1, 2).commitment_txid;
+        assert_eq!(queue.insert(reused.clone()), Ok(None));
+        assert_eq!(queue.channel_closed(reused.commitment_txid), Some(reused));
+        let data = queue.strict_serialize().unwrap();
+        assert_eq!(
+            ChannelDisclosureQueue::strict_deserialize(&data).unwrap(),
+            queue
+        );
+    }
+
+    #[test]
+    fn test_invalid_encoding() {
+        let mut corrupted = queue(2);
+        let updates = corrupted.channels.get_mut(&channel_id(2)).unwrap();
+        updates.get_mut(&2).unwrap().commitment_no = 3;
+        let data = corrupted.strict_serialize().unwrap();
+        assert!(ChannelDisclosureQueue::strict_deserialize(&data).is_err());
+
+        let mut corrupted = queue(2);
+        let txid = update(1, 0).commitment_txid;
+        let updates = corrupted.channels.get_mut(&channel_id(2)).unwrap();
+        updates.get_mut(&2).unwrap().commitment_txid = txid;
+        let data = corrupted.strict_serialize().unwrap();
+        assert!(ChannelDisclosureQueue::strict_deserialize(&data).is_err());
+
+        let mut corrupted = queue(2);
+        corrupted.channels.insert(channel_id(3), empty!());
+        let data = corrupted.strict_serialize().unwrap();
+        assert!(ChannelDisclosureQueue::strict_deserialize(&data).is_err());
+    }
+}
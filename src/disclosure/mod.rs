@@ -12,8 +12,10 @@
 //! Disclosure is the way to make certain confidential information about the
 //! stash public.
 
+mod channel;
+
 use std::collections::btree_map::Entry;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::io;
 use std::str::FromStr;
 
@@ -21,19 +23,27 @@ use amplify::Wrapper;
 use bitcoin::hashes::{self, sha256, sha256t, Hash, HashEngine};
 use bitcoin::secp256k1::ecdsa::Signature;
 use bitcoin::secp256k1::PublicKey;
+use bitcoin::{Transaction, Txid};
+use bp::dbc::tapret::TapretPathProof;
+use bp::dbc::Proof;
 use commit_verify::{
     commit_encode, lnpbp4, CommitEncode, CommitVerify, ConsensusCommit, PrehashedProtocol,
     TaggedHash,
 };
 use lnpbp_bech32::{self, FromBech32Str, ToBech32String};
-use strict_encoding::StrictEncode;
+use strict_encoding::{StrictDecode, StrictEncode};
 
+pub use self::channel::{ChannelDisclosure, ChannelDisclosureQueue, ChannelError, ChannelId};
+use crate::method::{opret_script, tapret_output};
 use crate::{
     seal, Anchor, AnchorId, ConcealAnchors, ConcealSeals, ConcealState, ContractId, Extension,
-    TransitionBundle,
+    TlvError, TlvMap, TransitionBundle,
 };
 
-pub const RGB_DISCLOSURE_VERSION: u16 = 0;
+/// Current version of the disclosure encoding. Version 1 adds
+/// [`Disclosure::tlv`] extension area; disclosures of version 0 are still
+/// decoded and re-encoded without it.
+pub const RGB_DISCLOSURE_VERSION: u16 = 1;
 
 // "rgb:disclosure"
 static MIDSTATE_DISCLOSURE_ID: [u8; 32] = [
@@ -41,6 +51,25 @@ static MIDSTATE_DISCLOSURE_ID: [u8; 32] = [
     121, 157, 241, 96, 84, 44, 86, 141, 48, 95, 119,
 ];
 
+/// Tag of the LNPBP-4 protocol under which disclosure ids are committed into
+/// bitcoin transactions
+pub const DISCLOSURE_PROTOCOL_TAG: &[u8] = b"rgb:disclosure:commitment";
+
+/// Returns LNPBP-4 protocol id for the disclosure commitments, which is a
+/// SHA256 hash of the [`DISCLOSURE_PROTOCOL_TAG`]
+pub(crate) fn disclosure_protocol_id() -> lnpbp4::ProtocolId {
+    lnpbp4::ProtocolId::from_inner(sha256::Hash::hash(DISCLOSURE_PROTOCOL_TAG).into_inner())
+}
+
+/// Errors updating [`Disclosure`] data
+#[derive(Clone, PartialEq, Eq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum DisclosureError {
+    /// anchor {0} conflicts with the anchor having the same id which is
+    /// already present in the disclosure
+    AnchorConflict(AnchorId),
+}
+
 /// Tag used for [`DisclosureId`] hash types
 pub struct DisclosureIdTag;
 
@@ -112,6 +141,8 @@ impl Hash for SigHash {
     type Inner = <sha256t::Hash<SigHashTag> as Hash>::Inner;
     const LEN: usize = sha256t::Hash::<SigHashTag>::LEN;
 
+    fn engine() -> Self::Engine { <Self as Wrapper>::Inner::engine() }
+
     fn from_engine(e: Self::Engine) -> Self { <Self as Wrapper>::Inner::from_engine(e).into() }
 
     fn from_slice(sl: &[u8]) -> Result<Self, hashes::Error> {
@@ -129,13 +160,14 @@ impl Hash for SigHash {
 /// RGB contracts to some external entity – or store them outside of the stash
 /// to be merged lately upon a certain event (for instance, withness transaction
 /// being mined or receiving a signature for the updated channel state from an
-/// LN channel counterparty).
+/// LN channel counterparty). Disclosures of the channel updates are kept with
+/// [`ChannelDisclosureQueue`] until the channel gets closed.
 ///
 /// MB: We are limited by 16-bit integer size for the number of anchors and
 /// extensions to disclose, but this is fine since we can produce multiple
 /// disclosures when needed
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(crate = "serde_crate"))]
-#[derive(Getters, Clone, PartialEq, Debug, Default, StrictEncode, StrictDecode)]
+#[derive(Getters, Clone, PartialEq, Debug)]
 pub struct Disclosure {
     /// Since these are not consensus-critical data structure (we never commit
     /// to it) we can use encoding versioning here
@@ -168,6 +200,57 @@ pub struct Disclosure {
     ///
     /// NB: For Schnorr keys ECDSA signature still has to be used here.
     signatures: BTreeMap<PublicKey, Signature>,
+
+    /// Extension records, present in the disclosures of version 1 and above
+    tlv: TlvMap,
+}
+
+impl Default for Disclosure {
+    fn default() -> Self {
+        Disclosure {
+            version: RGB_DISCLOSURE_VERSION as u8,
+            anchored_bundles: empty!(),
+            extensions: empty!(),
+            comment: None,
+            signatures: empty!(),
+            tlv: none!(),
+        }
+    }
+}
+
+impl StrictEncode for Disclosure {
+    fn strict_encode<E: io::Write>(&self, mut e: E) -> Result<usize, strict_encoding::Error> {
+        let mut len = strict_encode_list!(e;
+            self.version,
+            self.anchored_bundles,
+            self.extensions,
+            self.comment,
+            self.signatures
+        );
+        if self.version > 0 {
+            len += self.tlv.strict_encode(e)?;
+        }
+        Ok(len)
+    }
+}
+
+impl StrictDecode for Disclosure {
+    fn strict_decode<D: io::Read>(mut d: D) -> Result<Self, strict_encoding::Error> {
+        let version = u8::strict_decode(&mut d)?;
+        if u16::from(version) > RGB_DISCLOSURE_VERSION {
+            return Err(strict_encoding::Error::UnsupportedDataStructure(
+                "Disclosure versions above 1 are not supported",
+            ));
+        }
+        Ok(Disclosure {
+            version,
+            anchored_bundles: StrictDecode::strict_decode(&mut d)?,
+            extensions: StrictDecode::strict_decode(&mut d)?,
+            comment: StrictDecode::strict_decode(&mut d)?,
+            signatures: StrictDecode::strict_decode(&mut d)?,
+            tlv: if version > 0 { StrictDecode::strict_decode(&mut d)? } else { none!() },
+        })
+    }
 }
 
 impl CommitEncode for Disclosure {
@@ -177,8 +260,14 @@ impl CommitEncode for Disclosure {
         // 2. Do not include comment
         // 3. Do not include signature (since the signature signs commitment id
         //    + comment commitment)
+        // 4. Include extension records, if the version supports them
         (|| -> Result<usize, strict_encoding::Error> {
-            Ok(strict_encode_list!(e; self.version, self.anchored_bundles, self.extensions))
+            let mut len =
+                strict_encode_list!(e; self.version, self.anchored_bundles, self.extensions);
+            if self.version > 0 {
+                len += self.tlv.strict_encode(&mut e)?;
+            }
+            Ok(len)
         })()
         .expect("Commit encoding is in-memory encoding and must not fail")
     }
@@ -189,6 +278,7 @@ impl ConsensusCommit for Disclosure {
 }
 
 impl ConcealSeals for Disclosure {
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
     fn conceal_seals(&mut self, seals: &[seal::Confidential]) -> usize {
         let mut count = 0usize;
         for (_, map) in self.anchored_bundles.values_mut() {
@@ -209,6 +299,7 @@ impl ConcealSeals for Disclosure {
 }
 
 impl ConcealState for Disclosure {
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
     fn conceal_state_except(&mut self, seals: &[seal::Confidential]) -> usize {
         let mut count = 0usize;
         for (_, map) in self.anchored_bundles.values_mut() {
@@ -229,6 +320,7 @@ impl ConcealState for Disclosure {
 }
 
 impl ConcealAnchors for Disclosure {
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
     fn conceal_anchors_except(
         &mut self,
         contracts: impl AsRef<[ContractId]>,
@@ -242,24 +334,47 @@ impl ConcealAnchors for Disclosure {
 }
 
 impl Disclosure {
+    #[inline]
+    pub fn id(&self) -> DisclosureId { self.clone().consensus_commit() }
+
+    /// Returns LNPBP-4 message committing to the disclosure id
+    pub(crate) fn lnpbp4_message(&self) -> lnpbp4::Message {
+        lnpbp4::Message::from_inner(self.id().into_inner().into_inner())
+    }
+
+    /// Returns set of witness transaction ids for the anchors inside the
+    /// disclosure
+    #[inline]
+    pub fn txids(&self) -> BTreeSet<Txid> {
+        self.anchored_bundles
+            .values()
+            .map(|(anchor, _)| anchor.txid)
+            .collect()
+    }
+
+    /// Adds transition bundles committed with the `anchor` to the disclosure.
+    /// If the disclosure already has an anchor with the same id, both anchors
+    /// are merged; the disclosure is not modified if they conflict.
     pub fn insert_anchored_bundles(
         &mut self,
         anchor: Anchor<lnpbp4::MerkleBlock>,
         bundles: BTreeMap<ContractId, TransitionBundle>,
-    ) {
-        self.signatures = empty!();
-        match self.anchored_bundles.entry(anchor.anchor_id()) {
+    ) -> Result<(), DisclosureError> {
+        let anchor_id = anchor.anchor_id();
+        match self.anchored_bundles.entry(anchor_id) {
             Entry::Vacant(entry) => {
                 entry.insert((anchor, bundles));
             }
             Entry::Occupied(mut entry) => {
                 let (a, t) = entry.get_mut();
-                *a = anchor.merge_reveal(a.clone()).expect(
-                    "Anchor into_revealed procedure is broken for anchors with the same id",
-                );
+                *a = anchor
+                    .merge_reveal(a.clone())
+                    .map_err(|_| DisclosureError::AnchorConflict(anchor_id))?;
                 t.extend(bundles);
             }
         }
+        self.signatures = empty!();
+        Ok(())
     }
 
     pub fn insert_extensions(&mut self, contract_id: ContractId, extensions: Vec<Extension>) {
@@ -284,6 +399,27 @@ impl Disclosure {
         had_comment
     }
 
+    /// Adds extension record, returning the previous value of the record with
+    /// the same type. Fails for the unknown even record types.
+    ///
+    /// Disclosures of version 0 can't carry extension records, so they are
+    /// upgraded to version 1.
+    pub fn insert_tlv_record(
+        &mut self,
+        ty: u16,
+        value: Vec<u8>,
+    ) -> Result<Option<Vec<u8>>, TlvError> {
+        let prev = self.tlv.insert(ty, value)?;
+        self.version = self.version.max(1);
+        self.signatures = empty!();
+        Ok(prev)
+    }
+
+    pub fn remove_tlv_record(&mut self, ty: u16) -> Option<Vec<u8>> {
+        self.signatures = empty!();
+        self.tlv.remove(ty)
+    }
+
     pub fn sig_hash(&self) -> SigHash {
         let mut engine = SigHash::engine();
         self.commit_encode(&mut engine);
@@ -309,6 +445,52 @@ impl Disclosure {
     }
 }
 
+/// Proof that the disclosure id is committed into a bitcoin transaction,
+/// constructed by `Disclosure::commit_into_psbt`. Together with the
+/// transaction it proves that the disclosure existed before the transaction
+/// was mined.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[derive(StrictEncode, StrictDecode)]
+pub struct DisclosureCommitmentProof {
+    /// Anchor of the disclosure id to the transaction under the
+    /// [`DISCLOSURE_PROTOCOL_TAG`] protocol
+    pub anchor: Anchor<lnpbp4::MerkleProof>,
+}
+
+impl DisclosureCommitmentProof {
+    /// Returns id of the transaction containing the commitment
+    #[inline]
+    pub fn txid(&self) -> Txid { self.anchor.txid }
+
+    /// Verifies that the transaction `tx` commits to the `disclosure` id
+    pub fn verify(&self, disclosure: &Disclosure, tx: &Transaction) -> bool {
+        if tx.txid() != self.anchor.txid {
+            return false;
+        }
+        let commitment = match self
+            .anchor
+            .lnpbp4_proof
+            .convolve(disclosure_protocol_id(), disclosure.lnpbp4_message())
+        {
+            Ok(commitment) => commitment.into_inner(),
+            Err(_) => return false,
+        };
+        let mut scripts = tx.output.iter().map(|txout| &txout.script_pubkey);
+        match &self.anchor.dbc_proof {
+            Proof::OpretFirst => {
+                scripts.find(|script| script.is_op_return()) == Some(&opret_script(&commitment))
+            }
+            Proof::TapretFirst(proof) if proof.path_proof == TapretPathProof::root() => {
+                let expected =
+                    tapret_output(proof.internal_pk, &commitment).map(|(_, script)| script);
+                expected.is_some()
+                    && scripts.find(|script| script.is_v1_p2tr()) == expected.as_ref()
+            }
+            Proof::TapretFirst(_) => false,
+        }
+    }
+}
+
 // TODO #63: Validate disclosures
 
 #[cfg(test)]
@@ -331,5 +513,57 @@ mod test {
             midstate.into_inner().into_inner(),
             MIDSTATE_DISCLOSURE_SIG_HASH
         );
+        // Sig hashes must start from the tagged midstate
+        assert_eq!(
+            SigHash::engine().midstate().into_inner(),
+            MIDSTATE_DISCLOSURE_SIG_HASH
+        );
+    }
+
+    #[test]
+    fn test_tlv_preserved() {
+        let mut disclosure = Disclosure::default();
+        disclosure.insert_tlv_record(7, vec![1, 2, 3]).unwrap();
+        let id = disclosure.id();
+        let data = disclosure.strict_serialize().unwrap();
+
+        let mut decoded = Disclosure::strict_deserialize(&data).unwrap();
+        assert_eq!(decoded.tlv().get(7), Some(&[1u8, 2, 3][..]));
+        assert_eq!(decoded.id(), id);
+        decoded.change_comment(s!("comment"));
+        let decoded = Disclosure::strict_deserialize(decoded.strict_serialize().unwrap()).unwrap();
+        assert_eq!(decoded.tlv(), disclosure.tlv());
+        assert_eq!(decoded.id(), id);
+
+        disclosure.remove_tlv_record(7);
+        assert_ne!(disclosure.id(), id);
+    }
+
+    #[test]
+    fn test_tlv_unknown_even() {
+        let mut disclosure = Disclosure::default();
+        assert_eq!(
+            disclosure.insert_tlv_record(2, vec![]),
+            Err(TlvError::UnknownEvenType(2))
+        );
+        let mut data = disclosure.strict_serialize().unwrap();
+        data.truncate(data.len() - 2);
+        data.extend([1, 0, 2, 0, 1, 0, 0xFF]);
+        assert!(Disclosure::strict_deserialize(&data).is_err());
+    }
+
+    #[test]
+    fn test_version_0() {
+        let data = [0u8; 8];
+        let disclosure = Disclosure::strict_deserialize(&data).unwrap();
+        assert_eq!(*disclosure.version(), 0);
+        assert_eq!(disclosure.strict_serialize().unwrap(), data);
+
+        let mut disclosure = disclosure;
+        disclosure.insert_tlv_record(7, vec![1]).unwrap();
+        assert_eq!(*disclosure.version(), 1);
+        let decoded =
+            Disclosure::strict_deserialize(disclosure.strict_serialize().unwrap()).unwrap();
+        assert_eq!(decoded, disclosure);
     }
 }
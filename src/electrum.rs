@@ -0,0 +1,235 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Source of the witness transactions using an Electrum server.
+//!
+//! Electrum protocol does not provide lookup of the transaction mining height
+//! by its id, so the height is found from the history of the script of the
+//! first transaction output.
+
+use std::collections::BTreeMap;
+
+use bitcoin::{Script, Transaction, Txid};
+use electrum_client::{Client, ElectrumApi, Error};
+
+use crate::{CachingResolver, WitnessSource};
+
+/// Resolver of the witness transactions with an Electrum server
+pub type ElectrumResolver<T = Client> = CachingResolver<ElectrumSource<T>>;
+
+impl ElectrumResolver<Client> {
+    /// Connects to the Electrum server at `url`
+    pub fn with_url(url: &str) -> Result<Self, Error> {
+        Ok(CachingResolver::with(ElectrumSource::with(Client::new(url)?)))
+    }
+}
+
+/// Subset of the Electrum protocol used by the [`ElectrumSource`]. Each call
+/// sends a single batch request to the server.
+pub trait ElectrumTransport {
+    /// Retrieves transactions with the given ids, in the same order
+    fn batch_transaction_get(&self, txids: &[Txid]) -> Result<Vec<Transaction>, Error>;
+
+    /// Retrieves history of each of the scripts as a list of transaction ids
+    /// with their mining heights; heights of the unconfirmed transactions are
+    /// zero or negative
+    fn batch_script_get_history(&self, scripts: &[Script]) -> Result<Vec<Vec<(Txid, i32)>>, Error>;
+}
+
+impl ElectrumTransport for Client {
+    fn batch_transaction_get(&self, txids: &[Txid]) -> Result<Vec<Transaction>, Error> {
+        ElectrumApi::batch_transaction_get(self, txids)
+    }
+
+    fn batch_script_get_history(&self, scripts: &[Script]) -> Result<Vec<Vec<(Txid, i32)>>, Error> {
+        Ok(ElectrumApi::batch_script_get_history(self, scripts)?
+            .into_iter()
+            .map(|history| {
+                history
+                    .into_iter()
+                    .map(|item| (item.tx_hash, item.height))
+                    .collect()
+            })
+            .collect())
+    }
+}
+
+/// Witness source requesting an Electrum server with batch requests
+#[derive(Debug)]
+pub struct ElectrumSource<T = Client>
+where T: ElectrumTransport
+{
+    transport: T,
+}
+
+impl<T> ElectrumSource<T>
+where T: ElectrumTransport
+{
+    /// Constructs source using the `transport`
+    #[inline]
+    pub fn with(transport: T) -> Self { ElectrumSource { transport } }
+
+    /// Returns transport used by the source
+    #[inline]
+    pub fn transport(&self) -> &T { &self.transport }
+}
+
+impl<T> WitnessSource for ElectrumSource<T>
+where T: ElectrumTransport
+{
+    type Error = Error;
+
+    /// Requests all transactions with a single batch. Servers reply with a
+    /// protocol error to the batches containing unknown transactions, in
+    /// which case the transactions are requested one by one.
+    fn fetch_txs(&self, txids: &[Txid]) -> Result<Vec<Transaction>, Self::Error> {
+        match self.transport.batch_transaction_get(txids) {
+            Err(Error::Protocol(_)) if txids.len() > 1 => {}
+            Err(Error::Protocol(_)) => return Ok(vec![]),
+            res => return res,
+        }
+        let mut txs = Vec::with_capacity(txids.len());
+        for txid in txids {
+            match self.transport.batch_transaction_get(&[*txid]) {
+                Ok(tx) => txs.extend(tx),
+                Err(Error::Protocol(_)) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(txs)
+    }
+
+    fn fetch_heights(
+        &self,
+        txids: &[Txid],
+        txs: &BTreeMap<Txid, Transaction>,
+    ) -> Result<BTreeMap<Txid, u32>, Self::Error> {
+        let (txids, scripts): (Vec<_>, Vec<_>) = txids
+            .iter()
+            .filter_map(|txid| {
+                let output = txs.get(txid)?.output.first()?;
+                Some((*txid, output.script_pubkey.clone()))
+            })
+            .unzip();
+        if scripts.is_empty() {
+            return Ok(empty!());
+        }
+        let histories = self.transport.batch_script_get_history(&scripts)?;
+        Ok(txids
+            .into_iter()
+            .zip(histories)
+            .filter_map(|(txid, history)| {
+                let (_, height) = history.into_iter().find(|(id, _)| *id == txid)?;
+                (height > 0).then(|| (txid, height as u32))
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+    use std::io;
+
+    use super::*;
+    use crate::resolver::test::{resolver_conformance, tx};
+
+    /// Transport with a fixed set of transactions, counting the requests
+    #[derive(Default)]
+    struct MockTransport {
+        txs: Vec<Transaction>,
+        offline: Cell<bool>,
+        tx_requests: Cell<usize>,
+        history_requests: Cell<usize>,
+    }
+
+    impl MockTransport {
+        fn check_online(&self) -> Result<(), Error> {
+            if self.offline.get() {
+                return Err(Error::IOError(io::ErrorKind::ConnectionRefused.into()));
+            }
+            Ok(())
+        }
+    }
+
+    impl ElectrumTransport for MockTransport {
+        fn batch_transaction_get(&self, txids: &[Txid]) -> Result<Vec<Transaction>, Error> {
+            self.check_online()?;
+            self.tx_requests.set(self.tx_requests.get() + 1);
+            txids
+                .iter()
+                .map(|txid| {
+                    self.txs
+                        .iter()
+                        .find(|tx| tx.txid() == *txid)
+                        .cloned()
+                        .ok_or_else(|| Error::Protocol(s!("missing transaction").into()))
+                })
+                .collect()
+        }
+
+        fn batch_script_get_history(
+            &self,
+            scripts: &[Script],
+        ) -> Result<Vec<Vec<(Txid, i32)>>, Error> {
+            self.check_online()?;
+            self.history_requests.set(self.history_requests.get() + 1);
+            Ok(scripts
+                .iter()
+                .map(|script| {
+                    self.txs
+                        .iter()
+                        .filter(|tx| &tx.output[0].script_pubkey == script)
+                        .map(|tx| (tx.txid(), tx.input.len() as i32))
+                        .collect()
+                })
+                .collect())
+        }
+    }
+
+    fn resolver() -> ElectrumResolver<MockTransport> {
+        CachingResolver::with(ElectrumSource::with(MockTransport {
+            txs: (0..4).map(tx).collect(),
+            ..Default::default()
+        }))
+    }
+
+    #[test]
+    fn test_electrum_conformance() {
+        resolver_conformance(resolver(), |source, offline| {
+            source.transport().offline.set(offline)
+        });
+    }
+
+    #[test]
+    fn test_batching() {
+        let resolver = resolver();
+        let transport = resolver.source().transport();
+        let txids = (0..4).map(|no| tx(no).txid()).collect::<Vec<_>>();
+        resolver.prefetch(txids.iter().copied()).unwrap();
+        assert_eq!(transport.tx_requests.get(), 1);
+        assert_eq!(transport.history_requests.get(), 1);
+
+        for (no, txid) in txids.iter().enumerate().skip(1) {
+            assert_eq!(resolver.transaction(*txid).unwrap().unwrap().txid(), *txid);
+            assert_eq!(resolver.height(*txid).unwrap(), Some(no as u32));
+        }
+        assert_eq!(transport.tx_requests.get(), 1);
+        assert_eq!(transport.history_requests.get(), 1);
+
+        // Batch with an unknown transaction falls back to separate requests
+        resolver.clear_cache();
+        resolver.prefetch([txids[1], txids[2], tx(4).txid()]).unwrap();
+        assert_eq!(transport.tx_requests.get(), 5);
+        assert_eq!(transport.history_requests.get(), 2);
+        assert_eq!(resolver.height(txids[2]).unwrap(), Some(2));
+    }
+}
@@ -0,0 +1,217 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Crate-level error type unifying errors of the individual modules, such that
+//! applications may propagate them with `?` operator.
+
+use crate::fungible::{
+    self, allocation, AmountError, AuditError, BlindingError, BuilderError, BurnError,
+    InflationError, InvoiceMismatch, IssueError, NominationError, PaymentError, RegistryError,
+    RenominationError, SealCollision, SelectionError,
+};
+#[cfg(feature = "psbt")]
+use crate::psbt::{CommitError, PsbtRgbError};
+#[cfg(feature = "sled")]
+use crate::stash::SledStashError;
+use crate::{
+    BalanceOverflow, ChannelError, DisclosureError, LazyError, MemStashError, MergeError, ProofError,
+    SchemaViolation, SealError, StateApplyError, StateConversionError, UriError,
+};
+
+/// Errors returned by the RGB standard library
+#[derive(Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum Error {
+    /// data can't be encoded or decoded: {0}
+    #[from]
+    Encoding(strict_encoding::Error),
+
+    /// stash error: {0}
+    #[from]
+    Stash(MemStashError),
+
+    /// persistent stash error: {0}
+    #[cfg(feature = "sled")]
+    SledStash(SledStashError),
+
+    /// stash merge error: {0}
+    #[from]
+    Merge(MergeError),
+
+    /// invalid consignment: {0}
+    #[from]
+    Consignment(LazyError),
+
+    /// sealed consignment error: {0}
+    #[from]
+    Seal(SealError),
+
+    /// invalid RGB URI: {0}
+    #[from]
+    Uri(UriError),
+
+    /// invalid disclosure: {0}
+    #[from]
+    Disclosure(DisclosureError),
+
+    /// channel disclosure error: {0}
+    #[from]
+    Channel(ChannelError),
+
+    /// invalid ownership proof: {0}
+    #[from]
+    Proof(ProofError),
+
+    /// contract state can't be updated: {0}
+    #[from]
+    StateApply(StateApplyError),
+
+    /// contract state can't be converted: {0}
+    #[from]
+    StateConversion(StateConversionError),
+
+    /// contract state violates its schema: {0}
+    #[from]
+    SchemaViolation(SchemaViolation),
+
+    /// {0}
+    #[from]
+    BalanceOverflow(BalanceOverflow),
+
+    /// PSBT error: {0}
+    #[cfg(feature = "psbt")]
+    Psbt(PsbtRgbError),
+
+    /// disclosure can't be committed: {0}
+    #[cfg(feature = "psbt")]
+    DisclosureCommit(CommitError),
+
+    /// invalid fungible asset: {0}
+    #[from]
+    Asset(fungible::Error),
+
+    /// invalid asset amount: {0}
+    #[from]
+    Amount(AmountError),
+
+    /// invalid allocation: {0}
+    #[from]
+    Allocation(allocation::ParseError),
+
+    /// asset issue error: {0}
+    #[from]
+    Issue(IssueError),
+
+    /// state transfer can't be constructed: {0}
+    #[from]
+    Builder(BuilderError),
+
+    /// asset burn error: {0}
+    #[from]
+    Burn(BurnError),
+
+    /// secondary issue error: {0}
+    #[from]
+    Inflation(InflationError),
+
+    /// asset renomination error: {0}
+    #[from]
+    Renomination(RenominationError),
+
+    /// invalid asset nomination: {0}
+    #[from]
+    Nomination(NominationError),
+
+    /// asset registry error: {0}
+    #[from]
+    Registry(RegistryError),
+
+    /// asset supply audit error: {0}
+    #[from]
+    Audit(AuditError),
+
+    /// allocations can't be selected: {0}
+    #[from]
+    Selection(SelectionError),
+
+    /// state transfer does not match the invoice: {0}
+    #[from]
+    Invoice(InvoiceMismatch),
+
+    /// invalid payment: {0}
+    #[from]
+    Payment(PaymentError),
+
+    /// amount blinding error: {0}
+    #[from]
+    Blinding(BlindingError),
+
+    /// {0}
+    #[from]
+    SealCollision(SealCollision),
+}
+
+// Variants behind the feature flags have manual conversions, since the derive
+// macro does not propagate `cfg` attributes to the generated impls
+
+#[cfg(feature = "sled")]
+impl From<SledStashError> for Error {
+    #[inline]
+    fn from(err: SledStashError) -> Self { Error::SledStash(err) }
+}
+
+#[cfg(feature = "psbt")]
+impl From<PsbtRgbError> for Error {
+    #[inline]
+    fn from(err: PsbtRgbError) -> Self { Error::Psbt(err) }
+}
+
+#[cfg(feature = "psbt")]
+impl From<CommitError> for Error {
+    #[inline]
+    fn from(err: CommitError) -> Self { Error::DisclosureCommit(err) }
+}
+
+#[cfg(test)]
+mod test {
+    use strict_encoding::StrictEncode;
+
+    use super::*;
+    use crate::verify::test::consignment;
+    use crate::{LazyConsignment, TransferConsignment};
+
+    fn lazy_consignment(data: &[u8]) -> Result<usize, Error> {
+        let lazy = LazyConsignment::with(data)?;
+        let consignment = lazy.into_consignment::<TransferConsignment>()?;
+        Ok(consignment.anchored_bundles.len())
+    }
+
+    #[test]
+    fn test_propagation() {
+        let data = consignment(2).strict_serialize().unwrap();
+        assert_eq!(lazy_consignment(&data).unwrap(), 2);
+        let err = lazy_consignment(&data[..data.len() / 2]).unwrap_err();
+        assert!(matches!(err, Error::Consignment(_)));
+        assert!(err.to_string().starts_with("invalid consignment: "));
+    }
+
+    #[test]
+    fn test_display() {
+        let err = Error::from(AmountError::PrecisionMismatch(2, 3));
+        assert_eq!(
+            err.to_string(),
+            "invalid asset amount: amounts with different decimal precision 2 and 3 can't be \
+             added"
+        );
+        let err = Error::from(BalanceOverflow);
+        assert_eq!(err.to_string(), BalanceOverflow.to_string());
+    }
+}
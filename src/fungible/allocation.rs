@@ -51,7 +51,7 @@ use std::fmt::{self, Display, Formatter};
 use std::str::FromStr;
 
 use bitcoin::blockdata::transaction::ParseOutPointError;
-use bitcoin::secp256k1::rand::thread_rng;
+use bitcoin::secp256k1::rand::{thread_rng, RngCore};
 use bitcoin::OutPoint;
 use bp::seals;
 use bp::seals::txout::blind::RevealedSeal;
@@ -59,8 +59,6 @@ use bp::seals::txout::ExplicitSeal;
 use rgb_core::{EndpointValueMap, SealValueMap};
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
-#[cfg(feature = "serde")]
-use serde_with::{As, DisplayFromStr};
 
 use crate::{
     seal, value, Assignment, AssignmentVec, AtomicValue, IntoRevealedSeal, NodeId, NodeOutpoint,
@@ -193,11 +191,19 @@ impl FromStr for UtxobValue {
 }
 
 /// Information about an allocation, represented by RGB contract node output,
-/// seal definition and assigned value
+/// seal definition and assigned value.
+///
+/// With the `serde` feature allocation is (de)serialized in the wallet format
+/// used by [`Asset`](super::Asset), with the outpoint given as `txid:vout`
+/// string and the amount given as an atomic value.
 #[cfg_attr(
     feature = "serde",
     derive(Serialize, Deserialize),
-    serde(crate = "serde_crate", rename_all = "camelCase")
+    serde(
+        crate = "serde_crate",
+        into = "super::asset::serde_wallet::AllocationData",
+        try_from = "super::asset::serde_wallet::AllocationData"
+    )
 )]
 #[derive(Clone, Copy, Getters, PartialEq, Debug, Display)]
 #[derive(StrictEncode, StrictDecode)]
@@ -211,7 +217,6 @@ pub struct Allocation {
 
     /// Copy of the outpoint from corresponding entry in
     /// [`Asset::known_allocations`]
-    #[cfg_attr(feature = "serde", serde(with = "As::<DisplayFromStr>"))]
     outpoint: OutPoint,
 
     /// Revealed confidential amount consisting of an explicit atomic amount
@@ -283,44 +288,59 @@ pub trait AllocationMap {
     /// Returns sum of all atomic values inside the allocation map
     fn sum(&self) -> AtomicValue;
 
-    /// Turns allocation map into [`AssignmentVec`]
-    fn into_assignments(self) -> AssignmentVec;
+    /// Turns allocation map into [`AssignmentVec`], blinding the values with
+    /// the factors generated from the OS entropy
+    fn into_assignments(self) -> AssignmentVec
+    where Self: Sized {
+        self.into_assignments_with_rng(&mut thread_rng())
+    }
+
+    /// Turns allocation map into [`AssignmentVec`], blinding the values with
+    /// the factors generated by the provided `rng`
+    fn into_assignments_with_rng(self, rng: &mut impl RngCore) -> AssignmentVec;
 }
 
 impl AllocationMap for OutpointValueVec {
     fn sum(&self) -> u64 { self.iter().map(|v| v.value).sum() }
 
-    fn into_assignments(self) -> AssignmentVec { self.into_seal_value_map().into_assignments() }
+    fn into_assignments_with_rng(self, rng: &mut impl RngCore) -> AssignmentVec {
+        self.into_seal_value_map().into_assignments_with_rng(rng)
+    }
 }
 
 impl AllocationMap for OutpointValueMap {
     fn sum(&self) -> u64 { self.values().sum() }
 
-    fn into_assignments(self) -> AssignmentVec { self.into_seal_value_map().into_assignments() }
+    fn into_assignments_with_rng(self, rng: &mut impl RngCore) -> AssignmentVec {
+        self.into_seal_value_map().into_assignments_with_rng(rng)
+    }
 }
 
 impl AllocationMap for AllocationValueVec {
     fn sum(&self) -> u64 { self.iter().map(|v| v.value).sum() }
 
-    fn into_assignments(self) -> AssignmentVec { self.into_seal_value_map().into_assignments() }
+    fn into_assignments_with_rng(self, rng: &mut impl RngCore) -> AssignmentVec {
+        self.into_seal_value_map().into_assignments_with_rng(rng)
+    }
 }
 
 impl AllocationMap for AllocationValueMap {
     fn sum(&self) -> u64 { self.values().sum() }
 
-    fn into_assignments(self) -> AssignmentVec { self.into_seal_value_map().into_assignments() }
+    fn into_assignments_with_rng(self, rng: &mut impl RngCore) -> AssignmentVec {
+        self.into_seal_value_map().into_assignments_with_rng(rng)
+    }
 }
 
 impl AllocationMap for SealValueMap {
     fn sum(&self) -> u64 { self.values().sum() }
 
-    fn into_assignments(self) -> AssignmentVec {
-        let mut rng = thread_rng();
+    fn into_assignments_with_rng(self, rng: &mut impl RngCore) -> AssignmentVec {
         AssignmentVec::Fungible(
             self.into_iter()
                 .map(|(seal, value)| Assignment::Revealed {
                     seal_definition: seal,
-                    assigned_state: value::Revealed::with_amount(value, &mut rng),
+                    assigned_state: value::Revealed::with_amount(value, rng),
                 })
                 .collect(),
         )
@@ -330,12 +350,11 @@ impl AllocationMap for SealValueMap {
 impl AllocationMap for EndpointValueMap {
     fn sum(&self) -> u64 { self.values().sum() }
 
-    fn into_assignments(self) -> AssignmentVec {
-        let mut rng = thread_rng();
+    fn into_assignments_with_rng(self, rng: &mut impl RngCore) -> AssignmentVec {
         AssignmentVec::Fungible(
             self.into_iter()
                 .map(|(seal, value)| {
-                    let assigned_state = value::Revealed::with_amount(value, &mut rng);
+                    let assigned_state = value::Revealed::with_amount(value, rng);
                     match seal {
                         SealEndpoint::ConcealedUtxo(confidential) => Assignment::ConfidentialSeal {
                             seal_definition: confidential,
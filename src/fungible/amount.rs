@@ -26,6 +26,7 @@
 //! for methods returning atomic value.
 
 use std::ops::{Add, AddAssign};
+use std::str::FromStr;
 
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
@@ -105,7 +106,8 @@ impl PreciseAmount {
     /// Returns fractional amount
     #[inline]
     pub fn fractional_amount(&self) -> FractionalAmount {
-        self.0 as f64 / Self::DIVIDER[self.1 as usize] as f64
+        // Decimal precision of the decoded data may exceed the divider table
+        self.0 as f64 / 10f64.powi(self.1 as i32)
     }
 
     /// Returns atomic value
@@ -115,8 +117,24 @@ impl PreciseAmount {
     /// Returns decimal precision
     #[inline]
     pub fn decimal_precision(&self) -> u8 { self.1 }
+
+    /// Adds two amounts, failing if they have different decimal precision or
+    /// on overflow
+    pub fn checked_add(self, other: PreciseAmount) -> Result<PreciseAmount, AmountError> {
+        if self.1 != other.1 {
+            return Err(AmountError::PrecisionMismatch(self.1, other.1));
+        }
+        self.0
+            .checked_add(other.0)
+            .map(|value| PreciseAmount(value, self.1))
+            .ok_or(AmountError::Overflow)
+    }
 }
 
+/// # Panics
+///
+/// If the amounts have different decimal precision or on overflow; use
+/// [`PreciseAmount::checked_add`] for the amounts coming from untrusted data.
 impl Add for PreciseAmount {
     type Output = PreciseAmount;
 
@@ -132,6 +150,10 @@ impl Add for PreciseAmount {
     }
 }
 
+/// # Panics
+///
+/// If the amounts have different decimal precision or on overflow; use
+/// [`PreciseAmount::checked_add`] for the amounts coming from untrusted data.
 impl AddAssign for PreciseAmount {
     fn add_assign(&mut self, rhs: Self) {
         if self.decimal_precision() != rhs.decimal_precision() {
@@ -141,3 +163,253 @@ impl AddAssign for PreciseAmount {
         }
     }
 }
+
+/// Errors of the [`Amount`] arithmetic and parsing
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum AmountError {
+    /// amount exceeds the maximal value of 2^64 atomic units
+    Overflow,
+
+    /// decimal precision {0} exceeds the maximal supported precision of 19
+    /// decimal digits
+    InvalidPrecision(u8),
+
+    /// amount has more decimal digits than allowed by the asset precision {0}
+    ExcessiveDecimals(u8),
+
+    /// '{0}' is not a valid decimal amount
+    InvalidString(String),
+
+    /// amounts with different decimal precision {0} and {1} can't be added
+    PrecisionMismatch(u8, u8),
+}
+
+/// Amount of a fungible asset measured in atomic units, as it is kept in the
+/// contract state.
+///
+/// Amount itself does not know the asset precision, and all conversions from
+/// and into decimal representation require it to be provided explicitly.
+/// Decimal conversions do not use floating point arithmetic and are exact.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default, Display)]
+#[derive(StrictEncode, StrictDecode)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(crate = "serde_crate"))]
+#[display(inner)]
+pub struct Amount(AtomicValue);
+
+impl Amount {
+    /// Maximal decimal precision supported by the amounts
+    pub const MAX_PRECISION: u8 = 19;
+
+    /// Zero amount
+    pub const ZERO: Amount = Amount(0);
+
+    /// Returns multiplier converting whole asset units into atomic units
+    fn scale(precision: u8) -> Result<u64, AmountError> {
+        10u64
+            .checked_pow(precision as u32)
+            .ok_or(AmountError::InvalidPrecision(precision))
+    }
+
+    /// Constructs amount from the number of atomic units
+    #[inline]
+    pub fn from_atomic_value(value: AtomicValue) -> Amount { Amount(value) }
+
+    /// Returns number of atomic units in the amount
+    #[inline]
+    pub fn atomic_value(self) -> AtomicValue { self.0 }
+
+    /// Detects whether the amount is zero
+    #[inline]
+    pub fn is_zero(self) -> bool { self.0 == 0 }
+
+    /// Parses decimal string like `12.345` into amount using the asset decimal
+    /// `precision`. The string may not contain more decimal digits than the
+    /// precision allows.
+    pub fn from_decimal_str(s: &str, precision: u8) -> Result<Amount, AmountError> {
+        let scale = Amount::scale(precision)?;
+        let (int, fract) = match s.split_once('.') {
+            Some((int, fract)) if !fract.is_empty() => (int, fract),
+            Some(_) => return Err(AmountError::InvalidString(s.to_owned())),
+            None => (s, ""),
+        };
+        let is_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+        if int.is_empty() || !is_digits(int) || !is_digits(fract) {
+            return Err(AmountError::InvalidString(s.to_owned()));
+        }
+        if fract.len() > precision as usize {
+            return Err(AmountError::ExcessiveDecimals(precision));
+        }
+
+        // Strings consisting of digits only may fail to parse only because of
+        // the overflow
+        let int = int.parse::<u64>().map_err(|_| AmountError::Overflow)?;
+        let fract = match fract {
+            "" => 0,
+            fract => {
+                let missing = precision as u32 - fract.len() as u32;
+                let fract = fract.parse::<u64>().map_err(|_| AmountError::Overflow)?;
+                fract * 10u64.pow(missing)
+            }
+        };
+        int.checked_mul(scale)
+            .and_then(|value| value.checked_add(fract))
+            .map(Amount)
+            .ok_or(AmountError::Overflow)
+    }
+
+    /// Renders the amount as a decimal string using the asset decimal
+    /// `precision`. The string always contains exactly `precision` decimal
+    /// digits.
+    pub fn to_decimal_string(self, precision: u8) -> String {
+        let precision = precision as usize;
+        let digits = format!("{:0>width$}", self.0, width = precision + 1);
+        if precision == 0 {
+            return digits;
+        }
+        let (int, fract) = digits.split_at(digits.len() - precision);
+        format!("{}.{}", int, fract)
+    }
+
+    /// Adds two amounts, failing on overflow
+    #[inline]
+    pub fn checked_add(self, other: Amount) -> Result<Amount, AmountError> {
+        self.0
+            .checked_add(other.0)
+            .map(Amount)
+            .ok_or(AmountError::Overflow)
+    }
+
+    /// Subtracts `other` amount, failing if it exceeds this amount
+    #[inline]
+    pub fn checked_sub(self, other: Amount) -> Result<Amount, AmountError> {
+        self.0
+            .checked_sub(other.0)
+            .map(Amount)
+            .ok_or(AmountError::Overflow)
+    }
+
+    /// Multiplies amount by a number, failing on overflow
+    #[inline]
+    pub fn checked_mul(self, factor: u64) -> Result<Amount, AmountError> {
+        self.0
+            .checked_mul(factor)
+            .map(Amount)
+            .ok_or(AmountError::Overflow)
+    }
+}
+
+impl From<AtomicValue> for Amount {
+    #[inline]
+    fn from(value: AtomicValue) -> Self { Amount(value) }
+}
+
+impl From<Amount> for AtomicValue {
+    #[inline]
+    fn from(amount: Amount) -> Self { amount.0 }
+}
+
+/// Parses amount given in atomic units
+impl FromStr for Amount {
+    type Err = AmountError;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, Self::Err> { Amount::from_decimal_str(s, 0) }
+}
+
+#[cfg(test)]
+mod test {
+    use strict_encoding::StrictDecode;
+
+    use super::*;
+
+    #[test]
+    fn test_decimal_parsing() {
+        assert_eq!(Amount::from_decimal_str("12.345", 3), Ok(Amount(12345)));
+        assert_eq!(Amount::from_decimal_str("12.3", 3), Ok(Amount(12300)));
+        assert_eq!(Amount::from_decimal_str("12", 3), Ok(Amount(12000)));
+        assert_eq!(Amount::from_decimal_str("0.001", 3), Ok(Amount(1)));
+        assert_eq!(Amount::from_decimal_str("12", 0), Ok(Amount(12)));
+        assert_eq!(
+            Amount::from_decimal_str("12.345", 2),
+            Err(AmountError::ExcessiveDecimals(2))
+        );
+        assert_eq!(
+            Amount::from_decimal_str("1.5", 0),
+            Err(AmountError::ExcessiveDecimals(0))
+        );
+        for invalid in ["", ".5", "12.", "1.2.3", "-1", "+1", "1e3", " 1"] {
+            let err = AmountError::InvalidString(invalid.to_owned());
+            assert_eq!(Amount::from_decimal_str(invalid, 3), Err(err));
+        }
+        assert_eq!(
+            Amount::from_decimal_str("1", 20),
+            Err(AmountError::InvalidPrecision(20))
+        );
+    }
+
+    #[test]
+    fn test_precision_bounds() {
+        let amount = Amount::from_decimal_str("18.000000000000000001", 18).unwrap();
+        assert_eq!(amount, Amount(18_000_000_000_000_000_001));
+        assert_eq!(amount.to_decimal_string(18), "18.000000000000000001");
+        assert_eq!(
+            Amount::from_decimal_str("19", 18),
+            Err(AmountError::Overflow)
+        );
+        assert_eq!(
+            Amount::from_decimal_str("18446744073709551616", 0),
+            Err(AmountError::Overflow)
+        );
+        let max = Amount(u64::MAX);
+        assert_eq!(
+            Amount::from_decimal_str(&max.to_decimal_string(19), 19),
+            Ok(max)
+        );
+    }
+
+    #[test]
+    fn test_decimal_rendering() {
+        assert_eq!(Amount(12345).to_decimal_string(3), "12.345");
+        assert_eq!(Amount(1).to_decimal_string(3), "0.001");
+        assert_eq!(Amount(0).to_decimal_string(2), "0.00");
+        assert_eq!(Amount(12).to_decimal_string(0), "12");
+        assert_eq!(
+            Amount(u64::MAX).to_decimal_string(19),
+            "1.8446744073709551615"
+        );
+    }
+
+    #[test]
+    fn test_checked_arithmetic() {
+        let max = Amount(u64::MAX);
+        assert_eq!(Amount(2).checked_add(Amount(3)), Ok(Amount(5)));
+        assert_eq!(max.checked_add(Amount(1)), Err(AmountError::Overflow));
+        assert_eq!(Amount(3).checked_sub(Amount(2)), Ok(Amount(1)));
+        assert_eq!(Amount(2).checked_sub(Amount(3)), Err(AmountError::Overflow));
+        assert_eq!(Amount(3).checked_mul(4), Ok(Amount(12)));
+        assert_eq!(max.checked_mul(2), Err(AmountError::Overflow));
+    }
+
+    #[test]
+    fn test_precise_amount() {
+        let amount = PreciseAmount::from_atomic_value(12345, 2);
+        assert_eq!(
+            amount.checked_add(PreciseAmount::from_atomic_value(5, 2)),
+            Ok(PreciseAmount::from_atomic_value(12350, 2))
+        );
+        assert_eq!(
+            amount.checked_add(PreciseAmount::from_atomic_value(5, 3)),
+            Err(AmountError::PrecisionMismatch(2, 3))
+        );
+        assert_eq!(
+            amount.checked_add(PreciseAmount::from_atomic_value(u64::MAX, 2)),
+            Err(AmountError::Overflow)
+        );
+        assert_eq!(amount.fractional_amount(), 123.45);
+        // Decoded data may have any decimal precision
+        let amount = PreciseAmount::strict_deserialize(&[1, 0, 0, 0, 0, 0, 0, 0, 0xFF]).unwrap();
+        assert_eq!(amount.decimal_precision(), 0xFF);
+        assert!(amount.fractional_amount() < 1e-200);
+    }
+}
@@ -0,0 +1,1237 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! High-level representation of RGB20 fungible assets.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Display, Formatter};
+use std::mem;
+
+use bitcoin::{OutPoint, Txid};
+use commit_verify::lnpbp4;
+use lnpbp::chain::Chain;
+#[cfg(feature = "serde")]
+use serde_with::{As, DisplayFromStr};
+
+use super::allocation::Allocation;
+use super::amount::{Amount, AmountError};
+use super::nomination::{validate_precision, AssetName, Nominal, Precision, Ticker};
+use super::renomination::{Nomination, NominationChange};
+use super::schema::{self, FieldType, OwnedRightType};
+use crate::consignments::ConsignmentType;
+use crate::{
+    data, Anchor, AssignmentRef, ConcealedAssignment, Contract, ContractId, ContractState,
+    Extension, Genesis, InmemConsignment, Node, NodeId, NodeOutpoint, StateApplyError,
+    StateTransfer, ToMnemonic, Transition, TypedOutpoint,
+};
+
+/// Errors constructing or updating [`Asset`]
+#[derive(Clone, PartialEq, Eq, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum Error {
+    /// contract schema does not define RGB20 fungible asset
+    NotFungible,
+
+    /// genesis field {0} required for RGB20 asset is absent or has a wrong
+    /// type
+    MissingField(FieldType),
+
+    /// asset precision {0} exceeds the maximal precision of 18 decimal digits
+    InvalidPrecision(u8),
+
+    /// state belongs to a different contract {0}
+    ContractMismatch(ContractId),
+
+    /// renomination {node_id} changes asset precision from {current} to
+    /// {requested}, which would change amounts of all existing allocations
+    PrecisionChange {
+        node_id: NodeId,
+        current: u8,
+        requested: u8,
+    },
+
+    /// known issued supply {issued} exceeds the maximal asset supply
+    /// {max_supply}
+    OverIssuance { issued: Amount, max_supply: Amount },
+
+    /// invalid asset amount: {0}
+    #[from]
+    Amount(AmountError),
+
+    /// unable to apply contract state: {0}
+    #[from]
+    State(StateApplyError),
+}
+
+/// Filter selecting asset allocations by the bitcoin transaction outputs
+/// they are assigned to
+pub enum OutpointFilter<'filter> {
+    /// Selects allocations assigned to any outpoint
+    All,
+
+    /// Selects allocations assigned to one of the provided outpoints, for
+    /// instance the ones controlled by a wallet
+    Only(BTreeSet<OutPoint>),
+
+    /// Selects allocations assigned to the outpoints matching the predicate
+    Predicate(Box<dyn Fn(&OutPoint) -> bool + 'filter>),
+}
+
+impl<'filter> OutpointFilter<'filter> {
+    /// Constructs filter from the outpoint `predicate`
+    #[inline]
+    pub fn with(predicate: impl Fn(&OutPoint) -> bool + 'filter) -> Self {
+        OutpointFilter::Predicate(Box::new(predicate))
+    }
+
+    /// Checks whether the filter selects allocations assigned to `outpoint`
+    pub fn matches(&self, outpoint: &OutPoint) -> bool {
+        match self {
+            OutpointFilter::All => true,
+            OutpointFilter::Only(outpoints) => outpoints.contains(outpoint),
+            OutpointFilter::Predicate(predicate) => predicate(outpoint),
+        }
+    }
+}
+
+impl Default for OutpointFilter<'_> {
+    #[inline]
+    fn default() -> Self { OutpointFilter::All }
+}
+
+impl From<BTreeSet<OutPoint>> for OutpointFilter<'_> {
+    #[inline]
+    fn from(outpoints: BTreeSet<OutPoint>) -> Self { OutpointFilter::Only(outpoints) }
+}
+
+/// Changes in the wallet asset allocations made by a single witness
+/// transaction
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(crate = "serde_crate"))]
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct HistoryEntry {
+    /// Witness transaction of the state transitions
+    #[cfg_attr(feature = "serde", serde(with = "As::<DisplayFromStr>"))]
+    pub witness: Txid,
+
+    /// Height of the block mining the witness transaction; `None` for the
+    /// unconfirmed transactions
+    pub height: Option<u32>,
+
+    /// Amount assigned to the wallet outpoints
+    pub received: Amount,
+
+    /// Amount spent from the wallet outpoints
+    pub sent: Amount,
+
+    /// State transitions assigning or spending the wallet allocations
+    #[cfg_attr(feature = "serde", serde(with = "As::<BTreeSet<DisplayFromStr>>"))]
+    pub node_ids: BTreeSet<NodeId>,
+}
+
+impl HistoryEntry {
+    fn with(witness: Txid, height: Option<u32>) -> HistoryEntry {
+        HistoryEntry {
+            witness,
+            height,
+            received: Amount::ZERO,
+            sent: Amount::ZERO,
+            node_ids: empty!(),
+        }
+    }
+
+    /// Returns change of the wallet balance: positive for the received and
+    /// negative for the sent assets
+    #[inline]
+    pub fn net_change(&self) -> i128 {
+        i128::from(self.received.atomic_value()) - i128::from(self.sent.atomic_value())
+    }
+}
+
+/// RGB20 fungible asset with all its allocations known to the wallet.
+///
+/// With the `serde` feature asset is (de)serialized in the human-readable
+/// format used by the wallets for exchanging asset data, which does not
+/// include the contract state and the renomination history. Deserialized
+/// asset has an empty contract state; strict encoding has to be used for
+/// persisting the full asset data.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(
+        crate = "serde_crate",
+        into = "serde_wallet::AssetData",
+        try_from = "serde_wallet::AssetData"
+    )
+)]
+#[derive(Clone, PartialEq, Debug)]
+#[derive(StrictEncode, StrictDecode)]
+pub struct Asset {
+    /// Current nominal data of the asset
+    nomination: Nomination,
+
+    /// Nominal data replaced by the known renomination transitions, in the
+    /// order of their application
+    nomination_history: Vec<NominationChange>,
+
+    /// Supply issued by the asset genesis
+    issued_supply: Amount,
+
+    /// Maximal asset supply: the supply issued by the genesis together with
+    /// the inflation allowance defined by the genesis
+    max_supply: Amount,
+
+    /// Supply issued by the known secondary issue transitions
+    known_inflation: Amount,
+
+    /// Supply destroyed by the known burn and burn-and-replace transitions
+    burned_supply: Amount,
+
+    /// Part of the burned supply re-issued by the known burn-and-replace
+    /// transitions
+    replaced_supply: Amount,
+
+    /// Id of the asset contract
+    contract_id: ContractId,
+
+    /// Chain the asset is issued on
+    chain: Chain,
+
+    /// UNIX timestamp of the asset issue, if provided by the genesis
+    issue_date: Option<i64>,
+
+    /// Unspent allocations of the asset with revealed amounts
+    known_allocations: Vec<Allocation>,
+
+    /// Unspent inflation rights with revealed allowance
+    inflation_rights: Vec<Allocation>,
+
+    /// Contract state the asset data are extracted from
+    state: ContractState,
+}
+
+impl Asset {
+    /// Constructs asset from the contract consignment, checking that the
+    /// contract uses RGB20-style schema. The consignment must be validated
+    /// beforehand.
+    pub fn try_from_contract(consignment: &Contract) -> Result<Asset, Error> {
+        if !schema::is_fungible(&consignment.schema) {
+            return Err(Error::NotFungible);
+        }
+        let mut state = ContractState::with_genesis(&consignment.genesis);
+        apply_consignment(&mut state, consignment)?;
+        Asset::with_genesis_state(&consignment.genesis, state)
+    }
+
+    /// Constructs asset from the contract genesis and contract state, which
+    /// provides the asset allocations
+    pub fn with_genesis_state(genesis: &Genesis, state: ContractState) -> Result<Asset, Error> {
+        if state.contract_id != genesis.contract_id() {
+            return Err(Error::ContractMismatch(state.contract_id));
+        }
+
+        let field = |ty: FieldType| {
+            genesis
+                .metadata()
+                .into_iter()
+                .find(|(field_type, _)| **field_type == ty.into())
+                .and_then(|(_, values)| values.first())
+                .ok_or(Error::MissingField(ty))
+        };
+        let string = |ty: FieldType| match field(ty)? {
+            data::Revealed::String(s) => Ok(s.clone()),
+            _ => Err(Error::MissingField(ty)),
+        };
+        // Non-conforming ticker and name are kept for the wallets to display
+        // them with a warning, while the precision defines the amounts and
+        // must be valid
+        let ticker = Nominal::with(&string(FieldType::Ticker)?);
+        let name = Nominal::with(&string(FieldType::Name)?);
+        let precision = match field(FieldType::Precision)? {
+            data::Revealed::U8(precision) => {
+                validate_precision(*precision).map_err(|_| Error::InvalidPrecision(*precision))?
+            }
+            _ => return Err(Error::MissingField(FieldType::Precision)),
+        };
+        let issue_date = match field(FieldType::Timestamp) {
+            Ok(data::Revealed::I64(timestamp)) => Some(*timestamp),
+            _ => None,
+        };
+        let issued_supply = match field(FieldType::IssuedSupply)? {
+            data::Revealed::U64(supply) => Amount::from(*supply),
+            _ => return Err(Error::MissingField(FieldType::IssuedSupply)),
+        };
+
+        // Inflation rights assigned to concealed seals still count towards
+        // the allowance, since their amounts are known from the genesis
+        let genesis_id = genesis.node_id();
+        let inflation = u16::from(OwnedRightType::Inflation);
+        let revealed = state
+            .owned_values(inflation)
+            .filter(|assigned| assigned.outpoint.node_id == genesis_id)
+            .map(|assigned| assigned.state.value);
+        let concealed = state
+            .concealed(inflation)
+            .filter(|concealed| concealed.outpoint.node_id == genesis_id)
+            .filter_map(|concealed| AssignmentRef::from(concealed).as_amount().ok());
+        let allowance = revealed
+            .chain(concealed)
+            .try_fold(Amount::ZERO, |sum, value| {
+                sum.checked_add(Amount::from(value))
+            })?;
+        let max_supply = issued_supply.checked_add(allowance)?;
+
+        let mut asset = Asset {
+            nomination: Nomination {
+                ticker,
+                name,
+                precision,
+            },
+            nomination_history: vec![],
+            issued_supply,
+            max_supply,
+            known_inflation: Amount::ZERO,
+            burned_supply: Amount::ZERO,
+            replaced_supply: Amount::ZERO,
+            contract_id: genesis.contract_id(),
+            chain: genesis.chain().clone(),
+            issue_date,
+            known_allocations: vec![],
+            inflation_rights: vec![],
+            state,
+        };
+        asset.update_state()?;
+        Ok(asset)
+    }
+
+    /// Returns asset ticker, which may not conform to the RGB20 rules
+    #[inline]
+    pub fn ticker(&self) -> &Nominal<Ticker> { &self.nomination.ticker }
+
+    /// Returns full asset name, which may not conform to the RGB20 rules
+    #[inline]
+    pub fn name(&self) -> &Nominal<AssetName> { &self.nomination.name }
+
+    /// Returns decimal precision of the asset amounts
+    #[inline]
+    pub fn precision(&self) -> Precision { self.nomination.precision }
+
+    /// Returns current nominal data of the asset
+    #[inline]
+    pub fn nomination(&self) -> &Nomination { &self.nomination }
+
+    /// Returns nominal data replaced by the known renomination transitions
+    /// together with the ids of these transitions, in the order of their
+    /// application
+    #[inline]
+    pub fn nomination_history(&self) -> &[NominationChange] { &self.nomination_history }
+
+    /// Returns unspent renomination right, if it is known
+    pub fn renomination_right(&self) -> Option<NodeOutpoint> {
+        let ty = u16::from(OwnedRightType::Renomination);
+        self.state
+            .owned_rights(ty)
+            .map(|assigned| assigned.outpoint)
+            .find(|outpoint| !self.state.is_spent(&TypedOutpoint::with(*outpoint, ty)))
+    }
+
+    /// Returns supply issued by the asset genesis
+    #[inline]
+    pub fn issued_supply(&self) -> Amount { self.issued_supply }
+
+    /// Returns maximal asset supply, including the inflation allowance defined
+    /// by the genesis
+    #[inline]
+    pub fn max_supply(&self) -> Amount { self.max_supply }
+
+    /// Returns supply issued by the known secondary issue transitions
+    #[inline]
+    pub fn known_inflation(&self) -> Amount { self.known_inflation }
+
+    /// Returns supply destroyed by the known burn and burn-and-replace
+    /// transitions, including the re-issued supply
+    #[inline]
+    pub fn burned_supply(&self) -> Amount { self.burned_supply }
+
+    /// Returns part of the burned supply re-issued by the known
+    /// burn-and-replace transitions
+    #[inline]
+    pub fn replaced_supply(&self) -> Amount { self.replaced_supply }
+
+    /// Returns amount of the assets which still may be issued according to
+    /// the known secondary issue transitions
+    #[inline]
+    pub fn remaining_inflation_allowance(&self) -> Amount {
+        // Never fails since the supply is audited on each state update
+        self.max_supply
+            .checked_sub(self.issued_supply)
+            .and_then(|allowance| allowance.checked_sub(self.known_inflation))
+            .unwrap_or_default()
+    }
+
+    /// Parses decimal amount string like `12.345` using the asset precision
+    #[inline]
+    pub fn parse_amount(&self, s: &str) -> Result<Amount, AmountError> {
+        Amount::from_decimal_str(s, self.precision().into())
+    }
+
+    /// Renders the amount as a decimal string using the asset precision
+    #[inline]
+    pub fn format_amount(&self, amount: Amount) -> String {
+        amount.to_decimal_string(self.precision().into())
+    }
+
+    /// Returns total amount of the known asset allocations
+    pub fn known_amount(&self) -> Result<Amount, AmountError> {
+        self.known_allocations
+            .iter()
+            .try_fold(Amount::ZERO, |sum, allocation| {
+                sum.checked_add(Amount::from(allocation.value()))
+            })
+    }
+
+    /// Iterates over the unspent allocations with revealed amounts which are
+    /// assigned to the outpoints selected by the `filter`.
+    ///
+    /// Allocations with concealed amounts are never returned, since their
+    /// amounts are unknown; use [`Asset::concealed_allocations`] to access
+    /// them.
+    pub fn allocations<'asset>(
+        &'asset self,
+        filter: OutpointFilter<'asset>,
+    ) -> impl Iterator<Item = &'asset Allocation> + 'asset {
+        self.known_allocations
+            .iter()
+            .filter(move |allocation| filter.matches(allocation.outpoint()))
+    }
+
+    /// Iterates over the unspent asset assignments with concealed amounts
+    /// which are assigned to the outpoints selected by the `filter`.
+    /// Assignments with unknown seals are selected only by
+    /// [`OutpointFilter::All`].
+    pub fn concealed_allocations<'asset>(
+        &'asset self,
+        filter: OutpointFilter<'asset>,
+    ) -> impl Iterator<Item = &'asset ConcealedAssignment> + 'asset {
+        let state = &self.state;
+        let ty = u16::from(OwnedRightType::Assets);
+        state
+            .concealed(ty)
+            .filter(move |concealed| !state.is_spent(&TypedOutpoint::with(concealed.outpoint, ty)))
+            .filter(move |concealed| match (&filter, concealed.seal) {
+                (OutpointFilter::All, _) => true,
+                (_, Some(seal)) => filter.matches(&seal),
+                (_, None) => false,
+            })
+    }
+
+    /// Returns total amount of the revealed allocations assigned to the
+    /// outpoints selected by the `filter`
+    pub fn balance(&self, filter: OutpointFilter) -> Result<Amount, AmountError> {
+        self.allocations(filter)
+            .try_fold(Amount::ZERO, |sum, allocation| {
+                sum.checked_add(Amount::from(allocation.value()))
+            })
+    }
+
+    /// Returns revealed allocations assigned to the outpoints selected by the
+    /// `filter` whose witness transactions have at least `min_confirmations`
+    /// confirmations at the chain `tip_height`.
+    ///
+    /// `heights` provides block heights of the mined witness transactions and
+    /// returns `None` for the unconfirmed ones. Allocations created by
+    /// genesis and state extensions have no witness and are always
+    /// spendable.
+    pub fn spendable<'asset>(
+        &'asset self,
+        filter: OutpointFilter<'asset>,
+        min_confirmations: u32,
+        tip_height: u32,
+        heights: &impl Fn(Txid) -> Option<u32>,
+    ) -> Vec<&'asset Allocation> {
+        let confirmed = |allocation: &Allocation| {
+            let witness = self
+                .state
+                .nodes
+                .get(allocation.node_id())
+                .and_then(|node| node.witness);
+            let txid = match witness {
+                Some(txid) => txid,
+                None => return true,
+            };
+            let confirmations = match heights(txid) {
+                Some(height) if height <= tip_height => tip_height - height + 1,
+                _ => 0,
+            };
+            confirmations >= min_confirmations
+        };
+        self.allocations(filter)
+            .filter(|allocation| confirmed(allocation))
+            .collect()
+    }
+
+    /// Returns history of the allocations with revealed amounts which are
+    /// assigned to the wallet `outpoints`, grouped by the witness
+    /// transactions assigning or spending them.
+    ///
+    /// `heights` provides block heights of the mined witness transactions.
+    /// Entries are sorted by their height and witness transaction id, with
+    /// the unconfirmed entries going last. Allocations assigned by genesis and
+    /// state extensions have no witness transaction and are not included
+    /// into the history.
+    pub fn history(
+        &self,
+        outpoints: &BTreeSet<OutPoint>,
+        heights: &impl Fn(Txid) -> Option<u32>,
+    ) -> Result<Vec<HistoryEntry>, AmountError> {
+        let state = &self.state;
+        let ty = u16::from(OwnedRightType::Assets);
+        let mut entries = BTreeMap::<Txid, HistoryEntry>::new();
+        let owned = state
+            .owned_values(ty)
+            .filter(|assigned| outpoints.contains(&assigned.seal));
+        for assigned in owned {
+            let amount = Amount::from(assigned.state.value);
+            if let Some(txid) = assigned.witness {
+                let entry = entries
+                    .entry(txid)
+                    .or_insert_with(|| HistoryEntry::with(txid, heights(txid)));
+                entry.received = entry.received.checked_add(amount)?;
+                entry.node_ids.insert(assigned.outpoint.node_id);
+            }
+            let spending = state
+                .spending_transition(&TypedOutpoint::with(assigned.outpoint, ty))
+                .and_then(|node_id| Some((node_id, state.nodes.get(&node_id)?.witness?)));
+            if let Some((node_id, txid)) = spending {
+                let entry = entries
+                    .entry(txid)
+                    .or_insert_with(|| HistoryEntry::with(txid, heights(txid)));
+                entry.sent = entry.sent.checked_add(amount)?;
+                entry.node_ids.insert(node_id);
+            }
+        }
+
+        let mut history = entries.into_values().collect::<Vec<_>>();
+        history.sort_by_key(|entry| (entry.height.is_none(), entry.height, entry.witness));
+        Ok(history)
+    }
+
+    /// Returns id of the asset contract
+    #[inline]
+    pub fn contract_id(&self) -> ContractId { self.contract_id }
+
+    /// Returns chain the asset is issued on
+    #[inline]
+    pub fn chain(&self) -> &Chain { &self.chain }
+
+    /// Returns UNIX timestamp of the asset issue, if the genesis provides it
+    #[inline]
+    pub fn issue_date(&self) -> Option<i64> { self.issue_date }
+
+    /// Returns unspent allocations of the asset with revealed amounts
+    #[inline]
+    pub fn known_allocations(&self) -> &[Allocation] { &self.known_allocations }
+
+    /// Returns unspent inflation rights with revealed allowance, which may be
+    /// spent with [`super::InflationBuilder`]
+    #[inline]
+    pub fn inflation_rights(&self) -> &[Allocation] { &self.inflation_rights }
+
+    /// Returns contract state the asset data are extracted from
+    #[inline]
+    pub fn state(&self) -> &ContractState { &self.state }
+
+    /// Updates asset allocations with the state from the transfer consignment.
+    /// The consignment must be validated beforehand.
+    ///
+    /// Fails with [`Error::OverIssuance`] if the consignment issues more assets
+    /// than allowed by the genesis, leaving the asset unchanged.
+    pub fn update_with_transfer(&mut self, transfer: &StateTransfer) -> Result<(), Error> {
+        if transfer.contract_id() != self.contract_id {
+            return Err(Error::ContractMismatch(transfer.contract_id()));
+        }
+        let mut asset = self.clone();
+        apply_consignment(&mut asset.state, transfer)?;
+        asset.update_state()?;
+        *self = asset;
+        Ok(())
+    }
+
+    /// Updates allocations and inflation data from the contract state,
+    /// auditing the known issued supply
+    fn update_state(&mut self) -> Result<(), Error> {
+        let state = &self.state;
+        let unspent = |ty: OwnedRightType| {
+            let ty = u16::from(ty);
+            state
+                .owned_values(ty)
+                .filter(move |assigned| {
+                    !state.is_spent(&TypedOutpoint::with(assigned.outpoint, ty))
+                })
+                .map(|assigned| {
+                    let outpoint = assigned.outpoint;
+                    let value = assigned.state.clone();
+                    Allocation::with(outpoint.node_id, outpoint.output_no, assigned.seal, value)
+                })
+                .collect::<Vec<_>>()
+        };
+        let known_allocations = unspent(OwnedRightType::Assets);
+        let inflation_rights = unspent(OwnedRightType::Inflation);
+
+        // RGB20 defines issued supply metadata only for the genesis and the
+        // secondary issue transitions
+        let issued_supply = u16::from(FieldType::IssuedSupply);
+        let known_inflation = state
+            .nodes
+            .values()
+            .filter(|node| node.witness.is_some())
+            .filter_map(|node| node.metadata.get(&issued_supply))
+            .flatten()
+            .filter_map(|value| match value {
+                data::Revealed::U64(value) => Some(Amount::from(*value)),
+                _ => None,
+            })
+            .try_fold(Amount::ZERO, Amount::checked_add)?;
+
+        // Burn-and-replace transitions differ from the burns only by
+        // re-issuing the assets
+        let assets = u16::from(OwnedRightType::Assets);
+        let reissues = |node_id: NodeId| {
+            state
+                .owned_values(assets)
+                .any(|assigned| assigned.outpoint.node_id == node_id)
+                || state
+                    .concealed
+                    .get(&assets)
+                    .into_iter()
+                    .flatten()
+                    .any(|concealed| concealed.outpoint.node_id == node_id)
+        };
+        let burned_supply = u16::from(FieldType::BurnedSupply);
+        let mut burned = Amount::ZERO;
+        let mut replaced = Amount::ZERO;
+        for (node_id, node) in &state.nodes {
+            for value in node.metadata.get(&burned_supply).into_iter().flatten() {
+                if let data::Revealed::U64(value) = value {
+                    burned = burned.checked_add(Amount::from(*value))?;
+                    if reissues(*node_id) {
+                        replaced = replaced.checked_add(Amount::from(*value))?;
+                    }
+                }
+            }
+        }
+
+        // Genesis nomination is the first replaced one, if there were any
+        // renominations
+        let mut nomination = self
+            .nomination_history
+            .first()
+            .map(|change| change.previous.clone())
+            .unwrap_or_else(|| self.nomination.clone());
+        let mut nomination_history = vec![];
+        let precision = u16::from(FieldType::Precision);
+        for node_id in &state.history {
+            let node = match state.nodes.get(node_id) {
+                Some(node) if node.witness.is_some() => node,
+                _ => continue,
+            };
+            let current = u8::from(nomination.precision);
+            if let Some(data::Revealed::U8(requested)) = node
+                .metadata
+                .get(&precision)
+                .and_then(|values| values.first())
+            {
+                if *requested != current {
+                    return Err(Error::PrecisionChange {
+                        node_id: *node_id,
+                        current,
+                        requested: *requested,
+                    });
+                }
+            }
+            if let Some(renominated) = nomination.renominated(&node.metadata) {
+                let previous = mem::replace(&mut nomination, renominated);
+                nomination_history.push(NominationChange {
+                    node_id: *node_id,
+                    previous,
+                });
+            }
+        }
+
+        let issued = self.issued_supply.checked_add(known_inflation)?;
+        if issued > self.max_supply {
+            return Err(Error::OverIssuance {
+                issued,
+                max_supply: self.max_supply,
+            });
+        }
+
+        self.known_allocations = known_allocations;
+        self.inflation_rights = inflation_rights;
+        self.nomination = nomination;
+        self.nomination_history = nomination_history;
+        self.known_inflation = known_inflation;
+        self.burned_supply = burned;
+        self.replaced_supply = replaced;
+        Ok(())
+    }
+}
+
+impl Display for Asset {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let issued = self.format_amount(self.issued_supply);
+        writeln!(f, "{} ({})", self.ticker(), self.name())?;
+        if !self.nomination.is_conforming() {
+            writeln!(f, "warning: non-conforming ticker or name")?;
+        }
+        writeln!(f, "contract: {} ({})", self.contract_id, self.contract_id.to_mnemonic())?;
+        writeln!(f, "chain: {}", self.chain)?;
+        writeln!(f, "precision: {}", self.precision())?;
+        writeln!(f, "issued supply: {}", issued)?;
+        if self.max_supply > self.issued_supply {
+            let known_inflation = self.format_amount(self.known_inflation);
+            writeln!(f, "known inflation: {}", known_inflation)?;
+            writeln!(f, "max supply: {}", self.format_amount(self.max_supply))?;
+        }
+        if !self.burned_supply.is_zero() {
+            let burned = self.format_amount(self.burned_supply);
+            let replaced = self.format_amount(self.replaced_supply);
+            writeln!(f, "burned supply: {}", burned)?;
+            writeln!(f, "replaced supply: {}", replaced)?;
+        }
+        writeln!(f, "known allocations:")?;
+        for allocation in &self.known_allocations {
+            let amount = self.format_amount(Amount::from(allocation.value()));
+            writeln!(f, "  {}@{}", amount, allocation.outpoint())?;
+        }
+        Ok(())
+    }
+}
+
+/// State extension or anchored state transition of a consignment
+#[derive(Copy, Clone)]
+enum ConsignedNode<'consignment> {
+    Extension(&'consignment Extension),
+    Transition(
+        &'consignment Transition,
+        &'consignment Anchor<lnpbp4::MerkleProof>,
+    ),
+}
+
+impl<'consignment> ConsignedNode<'consignment> {
+    fn parents(self) -> BTreeSet<NodeId> {
+        match self {
+            ConsignedNode::Extension(extension) => ContractState::node_parents(extension),
+            ConsignedNode::Transition(transition, _) => ContractState::node_parents(transition),
+        }
+    }
+
+    fn apply(self, state: &mut ContractState) -> Result<(), StateApplyError> {
+        match self {
+            ConsignedNode::Extension(extension) => state.apply_extension(extension),
+            ConsignedNode::Transition(transition, anchor) => {
+                state.check_parents(transition)?;
+                state.extend(anchor.txid, transition)
+            }
+        }
+    }
+}
+
+/// Applies state extensions and anchored state transitions from the
+/// consignment to the contract state. Nodes are applied in topological
+/// order, so the parents of both kinds of nodes are checked to be known to
+/// the state; ties are resolved by the node ids.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(contract_id = %consignment.contract_id(), bundles = consignment.anchored_bundles.len())
+    )
+)]
+fn apply_consignment<T>(
+    state: &mut ContractState,
+    consignment: &InmemConsignment<T>,
+) -> Result<(), StateApplyError>
+where
+    T: ConsignmentType,
+{
+    let mut nodes = BTreeMap::<NodeId, ConsignedNode>::new();
+    for extension in consignment.state_extensions.iter() {
+        nodes.insert(extension.node_id(), ConsignedNode::Extension(extension));
+    }
+    for (anchor, bundle) in consignment.anchored_bundles.iter() {
+        for transition in bundle.known_transitions() {
+            nodes.insert(
+                transition.node_id(),
+                ConsignedNode::Transition(transition, anchor),
+            );
+        }
+    }
+
+    let mut pending = BTreeMap::<NodeId, usize>::new();
+    let mut children = BTreeMap::<NodeId, BTreeSet<NodeId>>::new();
+    for (node_id, node) in &nodes {
+        let parents = node
+            .parents()
+            .into_iter()
+            .filter(|parent| nodes.contains_key(parent))
+            .collect::<BTreeSet<_>>();
+        pending.insert(*node_id, parents.len());
+        for parent in parents {
+            children.entry(parent).or_default().insert(*node_id);
+        }
+    }
+
+    let mut ready = pending
+        .iter()
+        .filter(|(_, count)| **count == 0)
+        .map(|(node_id, _)| *node_id)
+        .collect::<BTreeSet<_>>();
+    while let Some(node_id) = ready.iter().next().copied() {
+        ready.remove(&node_id);
+        pending.remove(&node_id);
+        nodes[&node_id].apply(state)?;
+        for child in children.get(&node_id).into_iter().flatten() {
+            let count = pending.get_mut(child).expect("child is always pending");
+            *count -= 1;
+            if *count == 0 {
+                ready.insert(*child);
+            }
+        }
+    }
+    // Nodes with cyclic dependencies never get ready, and applying any of
+    // them fails on its unknown parents
+    match pending.keys().next() {
+        Some(node_id) => nodes[node_id].apply(state),
+        None => Ok(()),
+    }
+}
+
+/// Human-readable asset data format used by the wallets
+#[cfg(feature = "serde")]
+pub(crate) mod serde_wallet {
+    use std::convert::TryFrom;
+
+    use bitcoin::OutPoint;
+    use lnpbp::chain::Chain;
+    use serde_with::{As, DisplayFromStr};
+
+    use super::Asset;
+    use crate::fungible::allocation::Allocation;
+    use crate::fungible::amount::{Amount, AmountError};
+    use crate::fungible::nomination::{AssetName, Nominal, Precision, Ticker};
+    use crate::fungible::renomination::Nomination;
+    use crate::{value, ContractId, ContractState, NodeId};
+
+    /// Errors converting data in the wallet format into assets and
+    /// allocations
+    #[derive(Clone, PartialEq, Eq, Debug, Display, Error, From)]
+    #[display(doc_comments)]
+    pub enum FormatError {
+        /// amount must be given as an atomic value or, if the asset precision
+        /// is known, as a decimal string
+        NoAmount,
+
+        /// decimal amount {decimal} does not match atomic amount {atomic}
+        AmountMismatch { atomic: u64, decimal: String },
+
+        /// invalid amount: {0}
+        #[from]
+        Amount(AmountError),
+    }
+
+    /// Amount given both as an atomic value and as a decimal string using the
+    /// asset precision. Any of them may be absent in the input data.
+    #[derive(Serialize, Deserialize)]
+    #[serde(crate = "serde_crate")]
+    pub struct AmountData {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub atomic: Option<u64>,
+
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub decimal: Option<String>,
+    }
+
+    impl AmountData {
+        pub fn with(amount: Amount, precision: Option<Precision>) -> AmountData {
+            AmountData {
+                atomic: Some(amount.atomic_value()),
+                decimal: precision.map(|precision| amount.to_decimal_string(precision.into())),
+            }
+        }
+
+        pub fn resolve(&self, precision: Option<Precision>) -> Result<Amount, FormatError> {
+            let decimal = match (&self.decimal, precision) {
+                (Some(decimal), Some(precision)) => {
+                    Some(Amount::from_decimal_str(decimal, precision.into())?)
+                }
+                _ => None,
+            };
+            match (self.atomic.map(Amount::from), decimal) {
+                (Some(atomic), Some(decimal)) if atomic != decimal => {
+                    Err(FormatError::AmountMismatch {
+                        atomic: atomic.atomic_value(),
+                        decimal: self.decimal.clone().unwrap_or_default(),
+                    })
+                }
+                (Some(amount), _) | (None, Some(amount)) => Ok(amount),
+                (None, None) => Err(FormatError::NoAmount),
+            }
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(crate = "serde_crate", rename_all = "camelCase")]
+    pub struct AllocationData {
+        #[serde(with = "As::<DisplayFromStr>")]
+        pub outpoint: OutPoint,
+
+        pub amount: AmountData,
+
+        #[serde(with = "As::<DisplayFromStr>")]
+        pub node_id: NodeId,
+
+        pub index: u16,
+
+        pub blinding: value::BlindingFactor,
+    }
+
+    impl AllocationData {
+        pub fn with(allocation: &Allocation, precision: Option<Precision>) -> AllocationData {
+            AllocationData {
+                outpoint: *allocation.outpoint(),
+                amount: AmountData::with(Amount::from(allocation.value()), precision),
+                node_id: *allocation.node_id(),
+                index: *allocation.index(),
+                blinding: allocation.revealed_amount().blinding,
+            }
+        }
+
+        pub fn resolve(&self, precision: Option<Precision>) -> Result<Allocation, FormatError> {
+            let revealed = value::Revealed {
+                value: self.amount.resolve(precision)?.atomic_value(),
+                blinding: self.blinding,
+            };
+            let allocation = Allocation::with(self.node_id, self.index, self.outpoint, revealed);
+            Ok(allocation)
+        }
+    }
+
+    impl From<Allocation> for AllocationData {
+        #[inline]
+        fn from(allocation: Allocation) -> Self { AllocationData::with(&allocation, None) }
+    }
+
+    impl TryFrom<AllocationData> for Allocation {
+        type Error = FormatError;
+
+        #[inline]
+        fn try_from(data: AllocationData) -> Result<Self, Self::Error> { data.resolve(None) }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(crate = "serde_crate", rename_all = "camelCase")]
+    pub struct AssetData {
+        #[serde(with = "As::<DisplayFromStr>")]
+        pub contract_id: ContractId,
+
+        pub ticker: Nominal<Ticker>,
+
+        pub name: Nominal<AssetName>,
+
+        pub precision: Precision,
+
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub issue_date: Option<i64>,
+
+        #[serde(default, with = "As::<Option<DisplayFromStr>>")]
+        pub chain: Option<Chain>,
+
+        #[serde(default)]
+        pub issued_supply: Option<AmountData>,
+
+        #[serde(default)]
+        pub max_supply: Option<AmountData>,
+
+        #[serde(default)]
+        pub known_inflation: Option<AmountData>,
+
+        #[serde(default)]
+        pub burned_supply: Option<AmountData>,
+
+        #[serde(default)]
+        pub replaced_supply: Option<AmountData>,
+
+        #[serde(default)]
+        pub allocations: Vec<AllocationData>,
+
+        #[serde(default)]
+        pub inflation_rights: Vec<AllocationData>,
+    }
+
+    impl From<Asset> for AssetData {
+        fn from(asset: Asset) -> Self {
+            let precision = Some(asset.precision());
+            let amount = |amount: Amount| Some(AmountData::with(amount, precision));
+            let allocations = |allocations: &[Allocation]| {
+                allocations
+                    .iter()
+                    .map(|allocation| AllocationData::with(allocation, precision))
+                    .collect()
+            };
+            AssetData {
+                contract_id: asset.contract_id,
+                ticker: asset.nomination.ticker.clone(),
+                name: asset.nomination.name.clone(),
+                precision: asset.nomination.precision,
+                issue_date: asset.issue_date,
+                chain: Some(asset.chain.clone()),
+                issued_supply: amount(asset.issued_supply),
+                max_supply: amount(asset.max_supply),
+                known_inflation: amount(asset.known_inflation),
+                burned_supply: amount(asset.burned_supply),
+                replaced_supply: amount(asset.replaced_supply),
+                allocations: allocations(&asset.known_allocations),
+                inflation_rights: allocations(&asset.inflation_rights),
+            }
+        }
+    }
+
+    impl TryFrom<AssetData> for Asset {
+        type Error = FormatError;
+
+        /// Absent chain defaults to the bitcoin mainnet, absent issued supply
+        /// defaults to the sum of the allocations, absent maximal supply to
+        /// the issued supply and the rest of the absent amounts to zero
+        fn try_from(data: AssetData) -> Result<Self, Self::Error> {
+            let precision = Some(data.precision);
+            let amount = |amount: &Option<AmountData>| {
+                amount
+                    .as_ref()
+                    .map(|amount| amount.resolve(precision))
+                    .transpose()
+            };
+            let allocations = |allocations: &[AllocationData]| {
+                allocations
+                    .iter()
+                    .map(|allocation| allocation.resolve(precision))
+                    .collect::<Result<Vec<_>, _>>()
+            };
+
+            let known_allocations = allocations(&data.allocations)?;
+            let issued_supply = match amount(&data.issued_supply)? {
+                Some(supply) => supply,
+                None => known_allocations
+                    .iter()
+                    .try_fold(Amount::ZERO, |sum, allocation| {
+                        sum.checked_add(Amount::from(allocation.value()))
+                    })?,
+            };
+            Ok(Asset {
+                nomination: Nomination {
+                    ticker: data.ticker,
+                    name: data.name,
+                    precision: data.precision,
+                },
+                nomination_history: vec![],
+                issued_supply,
+                max_supply: amount(&data.max_supply)?.unwrap_or(issued_supply),
+                known_inflation: amount(&data.known_inflation)?.unwrap_or_default(),
+                burned_supply: amount(&data.burned_supply)?.unwrap_or_default(),
+                replaced_supply: amount(&data.replaced_supply)?.unwrap_or_default(),
+                contract_id: data.contract_id,
+                chain: data.chain.unwrap_or(Chain::Mainnet),
+                issue_date: data.issue_date,
+                known_allocations,
+                inflation_rights: allocations(&data.inflation_rights)?,
+                state: ContractState::new(data.contract_id),
+            })
+        }
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod test {
+    use bitcoin::hashes::Hash;
+    use serde_json::json;
+
+    use super::*;
+    use crate::fungible::{validate_precision, IssueBuilder};
+    use crate::{seal, ParentPublicRights, SchemaId};
+
+    fn asset() -> Asset {
+        let seal = |no: u8| seal::Revealed::from(OutPoint::new(Txid::from_inner([no; 32]), 0));
+        let (genesis, _) = IssueBuilder::with(SchemaId::default(), true, false)
+            .ticker("TCKR".parse().unwrap())
+            .name("Test asset".parse().unwrap())
+            .precision(validate_precision(8).unwrap())
+            .timestamp(1_600_000_000)
+            .allocate(seal(1), Amount::from(150_000_000))
+            .allocate(seal(2), Amount::from(600))
+            .finish(Chain::Testnet3)
+            .unwrap();
+        let state = ContractState::with_genesis(&genesis);
+        Asset::with_genesis_state(&genesis, state).unwrap()
+    }
+
+    #[test]
+    fn test_wallet_json_roundtrip() {
+        let asset = asset();
+        let json = serde_json::to_string(&asset).unwrap();
+        let deserialized: Asset = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized.nomination(), asset.nomination());
+        assert_eq!(deserialized.issue_date(), Some(1_600_000_000));
+        assert_eq!(deserialized.chain(), &Chain::Testnet3);
+        assert_eq!(deserialized.issued_supply(), asset.issued_supply());
+        assert_eq!(deserialized.max_supply(), asset.max_supply());
+        assert_eq!(deserialized.known_allocations(), asset.known_allocations());
+        let state = ContractState::new(asset.contract_id());
+        assert_eq!(deserialized.state(), &state);
+        assert_eq!(serde_json::to_string(&deserialized).unwrap(), json);
+
+        let allocation = asset.known_allocations()[0];
+        let json = serde_json::to_string(&allocation).unwrap();
+        let deserialized = serde_json::from_str::<Allocation>(&json).unwrap();
+        assert_eq!(deserialized, allocation);
+    }
+
+    #[test]
+    fn test_wallet_json_format() {
+        let asset = asset();
+        let amount = |atomic: u64, decimal: &str| json!({ "atomic": atomic, "decimal": decimal });
+        let allocations = asset
+            .known_allocations()
+            .iter()
+            .map(|allocation| {
+                let decimal = asset.format_amount(Amount::from(allocation.value()));
+                json!({
+                    "outpoint": allocation.outpoint().to_string(),
+                    "amount": amount(allocation.value(), &decimal),
+                    "nodeId": allocation.node_id().to_string(),
+                    "index": allocation.index(),
+                    "blinding": allocation.revealed_amount().blinding
+                })
+            })
+            .collect::<Vec<_>>();
+        let expected = json!({
+            "contractId": asset.contract_id().to_string(),
+            "ticker": "TCKR",
+            "name": "Test asset",
+            "precision": 8,
+            "issueDate": 1_600_000_000,
+            "chain": Chain::Testnet3.to_string(),
+            "issuedSupply": amount(150_000_600, "1.50000600"),
+            "maxSupply": amount(150_000_600, "1.50000600"),
+            "knownInflation": amount(0, "0.00000000"),
+            "burnedSupply": amount(0, "0.00000000"),
+            "replacedSupply": amount(0, "0.00000000"),
+            "allocations": allocations,
+            "inflationRights": []
+        });
+        assert_eq!(serde_json::to_value(&asset).unwrap(), expected);
+
+        // Data produced by a wallet which does not provide the optional
+        // fields and gives amounts as decimal strings only
+        let allocation = asset.known_allocations()[0];
+        let blob = format!(
+            r#"{{
+                "contractId": "{}",
+                "ticker": "tckr",
+                "name": "Test asset",
+                "precision": 8,
+                "allocations": [{{
+                    "outpoint": "{}",
+                    "amount": {{ "decimal": "1.5" }},
+                    "nodeId": "{}",
+                    "index": {},
+                    "blinding": {}
+                }}]
+            }}"#,
+            asset.contract_id(),
+            allocation.outpoint(),
+            allocation.node_id(),
+            allocation.index(),
+            serde_json::to_string(&allocation.revealed_amount().blinding).unwrap()
+        );
+        let deserialized: Asset = serde_json::from_str(&blob).unwrap();
+        assert_eq!(deserialized.ticker(), &Nominal::Nonconforming(s!("tckr")));
+        assert_eq!(deserialized.issue_date(), None);
+        assert_eq!(deserialized.chain(), &Chain::Mainnet);
+        assert_eq!(deserialized.issued_supply(), Amount::from(150_000_000));
+        assert_eq!(deserialized.max_supply(), Amount::from(150_000_000));
+        assert_eq!(deserialized.burned_supply(), Amount::ZERO);
+        assert_eq!(deserialized.known_allocations(), &[allocation]);
+
+        let decimal = r#"{ "decimal": "1.5" }"#;
+        let mismatch = blob.replace(decimal, r#"{ "atomic": 1, "decimal": "1.5" }"#);
+        assert!(serde_json::from_str::<Asset>(&mismatch).is_err());
+        let missing = blob.replace(decimal, "{}");
+        assert!(serde_json::from_str::<Asset>(&missing).is_err());
+    }
+
+    #[test]
+    fn test_apply_consignment_order() {
+        let mut consignment = crate::verify::test::consignment(2);
+        let contract_id = consignment.contract_id();
+        let extension = |parent: NodeId| {
+            Extension::with(
+                1,
+                contract_id,
+                empty!(),
+                ParentPublicRights::from_inner(bmap! { parent => bset![1] }),
+                empty!(),
+                empty!(),
+            )
+        };
+        let transition_id = consignment
+            .anchored_bundles
+            .iter()
+            .flat_map(|(_, bundle)| bundle.known_transitions())
+            .map(Transition::node_id)
+            .last()
+            .unwrap();
+
+        // Extension depending on a transition is applied after it, even
+        // though extensions precede transitions in the consignment
+        let dependent = extension(transition_id);
+        consignment.state_extensions = vec![dependent.clone()].try_into().unwrap();
+        let mut state = ContractState::with_genesis(&consignment.genesis);
+        apply_consignment(&mut state, &consignment).unwrap();
+        assert_eq!(state.history.len(), 4);
+        assert_eq!(state.history.last(), Some(&dependent.node_id()));
+        assert_eq!(
+            state.nodes[&transition_id].children,
+            bset![dependent.node_id()]
+        );
+
+        // Extensions are checked for their parents as transitions are
+        let orphan = extension(NodeId::default());
+        consignment.state_extensions = vec![orphan.clone()].try_into().unwrap();
+        let mut state = ContractState::with_genesis(&consignment.genesis);
+        assert_eq!(
+            apply_consignment(&mut state, &consignment),
+            Err(StateApplyError::UnknownParents {
+                node_id: orphan.node_id(),
+                missing: bset![NodeId::default()]
+            })
+        );
+    }
+    #[test]
+    #[cfg(feature = "tracing")]
+    fn test_apply_consignment_span() {
+        let consignment = crate::verify::test::consignment(2);
+        let mut state = ContractState::with_genesis(&consignment.genesis);
+        let spans = crate::trace::test::record_spans(|| {
+            apply_consignment(&mut state, &consignment).unwrap();
+        });
+        assert_eq!(spans, vec![("apply_consignment", None)]);
+    }
+}
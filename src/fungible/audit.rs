@@ -0,0 +1,596 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Audit of the RGB20 asset supply over the whole contract history.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use amplify::Wrapper;
+use commit_verify::CommitConceal;
+use rgb_core::{secp256k1zkp, value};
+#[cfg(feature = "serde")]
+use serde_with::{As, DisplayFromStr};
+
+use super::amount::{Amount, AmountError};
+use super::pedersen::verify_balance;
+use super::schema::{self, FieldType, OwnedRightType, TransitionType};
+use crate::{
+    data, Assignment, AssignmentVec, AtomicValue, Contract, Extension, Genesis, Metadata, Node,
+    NodeId, Transition,
+};
+
+/// Errors auditing asset supply with [`audit_supply`]
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum AuditError {
+    /// contract does not use RGB20 schema
+    NotFungible,
+
+    /// node {node_id} does not provide {field} metadata
+    MissingField { node_id: NodeId, field: FieldType },
+
+    /// node {node_id} raises issued supply to {issued}, which exceeds the
+    /// maximal supply of {max_supply}
+    OverIssuance {
+        node_id: NodeId,
+        issued: Amount,
+        max_supply: Amount,
+    },
+
+    /// node {node_id} issues {issued}, exceeding the inflation allowance of
+    /// {allowance} spent by it
+    ExceedsAllowance {
+        node_id: NodeId,
+        issued: Amount,
+        allowance: Amount,
+    },
+
+    /// supply declared by node {0} metadata does not match asset amounts
+    /// it consumes or assigns
+    SupplyMismatch(NodeId),
+
+    /// commitments to the amounts consumed and assigned by node {0} are not
+    /// balanced
+    Unbalanced(NodeId),
+
+    /// invalid supply amount: {0}
+    #[from]
+    Amount(AmountError),
+}
+
+/// Results of the asset supply audit
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(crate = "serde_crate"))]
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct SupplyAudit {
+    /// Supply issued by the asset genesis
+    pub issued_supply: Amount,
+
+    /// Supply issued by the secondary issue transitions
+    pub inflated_supply: Amount,
+
+    /// Supply destroyed by the burn and burn-and-replace transitions
+    pub burned_supply: Amount,
+
+    /// Part of the burned supply re-issued by the burn-and-replace
+    /// transitions
+    pub replaced_supply: Amount,
+
+    /// Supply in circulation: issued and inflated supply without the burned
+    /// and not replaced one
+    pub known_supply: Amount,
+
+    /// Maximal asset supply: the supply issued by the genesis together with
+    /// the inflation allowance defined by the genesis
+    pub max_supply: Amount,
+
+    /// Nodes whose supply claims can't be checked since the amounts they
+    /// consume or assign are concealed
+    #[cfg_attr(feature = "serde", serde(with = "As::<Vec<DisplayFromStr>>"))]
+    pub unauditable: Vec<NodeId>,
+}
+
+impl SupplyAudit {
+    /// Detects whether supply claims of all contract nodes were checked
+    #[inline]
+    pub fn is_complete(&self) -> bool { self.unauditable.is_empty() }
+}
+
+/// Amount assigned to a single-use seal, which is known at least as a
+/// Pedersen commitment
+#[derive(Clone, PartialEq, Debug)]
+struct AuditValue {
+    commitment: value::Confidential,
+    value: Option<AtomicValue>,
+}
+
+/// Audits the asset supply over the whole contract history, checking it
+/// against the RGB20 rules. The consignment must be validated beforehand.
+///
+/// State transitions are audited in topological order: the secondary issues
+/// may not exceed allowance they spend and may not raise total issued supply
+/// above the maximal supply, while burns must consume exactly the declared
+/// amount. Transfers are checked by balancing amount commitments, so they are
+/// auditable even with concealed amounts. Issues and burns with concealed
+/// amounts are reported in [`SupplyAudit::unauditable`].
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(contract_id = %contract.contract_id(), bundles = contract.anchored_bundles.len())
+    )
+)]
+pub fn audit_supply(contract: &Contract) -> Result<SupplyAudit, AuditError> {
+    if !schema::is_fungible(&contract.schema) {
+        return Err(AuditError::NotFungible);
+    }
+    let transitions = contract
+        .anchored_bundles
+        .iter()
+        .flat_map(|(_, bundle)| bundle.known_transitions());
+    let extensions = contract.state_extensions.iter();
+    audit(&contract.genesis, extensions, transitions)
+}
+
+fn audit<'node>(
+    genesis: &'node Genesis,
+    extensions: impl IntoIterator<Item = &'node Extension>,
+    transitions: impl IntoIterator<Item = &'node Transition>,
+) -> Result<SupplyAudit, AuditError> {
+    let assets = u16::from(OwnedRightType::Assets);
+    let inflation = u16::from(OwnedRightType::Inflation);
+    let mut report = SupplyAudit::default();
+    let mut unauditable = BTreeSet::new();
+
+    let genesis_id = genesis.node_id();
+    let mut outputs = BTreeMap::new();
+    for ty in [assets, inflation] {
+        outputs.insert((genesis_id, ty), audit_values(genesis, ty));
+    }
+    // RGB20 extensions can't assign assets, so such assignments are not
+    // accounted by the audit
+    for extension in extensions {
+        let node_id = extension.node_id();
+        let assigned = audit_values(extension, assets);
+        if !assigned.is_empty() {
+            unauditable.insert(node_id);
+        }
+        outputs.insert((node_id, assets), assigned);
+    }
+    let transitions = transitions
+        .into_iter()
+        .map(|transition| (transition.node_id(), transition))
+        .collect::<BTreeMap<_, _>>();
+    for (node_id, transition) in &transitions {
+        for ty in [assets, inflation] {
+            outputs.insert((*node_id, ty), audit_values(*transition, ty));
+        }
+    }
+
+    // Genesis supply is committed to with the unit blinding factor, so it may
+    // be audited even if the allocated amounts are concealed
+    let issued = supply_field(genesis_id, genesis.metadata(), FieldType::IssuedSupply)?;
+    let genesis_assets = &outputs[&(genesis_id, assets)];
+    match sum(genesis_assets)? {
+        Some(allocated) if allocated != issued => {
+            return Err(AuditError::SupplyMismatch(genesis_id))
+        }
+        Some(_) => {}
+        None => {
+            let supply = value::Revealed {
+                value: issued.atomic_value(),
+                blinding: secp256k1zkp::key::ONE_KEY.into(),
+            };
+            if !verify_balance(&[supply.commit_conceal()], &commitments(genesis_assets)) {
+                unauditable.insert(genesis_id);
+            }
+        }
+    }
+    let allowance = match sum(&outputs[&(genesis_id, inflation)])? {
+        Some(allowance) => allowance,
+        None => {
+            unauditable.insert(genesis_id);
+            known_sum(&outputs[&(genesis_id, inflation)])?
+        }
+    };
+    report.issued_supply = issued;
+    report.max_supply = issued.checked_add(allowance)?;
+
+    for node_id in topological_order(&transitions) {
+        let transition = transitions[&node_id];
+        let ty = transition.transition_type();
+        let inputs = |right_type: u16| {
+            transition
+                .parent_outputs_by_type(right_type)
+                .into_iter()
+                .map(|parent| {
+                    outputs
+                        .get(&(parent.node_id, right_type))
+                        .and_then(|values| values.get(parent.output_no as usize))
+                        .cloned()
+                })
+                .collect::<Option<Vec<_>>>()
+        };
+        let spent = inputs(assets);
+        if spent.is_none() {
+            unauditable.insert(node_id);
+        }
+        let assigned = &outputs[&(node_id, assets)];
+
+        if ty == u16::from(TransitionType::Issue) {
+            let issued = supply_field(node_id, transition.metadata(), FieldType::IssuedSupply)?;
+            report.inflated_supply = report.inflated_supply.checked_add(issued)?;
+            let total = report.issued_supply.checked_add(report.inflated_supply)?;
+            if total > report.max_supply {
+                return Err(AuditError::OverIssuance {
+                    node_id,
+                    issued: total,
+                    max_supply: report.max_supply,
+                });
+            }
+            if matches!(&spent, Some(spent) if !spent.is_empty()) {
+                return Err(AuditError::SupplyMismatch(node_id));
+            }
+            match sum(assigned)? {
+                Some(allocated) if allocated != issued => {
+                    return Err(AuditError::SupplyMismatch(node_id))
+                }
+                Some(_) => {}
+                None => {
+                    unauditable.insert(node_id);
+                }
+            }
+            let allowance = match inputs(inflation) {
+                Some(spent) => sum(&spent)?,
+                None => None,
+            };
+            match (allowance, sum(&outputs[&(node_id, inflation)])?) {
+                (Some(allowance), Some(residual)) => {
+                    if issued.checked_add(residual)? > allowance {
+                        return Err(AuditError::ExceedsAllowance {
+                            node_id,
+                            issued,
+                            allowance,
+                        });
+                    }
+                }
+                _ => {
+                    unauditable.insert(node_id);
+                }
+            }
+        } else if ty == u16::from(TransitionType::Burn)
+            || ty == u16::from(TransitionType::BurnAndReplace)
+        {
+            let burned = supply_field(node_id, transition.metadata(), FieldType::BurnedSupply)?;
+            report.burned_supply = report.burned_supply.checked_add(burned)?;
+            let consumed = match &spent {
+                Some(spent) => sum(spent)?,
+                None => None,
+            };
+            match consumed {
+                Some(consumed) if consumed != burned => {
+                    return Err(AuditError::SupplyMismatch(node_id))
+                }
+                Some(_) => {}
+                None => {
+                    unauditable.insert(node_id);
+                }
+            }
+            if ty == u16::from(TransitionType::Burn) && !assigned.is_empty() {
+                return Err(AuditError::SupplyMismatch(node_id));
+            }
+            // Replacement is balanced against the burned allocations
+            if !assigned.is_empty() {
+                if let Some(spent) = &spent {
+                    if !verify_balance(&commitments(spent), &commitments(assigned)) {
+                        return Err(AuditError::Unbalanced(node_id));
+                    }
+                }
+                report.replaced_supply = report.replaced_supply.checked_add(burned)?;
+            }
+        } else if let Some(spent) = &spent {
+            if (!spent.is_empty() || !assigned.is_empty())
+                && !verify_balance(&commitments(spent), &commitments(assigned))
+            {
+                return Err(AuditError::Unbalanced(node_id));
+            }
+        }
+    }
+
+    report.known_supply = report
+        .issued_supply
+        .checked_add(report.inflated_supply)?
+        .checked_add(report.replaced_supply)?
+        .checked_sub(report.burned_supply)?;
+    report.unauditable = unauditable.into_iter().collect();
+    Ok(report)
+}
+
+/// Orders state transitions such that each transition follows all its
+/// parent transitions; ties are resolved by the node ids
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(transitions = transitions.len()))
+)]
+fn topological_order(transitions: &BTreeMap<NodeId, &Transition>) -> Vec<NodeId> {
+    let mut pending = BTreeMap::<NodeId, usize>::new();
+    let mut children = BTreeMap::<NodeId, BTreeSet<NodeId>>::new();
+    for (node_id, transition) in transitions {
+        let parents = transition
+            .parent_outputs()
+            .into_iter()
+            .map(|parent| parent.node_id)
+            .filter(|parent| transitions.contains_key(parent))
+            .collect::<BTreeSet<_>>();
+        pending.insert(*node_id, parents.len());
+        for parent in parents {
+            children.entry(parent).or_default().insert(*node_id);
+        }
+    }
+
+    let mut ready = pending
+        .iter()
+        .filter(|(_, count)| **count == 0)
+        .map(|(node_id, _)| *node_id)
+        .collect::<BTreeSet<_>>();
+    let mut order = Vec::with_capacity(transitions.len());
+    while let Some(node_id) = ready.iter().next().copied() {
+        ready.remove(&node_id);
+        order.push(node_id);
+        for child in children.get(&node_id).into_iter().flatten() {
+            let count = pending.get_mut(child).expect("child is always pending");
+            *count -= 1;
+            if *count == 0 {
+                ready.insert(*child);
+            }
+        }
+    }
+    if order.len() < transitions.len() {
+        warn_event!(
+            skipped = transitions.len() - order.len(),
+            "transitions with cyclic dependencies are left out of the order"
+        );
+    }
+    order
+}
+
+/// Extracts amounts of the fungible assignments of the `ty` type defined by
+/// the node, in the order of their outputs
+fn audit_values(node: &impl Node, ty: u16) -> Vec<AuditValue> {
+    let assignments = match node.owned_rights_by_type(ty) {
+        Some(AssignmentVec::Fungible(assignments)) => assignments,
+        _ => return vec![],
+    };
+    assignments
+        .iter()
+        .map(|assignment| match assignment {
+            Assignment::Revealed { assigned_state, .. }
+            | Assignment::ConfidentialSeal { assigned_state, .. } => AuditValue {
+                commitment: assigned_state.commit_conceal(),
+                value: Some(assigned_state.value),
+            },
+            Assignment::Confidential { assigned_state, .. }
+            | Assignment::ConfidentialAmount { assigned_state, .. } => AuditValue {
+                commitment: assigned_state.clone(),
+                value: None,
+            },
+        })
+        .collect()
+}
+
+/// Sums the amounts, returning `None` if any of them is concealed
+fn sum(values: &[AuditValue]) -> Result<Option<Amount>, AmountError> {
+    if values.iter().any(|audit| audit.value.is_none()) {
+        return Ok(None);
+    }
+    known_sum(values).map(Some)
+}
+
+/// Sums the revealed amounts, ignoring the concealed ones
+fn known_sum(values: &[AuditValue]) -> Result<Amount, AmountError> {
+    values
+        .iter()
+        .filter_map(|audit| audit.value)
+        .try_fold(Amount::ZERO, |sum, value| {
+            sum.checked_add(Amount::from(value))
+        })
+}
+
+fn commitments(values: &[AuditValue]) -> Vec<value::Confidential> {
+    values
+        .iter()
+        .map(|audit| audit.commitment.clone())
+        .collect()
+}
+
+fn supply_field(
+    node_id: NodeId,
+    metadata: &Metadata,
+    field: FieldType,
+) -> Result<Amount, AuditError> {
+    match metadata
+        .as_inner()
+        .get(&u16::from(field))
+        .and_then(|values| values.first())
+    {
+        Some(data::Revealed::U64(value)) => Ok(Amount::from(*value)),
+        _ => Err(AuditError::MissingField { node_id, field }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoin::hashes::Hash;
+    use bitcoin::{OutPoint, Txid};
+    use lnpbp::chain::Chain;
+    use rgb_core::SealValueMap;
+
+    use super::*;
+    use crate::fungible::allocation::{Allocation, AllocationMap};
+    use crate::fungible::{validate_precision, Asset, BlindingFactors, IssueBuilder};
+    use crate::{seal, ContractState, OwnedRights, ParentOwnedRights, SchemaId};
+
+    fn seal(no: u8) -> seal::Revealed {
+        seal::Revealed::from(OutPoint::new(Txid::from_inner([no; 32]), 0))
+    }
+
+    fn transition(
+        ty: TransitionType,
+        metadata: Option<(FieldType, u64)>,
+        owned_rights: BTreeMap<u16, AssignmentVec>,
+        parents: &[(&Allocation, OwnedRightType)],
+    ) -> Transition {
+        let metadata = metadata
+            .map(|(field, value)| bmap! { u16::from(field) => vec![data::Revealed::U64(value)] })
+            .unwrap_or_default();
+        let mut parent_owned_rights = BTreeMap::<_, BTreeMap<_, Vec<u16>>>::new();
+        for (allocation, ty) in parents {
+            parent_owned_rights
+                .entry(*allocation.node_id())
+                .or_default()
+                .entry(u16::from(*ty))
+                .or_default()
+                .push(*allocation.index());
+        }
+        Transition::with(
+            u16::from(ty),
+            Metadata::from_inner(metadata),
+            empty!(),
+            OwnedRights::from_inner(owned_rights),
+            empty!(),
+            ParentOwnedRights::from_inner(parent_owned_rights),
+        )
+    }
+
+    fn assets(allocations: SealValueMap) -> BTreeMap<u16, AssignmentVec> {
+        bmap! { u16::from(OwnedRightType::Assets) => allocations.into_assignments() }
+    }
+
+    fn genesis() -> (Genesis, Asset) {
+        let (genesis, _) = IssueBuilder::with(SchemaId::default(), true, true)
+            .ticker("TCKR".parse().unwrap())
+            .name("Test asset".parse().unwrap())
+            .precision(validate_precision(8).unwrap())
+            .allocate(seal(1), Amount::from(600))
+            .allocate(seal(2), Amount::from(400))
+            .inflation_allowance(seal(3), Amount::from(500))
+            .finish(Chain::Testnet3)
+            .unwrap();
+        let state = ContractState::with_genesis(&genesis);
+        let asset = Asset::with_genesis_state(&genesis, state).unwrap();
+        (genesis, asset)
+    }
+
+    fn allocation(asset: &Asset, value: AtomicValue) -> Allocation {
+        *asset
+            .known_allocations()
+            .iter()
+            .find(|allocation| allocation.value() == value)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_supply_audit() {
+        let (genesis, asset) = genesis();
+        let report = audit(&genesis, vec![], vec![]).unwrap();
+        assert_eq!(report.issued_supply, Amount::from(1000));
+        assert_eq!(report.max_supply, Amount::from(1500));
+        assert_eq!(report.known_supply, Amount::from(1000));
+        assert!(report.is_complete());
+
+        let spent = allocation(&asset, 600);
+        let mut factors = BlindingFactors::new(&[*spent.revealed_amount()]);
+        let (_, first) = factors.add_output(Amount::from(100));
+        let second = factors.close(Amount::from(500)).unwrap();
+        let assets_type = u16::from(OwnedRightType::Assets);
+        let assignments = vec![
+            Assignment::Revealed {
+                seal_definition: seal(4),
+                assigned_state: first,
+            },
+            Assignment::Revealed {
+                seal_definition: seal(5),
+                assigned_state: second,
+            },
+        ];
+        let owned_rights = bmap! { assets_type => AssignmentVec::Fungible(assignments) };
+        let parents = [(&spent, OwnedRightType::Assets)];
+        let transfer = transition(TransitionType::Transfer, None, owned_rights, &parents);
+
+        let right = asset.inflation_rights()[0];
+        let mut owned_rights = assets(bmap! { seal(6) => 200 });
+        let inflation_type = u16::from(OwnedRightType::Inflation);
+        let residual: SealValueMap = bmap! { seal(7) => 300 };
+        owned_rights.insert(inflation_type, residual.into_assignments());
+        let issued = Some((FieldType::IssuedSupply, 200));
+        let parents = [(&right, OwnedRightType::Inflation)];
+        let issue = transition(TransitionType::Issue, issued, owned_rights, &parents);
+
+        let burned = allocation(&asset, 400);
+        let metadata = Some((FieldType::BurnedSupply, 400));
+        let parents = [(&burned, OwnedRightType::Assets)];
+        let burn = transition(TransitionType::Burn, metadata, empty!(), &parents);
+
+        let report = audit(&genesis, vec![], vec![&transfer, &issue, &burn]).unwrap();
+        assert_eq!(report.inflated_supply, Amount::from(200));
+        assert_eq!(report.burned_supply, Amount::from(400));
+        assert_eq!(report.replaced_supply, Amount::ZERO);
+        assert_eq!(report.known_supply, Amount::from(800));
+        assert!(report.is_complete());
+
+        // Outputs of the transfer with random blinding factors are not
+        // balanced against the spent allocation
+        let outputs = assets(bmap! { seal(4) => 600 });
+        let parents = [(&spent, OwnedRightType::Assets)];
+        let unbalanced = transition(TransitionType::Transfer, None, outputs, &parents);
+        assert_eq!(
+            audit(&genesis, vec![], vec![&unbalanced]),
+            Err(AuditError::Unbalanced(unbalanced.node_id()))
+        );
+    }
+
+    #[test]
+    fn test_supply_violations() {
+        let (genesis, asset) = genesis();
+        let right = asset.inflation_rights()[0];
+        let issued = Some((FieldType::IssuedSupply, 600));
+        let parents = [(&right, OwnedRightType::Inflation)];
+        let outputs = assets(bmap! { seal(6) => 600 });
+        let issue = transition(TransitionType::Issue, issued, outputs, &parents);
+        assert_eq!(
+            audit(&genesis, vec![], vec![&issue]),
+            Err(AuditError::OverIssuance {
+                node_id: issue.node_id(),
+                issued: Amount::from(1600),
+                max_supply: Amount::from(1500)
+            })
+        );
+
+        let burned = allocation(&asset, 400);
+        let metadata = Some((FieldType::BurnedSupply, 300));
+        let parents = [(&burned, OwnedRightType::Assets)];
+        let burn = transition(TransitionType::Burn, metadata, empty!(), &parents);
+        assert_eq!(
+            audit(&genesis, vec![], vec![&burn]),
+            Err(AuditError::SupplyMismatch(burn.node_id()))
+        );
+
+        let parents = [(&burned, OwnedRightType::Assets)];
+        let burn = transition(TransitionType::Burn, None, empty!(), &parents);
+        assert_eq!(
+            audit(&genesis, vec![], vec![&burn]),
+            Err(AuditError::MissingField {
+                node_id: burn.node_id(),
+                field: FieldType::BurnedSupply
+            })
+        );
+    }
+}
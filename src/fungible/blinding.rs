@@ -0,0 +1,180 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Generation of blinded seals for receiving assets and tracking of their
+//! secrets.
+
+use std::collections::{btree_map, BTreeMap};
+
+use bitcoin::secp256k1::rand::RngCore;
+use bitcoin::OutPoint;
+use commit_verify::CommitConceal;
+#[cfg(feature = "serde")]
+use serde_with::{As, Same};
+
+use crate::consignments::ConsignmentType;
+use crate::{seal, InmemConsignment};
+
+/// Error indicating that two different revealed seals have the same
+/// confidential commitment
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Display, Error)]
+#[display("different revealed seals have the same commitment {0}")]
+pub struct SealCollision(pub seal::Confidential);
+
+/// Helpers for generating blinded seals which are handed out to the payers
+pub struct BlindedSeal;
+
+impl BlindedSeal {
+    /// Blinds the `outpoint` with a cryptographically random blinding factor,
+    /// returning both the blinded seal and its secret
+    pub fn blind(outpoint: OutPoint) -> (seal::Confidential, seal::Revealed) {
+        let revealed = seal::Revealed::from(outpoint);
+        (revealed.commit_conceal(), revealed)
+    }
+
+    /// Blinds the `outpoint` with a blinding factor generated by the provided
+    /// `rng`, for the platforms where OS entropy is not available
+    pub fn blind_with_rng(
+        outpoint: OutPoint,
+        rng: &mut impl RngCore,
+    ) -> (seal::Confidential, seal::Revealed) {
+        BlindedSeal::blind_with(outpoint, rng.next_u64())
+    }
+
+    /// Blinds the `outpoint` with the provided blinding factor, which may be
+    /// deterministically derived from the wallet seed
+    pub fn blind_with(outpoint: OutPoint, blinding: u64) -> (seal::Confidential, seal::Revealed) {
+        let mut revealed = seal::Revealed::from(outpoint);
+        revealed.blinding = blinding;
+        (revealed.commit_conceal(), revealed)
+    }
+}
+
+/// Secrets of the blinded seals, indexed by the seal commitments
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(crate = "serde_crate"))]
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+#[derive(StrictEncode, StrictDecode)]
+pub struct SealSecrets(
+    #[cfg_attr(feature = "serde", serde(with = "As::<Vec<(Same, Same)>>"))]
+    BTreeMap<seal::Confidential, seal::Revealed>,
+);
+
+impl SealSecrets {
+    /// Constructs empty set of the seal secrets
+    #[inline]
+    pub fn new() -> SealSecrets { SealSecrets::default() }
+
+    /// Adds revealed seal to the secrets, returning its commitment. Adding the
+    /// already known seal does nothing.
+    pub fn insert(
+        &mut self,
+        revealed: seal::Revealed,
+    ) -> Result<seal::Confidential, SealCollision> {
+        let confidential = revealed.commit_conceal();
+        match self.0.entry(confidential) {
+            btree_map::Entry::Vacant(entry) => {
+                entry.insert(revealed);
+            }
+            btree_map::Entry::Occupied(entry) if *entry.get() != revealed => {
+                return Err(SealCollision(confidential))
+            }
+            btree_map::Entry::Occupied(_) => {}
+        }
+        Ok(confidential)
+    }
+
+    /// Blinds the `outpoint` with a random blinding factor and keeps the seal
+    /// secret, returning the blinded seal
+    pub fn blind(&mut self, outpoint: OutPoint) -> Result<seal::Confidential, SealCollision> {
+        let (_, revealed) = BlindedSeal::blind(outpoint);
+        self.insert(revealed)
+    }
+
+    /// Returns secret of the blinded seal, if known
+    #[inline]
+    pub fn get(&self, confidential: &seal::Confidential) -> Option<&seal::Revealed> {
+        self.0.get(confidential)
+    }
+
+    /// Returns number of the known seal secrets
+    #[inline]
+    pub fn len(&self) -> usize { self.0.len() }
+
+    /// Detects whether there are no known seal secrets
+    #[inline]
+    pub fn is_empty(&self) -> bool { self.0.is_empty() }
+
+    /// Iterates over all known seal secrets
+    #[inline]
+    pub fn revealed(&self) -> btree_map::Values<seal::Confidential, seal::Revealed> {
+        self.0.values()
+    }
+
+    /// Reveals seals known to this set in the consignment, returning number
+    /// of the revealed seals. See [`InmemConsignment::reveal_seals`].
+    #[inline]
+    pub fn reveal_in<T>(&self, consignment: &mut InmemConsignment<T>) -> usize
+    where T: ConsignmentType {
+        consignment.reveal_seals(self.revealed())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoin::hashes::Hash;
+    use bitcoin::secp256k1::rand::rngs::StdRng;
+    use bitcoin::secp256k1::rand::SeedableRng;
+    use bitcoin::Txid;
+    use strict_encoding::{StrictDecode, StrictEncode};
+
+    use super::*;
+
+    #[test]
+    fn test_blind_with() {
+        let outpoint = OutPoint::new(Txid::from_inner([1u8; 32]), 3);
+        let (confidential, revealed) = BlindedSeal::blind_with(outpoint, 42);
+        assert_eq!(revealed.blinding, 42);
+        assert_eq!(revealed.commit_conceal(), confidential);
+        assert_eq!(BlindedSeal::blind_with(outpoint, 42).0, confidential);
+        assert_ne!(BlindedSeal::blind_with(outpoint, 43).0, confidential);
+
+        let (confidential, revealed) = BlindedSeal::blind(outpoint);
+        assert_eq!(revealed.commit_conceal(), confidential);
+
+        let mut rng = StdRng::seed_from_u64(7);
+        let (confidential, revealed) = BlindedSeal::blind_with_rng(outpoint, &mut rng);
+        assert_eq!(revealed.commit_conceal(), confidential);
+        let mut rng = StdRng::seed_from_u64(7);
+        assert_eq!(BlindedSeal::blind_with_rng(outpoint, &mut rng).0, confidential);
+    }
+
+    #[test]
+    fn test_seal_secrets() {
+        let outpoint = OutPoint::new(Txid::from_inner([1u8; 32]), 3);
+        let mut secrets = SealSecrets::new();
+        let confidential = secrets.blind(outpoint).unwrap();
+        let (_, revealed) = BlindedSeal::blind_with(outpoint, 42);
+        assert_eq!(secrets.insert(revealed), Ok(revealed.commit_conceal()));
+        assert_eq!(secrets.insert(revealed), Ok(revealed.commit_conceal()));
+        assert_eq!(secrets.len(), 2);
+        assert_eq!(secrets.get(&confidential).unwrap().vout, 3);
+
+        // Commitment collision may not happen with real seals
+        let mut collision = revealed;
+        collision.blinding = 43;
+        secrets.0.insert(collision.commit_conceal(), revealed);
+        let err = SealCollision(collision.commit_conceal());
+        assert_eq!(secrets.insert(collision), Err(err));
+
+        let data = secrets.strict_serialize().unwrap();
+        assert_eq!(SealSecrets::strict_deserialize(data).unwrap(), secrets);
+    }
+}
@@ -0,0 +1,562 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Construction of RGB20 asset transfer state transitions.
+
+use std::collections::BTreeMap;
+
+use amplify::Wrapper;
+use bitcoin::secp256k1::rand::{thread_rng, RngCore};
+use rgb_core::{EndpointValueMap, SealValueMap};
+
+use super::allocation::Allocation;
+use super::amount::Amount;
+use super::pedersen::{BlindingError, BlindingFactors};
+use super::schema::{self, OwnedRightType, TransitionType};
+use crate::{
+    seal, Assignment, AssignmentVec, NodeOutpoint, OwnedRights, ParentOwnedRights, Schema,
+    SealEndpoint, Transition,
+};
+
+/// Errors constructing asset transfer with [`TransitionBuilder`]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum BuilderError {
+    /// schema does not define RGB20 asset transfers
+    NotFungible,
+
+    /// transfer must spend at least one allocation
+    NoInputs,
+
+    /// transfer must assign assets to at least one beneficiary
+    NoOutputs,
+
+    /// allocation {0} is already spent by the transfer
+    DuplicateInput(NodeOutpoint),
+
+    /// beneficiary seal is already present in the transfer
+    DuplicateOutput,
+
+    /// amount assigned to the beneficiary must be non-zero
+    ZeroAmount,
+
+    /// transfer outputs require {required}, while the inputs provide only
+    /// {available}
+    InsufficientInputs { required: Amount, available: Amount },
+
+    /// inputs exceed outputs by {0}, but no change seal is provided
+    NoChangeSeal(Amount),
+
+    /// change {amount} is below the dust threshold {threshold}; different
+    /// inputs must be selected
+    DustChange { amount: Amount, threshold: Amount },
+
+    /// output receiving the dust change is not present in the transfer
+    UnknownDustOutput,
+
+    /// total amount of the transfer inputs or outputs exceeds 2^64
+    Overflow,
+
+    /// unable to balance amount commitments: {0}
+    #[from]
+    Blinding(BlindingError),
+}
+
+/// Handling of the transfer change below the dust threshold
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum DustPolicy {
+    /// Dust change is rejected with [`BuilderError::DustChange`], requiring
+    /// the caller to select different inputs
+    Reject,
+
+    /// Dust change is added to the beneficiary output with the largest
+    /// amount, overpaying the beneficiary
+    Overpay,
+
+    /// Dust change is added to the given transfer output, which must belong
+    /// to the wallet and already receive some change
+    Merge(SealEndpoint),
+}
+
+/// Options of the change handling by [`TransitionBuilder`]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct TransferOptions {
+    /// Change amounts below this threshold are handled according to the
+    /// dust policy instead of being assigned to the change seal
+    pub dust_threshold: Amount,
+
+    /// Handling of the dust change
+    pub dust_policy: DustPolicy,
+}
+
+impl Default for TransferOptions {
+    fn default() -> Self {
+        TransferOptions {
+            dust_threshold: Amount::ZERO,
+            dust_policy: DustPolicy::Reject,
+        }
+    }
+}
+
+/// Change of the transfer, as it is assigned by the builder
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Change {
+    /// Transfer outputs spend the inputs completely
+    None,
+
+    /// Change is assigned to the change seal
+    Seal {
+        seal: seal::Revealed,
+        amount: Amount,
+    },
+
+    /// Dust change is added to the transfer output
+    Dust {
+        output: SealEndpoint,
+        amount: Amount,
+    },
+}
+
+/// Builder for the RGB20 asset transfer state transitions.
+///
+/// The builder spends provided allocations, assigns requested amounts to the
+/// beneficiaries and assigns the rest of the spent assets back to the change
+/// seal. Blinding factors of the output amounts are generated such that the
+/// Pedersen commitments of the outputs balance the inputs. Change below the
+/// dust threshold is handled according to the [`TransferOptions`].
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct TransitionBuilder {
+    inputs: BTreeMap<NodeOutpoint, Allocation>,
+    outputs: EndpointValueMap,
+    change: Option<seal::Revealed>,
+    options: TransferOptions,
+}
+
+impl TransitionBuilder {
+    /// Constructs builder for the transfer without inputs and outputs
+    #[inline]
+    pub fn new() -> TransitionBuilder { TransitionBuilder::default() }
+
+    /// Constructs builder for the transfer without inputs and outputs, which
+    /// handles change according to the `options`
+    #[inline]
+    pub fn with_options(options: TransferOptions) -> TransitionBuilder {
+        TransitionBuilder {
+            options,
+            ..TransitionBuilder::default()
+        }
+    }
+
+    /// Adds allocation to be spent by the transfer
+    pub fn add_input(&mut self, allocation: Allocation) -> Result<(), BuilderError> {
+        let outpoint = allocation.node_output();
+        if self.inputs.insert(outpoint, allocation).is_some() {
+            return Err(BuilderError::DuplicateInput(outpoint));
+        }
+        Ok(())
+    }
+
+    /// Adds beneficiary receiving `amount` of assets to the transfer
+    pub fn add_output(&mut self, seal: SealEndpoint, amount: Amount) -> Result<(), BuilderError> {
+        if amount.is_zero() {
+            return Err(BuilderError::ZeroAmount);
+        }
+        if self.outputs.insert(seal, amount.atomic_value()).is_some() {
+            return Err(BuilderError::DuplicateOutput);
+        }
+        Ok(())
+    }
+
+    /// Sets seal receiving the change, replacing the previously set one
+    #[inline]
+    pub fn add_change(&mut self, seal: seal::Revealed) { self.change = Some(seal) }
+
+    /// Returns total amount of the spent allocations
+    pub fn input_amount(&self) -> Result<Amount, BuilderError> {
+        self.inputs
+            .values()
+            .try_fold(Amount::ZERO, |sum, allocation| {
+                sum.checked_add(Amount::from(allocation.value()))
+            })
+            .map_err(|_| BuilderError::Overflow)
+    }
+
+    /// Returns total amount assigned to the beneficiaries
+    pub fn output_amount(&self) -> Result<Amount, BuilderError> {
+        self.outputs
+            .values()
+            .try_fold(Amount::ZERO, |sum, amount| {
+                sum.checked_add(Amount::from(*amount))
+            })
+            .map_err(|_| BuilderError::Overflow)
+    }
+
+    /// Returns amount which is assigned to the change seal
+    pub fn change_amount(&self) -> Result<Amount, BuilderError> {
+        let available = self.input_amount()?;
+        let required = self.output_amount()?;
+        available
+            .checked_sub(required)
+            .map_err(|_| BuilderError::InsufficientInputs {
+                required,
+                available,
+            })
+    }
+
+    /// Returns change of the transfer as it will be assigned by
+    /// [`TransitionBuilder::finish`], accounting for the dust policy
+    pub fn estimate_change(&self) -> Result<Change, BuilderError> {
+        let amount = self.change_amount()?;
+        if amount.is_zero() {
+            return Ok(Change::None);
+        }
+        let threshold = self.options.dust_threshold;
+        if amount >= threshold {
+            return match self.change {
+                Some(seal) => Ok(Change::Seal { seal, amount }),
+                None => Err(BuilderError::NoChangeSeal(amount)),
+            };
+        }
+        let output = match self.options.dust_policy {
+            DustPolicy::Reject => return Err(BuilderError::DustChange { amount, threshold }),
+            DustPolicy::Overpay => self
+                .outputs
+                .iter()
+                .max_by_key(|(_, value)| **value)
+                .map(|(output, _)| *output)
+                .ok_or(BuilderError::NoOutputs)?,
+            DustPolicy::Merge(output) if self.outputs.contains_key(&output) => output,
+            DustPolicy::Merge(_) => return Err(BuilderError::UnknownDustOutput),
+        };
+        Ok(Change::Dust { output, amount })
+    }
+
+    /// Constructs the transfer state transition, checking that the `schema`
+    /// defines RGB20 asset transfers
+    #[inline]
+    pub fn finish(&self, schema: &Schema) -> Result<Transition, BuilderError> {
+        self.finish_with_rng(schema, &mut thread_rng())
+    }
+
+    /// Constructs the transfer state transition like [`Self::finish`], using
+    /// the `rng` for generating the output blinding factors
+    pub fn finish_with_rng(
+        &self,
+        schema: &Schema,
+        rng: &mut impl RngCore,
+    ) -> Result<Transition, BuilderError> {
+        let assets = u16::from(OwnedRightType::Assets);
+        let transfer = schema
+            .transitions
+            .get(&u16::from(TransitionType::Transfer))
+            .ok_or(BuilderError::NotFungible)?;
+        if !schema::is_fungible(schema)
+            || !transfer.closes.contains_key(&assets)
+            || !transfer.owned_rights.contains_key(&assets)
+        {
+            return Err(BuilderError::NotFungible);
+        }
+        self.build(rng)
+    }
+
+    fn build(&self, rng: &mut impl RngCore) -> Result<Transition, BuilderError> {
+        if self.inputs.is_empty() {
+            return Err(BuilderError::NoInputs);
+        }
+        if self.outputs.is_empty() {
+            return Err(BuilderError::NoOutputs);
+        }
+
+        let mut ours = SealValueMap::new();
+        let mut outputs = self.outputs.clone();
+        match self.estimate_change()? {
+            Change::None => {}
+            Change::Seal { seal, amount } => {
+                ours.insert(seal, amount.atomic_value());
+            }
+            Change::Dust { output, amount } => {
+                let value = outputs
+                    .get_mut(&output)
+                    .expect("dust output is checked to be present");
+                *value = Amount::from(*value)
+                    .checked_add(amount)
+                    .map_err(|_| BuilderError::Overflow)?
+                    .atomic_value();
+            }
+        }
+
+        let assets = u16::from(OwnedRightType::Assets);
+        let inputs = self
+            .inputs
+            .values()
+            .map(|allocation| *allocation.revealed_amount())
+            .collect::<Vec<_>>();
+        // All outputs but the last one receive random blinding factors, while
+        // the last one balances the commitments
+        let values = ours
+            .values()
+            .chain(outputs.values())
+            .map(|value| Amount::from(*value))
+            .collect::<Vec<_>>();
+        let (last, rest) = values.split_last().expect("transfer always has outputs");
+        let mut factors = BlindingFactors::new(&inputs);
+        let mut revealed = rest
+            .iter()
+            .map(|amount| factors.add_output_with_rng(*amount, rng).1)
+            .collect::<Vec<_>>();
+        revealed.push(factors.close(*last)?);
+
+        let mut revealed = revealed.into_iter();
+        let mut assignments = ours
+            .into_iter()
+            .zip(&mut revealed)
+            .map(|((seal, _), assigned_state)| Assignment::Revealed {
+                seal_definition: seal,
+                assigned_state,
+            })
+            .collect::<Vec<_>>();
+        for ((endpoint, _), assigned_state) in outputs.iter().zip(revealed) {
+            assignments.push(match *endpoint {
+                SealEndpoint::ConcealedUtxo(confidential) => Assignment::ConfidentialSeal {
+                    seal_definition: confidential,
+                    assigned_state,
+                },
+                SealEndpoint::WitnessVout {
+                    method,
+                    vout,
+                    blinding,
+                } => Assignment::Revealed {
+                    seal_definition: seal::Revealed {
+                        method,
+                        txid: None,
+                        vout,
+                        blinding,
+                    },
+                    assigned_state,
+                },
+            });
+        }
+        let assignments = AssignmentVec::Fungible(assignments);
+
+        let mut parent_owned_rights = BTreeMap::<_, BTreeMap<_, Vec<u16>>>::new();
+        for outpoint in self.inputs.keys() {
+            parent_owned_rights
+                .entry(outpoint.node_id)
+                .or_default()
+                .entry(assets)
+                .or_default()
+                .push(outpoint.output_no);
+        }
+
+        Ok(Transition::with(
+            u16::from(TransitionType::Transfer),
+            empty!(),
+            empty!(),
+            OwnedRights::from_inner(bmap! { assets => assignments }),
+            empty!(),
+            ParentOwnedRights::from_inner(parent_owned_rights),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+
+    use bitcoin::hashes::{sha256t, Hash};
+    use bitcoin::{OutPoint, Txid};
+    use commit_verify::CommitConceal;
+    use rgb_core::value;
+
+    use super::*;
+    use crate::fungible::verify_balance;
+    use crate::{AtomicValue, Node, NodeId, TransitionBundle};
+
+    fn allocation(no: u8, value: AtomicValue) -> Allocation {
+        let node_id = NodeId::from_inner(sha256t::Hash::from_inner([no; 32]));
+        let outpoint = OutPoint::new(Txid::from_inner([no; 32]), 0);
+        let revealed = value::Revealed::with_amount(value, &mut thread_rng());
+        Allocation::with(node_id, 0, outpoint, revealed)
+    }
+
+    #[test]
+    fn test_transfer() {
+        let mut rng = thread_rng();
+        let beneficiary = seal::Revealed::from(OutPoint::new(Txid::from_inner([8u8; 32]), 1));
+        let beneficiary = SealEndpoint::ConcealedUtxo(beneficiary.commit_conceal());
+        let change = seal::Revealed::from(OutPoint::new(Txid::from_inner([9u8; 32]), 0));
+
+        let mut builder = TransitionBuilder::new();
+        assert_eq!(builder.build(&mut rng), Err(BuilderError::NoInputs));
+        builder.add_input(allocation(1, 600)).unwrap();
+        builder.add_input(allocation(2, 400)).unwrap();
+        let duplicate = allocation(2, 400);
+        let err = BuilderError::DuplicateInput(duplicate.node_output());
+        assert_eq!(builder.add_input(duplicate), Err(err));
+        assert_eq!(builder.build(&mut rng), Err(BuilderError::NoOutputs));
+
+        assert_eq!(
+            builder.add_output(beneficiary, Amount::ZERO),
+            Err(BuilderError::ZeroAmount)
+        );
+        builder.add_output(beneficiary, Amount::from(700)).unwrap();
+        let change_amount = Amount::from(300);
+        assert_eq!(
+            builder.build(&mut rng),
+            Err(BuilderError::NoChangeSeal(change_amount))
+        );
+        builder.add_change(change);
+        assert_eq!(builder.change_amount(), Ok(change_amount));
+
+        let transition = builder.build(&mut rng).unwrap();
+        let transfer = u16::from(TransitionType::Transfer);
+        assert_eq!(transition.transition_type(), transfer);
+        let parents = transition
+            .parent_outputs()
+            .into_iter()
+            .collect::<BTreeSet<_>>();
+        let inputs = builder.inputs.keys().copied().collect::<BTreeSet<_>>();
+        assert_eq!(parents, inputs);
+
+        let mut assigned = BTreeMap::new();
+        let mut outputs = vec![];
+        for (ty, assignments) in transition.owned_rights().iter() {
+            assert_eq!(*ty, u16::from(OwnedRightType::Assets));
+            let assignments = match assignments {
+                AssignmentVec::Fungible(assignments) => assignments,
+                _ => panic!("non-fungible assignment in the transfer"),
+            };
+            for assignment in assignments {
+                let revealed = match assignment {
+                    Assignment::Revealed { assigned_state, .. }
+                    | Assignment::ConfidentialSeal { assigned_state, .. } => assigned_state,
+                    _ => panic!("concealed amount in the transfer"),
+                };
+                assigned.insert(assignment.to_confidential_seal(), revealed.value);
+                outputs.push(revealed.commit_conceal());
+            }
+        }
+        assert_eq!(assigned.len(), 2);
+        assert_eq!(assigned[&beneficiary.commit_conceal()], 700);
+        assert_eq!(assigned[&change.commit_conceal()], 300);
+        let inputs = builder
+            .inputs
+            .values()
+            .map(|allocation| allocation.revealed_amount().commit_conceal())
+            .collect::<Vec<_>>();
+        assert!(verify_balance(&inputs, &outputs));
+
+        let node_id = transition.node_id();
+        let bundle = TransitionBundle::from(bmap! { transition => bset![0u16, 1u16] });
+        let known = bundle
+            .known_transitions()
+            .map(Transition::node_id)
+            .collect::<Vec<_>>();
+        assert_eq!(known, vec![node_id]);
+    }
+
+    #[test]
+    fn test_insufficient_inputs() {
+        let mut rng = thread_rng();
+        let beneficiary = seal::Revealed::from(OutPoint::default()).commit_conceal();
+        let mut builder = TransitionBuilder::new();
+        builder.add_input(allocation(1, 100)).unwrap();
+        builder
+            .add_output(SealEndpoint::ConcealedUtxo(beneficiary), Amount::from(150))
+            .unwrap();
+        assert_eq!(
+            builder.build(&mut rng),
+            Err(BuilderError::InsufficientInputs {
+                required: Amount::from(150),
+                available: Amount::from(100)
+            })
+        );
+    }
+
+    fn dust_builder(policy: DustPolicy) -> (TransitionBuilder, SealEndpoint) {
+        let beneficiary = seal::Revealed::from(OutPoint::new(Txid::from_inner([8u8; 32]), 1));
+        let beneficiary = SealEndpoint::ConcealedUtxo(beneficiary.commit_conceal());
+        let change = seal::Revealed::from(OutPoint::new(Txid::from_inner([9u8; 32]), 0));
+        let mut builder = TransitionBuilder::with_options(TransferOptions {
+            dust_threshold: Amount::from(10),
+            dust_policy: policy,
+        });
+        builder.add_input(allocation(1, 705)).unwrap();
+        builder.add_change(change);
+        (builder, beneficiary)
+    }
+
+    #[test]
+    fn test_dust_change() {
+        let mut rng = thread_rng();
+        let (mut builder, beneficiary) = dust_builder(DustPolicy::Reject);
+        builder.add_output(beneficiary, Amount::from(705)).unwrap();
+        assert_eq!(builder.estimate_change(), Ok(Change::None));
+        assert!(builder.build(&mut rng).is_ok());
+
+        let (mut builder, beneficiary) = dust_builder(DustPolicy::Reject);
+        builder.add_output(beneficiary, Amount::from(695)).unwrap();
+        let change = builder.estimate_change().unwrap();
+        assert!(matches!(change, Change::Seal { amount, .. } if amount == Amount::from(10)));
+
+        let (mut builder, beneficiary) = dust_builder(DustPolicy::Reject);
+        builder.add_output(beneficiary, Amount::from(700)).unwrap();
+        assert_eq!(
+            builder.build(&mut rng),
+            Err(BuilderError::DustChange {
+                amount: Amount::from(5),
+                threshold: Amount::from(10)
+            })
+        );
+
+        let other = seal::Revealed::from(OutPoint::new(Txid::from_inner([7u8; 32]), 0));
+        let other = SealEndpoint::ConcealedUtxo(other.commit_conceal());
+        let (mut builder, beneficiary) = dust_builder(DustPolicy::Merge(other));
+        builder.add_output(beneficiary, Amount::from(700)).unwrap();
+        assert_eq!(
+            builder.estimate_change(),
+            Err(BuilderError::UnknownDustOutput)
+        );
+    }
+
+    #[test]
+    fn test_dust_overpay() {
+        let mut rng = thread_rng();
+        let (mut builder, beneficiary) = dust_builder(DustPolicy::Overpay);
+        builder.add_output(beneficiary, Amount::from(700)).unwrap();
+        assert_eq!(
+            builder.estimate_change(),
+            Ok(Change::Dust {
+                output: beneficiary,
+                amount: Amount::from(5)
+            })
+        );
+
+        let transition = builder.build(&mut rng).unwrap();
+        let assets = u16::from(OwnedRightType::Assets);
+        let assignments = match transition.owned_rights_by_type(assets) {
+            Some(AssignmentVec::Fungible(assignments)) => assignments,
+            _ => panic!("transfer does not assign assets"),
+        };
+        assert_eq!(assignments.len(), 1);
+        let revealed = match &assignments[0] {
+            Assignment::ConfidentialSeal { assigned_state, .. } => assigned_state,
+            _ => panic!("beneficiary seal must be concealed"),
+        };
+        assert_eq!(revealed.value, 705);
+        let inputs = builder
+            .inputs
+            .values()
+            .map(|allocation| allocation.revealed_amount().commit_conceal())
+            .collect::<Vec<_>>();
+        assert!(verify_balance(&inputs, &[revealed.commit_conceal()]));
+    }
+}
@@ -0,0 +1,363 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Construction of RGB20 burn and burn-and-replace state transitions.
+
+use std::collections::BTreeMap;
+
+use amplify::Wrapper;
+use rgb_core::SealValueMap;
+
+use super::allocation::Allocation;
+use super::amount::Amount;
+use super::schema::{self, FieldType, OwnedRightType, TransitionType};
+use crate::{
+    data, seal, Assignment, AssignmentVec, Metadata, NodeOutpoint, OwnedRights, ParentOwnedRights,
+    Schema, Transition,
+};
+
+/// Errors constructing burn with [`BurnBuilder`]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum BurnError {
+    /// schema does not define RGB20 burn or burn-and-replace transitions
+    NotFungible,
+
+    /// burn must consume at least one allocation
+    NoInputs,
+
+    /// allocation {0} is already consumed by the burn
+    DuplicateInput(NodeOutpoint),
+
+    /// burned or replaced amount must be non-zero
+    ZeroAmount,
+
+    /// seal {0} receives replaced assets more than once
+    DuplicateSeal(seal::Revealed),
+
+    /// burn declares {declared} of burned assets, while the consumed
+    /// allocations contain {consumed}; allocations may be burned only as a
+    /// whole
+    BurnMismatch { declared: Amount, consumed: Amount },
+
+    /// burn-and-replace re-issues {replaced}, while burning {burned}
+    ReplaceMismatch { burned: Amount, replaced: Amount },
+
+    /// total amount of the burned or replaced assets exceeds 2^64
+    Overflow,
+}
+
+/// Builder for the RGB20 burn and burn-and-replace state transitions.
+///
+/// The burn spends the burn right together with the allocations being burned
+/// and records the burned amount in the transition metadata, leaving no
+/// spendable assets. If replacement seals are provided, the builder produces
+/// burn-and-replace transition which re-issues exactly the burned amount to
+/// these seals.
+#[derive(Clone, PartialEq, Debug)]
+pub struct BurnBuilder {
+    burn_right: NodeOutpoint,
+    amount: Amount,
+    inputs: BTreeMap<NodeOutpoint, Allocation>,
+    replacement: SealValueMap,
+    next_burn_right: Option<seal::Revealed>,
+}
+
+impl BurnBuilder {
+    /// Constructs builder spending the `burn_right` for burning exactly
+    /// `amount` of the assets
+    #[inline]
+    pub fn new(burn_right: NodeOutpoint, amount: Amount) -> BurnBuilder {
+        BurnBuilder {
+            burn_right,
+            amount,
+            inputs: empty!(),
+            replacement: empty!(),
+            next_burn_right: None,
+        }
+    }
+
+    /// Adds allocation to be burned
+    pub fn add_input(&mut self, allocation: Allocation) -> Result<(), BurnError> {
+        let outpoint = allocation.node_output();
+        if self.inputs.insert(outpoint, allocation).is_some() {
+            return Err(BurnError::DuplicateInput(outpoint));
+        }
+        Ok(())
+    }
+
+    /// Re-issues `amount` of the burned assets to the `seal`, turning the
+    /// burn into burn-and-replace
+    pub fn replace(&mut self, seal: seal::Revealed, amount: Amount) -> Result<(), BurnError> {
+        if amount.is_zero() {
+            return Err(BurnError::ZeroAmount);
+        }
+        if self
+            .replacement
+            .insert(seal, amount.atomic_value())
+            .is_some()
+        {
+            return Err(BurnError::DuplicateSeal(seal));
+        }
+        Ok(())
+    }
+
+    /// Sets seal receiving the burn right for the future burns, replacing the
+    /// previously set one
+    #[inline]
+    pub fn add_burn_right(&mut self, seal: seal::Revealed) { self.next_burn_right = Some(seal) }
+
+    /// Detects whether the builder produces burn-and-replace transition
+    #[inline]
+    pub fn is_replace(&self) -> bool { !self.replacement.is_empty() }
+
+    /// Returns total amount of the consumed allocations
+    pub fn input_amount(&self) -> Result<Amount, BurnError> {
+        self.inputs
+            .values()
+            .try_fold(Amount::ZERO, |sum, allocation| {
+                sum.checked_add(Amount::from(allocation.value()))
+            })
+            .map_err(|_| BurnError::Overflow)
+    }
+
+    /// Returns total amount re-issued to the replacement seals
+    pub fn replaced_amount(&self) -> Result<Amount, BurnError> {
+        self.replacement
+            .values()
+            .try_fold(Amount::ZERO, |sum, amount| {
+                sum.checked_add(Amount::from(*amount))
+            })
+            .map_err(|_| BurnError::Overflow)
+    }
+
+    /// Constructs the burn or burn-and-replace state transition, checking
+    /// that the `schema` defines it
+    pub fn finish(&self, schema: &Schema) -> Result<Transition, BurnError> {
+        let assets = u16::from(OwnedRightType::Assets);
+        let transition_type = u16::from(self.transition_type());
+        let transition = schema
+            .transitions
+            .get(&transition_type)
+            .ok_or(BurnError::NotFungible)?;
+        if !schema::is_fungible(schema)
+            || !transition.closes.contains_key(&assets)
+            || !transition
+                .closes
+                .contains_key(&u16::from(OwnedRightType::BurnReplace))
+            || (self.is_replace() && !transition.owned_rights.contains_key(&assets))
+        {
+            return Err(BurnError::NotFungible);
+        }
+        self.build()
+    }
+
+    fn transition_type(&self) -> TransitionType {
+        if self.is_replace() {
+            TransitionType::BurnAndReplace
+        } else {
+            TransitionType::Burn
+        }
+    }
+
+    fn build(&self) -> Result<Transition, BurnError> {
+        if self.inputs.is_empty() {
+            return Err(BurnError::NoInputs);
+        }
+        if self.amount.is_zero() {
+            return Err(BurnError::ZeroAmount);
+        }
+        let consumed = self.input_amount()?;
+        if consumed != self.amount {
+            return Err(BurnError::BurnMismatch {
+                declared: self.amount,
+                consumed,
+            });
+        }
+        let replaced = self.replaced_amount()?;
+        if self.is_replace() && replaced != self.amount {
+            return Err(BurnError::ReplaceMismatch {
+                burned: self.amount,
+                replaced,
+            });
+        }
+
+        let assets = u16::from(OwnedRightType::Assets);
+        let burn_replace = u16::from(OwnedRightType::BurnReplace);
+        let burned = data::Revealed::U64(self.amount.atomic_value());
+        let metadata = bmap! { u16::from(FieldType::BurnedSupply) => vec![burned] };
+
+        let mut owned_rights = BTreeMap::new();
+        if self.is_replace() {
+            // Replacement amounts are equal to the burned ones, so they are
+            // balanced against the consumed allocations
+            let inputs = self
+                .inputs
+                .values()
+                .map(|allocation| *allocation.revealed_amount())
+                .collect::<Vec<_>>();
+            let replacement = self.replacement.clone();
+            let assignments = AssignmentVec::zero_balanced(inputs, replacement, empty!());
+            owned_rights.insert(assets, assignments);
+        }
+        if let Some(seal) = self.next_burn_right {
+            let right = Assignment::Revealed {
+                seal_definition: seal,
+                assigned_state: data::Void::default(),
+            };
+            owned_rights.insert(burn_replace, AssignmentVec::Declarative(vec![right]));
+        }
+
+        let mut parent_owned_rights = BTreeMap::<_, BTreeMap<_, Vec<u16>>>::new();
+        let spent = self
+            .inputs
+            .keys()
+            .map(|outpoint| (*outpoint, assets))
+            .chain([(self.burn_right, burn_replace)]);
+        for (outpoint, ty) in spent {
+            parent_owned_rights
+                .entry(outpoint.node_id)
+                .or_default()
+                .entry(ty)
+                .or_default()
+                .push(outpoint.output_no);
+        }
+
+        Ok(Transition::with(
+            u16::from(self.transition_type()),
+            Metadata::from_inner(metadata),
+            empty!(),
+            OwnedRights::from_inner(owned_rights),
+            empty!(),
+            ParentOwnedRights::from_inner(parent_owned_rights),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+
+    use bitcoin::hashes::{sha256t, Hash};
+    use bitcoin::secp256k1::rand::thread_rng;
+    use bitcoin::{OutPoint, Txid};
+    use rgb_core::value;
+
+    use super::*;
+    use crate::{Node, NodeId};
+
+    fn node_id(no: u8) -> NodeId { NodeId::from_inner(sha256t::Hash::from_inner([no; 32])) }
+
+    fn seal(no: u8) -> seal::Revealed {
+        seal::Revealed::from(OutPoint::new(Txid::from_inner([no; 32]), 0))
+    }
+
+    fn allocation(no: u8, value: u64) -> Allocation {
+        let outpoint = OutPoint::new(Txid::from_inner([no; 32]), 0);
+        let revealed = value::Revealed::with_amount(value, &mut thread_rng());
+        Allocation::with(node_id(no), 0, outpoint, revealed)
+    }
+
+    #[test]
+    fn test_burn() {
+        let burn_right = NodeOutpoint::new(node_id(9), 3);
+        let mut builder = BurnBuilder::new(burn_right, Amount::from(1000));
+        assert_eq!(builder.build(), Err(BurnError::NoInputs));
+        builder.add_input(allocation(1, 600)).unwrap();
+        builder.add_input(allocation(2, 400)).unwrap();
+        builder.add_burn_right(seal(3));
+        assert!(!builder.is_replace());
+
+        let transition = builder.build().unwrap();
+        let burn = u16::from(TransitionType::Burn);
+        assert_eq!(transition.transition_type(), burn);
+        let burned = transition
+            .metadata()
+            .as_inner()
+            .get(&u16::from(FieldType::BurnedSupply))
+            .cloned();
+        assert_eq!(burned, Some(vec![data::Revealed::U64(1000)]));
+
+        let parents = transition
+            .parent_outputs()
+            .into_iter()
+            .collect::<BTreeSet<_>>();
+        let mut spent = builder.inputs.keys().copied().collect::<BTreeSet<_>>();
+        spent.insert(burn_right);
+        assert_eq!(parents, spent);
+
+        let owned_rights = transition.owned_rights().as_inner();
+        assert!(!owned_rights.contains_key(&u16::from(OwnedRightType::Assets)));
+        assert!(matches!(
+            owned_rights.get(&u16::from(OwnedRightType::BurnReplace)),
+            Some(AssignmentVec::Declarative(rights)) if rights.len() == 1
+        ));
+    }
+
+    #[test]
+    fn test_burn_and_replace() {
+        let burn_right = NodeOutpoint::new(node_id(9), 3);
+        let mut builder = BurnBuilder::new(burn_right, Amount::from(1000));
+        builder.add_input(allocation(1, 1000)).unwrap();
+        builder.replace(seal(4), Amount::from(700)).unwrap();
+        assert_eq!(
+            builder.build(),
+            Err(BurnError::ReplaceMismatch {
+                burned: Amount::from(1000),
+                replaced: Amount::from(700)
+            })
+        );
+        builder.replace(seal(5), Amount::from(300)).unwrap();
+        assert_eq!(
+            builder.replace(seal(5), Amount::from(1)),
+            Err(BurnError::DuplicateSeal(seal(5)))
+        );
+
+        let transition = builder.build().unwrap();
+        let burn_and_replace = u16::from(TransitionType::BurnAndReplace);
+        assert_eq!(transition.transition_type(), burn_and_replace);
+        let owned_rights = transition.owned_rights().as_inner();
+        let replaced = match owned_rights.get(&u16::from(OwnedRightType::Assets)) {
+            Some(AssignmentVec::Fungible(assignments)) => assignments
+                .iter()
+                .filter_map(|assignment| match assignment {
+                    Assignment::Revealed { assigned_state, .. } => Some(assigned_state.value),
+                    _ => None,
+                })
+                .sum::<u64>(),
+            _ => panic!("burn-and-replace does not re-issue assets"),
+        };
+        assert_eq!(replaced, 1000);
+        assert!(!owned_rights.contains_key(&u16::from(OwnedRightType::BurnReplace)));
+    }
+
+    #[test]
+    fn test_partial_burn() {
+        let burn_right = NodeOutpoint::new(node_id(9), 3);
+        let mut builder = BurnBuilder::new(burn_right, Amount::from(500));
+        builder.add_input(allocation(1, 600)).unwrap();
+        assert_eq!(
+            builder.build(),
+            Err(BurnError::BurnMismatch {
+                declared: Amount::from(500),
+                consumed: Amount::from(600)
+            })
+        );
+
+        let duplicate = allocation(1, 600);
+        let err = BurnError::DuplicateInput(duplicate.node_output());
+        assert_eq!(builder.add_input(duplicate), Err(err));
+        assert_eq!(
+            BurnBuilder::new(burn_right, Amount::ZERO).build(),
+            Err(BurnError::NoInputs)
+        );
+    }
+}
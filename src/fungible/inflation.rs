@@ -0,0 +1,285 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Construction of RGB20 secondary issue state transitions.
+
+use amplify::Wrapper;
+use rgb_core::SealValueMap;
+
+use super::allocation::{Allocation, AllocationMap};
+use super::amount::Amount;
+use super::schema::{self, FieldType, OwnedRightType, TransitionType};
+use crate::{data, seal, Metadata, OwnedRights, ParentOwnedRights, Schema, Transition};
+
+/// Errors constructing secondary issue with [`InflationBuilder`]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum InflationError {
+    /// schema does not define RGB20 secondary issue
+    NotFungible,
+
+    /// secondary issue must allocate new supply to at least one seal
+    NoAllocations,
+
+    /// allocated amount must be non-zero
+    ZeroAmount,
+
+    /// seal {0} is allocated more than once
+    DuplicateSeal(seal::Revealed),
+
+    /// secondary issue of {requested} exceeds the inflation allowance of
+    /// {allowance}
+    ExceedsAllowance {
+        requested: Amount,
+        allowance: Amount,
+    },
+
+    /// inflation allowance exceeds the issued amount by {0}, but no seal for
+    /// the residual inflation right is provided
+    NoResidualSeal(Amount),
+
+    /// total issued amount exceeds 2^64 atomic units
+    Overflow,
+}
+
+/// Builder for the RGB20 secondary issue (inflation) state transitions.
+///
+/// The builder spends inflation right allocation, issues new supply to the
+/// provided seals and assigns the rest of the inflation allowance to the
+/// residual inflation right seal.
+#[derive(Clone, PartialEq, Debug)]
+pub struct InflationBuilder {
+    inflation: Allocation,
+    allocations: SealValueMap,
+    residual: Option<seal::Revealed>,
+}
+
+impl InflationBuilder {
+    /// Constructs builder spending the `inflation` right allocation, with the
+    /// allowance equal to the allocation value
+    #[inline]
+    pub fn new(inflation: Allocation) -> InflationBuilder {
+        InflationBuilder {
+            inflation,
+            allocations: empty!(),
+            residual: None,
+        }
+    }
+
+    /// Allocates `amount` of the newly issued assets to the `seal`
+    pub fn allocate(&mut self, seal: seal::Revealed, amount: Amount) -> Result<(), InflationError> {
+        if amount.is_zero() {
+            return Err(InflationError::ZeroAmount);
+        }
+        if self
+            .allocations
+            .insert(seal, amount.atomic_value())
+            .is_some()
+        {
+            return Err(InflationError::DuplicateSeal(seal));
+        }
+        Ok(())
+    }
+
+    /// Sets seal receiving the residual inflation right, replacing the
+    /// previously set one
+    #[inline]
+    pub fn add_residual(&mut self, seal: seal::Revealed) { self.residual = Some(seal) }
+
+    /// Returns inflation allowance provided by the spent inflation right
+    #[inline]
+    pub fn allowance(&self) -> Amount { Amount::from(self.inflation.value()) }
+
+    /// Returns total amount of the newly issued assets
+    pub fn issued_amount(&self) -> Result<Amount, InflationError> {
+        self.allocations
+            .values()
+            .try_fold(Amount::ZERO, |sum, amount| {
+                sum.checked_add(Amount::from(*amount))
+            })
+            .map_err(|_| InflationError::Overflow)
+    }
+
+    /// Returns inflation allowance which is assigned to the residual
+    /// inflation right
+    pub fn residual_amount(&self) -> Result<Amount, InflationError> {
+        let allowance = self.allowance();
+        let requested = self.issued_amount()?;
+        allowance
+            .checked_sub(requested)
+            .map_err(|_| InflationError::ExceedsAllowance {
+                requested,
+                allowance,
+            })
+    }
+
+    /// Constructs the secondary issue state transition, checking that the
+    /// `schema` defines RGB20 secondary issue
+    pub fn finish(&self, schema: &Schema) -> Result<Transition, InflationError> {
+        let inflation = u16::from(OwnedRightType::Inflation);
+        let issue = schema
+            .transitions
+            .get(&u16::from(TransitionType::Issue))
+            .ok_or(InflationError::NotFungible)?;
+        if !schema::is_fungible(schema)
+            || !issue.closes.contains_key(&inflation)
+            || !issue
+                .owned_rights
+                .contains_key(&u16::from(OwnedRightType::Assets))
+        {
+            return Err(InflationError::NotFungible);
+        }
+        self.build()
+    }
+
+    fn build(&self) -> Result<Transition, InflationError> {
+        if self.allocations.is_empty() {
+            return Err(InflationError::NoAllocations);
+        }
+
+        let issued = self.issued_amount()?;
+        let residual = self.residual_amount()?;
+        let mut residual_map = SealValueMap::new();
+        match self.residual {
+            Some(seal) if !residual.is_zero() => {
+                residual_map.insert(seal, residual.atomic_value());
+            }
+            None if !residual.is_zero() => return Err(InflationError::NoResidualSeal(residual)),
+            _ => {}
+        }
+
+        let inflation = u16::from(OwnedRightType::Inflation);
+        let metadata = bmap! {
+            u16::from(FieldType::IssuedSupply) => vec![data::Revealed::U64(issued.atomic_value())]
+        };
+        let mut owned_rights = bmap! {
+            u16::from(OwnedRightType::Assets) => self.allocations.clone().into_assignments()
+        };
+        if !residual_map.is_empty() {
+            owned_rights.insert(inflation, residual_map.into_assignments());
+        }
+        let parent_owned_rights = bmap! {
+            *self.inflation.node_id() => bmap! { inflation => vec![*self.inflation.index()] }
+        };
+
+        Ok(Transition::with(
+            u16::from(TransitionType::Issue),
+            Metadata::from_inner(metadata),
+            empty!(),
+            OwnedRights::from_inner(owned_rights),
+            empty!(),
+            ParentOwnedRights::from_inner(parent_owned_rights),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use bitcoin::hashes::{sha256t, Hash};
+    use bitcoin::secp256k1::rand::thread_rng;
+    use bitcoin::{OutPoint, Txid};
+    use rgb_core::value;
+
+    use super::*;
+    use crate::{Assignment, AssignmentVec, Node, NodeId};
+
+    fn seal(no: u8) -> seal::Revealed {
+        seal::Revealed::from(OutPoint::new(Txid::from_inner([no; 32]), 0))
+    }
+
+    fn inflation_right(allowance: u64) -> Allocation {
+        let node_id = NodeId::from_inner(sha256t::Hash::from_inner([1u8; 32]));
+        let outpoint = OutPoint::new(Txid::from_inner([1u8; 32]), 0);
+        let revealed = value::Revealed::with_amount(allowance, &mut thread_rng());
+        Allocation::with(node_id, 2, outpoint, revealed)
+    }
+
+    #[test]
+    fn test_inflation() {
+        let right = inflation_right(1000);
+        let mut builder = InflationBuilder::new(right);
+        assert_eq!(builder.build(), Err(InflationError::NoAllocations));
+        assert_eq!(
+            builder.allocate(seal(2), Amount::ZERO),
+            Err(InflationError::ZeroAmount)
+        );
+        builder.allocate(seal(2), Amount::from(600)).unwrap();
+        assert_eq!(
+            builder.allocate(seal(2), Amount::from(1)),
+            Err(InflationError::DuplicateSeal(seal(2)))
+        );
+        let residual = Amount::from(400);
+        assert_eq!(
+            builder.build(),
+            Err(InflationError::NoResidualSeal(residual))
+        );
+        builder.add_residual(seal(3));
+        assert_eq!(builder.residual_amount(), Ok(residual));
+
+        let transition = builder.build().unwrap();
+        let issue = u16::from(TransitionType::Issue);
+        assert_eq!(transition.transition_type(), issue);
+        assert_eq!(transition.parent_outputs(), vec![right.node_output()]);
+        let issued = transition
+            .metadata()
+            .as_inner()
+            .get(&u16::from(FieldType::IssuedSupply))
+            .cloned();
+        assert_eq!(issued, Some(vec![data::Revealed::U64(600)]));
+
+        let mut assigned = BTreeMap::new();
+        for (ty, assignments) in transition.owned_rights().iter() {
+            let assignments = match assignments {
+                AssignmentVec::Fungible(assignments) => assignments,
+                _ => panic!("non-fungible assignment in the secondary issue"),
+            };
+            for assignment in assignments {
+                let (seal, value) = match assignment {
+                    Assignment::Revealed {
+                        seal_definition,
+                        assigned_state,
+                    } => (*seal_definition, assigned_state.value),
+                    _ => panic!("concealed assignment in the secondary issue"),
+                };
+                assigned.insert(seal, (*ty, value));
+            }
+        }
+        let assets = u16::from(OwnedRightType::Assets);
+        let inflation = u16::from(OwnedRightType::Inflation);
+        assert_eq!(
+            assigned,
+            bmap! { seal(2) => (assets, 600), seal(3) => (inflation, 400) }
+        );
+    }
+
+    #[test]
+    fn test_exceeding_allowance() {
+        let mut builder = InflationBuilder::new(inflation_right(100));
+        builder.allocate(seal(2), Amount::from(150)).unwrap();
+        assert_eq!(
+            builder.build(),
+            Err(InflationError::ExceedsAllowance {
+                requested: Amount::from(150),
+                allowance: Amount::from(100)
+            })
+        );
+
+        // Issue of the whole allowance requires no residual seal
+        let mut builder = InflationBuilder::new(inflation_right(100));
+        builder.allocate(seal(2), Amount::from(100)).unwrap();
+        let transition = builder.build().unwrap();
+        let inflation = u16::from(OwnedRightType::Inflation);
+        let owned_rights = transition.owned_rights().as_inner();
+        assert!(!owned_rights.contains_key(&inflation));
+    }
+}
@@ -0,0 +1,264 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Invoices requesting payment in RGB20 assets.
+
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use commit_verify::CommitConceal;
+use lnpbp_bech32::{FromBech32Str, ToBech32String};
+
+use super::amount::Amount;
+use super::schema::OwnedRightType;
+use crate::{seal, Assignment, AssignmentVec, CloseMethod, ContractId, Node, StateTransfer};
+
+/// Seal which has to receive the assets paid by the invoice
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Display)]
+#[derive(StrictEncode, StrictDecode)]
+pub enum Beneficiary {
+    /// Blinded transaction output provided by the payee
+    #[display(inner)]
+    BlindedSeal(seal::Confidential),
+
+    /// Explicit transaction output seal; the payer is expected to assign the
+    /// assets to the concealed form of this seal
+    #[display(inner)]
+    Seal(seal::Revealed),
+}
+
+impl Beneficiary {
+    /// Returns concealed form of the beneficiary seal
+    pub fn to_confidential_seal(&self) -> seal::Confidential {
+        match self {
+            Beneficiary::BlindedSeal(seal) => *seal,
+            Beneficiary::Seal(seal) => seal.commit_conceal(),
+        }
+    }
+}
+
+/// Reasons for the state transfer to not match the invoice
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum InvoiceMismatch {
+    /// transfer belongs to a different contract {0}
+    ContractMismatch(ContractId),
+
+    /// transfer endpoints do not include the invoice beneficiary seal
+    NoBeneficiary,
+
+    /// transfer assigns {assigned} to the beneficiary, while the invoice
+    /// requests {requested}
+    InsufficientAmount { requested: Amount, assigned: Amount },
+
+    /// invoice requires {required} close method, while the transfer endpoints
+    /// are anchored with {used}
+    CloseMethodMismatch {
+        required: CloseMethod,
+        used: CloseMethod,
+    },
+}
+
+/// Invoice requesting payment in RGB20 asset
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[derive(StrictEncode, StrictDecode)]
+pub struct Invoice {
+    /// Asset contract the payment is requested in
+    pub contract_id: ContractId,
+
+    /// Requested amount; `None` for invoices accepting any amount
+    pub amount: Option<Amount>,
+
+    /// Seal receiving the payment
+    pub beneficiary: Beneficiary,
+
+    /// UNIX timestamp after which the invoice expires
+    pub expiry: Option<i64>,
+
+    /// Merchant-specific information, like the order details
+    pub merchant: Option<String>,
+
+    /// Method which the payee expects the witness transaction to commit
+    /// with; `None` accepts any method
+    pub close_method: Option<CloseMethod>,
+}
+
+impl Invoice {
+    /// Constructs invoice requesting payment to the blinded transaction output
+    pub fn with_blinded_seal(
+        contract_id: ContractId,
+        amount: Option<Amount>,
+        seal: seal::Confidential,
+    ) -> Invoice {
+        Invoice {
+            contract_id,
+            amount,
+            beneficiary: Beneficiary::BlindedSeal(seal),
+            expiry: None,
+            merchant: None,
+            close_method: None,
+        }
+    }
+
+    /// Constructs invoice requesting payment to the explicit seal
+    pub fn with_seal(
+        contract_id: ContractId,
+        amount: Option<Amount>,
+        seal: seal::Revealed,
+    ) -> Invoice {
+        Invoice {
+            contract_id,
+            amount,
+            beneficiary: Beneficiary::Seal(seal),
+            expiry: None,
+            merchant: None,
+            close_method: None,
+        }
+    }
+
+    /// Detects whether the invoice is expired at given UNIX `timestamp`
+    #[inline]
+    pub fn is_expired_at(&self, timestamp: i64) -> bool {
+        matches!(self.expiry, Some(expiry) if expiry <= timestamp)
+    }
+
+    /// Detects whether the invoice is already expired according to the system
+    /// time. Panics on `wasm32-unknown-unknown`, which has no system time; use
+    /// [`Invoice::is_expired_at`] there.
+    pub fn is_expired(&self) -> bool {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs() as i64)
+            .unwrap_or_default();
+        self.is_expired_at(now)
+    }
+
+    /// Performs basic check that the transfer pays the invoice: the transfer
+    /// endpoints must include the beneficiary seal and the endpoint
+    /// transitions must assign to it at least the requested amount of the
+    /// assets with the revealed value. If the invoice requires a close
+    /// method, all endpoint bundles must be anchored with it.
+    ///
+    /// The check does not validate the transfer and does not account for the
+    /// invoice expiry, which has to be checked with [`Invoice::is_expired`].
+    pub fn matches(&self, transfer: &StateTransfer) -> Result<(), InvoiceMismatch> {
+        if transfer.contract_id() != self.contract_id {
+            return Err(InvoiceMismatch::ContractMismatch(transfer.contract_id()));
+        }
+
+        let seal = self.beneficiary.to_confidential_seal();
+        if !transfer
+            .endpoints
+            .iter()
+            .any(|(_, endpoint)| endpoint.commit_conceal() == seal)
+        {
+            return Err(InvoiceMismatch::NoBeneficiary);
+        }
+
+        if let Some(required) = self.close_method {
+            if let Some(used) = transfer
+                .endpoint_close_methods()
+                .into_iter()
+                .find(|method| *method != required)
+            {
+                return Err(InvoiceMismatch::CloseMethodMismatch { required, used });
+            }
+        }
+
+        let requested = match self.amount {
+            Some(amount) => amount,
+            None => return Ok(()),
+        };
+        let assets = u16::from(OwnedRightType::Assets);
+        let assigned = transfer
+            .endpoint_bundles()
+            .into_iter()
+            .flat_map(|bundle| bundle.known_transitions())
+            .flat_map(|transition| transition.owned_rights().iter())
+            .filter_map(|(ty, assignments)| match assignments {
+                AssignmentVec::Fungible(assignments) if *ty == assets => Some(assignments),
+                _ => None,
+            })
+            .flatten()
+            .filter(|assignment| assignment.to_confidential_seal() == seal)
+            .filter_map(|assignment| match assignment {
+                Assignment::Revealed { assigned_state, .. }
+                | Assignment::ConfidentialSeal { assigned_state, .. } => Some(assigned_state.value),
+                _ => None,
+            })
+            .fold(0u64, u64::saturating_add);
+        let assigned = Amount::from(assigned);
+        if assigned < requested {
+            return Err(InvoiceMismatch::InsufficientAmount {
+                requested,
+                assigned,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl lnpbp_bech32::Strategy for Invoice {
+    const HRP: &'static str = "rgbinv";
+    type Strategy = lnpbp_bech32::strategies::UsingStrictEncoding;
+}
+
+impl Display for Invoice {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result { f.write_str(&self.to_bech32_string()) }
+}
+
+impl FromStr for Invoice {
+    type Err = lnpbp_bech32::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> { Self::from_bech32_str(s) }
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoin::hashes::Hash;
+    use bitcoin::{OutPoint, Txid};
+
+    use super::*;
+
+    #[test]
+    fn test_invoice_bech32() {
+        let outpoint = OutPoint::new(Txid::from_inner([1u8; 32]), 2);
+        let seal = seal::Revealed::from(outpoint);
+        let amount = Some(Amount::from(1000));
+        let mut invoice = Invoice::with_seal(ContractId::default(), amount, seal);
+        invoice.merchant = Some(s!("order #1"));
+        invoice.expiry = Some(1_600_000_000);
+        invoice.close_method = Some(CloseMethod::TapretFirst);
+
+        let s = invoice.to_string();
+        assert!(s.starts_with("rgbinv1"));
+        assert_eq!(Invoice::from_str(&s).unwrap(), invoice);
+
+        let confidential = seal.commit_conceal();
+        let blinded = Invoice::with_blinded_seal(ContractId::default(), None, confidential);
+        assert_eq!(blinded.beneficiary.to_confidential_seal(), confidential);
+        assert_eq!(invoice.beneficiary.to_confidential_seal(), confidential);
+        assert_eq!(Invoice::from_str(&blinded.to_string()).unwrap(), blinded);
+    }
+
+    #[test]
+    fn test_invoice_expiry() {
+        let seal = seal::Revealed::from(OutPoint::default()).commit_conceal();
+        let mut invoice = Invoice::with_blinded_seal(ContractId::default(), None, seal);
+        assert!(!invoice.is_expired());
+
+        invoice.expiry = Some(1_600_000_000);
+        assert!(!invoice.is_expired_at(1_599_999_999));
+        assert!(invoice.is_expired_at(1_600_000_000));
+        assert!(invoice.is_expired());
+    }
+}
@@ -0,0 +1,646 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Issue of new RGB20 assets.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use amplify::Wrapper;
+use lnpbp::chain::Chain;
+use rgb_core::secp256k1zkp;
+use rgb_core::{value, SealValueMap};
+
+use super::allocation::AllocationMap;
+use super::amount::Amount;
+use super::nomination::{AssetName, Nominal, NominationError, Precision, Ticker};
+use super::renomination::Nomination;
+use super::schema::{self, FieldType, OwnedRightType};
+use crate::{
+    data, seal, AssignmentVec, ContractId, Genesis, Metadata, OwnedRights, Schema, SchemaId,
+};
+
+/// Errors issuing asset with [`IssueBuilder`]
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum IssueError {
+    /// schema does not define RGB20 asset genesis
+    NotFungible,
+
+    /// invalid nominal data: {0}
+    #[from]
+    InvalidNomination(NominationError),
+
+    /// asset must be allocated to at least one seal
+    NoAllocations,
+
+    /// allocated amount must be non-zero
+    ZeroAmount,
+
+    /// seal {0} is allocated more than once
+    DuplicateSeal(seal::Revealed),
+
+    /// schema does not allow secondary issue of the asset
+    InflationUnsupported,
+
+    /// total issued supply or inflation allowance exceeds 2^64 atomic units
+    Overflow,
+}
+
+/// Builder for the genesis of RGB20 fungible asset.
+///
+/// Issued supply of the asset is equal to the sum of all allocations. Blinding
+/// factors of the allocated amounts are generated such that the Pedersen
+/// commitments of the allocations balance the issued supply committed with the
+/// unit blinding factor.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct IssueBuilder {
+    schema_id: SchemaId,
+    timestamp_field: bool,
+    inflation_right: bool,
+    nomination: Nomination,
+    timestamp: Option<i64>,
+    allocations: Vec<(seal::Revealed, Amount)>,
+    inflation: Vec<(seal::Revealed, Amount)>,
+}
+
+impl IssueBuilder {
+    /// Constructs builder for the asset using RGB20-style `schema`
+    pub fn new(schema: &Schema) -> Result<IssueBuilder, IssueError> {
+        if !schema::is_fungible(schema)
+            || !schema
+                .genesis
+                .owned_rights
+                .contains_key(&u16::from(OwnedRightType::Assets))
+        {
+            return Err(IssueError::NotFungible);
+        }
+        let genesis = &schema.genesis;
+        Ok(IssueBuilder::with(
+            schema.schema_id(),
+            genesis
+                .metadata
+                .contains_key(&u16::from(FieldType::Timestamp)),
+            genesis
+                .owned_rights
+                .contains_key(&u16::from(OwnedRightType::Inflation)),
+        ))
+    }
+
+    pub(super) fn with(
+        schema_id: SchemaId,
+        timestamp_field: bool,
+        inflation_right: bool,
+    ) -> IssueBuilder {
+        IssueBuilder {
+            schema_id,
+            timestamp_field,
+            inflation_right,
+            // Empty ticker and name are reported by `finish` unless set
+            nomination: Nomination {
+                ticker: Nominal::Nonconforming(empty!()),
+                name: Nominal::Nonconforming(empty!()),
+                precision: Precision::default(),
+            },
+            timestamp: None,
+            allocations: vec![],
+            inflation: vec![],
+        }
+    }
+
+    /// Sets asset ticker
+    pub fn ticker(mut self, ticker: Ticker) -> Self {
+        self.nomination.ticker = Nominal::Conforming(ticker);
+        self
+    }
+
+    /// Sets full asset name
+    pub fn name(mut self, name: AssetName) -> Self {
+        self.nomination.name = Nominal::Conforming(name);
+        self
+    }
+
+    /// Sets decimal precision of the asset amounts; defaults to zero
+    pub fn precision(mut self, precision: Precision) -> Self {
+        self.nomination.precision = precision;
+        self
+    }
+
+    /// Sets UNIX timestamp of the asset issue; defaults to the system time at
+    /// the moment of [`IssueBuilder::finish`] call. Must be set on
+    /// `wasm32-unknown-unknown`, which has no system time.
+    pub fn timestamp(mut self, timestamp: i64) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    /// Allocates `amount` of the issued assets to the `seal`
+    pub fn allocate(mut self, seal: seal::Revealed, amount: Amount) -> Self {
+        self.allocations.push((seal, amount));
+        self
+    }
+
+    /// Allows the owner of the `seal` to perform secondary issue of up to
+    /// `max_amount` of the assets
+    pub fn inflation_allowance(mut self, seal: seal::Revealed, max_amount: Amount) -> Self {
+        self.inflation.push((seal, max_amount));
+        self
+    }
+
+    /// Constructs the asset genesis for the `chain`, returning it together
+    /// with the id of the issued contract
+    pub fn finish(&self, chain: Chain) -> Result<(Genesis, ContractId), IssueError> {
+        let Nomination {
+            ticker,
+            name,
+            precision,
+        } = self.nomination.to_conforming()?;
+        if self.allocations.is_empty() {
+            return Err(IssueError::NoAllocations);
+        }
+        if !self.inflation.is_empty() && !self.inflation_right {
+            return Err(IssueError::InflationUnsupported);
+        }
+
+        let (allocations, supply) = seal_value_map(&self.allocations)?;
+        let (inflation, _) = seal_value_map(&self.inflation)?;
+
+        let mut metadata = bmap! {
+            u16::from(FieldType::Ticker) => vec![data::Revealed::String(ticker.to_string())],
+            u16::from(FieldType::Name) => vec![data::Revealed::String(name.to_string())],
+            u16::from(FieldType::Precision) => vec![data::Revealed::U8(precision.into())],
+            u16::from(FieldType::IssuedSupply) => vec![data::Revealed::U64(supply.atomic_value())]
+        };
+        if self.timestamp_field {
+            let timestamp = self.timestamp.unwrap_or_else(|| {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|duration| duration.as_secs() as i64)
+                    .unwrap_or_default()
+            });
+            let timestamp = vec![data::Revealed::I64(timestamp)];
+            metadata.insert(u16::from(FieldType::Timestamp), timestamp);
+        }
+
+        let supply = value::Revealed {
+            value: supply.atomic_value(),
+            blinding: secp256k1zkp::key::ONE_KEY.into(),
+        };
+        let mut owned_rights = bmap! {
+            u16::from(OwnedRightType::Assets) =>
+                AssignmentVec::zero_balanced(vec![supply], allocations, empty!())
+        };
+        if !inflation.is_empty() {
+            let inflation = inflation.into_assignments();
+            owned_rights.insert(u16::from(OwnedRightType::Inflation), inflation);
+        }
+
+        let genesis = Genesis::with(
+            self.schema_id,
+            chain,
+            Metadata::from_inner(metadata),
+            OwnedRights::from_inner(owned_rights),
+            empty!(),
+        );
+        let contract_id = genesis.contract_id();
+        Ok((genesis, contract_id))
+    }
+}
+
+/// Collects allocated amounts into [`SealValueMap`], returning it together
+/// with the total amount. Checks that the seals are unique and the amounts are
+/// non-zero and do not overflow in total.
+fn seal_value_map(
+    allocations: &[(seal::Revealed, Amount)],
+) -> Result<(SealValueMap, Amount), IssueError> {
+    let mut map = SealValueMap::new();
+    let mut total = Amount::ZERO;
+    for (seal, amount) in allocations {
+        if amount.is_zero() {
+            return Err(IssueError::ZeroAmount);
+        }
+        if map.insert(*seal, amount.atomic_value()).is_some() {
+            return Err(IssueError::DuplicateSeal(*seal));
+        }
+        total = total
+            .checked_add(*amount)
+            .map_err(|_| IssueError::Overflow)?;
+    }
+    Ok((map, total))
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use bitcoin::hashes::{sha256t, Hash};
+    use bitcoin::{OutPoint, Txid};
+    use commit_verify::CommitConceal;
+    use rgb_core::ConcealSeals;
+
+    use super::*;
+    use crate::fungible::allocation::Allocation;
+    use crate::fungible::schema::TransitionType;
+    use crate::fungible::{validate_precision, Asset, Error, OutpointFilter};
+    use crate::{Assignment, ContractState, Node, NodeId, ParentOwnedRights, Transition};
+
+    fn seal(no: u8) -> seal::Revealed {
+        seal::Revealed::from(OutPoint::new(Txid::from_inner([no; 32]), 0))
+    }
+
+    fn builder() -> IssueBuilder {
+        IssueBuilder::with(SchemaId::default(), true, true)
+            .ticker("TCKR".parse().unwrap())
+            .name("Test asset".parse().unwrap())
+            .precision(validate_precision(8).unwrap())
+            .timestamp(1_600_000_000)
+    }
+
+    #[test]
+    fn test_issue() {
+        let (genesis, contract_id) = builder()
+            .allocate(seal(1), Amount::from(600))
+            .allocate(seal(2), Amount::from(400))
+            .inflation_allowance(seal(3), Amount::from(5000))
+            .finish(Chain::Testnet3)
+            .unwrap();
+        assert_eq!(genesis.contract_id(), contract_id);
+
+        let mut values = BTreeMap::new();
+        for (ty, assignments) in genesis.owned_rights().iter() {
+            let assignments = match assignments {
+                AssignmentVec::Fungible(assignments) => assignments,
+                _ => panic!("non-fungible assignment in the genesis"),
+            };
+            for assignment in assignments {
+                let (seal, value) = match assignment {
+                    Assignment::Revealed {
+                        seal_definition,
+                        assigned_state,
+                    } => (*seal_definition, assigned_state.value),
+                    _ => panic!("concealed assignment in the genesis"),
+                };
+                values.insert(seal, (*ty, value));
+            }
+        }
+        let assets = u16::from(OwnedRightType::Assets);
+        let inflation = u16::from(OwnedRightType::Inflation);
+        assert_eq!(
+            values,
+            bmap! {
+                seal(1) => (assets, 600),
+                seal(2) => (assets, 400),
+                seal(3) => (inflation, 5000)
+            }
+        );
+
+        let state = ContractState::with_genesis(&genesis);
+        let asset = Asset::with_genesis_state(&genesis, state).unwrap();
+        assert_eq!(asset.ticker().as_str(), "TCKR");
+        assert_eq!(asset.name().as_str(), "Test asset");
+        assert_eq!(u8::from(asset.precision()), 8);
+        assert_eq!(asset.issued_supply(), Amount::from(1000));
+        assert_eq!(asset.contract_id(), contract_id);
+        assert_eq!(asset.chain(), &Chain::Testnet3);
+        assert_eq!(asset.known_amount(), Ok(Amount::from(1000)));
+    }
+
+    #[test]
+    fn test_inflation_audit() {
+        let (genesis, _) = builder()
+            .allocate(seal(1), Amount::from(1000))
+            .inflation_allowance(seal(2), Amount::from(500))
+            .finish(Chain::Testnet3)
+            .unwrap();
+        let state = ContractState::with_genesis(&genesis);
+        let asset = Asset::with_genesis_state(&genesis, state.clone()).unwrap();
+        assert_eq!(asset.max_supply(), Amount::from(1500));
+        assert_eq!(asset.known_inflation(), Amount::ZERO);
+        assert_eq!(asset.remaining_inflation_allowance(), Amount::from(500));
+        assert_eq!(asset.inflation_rights().len(), 1);
+
+        // Allowance assigned to a concealed seal is still known
+        let mut concealed = genesis.clone();
+        concealed.conceal_seals(&[seal(2).commit_conceal()]);
+        let concealed_state = ContractState::with_genesis(&concealed);
+        let asset = Asset::with_genesis_state(&concealed, concealed_state).unwrap();
+        assert_eq!(asset.max_supply(), Amount::from(1500));
+        assert!(asset.inflation_rights().is_empty());
+
+        let inflation = u16::from(OwnedRightType::Inflation);
+        let right = asset.inflation_rights()[0];
+        let issue = |issued: u64| {
+            let mut allocations = SealValueMap::new();
+            allocations.insert(seal(3), issued);
+            Transition::with(
+                u16::from(TransitionType::Issue),
+                Metadata::from_inner(bmap! {
+                    u16::from(FieldType::IssuedSupply) => vec![data::Revealed::U64(issued)]
+                }),
+                empty!(),
+                OwnedRights::from_inner(bmap! {
+                    u16::from(OwnedRightType::Assets) => allocations.into_assignments()
+                }),
+                empty!(),
+                ParentOwnedRights::from_inner(bmap! {
+                    *right.node_id() => bmap! { inflation => vec![*right.index()] }
+                }),
+            )
+        };
+
+        let mut inflated = state.clone();
+        inflated
+            .extend(Txid::from_inner([5u8; 32]), &issue(200))
+            .unwrap();
+        let asset = Asset::with_genesis_state(&genesis, inflated).unwrap();
+        assert_eq!(asset.known_inflation(), Amount::from(200));
+        assert_eq!(asset.remaining_inflation_allowance(), Amount::from(300));
+        assert_eq!(asset.known_amount(), Ok(Amount::from(1200)));
+        assert!(asset.inflation_rights().is_empty());
+
+        let mut overissued = state;
+        overissued
+            .extend(Txid::from_inner([5u8; 32]), &issue(501))
+            .unwrap();
+        assert_eq!(
+            Asset::with_genesis_state(&genesis, overissued),
+            Err(Error::OverIssuance {
+                issued: Amount::from(1501),
+                max_supply: Amount::from(1500)
+            })
+        );
+    }
+
+    #[test]
+    fn test_burn_accounting() {
+        let (genesis, _) = builder()
+            .allocate(seal(1), Amount::from(600))
+            .allocate(seal(2), Amount::from(400))
+            .finish(Chain::Testnet3)
+            .unwrap();
+        let mut state = ContractState::with_genesis(&genesis);
+        let asset = Asset::with_genesis_state(&genesis, state.clone()).unwrap();
+        assert_eq!(asset.burned_supply(), Amount::ZERO);
+
+        let assets = u16::from(OwnedRightType::Assets);
+        let burn = |allocation: &Allocation, replace: bool| {
+            let mut owned_rights = BTreeMap::new();
+            let mut transition_type = TransitionType::Burn;
+            if replace {
+                transition_type = TransitionType::BurnAndReplace;
+                let mut replacement = SealValueMap::new();
+                replacement.insert(seal(5), allocation.value());
+                owned_rights.insert(assets, replacement.into_assignments());
+            }
+            let burned = vec![data::Revealed::U64(allocation.value())];
+            Transition::with(
+                u16::from(transition_type),
+                Metadata::from_inner(bmap! { u16::from(FieldType::BurnedSupply) => burned }),
+                empty!(),
+                OwnedRights::from_inner(owned_rights),
+                empty!(),
+                ParentOwnedRights::from_inner(bmap! {
+                    *allocation.node_id() => bmap! { assets => vec![*allocation.index()] }
+                }),
+            )
+        };
+        let mut allocations = asset.known_allocations().to_vec();
+        allocations.sort_by_key(Allocation::value);
+        state
+            .extend(Txid::from_inner([5u8; 32]), &burn(&allocations[0], false))
+            .unwrap();
+        state
+            .extend(Txid::from_inner([6u8; 32]), &burn(&allocations[1], true))
+            .unwrap();
+
+        let asset = Asset::with_genesis_state(&genesis, state).unwrap();
+        assert_eq!(asset.burned_supply(), Amount::from(1000));
+        assert_eq!(asset.replaced_supply(), Amount::from(600));
+        assert_eq!(asset.known_amount(), Ok(Amount::from(600)));
+    }
+
+    #[test]
+    fn test_allocation_filter() {
+        let (genesis, _) = builder()
+            .allocate(seal(1), Amount::from(600))
+            .allocate(seal(2), Amount::from(400))
+            .finish(Chain::Testnet3)
+            .unwrap();
+        let mut state = ContractState::with_genesis(&genesis);
+        let asset = Asset::with_genesis_state(&genesis, state.clone()).unwrap();
+        let outpoint = |no: u8| OutPoint::new(Txid::from_inner([no; 32]), 0);
+        assert_eq!(asset.allocations(OutpointFilter::All).count(), 2);
+        assert_eq!(asset.balance(OutpointFilter::All), Ok(Amount::from(1000)));
+        let wallet = OutpointFilter::from(bset! { outpoint(1) });
+        assert_eq!(asset.balance(wallet), Ok(Amount::from(600)));
+        let filter = OutpointFilter::with(|seal| seal.txid == outpoint(2).txid);
+        assert_eq!(asset.balance(filter), Ok(Amount::from(400)));
+
+        // Transfer the first allocation to a new seal, witnessed at height 100
+        let assets = u16::from(OwnedRightType::Assets);
+        let spent = asset
+            .allocations(OutpointFilter::from(bset! { outpoint(1) }))
+            .next()
+            .copied()
+            .unwrap();
+        let mut allocations = SealValueMap::new();
+        allocations.insert(seal(3), spent.value());
+        let transfer = Transition::with(
+            u16::from(TransitionType::Transfer),
+            empty!(),
+            empty!(),
+            OwnedRights::from_inner(bmap! { assets => allocations.into_assignments() }),
+            empty!(),
+            ParentOwnedRights::from_inner(bmap! {
+                *spent.node_id() => bmap! { assets => vec![*spent.index()] }
+            }),
+        );
+        let witness = Txid::from_inner([5u8; 32]);
+        state.extend(witness, &transfer).unwrap();
+        let asset = Asset::with_genesis_state(&genesis, state).unwrap();
+        let wallet = || OutpointFilter::from(bset! { outpoint(1), outpoint(3) });
+        assert_eq!(asset.balance(wallet()), Ok(Amount::from(600)));
+
+        let heights = |txid: Txid| if txid == witness { Some(100) } else { None };
+        assert_eq!(asset.spendable(wallet(), 2, 101, &heights).len(), 1);
+        assert!(asset.spendable(wallet(), 3, 101, &heights).is_empty());
+        assert!(asset.spendable(wallet(), 1, 99, &heights).is_empty());
+        // Genesis allocations have no witness and are always spendable
+        let spendable = asset.spendable(OutpointFilter::All, 6, 0, &heights);
+        assert_eq!(spendable.len(), 1);
+        assert_eq!(asset.concealed_allocations(OutpointFilter::All).count(), 0);
+    }
+
+    #[test]
+    fn test_wallet_history() {
+        let (genesis, _) = builder()
+            .allocate(seal(1), Amount::from(600))
+            .allocate(seal(2), Amount::from(400))
+            .finish(Chain::Testnet3)
+            .unwrap();
+        let mut state = ContractState::with_genesis(&genesis);
+        let asset = Asset::with_genesis_state(&genesis, state.clone()).unwrap();
+        let outpoint = |no: u8| OutPoint::new(Txid::from_inner([no; 32]), 0);
+        let assets = u16::from(OwnedRightType::Assets);
+        let transfer = |spent: &Allocation, allocations: SealValueMap| {
+            Transition::with(
+                u16::from(TransitionType::Transfer),
+                empty!(),
+                empty!(),
+                OwnedRights::from_inner(bmap! { assets => allocations.into_assignments() }),
+                empty!(),
+                ParentOwnedRights::from_inner(bmap! {
+                    *spent.node_id() => bmap! { assets => vec![*spent.index()] }
+                }),
+            )
+        };
+
+        // Wallet owning the first genesis allocation sends 200 and keeps 400
+        // as a change
+        let mut allocations = asset.known_allocations().to_vec();
+        allocations.sort_by_key(Allocation::value);
+        let payment = transfer(&allocations[1], bmap! { seal(3) => 200, seal(4) => 400 });
+        let confirmed = Txid::from_inner([5u8; 32]);
+        state.extend(confirmed, &payment).unwrap();
+        // Wallet receives 400 from the second genesis allocation, which is
+        // not mined yet
+        let receipt = transfer(&allocations[0], bmap! { seal(6) => 400 });
+        let unconfirmed = Txid::from_inner([6u8; 32]);
+        state.extend(unconfirmed, &receipt).unwrap();
+
+        let asset = Asset::with_genesis_state(&genesis, state).unwrap();
+        let wallet = bset! { outpoint(1), outpoint(4), outpoint(6) };
+        let heights = |txid: Txid| if txid == confirmed { Some(100) } else { None };
+        let history = asset.history(&wallet, &heights).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].witness, confirmed);
+        assert_eq!(history[0].height, Some(100));
+        assert_eq!(history[0].net_change(), -200);
+        assert_eq!(history[0].node_ids, bset! { payment.node_id() });
+        assert_eq!(history[1].witness, unconfirmed);
+        assert_eq!(history[1].height, None);
+        assert_eq!(history[1].received, Amount::from(400));
+        assert_eq!(history[1].net_change(), 400);
+    }
+
+    #[test]
+    fn test_renomination_history() {
+        let (genesis, _) = builder()
+            .allocate(seal(1), Amount::from(1000))
+            .finish(Chain::Testnet3)
+            .unwrap();
+        let state = ContractState::with_genesis(&genesis);
+        let asset = Asset::with_genesis_state(&genesis, state.clone()).unwrap();
+        let genesis_nomination = asset.nomination().clone();
+        let rename = |no: u8, metadata: Metadata| {
+            let right = NodeId::from_inner(sha256t::Hash::from_inner([no; 32]));
+            Transition::with(
+                u16::from(TransitionType::Rename),
+                metadata,
+                empty!(),
+                empty!(),
+                empty!(),
+                ParentOwnedRights::from_inner(bmap! {
+                    right => bmap! { u16::from(OwnedRightType::Renomination) => vec![0] }
+                }),
+            )
+        };
+        let ticker = |ticker: &str| {
+            Metadata::from_inner(bmap! {
+                u16::from(FieldType::Ticker) => vec![data::Revealed::String(ticker.to_owned())]
+            })
+        };
+
+        let mut state = state;
+        let first = rename(7, ticker("NEW"));
+        let second = rename(8, ticker("NEWER"));
+        state.extend(Txid::from_inner([5u8; 32]), &first).unwrap();
+        state.extend(Txid::from_inner([6u8; 32]), &second).unwrap();
+        let asset = Asset::with_genesis_state(&genesis, state.clone()).unwrap();
+        assert_eq!(asset.ticker().as_str(), "NEWER");
+        assert_eq!(asset.name().as_str(), "Test asset");
+        let history = asset
+            .nomination_history()
+            .iter()
+            .map(|change| (change.node_id, change.previous.ticker.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            history,
+            vec![(first.node_id(), "TCKR"), (second.node_id(), "NEW")]
+        );
+        assert_eq!(asset.nomination_history()[0].previous, genesis_nomination);
+
+        let mut nonconforming = state.clone();
+        nonconforming
+            .extend(Txid::from_inner([8u8; 32]), &rename(10, ticker("new")))
+            .unwrap();
+        let asset = Asset::with_genesis_state(&genesis, nonconforming).unwrap();
+        assert_eq!(asset.ticker(), &Nominal::Nonconforming(s!("new")));
+        assert!(!asset.nomination().is_conforming());
+        assert!(asset.to_string().contains("warning"));
+
+        let precision = Metadata::from_inner(bmap! {
+            u16::from(FieldType::Precision) => vec![data::Revealed::U8(2)]
+        });
+        let third = rename(9, precision);
+        state.extend(Txid::from_inner([7u8; 32]), &third).unwrap();
+        assert_eq!(
+            Asset::with_genesis_state(&genesis, state),
+            Err(Error::PrecisionChange {
+                node_id: third.node_id(),
+                current: 8,
+                requested: 2
+            })
+        );
+    }
+
+    #[test]
+    fn test_issue_errors() {
+        let allocated = builder().allocate(seal(1), Amount::from(1));
+        let testnet = Chain::Testnet3;
+        let err = |builder: IssueBuilder| builder.finish(testnet.clone()).unwrap_err();
+
+        let unnamed = IssueBuilder::with(SchemaId::default(), true, true)
+            .ticker("TCKR".parse().unwrap())
+            .allocate(seal(1), Amount::from(1));
+        assert_eq!(
+            err(unnamed.clone()),
+            IssueError::InvalidNomination(NominationError::EmptyName)
+        );
+        let unnamed = IssueBuilder {
+            nomination: Nomination {
+                ticker: Nominal::Nonconforming(empty!()),
+                ..unnamed.nomination
+            },
+            ..unnamed
+        };
+        assert_eq!(
+            err(unnamed),
+            IssueError::InvalidNomination(NominationError::EmptyTicker)
+        );
+        assert_eq!(err(builder()), IssueError::NoAllocations);
+        assert_eq!(
+            err(allocated.clone().allocate(seal(2), Amount::ZERO)),
+            IssueError::ZeroAmount
+        );
+        assert_eq!(
+            err(allocated.clone().allocate(seal(1), Amount::from(1))),
+            IssueError::DuplicateSeal(seal(1))
+        );
+        assert_eq!(
+            err(allocated.clone().allocate(seal(2), Amount::from(u64::MAX))),
+            IssueError::Overflow
+        );
+
+        let mut no_inflation = allocated.inflation_allowance(seal(3), Amount::from(1));
+        no_inflation.inflation_right = false;
+        assert_eq!(err(no_inflation), IssueError::InflationUnsupported);
+    }
+}
@@ -11,3 +11,37 @@
 
 pub mod amount;
 pub mod allocation;
+mod asset;
+mod audit;
+mod blinding;
+mod builder;
+mod burn;
+mod inflation;
+mod invoice;
+mod issue;
+mod nomination;
+mod payment;
+mod pedersen;
+mod registry;
+mod renomination;
+pub mod schema;
+mod selection;
+
+pub use amount::{Amount, AmountError};
+pub use asset::{Asset, Error, HistoryEntry, OutpointFilter};
+pub use audit::{audit_supply, AuditError, SupplyAudit};
+pub use blinding::{BlindedSeal, SealCollision, SealSecrets};
+pub use builder::{BuilderError, Change, DustPolicy, TransferOptions, TransitionBuilder};
+pub use burn::{BurnBuilder, BurnError};
+pub use inflation::{InflationBuilder, InflationError};
+pub use invoice::{Beneficiary, Invoice, InvoiceMismatch};
+pub use issue::{IssueBuilder, IssueError};
+pub use nomination::{
+    validate_name, validate_precision, validate_ticker, AssetName, Nominal, NominationError,
+    Precision, Ticker, MAX_NAME_LEN, MAX_PRECISION, MAX_TICKER_LEN,
+};
+pub use payment::{verify_payment, verify_payment_with, AmountMatch, PaymentError, PaymentProof};
+pub use pedersen::{verify_balance, BlindingError, BlindingFactors};
+pub use registry::{AssetRegistry, RegistryError};
+pub use renomination::{Nomination, NominationChange, RenominationBuilder, RenominationError};
+pub use selection::{coin_select, Selection, SelectionError, SelectionStrategy};
@@ -0,0 +1,406 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Validation rules for the RGB20 asset ticker, name and precision.
+
+use std::convert::TryFrom;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::str::FromStr;
+
+use strict_encoding::{StrictDecode, StrictEncode};
+
+/// Maximal length of the RGB20 asset ticker
+pub const MAX_TICKER_LEN: usize = 8;
+
+/// Maximal length of the RGB20 asset name
+pub const MAX_NAME_LEN: usize = 256;
+
+/// Maximal decimal precision of the RGB20 asset amounts
+pub const MAX_PRECISION: u8 = 18;
+
+/// Errors validating nominal data of RGB20 asset
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum NominationError {
+    /// asset ticker must be non-empty
+    EmptyTicker,
+
+    /// asset ticker '{0}' exceeds 8 characters
+    TickerTooLong(String),
+
+    /// asset ticker '{0}' must consist of uppercase letters A-Z and digits
+    /// only
+    InvalidTicker(String),
+
+    /// asset ticker '{0}' must not start with a digit
+    TickerStartsWithDigit(String),
+
+    /// asset name must be non-empty
+    EmptyName,
+
+    /// asset name exceeds 256 characters
+    NameTooLong,
+
+    /// asset name '{0}' contains non-printable characters
+    InvalidName(String),
+
+    /// asset name '{0}' has leading or trailing whitespace
+    UntrimmedName(String),
+
+    /// asset precision {0} exceeds the maximal precision of 18 decimal digits
+    InvalidPrecision(u8),
+}
+
+/// Validates asset ticker, which must consist of 1 to 8 uppercase letters A-Z
+/// and digits and must not start with a digit
+pub fn validate_ticker(ticker: &str) -> Result<Ticker, NominationError> {
+    let first = ticker.chars().next().ok_or(NominationError::EmptyTicker)?;
+    if ticker.chars().count() > MAX_TICKER_LEN {
+        return Err(NominationError::TickerTooLong(ticker.to_owned()));
+    }
+    if !ticker
+        .chars()
+        .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+    {
+        return Err(NominationError::InvalidTicker(ticker.to_owned()));
+    }
+    if first.is_ascii_digit() {
+        return Err(NominationError::TickerStartsWithDigit(ticker.to_owned()));
+    }
+    Ok(Ticker(ticker.to_owned()))
+}
+
+/// Validates full asset name, which must be non-empty, must not exceed 256
+/// characters, must not contain control characters and must not have leading
+/// or trailing whitespace
+pub fn validate_name(name: &str) -> Result<AssetName, NominationError> {
+    if name.is_empty() {
+        return Err(NominationError::EmptyName);
+    }
+    if name.chars().count() > MAX_NAME_LEN {
+        return Err(NominationError::NameTooLong);
+    }
+    if name.chars().any(char::is_control) {
+        return Err(NominationError::InvalidName(name.to_owned()));
+    }
+    if name.trim() != name {
+        return Err(NominationError::UntrimmedName(name.to_owned()));
+    }
+    Ok(AssetName(name.to_owned()))
+}
+
+/// Validates decimal precision of the asset amounts, which may not exceed 18
+/// decimal digits
+pub fn validate_precision(precision: u8) -> Result<Precision, NominationError> {
+    if precision > MAX_PRECISION {
+        return Err(NominationError::InvalidPrecision(precision));
+    }
+    Ok(Precision(precision))
+}
+
+/// Asset ticker conforming to the RGB20 rules, see [`validate_ticker`]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate", try_from = "String", into = "String")
+)]
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
+#[display(inner)]
+pub struct Ticker(String);
+
+impl Ticker {
+    /// Returns ticker as a string slice
+    #[inline]
+    pub fn as_str(&self) -> &str { &self.0 }
+}
+
+impl AsRef<str> for Ticker {
+    #[inline]
+    fn as_ref(&self) -> &str { &self.0 }
+}
+
+impl FromStr for Ticker {
+    type Err = NominationError;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, Self::Err> { validate_ticker(s) }
+}
+
+impl TryFrom<String> for Ticker {
+    type Error = NominationError;
+
+    #[inline]
+    fn try_from(ticker: String) -> Result<Self, Self::Error> { validate_ticker(&ticker) }
+}
+
+impl From<Ticker> for String {
+    #[inline]
+    fn from(ticker: Ticker) -> Self { ticker.0 }
+}
+
+/// Full asset name conforming to the RGB20 rules, see [`validate_name`]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate", try_from = "String", into = "String")
+)]
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
+#[display(inner)]
+pub struct AssetName(String);
+
+impl AssetName {
+    /// Returns name as a string slice
+    #[inline]
+    pub fn as_str(&self) -> &str { &self.0 }
+}
+
+impl AsRef<str> for AssetName {
+    #[inline]
+    fn as_ref(&self) -> &str { &self.0 }
+}
+
+impl FromStr for AssetName {
+    type Err = NominationError;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, Self::Err> { validate_name(s) }
+}
+
+impl TryFrom<String> for AssetName {
+    type Error = NominationError;
+
+    #[inline]
+    fn try_from(name: String) -> Result<Self, Self::Error> { validate_name(&name) }
+}
+
+impl From<AssetName> for String {
+    #[inline]
+    fn from(name: AssetName) -> Self { name.0 }
+}
+
+/// Decimal precision of the asset amounts conforming to the RGB20 rules, see
+/// [`validate_precision`]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate", try_from = "u8", into = "u8")
+)]
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display, Default)]
+#[display(inner)]
+pub struct Precision(u8);
+
+impl TryFrom<u8> for Precision {
+    type Error = NominationError;
+
+    #[inline]
+    fn try_from(precision: u8) -> Result<Self, Self::Error> { validate_precision(precision) }
+}
+
+impl From<Precision> for u8 {
+    #[inline]
+    fn from(precision: Precision) -> Self { precision.0 }
+}
+
+impl StrictEncode for Precision {
+    #[inline]
+    fn strict_encode<E: io::Write>(&self, e: E) -> Result<usize, strict_encoding::Error> {
+        self.0.strict_encode(e)
+    }
+}
+
+impl StrictDecode for Precision {
+    fn strict_decode<D: io::Read>(d: D) -> Result<Self, strict_encoding::Error> {
+        validate_precision(u8::strict_decode(d)?)
+            .map_err(|err| strict_encoding::Error::DataIntegrityError(err.to_string()))
+    }
+}
+
+/// Nominal data field, which may not conform to the RGB20 rules when it is
+/// taken from an existing contract.
+///
+/// Wallets should display non-conforming values with a warning, since they
+/// may be used to mimic other assets.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate", from = "String", into = "String")
+)]
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Nominal<T>
+where T: FromStr<Err = NominationError> + AsRef<str> + Clone
+{
+    /// Value conforming to the RGB20 rules
+    Conforming(T),
+
+    /// Value violating the RGB20 rules
+    Nonconforming(String),
+}
+
+impl<T> Nominal<T>
+where T: FromStr<Err = NominationError> + AsRef<str> + Clone
+{
+    /// Validates the raw `value`, marking it as non-conforming if it violates
+    /// the RGB20 rules
+    pub fn with(value: &str) -> Nominal<T> {
+        value
+            .parse()
+            .map(Nominal::Conforming)
+            .unwrap_or_else(|_| Nominal::Nonconforming(value.to_owned()))
+    }
+
+    /// Returns the raw value
+    pub fn as_str(&self) -> &str {
+        match self {
+            Nominal::Conforming(value) => value.as_ref(),
+            Nominal::Nonconforming(value) => value,
+        }
+    }
+
+    /// Detects whether the value conforms to the RGB20 rules
+    #[inline]
+    pub fn is_conforming(&self) -> bool { matches!(self, Nominal::Conforming(_)) }
+
+    /// Returns the conforming value, re-validating the non-conforming one
+    pub fn to_conforming(&self) -> Result<T, NominationError> {
+        match self {
+            Nominal::Conforming(value) => Ok(value.clone()),
+            Nominal::Nonconforming(value) => value.parse(),
+        }
+    }
+}
+
+impl<T> Display for Nominal<T>
+where T: FromStr<Err = NominationError> + AsRef<str> + Clone
+{
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result { f.write_str(self.as_str()) }
+}
+
+impl<T> From<T> for Nominal<T>
+where T: FromStr<Err = NominationError> + AsRef<str> + Clone
+{
+    #[inline]
+    fn from(value: T) -> Self { Nominal::Conforming(value) }
+}
+
+impl<T> From<String> for Nominal<T>
+where T: FromStr<Err = NominationError> + AsRef<str> + Clone
+{
+    #[inline]
+    fn from(value: String) -> Self { Nominal::with(&value) }
+}
+
+impl<T> From<Nominal<T>> for String
+where T: FromStr<Err = NominationError> + AsRef<str> + Clone
+{
+    #[inline]
+    fn from(value: Nominal<T>) -> Self { value.as_str().to_owned() }
+}
+
+impl<T> StrictEncode for Nominal<T>
+where T: FromStr<Err = NominationError> + AsRef<str> + Clone
+{
+    #[inline]
+    fn strict_encode<E: io::Write>(&self, e: E) -> Result<usize, strict_encoding::Error> {
+        self.as_str().to_owned().strict_encode(e)
+    }
+}
+
+impl<T> StrictDecode for Nominal<T>
+where T: FromStr<Err = NominationError> + AsRef<str> + Clone
+{
+    #[inline]
+    fn strict_decode<D: io::Read>(d: D) -> Result<Self, strict_encoding::Error> {
+        Ok(Nominal::with(&String::strict_decode(d)?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ticker() {
+        assert_eq!(validate_ticker("TCKR").unwrap().as_str(), "TCKR");
+        assert_eq!(validate_ticker("A1B2C3D4").unwrap().as_str(), "A1B2C3D4");
+        assert_eq!(validate_ticker(""), Err(NominationError::EmptyTicker));
+        assert_eq!(
+            validate_ticker("TOOLONGTICKER"),
+            Err(NominationError::TickerTooLong(s!("TOOLONGTICKER")))
+        );
+        for invalid in ["tckr", "T-1", "TÜR", "T KR"] {
+            assert_eq!(
+                validate_ticker(invalid),
+                Err(NominationError::InvalidTicker(invalid.to_owned()))
+            );
+        }
+        assert_eq!(
+            validate_ticker("1TCKR"),
+            Err(NominationError::TickerStartsWithDigit(s!("1TCKR")))
+        );
+    }
+
+    #[test]
+    fn test_name() {
+        assert_eq!(validate_name("Test asset").unwrap().as_str(), "Test asset");
+        assert!(validate_name(&"a".repeat(MAX_NAME_LEN)).is_ok());
+        assert_eq!(validate_name(""), Err(NominationError::EmptyName));
+        assert_eq!(
+            validate_name(&"a".repeat(MAX_NAME_LEN + 1)),
+            Err(NominationError::NameTooLong)
+        );
+        assert_eq!(
+            validate_name("Test\nasset"),
+            Err(NominationError::InvalidName(s!("Test\nasset")))
+        );
+        assert_eq!(
+            validate_name(" Test asset"),
+            Err(NominationError::UntrimmedName(s!(" Test asset")))
+        );
+    }
+
+    #[test]
+    fn test_precision() {
+        assert_eq!(validate_precision(0).map(u8::from), Ok(0));
+        assert_eq!(validate_precision(18).map(u8::from), Ok(18));
+        assert_eq!(
+            validate_precision(19),
+            Err(NominationError::InvalidPrecision(19))
+        );
+
+        let data = 19u8.strict_serialize().unwrap();
+        assert!(Precision::strict_deserialize(data).is_err());
+    }
+
+    #[test]
+    fn test_nonconforming() {
+        let conforming = Nominal::<Ticker>::with("TCKR");
+        assert!(conforming.is_conforming());
+        assert_eq!(conforming, Nominal::from(validate_ticker("TCKR").unwrap()));
+
+        let nonconforming = Nominal::<Ticker>::with("tckr");
+        assert_eq!(nonconforming, Nominal::Nonconforming(s!("tckr")));
+        assert_eq!(nonconforming.to_string(), "tckr");
+        assert_eq!(
+            nonconforming.to_conforming(),
+            Err(NominationError::InvalidTicker(s!("tckr")))
+        );
+
+        let data = s!("Test\u{0}asset").strict_serialize().unwrap();
+        let name = Nominal::<AssetName>::strict_deserialize(data).unwrap();
+        assert_eq!(name, Nominal::Nonconforming(s!("Test\u{0}asset")));
+        assert_eq!(
+            name.strict_serialize().unwrap(),
+            s!("Test\u{0}asset").strict_serialize().unwrap()
+        );
+    }
+}
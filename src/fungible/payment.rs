@@ -0,0 +1,256 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Verification that a received state transfer pays an invoice.
+
+use bitcoin::{OutPoint, Txid};
+use commit_verify::CommitConceal;
+#[cfg(feature = "serde")]
+use serde_with::{As, DisplayFromStr};
+
+use super::amount::Amount;
+use super::blinding::SealSecrets;
+use super::invoice::{Beneficiary, Invoice};
+use super::schema::OwnedRightType;
+use crate::{
+    seal, AnchorCloseMethod, Assignment, AssignmentVec, CloseMethod, ContractId, Node, NodeId,
+    StateTransfer,
+};
+
+/// Rule for comparing the paid amount with the amount requested by the
+/// invoice
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(crate = "serde_crate"))]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Display)]
+#[display(Debug)]
+pub enum AmountMatch {
+    /// Payment must be equal to or exceed the requested amount
+    AtLeast,
+
+    /// Payment must be exactly equal to the requested amount
+    Exact,
+}
+
+impl Default for AmountMatch {
+    #[inline]
+    fn default() -> Self { AmountMatch::AtLeast }
+}
+
+/// Errors verifying invoice payment with [`verify_payment`]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum PaymentError {
+    /// transfer belongs to a different contract {0}
+    ContractMismatch(ContractId),
+
+    /// transfer does not assign assets to the invoice beneficiary seal
+    NoBeneficiary,
+
+    /// secret of the blinded seal {0} is unknown
+    UnknownSeal(seal::Confidential),
+
+    /// transfer conceals the amount assigned to the beneficiary seal
+    ConcealedAmount,
+
+    /// transfer pays {paid}, while the invoice requests {requested}
+    Underpayment { requested: Amount, paid: Amount },
+
+    /// transfer pays {paid}, while the invoice requests exactly {requested}
+    Overpayment { requested: Amount, paid: Amount },
+
+    /// invoice requires {required} close method, while the payment is
+    /// anchored with {used}
+    CloseMethodMismatch {
+        required: CloseMethod,
+        used: CloseMethod,
+    },
+
+    /// invoice expired at {expiry}, before the witness transaction was mined
+    /// at {paid_at}
+    Expired { expiry: i64, paid_at: i64 },
+
+    /// total amount assigned to the beneficiary exceeds 2^64 atomic units
+    Overflow,
+}
+
+/// Proof that the state transfer pays the invoice
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(crate = "serde_crate"))]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct PaymentProof {
+    /// Id of the state transition assigning the assets to the beneficiary
+    #[cfg_attr(feature = "serde", serde(with = "As::<DisplayFromStr>"))]
+    pub node_id: NodeId,
+
+    /// Amount assigned to the beneficiary
+    pub amount: Amount,
+
+    /// Transaction output which receives the assets
+    #[cfg_attr(feature = "serde", serde(with = "As::<DisplayFromStr>"))]
+    pub outpoint: OutPoint,
+
+    /// Witness transaction of the paying state transition
+    #[cfg_attr(feature = "serde", serde(with = "As::<DisplayFromStr>"))]
+    pub witness: Txid,
+}
+
+/// Verifies that the `transfer` pays the `invoice`, accepting payments which
+/// are equal to or exceed the requested amount and not checking the invoice
+/// expiry. See [`verify_payment_with`] for the details.
+#[inline]
+pub fn verify_payment(
+    invoice: &Invoice,
+    transfer: &StateTransfer,
+    secrets: &SealSecrets,
+) -> Result<PaymentProof, PaymentError> {
+    verify_payment_with(invoice, transfer, secrets, AmountMatch::AtLeast, &|_| None)
+}
+
+/// Verifies that the `transfer` pays the `invoice`.
+///
+/// The transfer endpoints must include the invoice beneficiary seal, which is
+/// revealed using the seal `secrets` for the blinded beneficiaries. The
+/// amount assigned to the seal by the endpoint transition is compared with the
+/// requested one according to the `amount_match` rule. If `timestamps`
+/// resolves the mining time of the witness transaction, the payment must be
+/// mined before the invoice expiry. If the invoice requires a close method,
+/// the paying bundle must be anchored with it.
+///
+/// The check does not validate the transfer, which has to be done before
+/// accepting it.
+pub fn verify_payment_with(
+    invoice: &Invoice,
+    transfer: &StateTransfer,
+    secrets: &SealSecrets,
+    amount_match: AmountMatch,
+    timestamps: &impl Fn(Txid) -> Option<i64>,
+) -> Result<PaymentProof, PaymentError> {
+    if transfer.contract_id() != invoice.contract_id {
+        return Err(PaymentError::ContractMismatch(transfer.contract_id()));
+    }
+
+    let confidential = invoice.beneficiary.to_confidential_seal();
+    let bundle_id = transfer
+        .endpoints
+        .iter()
+        .find(|(_, endpoint)| endpoint.commit_conceal() == confidential)
+        .map(|(bundle_id, _)| *bundle_id)
+        .ok_or(PaymentError::NoBeneficiary)?;
+    let revealed = match invoice.beneficiary {
+        Beneficiary::Seal(seal) => seal,
+        Beneficiary::BlindedSeal(seal) => {
+            *secrets.get(&seal).ok_or(PaymentError::UnknownSeal(seal))?
+        }
+    };
+    let (anchor, bundle) = transfer
+        .anchored_bundles
+        .iter()
+        .find(|(_, bundle)| bundle.bundle_id() == bundle_id)
+        .ok_or(PaymentError::NoBeneficiary)?;
+    if let Some(required) = invoice.close_method {
+        let used = anchor.close_method();
+        if used != required {
+            return Err(PaymentError::CloseMethodMismatch { required, used });
+        }
+    }
+
+    // The first endpoint transition assigning assets to the beneficiary is
+    // the paying one
+    let assets = u16::from(OwnedRightType::Assets);
+    let mut payment = None;
+    for transition in bundle.known_transitions() {
+        let assignments = match transition.owned_rights_by_type(assets) {
+            Some(AssignmentVec::Fungible(assignments)) => assignments,
+            _ => continue,
+        };
+        let mut paid = None;
+        for assignment in assignments
+            .iter()
+            .filter(|assignment| assignment.to_confidential_seal() == confidential)
+        {
+            let value = match assignment {
+                Assignment::Revealed { assigned_state, .. }
+                | Assignment::ConfidentialSeal { assigned_state, .. } => assigned_state.value,
+                _ => return Err(PaymentError::ConcealedAmount),
+            };
+            let sum = paid.unwrap_or(Amount::ZERO);
+            paid = Some(
+                sum.checked_add(Amount::from(value))
+                    .map_err(|_| PaymentError::Overflow)?,
+            );
+        }
+        if let Some(paid) = paid {
+            payment = Some((transition.node_id(), paid));
+            break;
+        }
+    }
+    let (node_id, amount) = payment.ok_or(PaymentError::NoBeneficiary)?;
+
+    if let Some(requested) = invoice.amount {
+        check_amount(requested, amount, amount_match)?;
+    }
+    let witness = anchor.txid;
+    if let (Some(expiry), Some(paid_at)) = (invoice.expiry, timestamps(witness)) {
+        if invoice.is_expired_at(paid_at) {
+            return Err(PaymentError::Expired { expiry, paid_at });
+        }
+    }
+
+    Ok(PaymentProof {
+        node_id,
+        amount,
+        outpoint: revealed.outpoint_or(witness),
+        witness,
+    })
+}
+
+fn check_amount(
+    requested: Amount,
+    paid: Amount,
+    amount_match: AmountMatch,
+) -> Result<(), PaymentError> {
+    if paid < requested {
+        return Err(PaymentError::Underpayment { requested, paid });
+    }
+    if amount_match == AmountMatch::Exact && paid > requested {
+        return Err(PaymentError::Overpayment { requested, paid });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_amount_match() {
+        let requested = Amount::from(1000);
+        let less = Amount::from(999);
+        let more = Amount::from(1001);
+        for amount_match in [AmountMatch::AtLeast, AmountMatch::Exact] {
+            assert_eq!(check_amount(requested, requested, amount_match), Ok(()));
+            assert_eq!(
+                check_amount(requested, less, amount_match),
+                Err(PaymentError::Underpayment {
+                    requested,
+                    paid: less
+                })
+            );
+        }
+        assert_eq!(check_amount(requested, more, AmountMatch::AtLeast), Ok(()));
+        assert_eq!(
+            check_amount(requested, more, AmountMatch::Exact),
+            Err(PaymentError::Overpayment {
+                requested,
+                paid: more
+            })
+        );
+        assert_eq!(AmountMatch::default(), AmountMatch::AtLeast);
+    }
+}
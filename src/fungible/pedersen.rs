@@ -0,0 +1,215 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Balancing of blinding factors for the Pedersen commitments to the asset
+//! amounts.
+
+use bitcoin::secp256k1::rand::{thread_rng, RngCore};
+use rgb_core::secp256k1zkp::{self, ContextFlag, Secp256k1};
+use rgb_core::value;
+
+use super::amount::Amount;
+
+/// Errors balancing blinding factors with [`BlindingFactors`]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum BlindingError {
+    /// outputs amount {outputs} does not match inputs amount {inputs}
+    AmountMismatch { inputs: Amount, outputs: Amount },
+
+    /// total amount of the inputs or outputs exceeds 2^64 atomic units
+    Overflow,
+
+    /// output blinding factors cancel out the input blinding factors, so the
+    /// balancing blinding factor can't be computed
+    Unbalanceable,
+}
+
+/// Generator of the output amounts blinded in such a way that the sum of
+/// their Pedersen commitments is equal to the sum of the input commitments.
+///
+/// All outputs but the last one receive random blinding factors; the last
+/// output, constructed with [`BlindingFactors::close`], receives the
+/// balancing blinding factor.
+#[derive(Clone, PartialEq, Debug)]
+pub struct BlindingFactors {
+    inputs: Vec<value::Revealed>,
+    outputs: Vec<value::Revealed>,
+}
+
+impl BlindingFactors {
+    /// Constructs generator for the outputs balancing the revealed `inputs`
+    pub fn new(inputs: &[value::Revealed]) -> BlindingFactors {
+        BlindingFactors {
+            inputs: inputs.to_vec(),
+            outputs: vec![],
+        }
+    }
+
+    /// Returns outputs generated so far, except the closing one
+    #[inline]
+    pub fn outputs(&self) -> &[value::Revealed] { &self.outputs }
+
+    /// Generates output with the `amount` and a random blinding factor,
+    /// returning both the blinding factor and the revealed output amount
+    #[inline]
+    pub fn add_output(&mut self, amount: Amount) -> (value::BlindingFactor, value::Revealed) {
+        self.add_output_with_rng(amount, &mut thread_rng())
+    }
+
+    /// Generates output with the `amount` and a blinding factor produced by
+    /// the `rng`, returning both the blinding factor and the revealed output
+    /// amount
+    pub fn add_output_with_rng(
+        &mut self,
+        amount: Amount,
+        rng: &mut impl RngCore,
+    ) -> (value::BlindingFactor, value::Revealed) {
+        let revealed = value::Revealed::with_amount(amount.atomic_value(), rng);
+        self.outputs.push(revealed);
+        (revealed.blinding, revealed)
+    }
+
+    /// Generates the last output with the `amount`, which must be equal to
+    /// the inputs amount not yet assigned to the outputs, and the blinding
+    /// factor balancing all the commitments
+    pub fn close(self, amount: Amount) -> Result<value::Revealed, BlindingError> {
+        let sum = |values: &[value::Revealed]| {
+            values
+                .iter()
+                .try_fold(Amount::ZERO, |sum, revealed| {
+                    sum.checked_add(Amount::from(revealed.value))
+                })
+                .map_err(|_| BlindingError::Overflow)
+        };
+        let inputs = sum(&self.inputs)?;
+        let outputs = sum(&self.outputs)?
+            .checked_add(amount)
+            .map_err(|_| BlindingError::Overflow)?;
+        if inputs != outputs {
+            return Err(BlindingError::AmountMismatch { inputs, outputs });
+        }
+
+        let secp = Secp256k1::with_caps(ContextFlag::Commit);
+        let blindings = |values: &[value::Revealed]| {
+            values
+                .iter()
+                .map(|revealed| revealed.blinding)
+                .collect::<Vec<_>>()
+        };
+        let blinding = secp
+            .blind_sum(blindings(&self.inputs), blindings(&self.outputs))
+            .map_err(|_| BlindingError::Unbalanceable)?;
+        if blinding == secp256k1zkp::key::ZERO_KEY {
+            return Err(BlindingError::Unbalanceable);
+        }
+        Ok(value::Revealed {
+            value: amount.atomic_value(),
+            blinding,
+        })
+    }
+}
+
+/// Checks that the sum of the `inputs` commitments is equal to the sum of the
+/// `outputs` commitments, i.e. that the outputs don't create or destroy assets
+pub fn verify_balance(inputs: &[value::Confidential], outputs: &[value::Confidential]) -> bool {
+    let commitments = |values: &[value::Confidential]| {
+        values
+            .iter()
+            .map(|confidential| confidential.commitment)
+            .collect::<Vec<_>>()
+    };
+    value::Confidential::verify_commit_sum(commitments(inputs), commitments(outputs))
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoin::secp256k1::rand::rngs::StdRng;
+    use bitcoin::secp256k1::rand::SeedableRng;
+    use commit_verify::CommitConceal;
+
+    use super::*;
+
+    fn inputs(values: &[u64]) -> Vec<value::Revealed> {
+        values
+            .iter()
+            .map(|value| value::Revealed::with_amount(*value, &mut thread_rng()))
+            .collect()
+    }
+
+    fn conceal(values: &[value::Revealed]) -> Vec<value::Confidential> {
+        values.iter().map(CommitConceal::commit_conceal).collect()
+    }
+
+    #[test]
+    fn test_single_output() {
+        let inputs = inputs(&[600, 400]);
+        let factors = BlindingFactors::new(&inputs);
+        let output = factors.close(Amount::from(1000)).unwrap();
+        assert_eq!(output.value, 1000);
+        assert!(verify_balance(&conceal(&inputs), &conceal(&[output])));
+    }
+
+    #[test]
+    fn test_multiple_outputs() {
+        let inputs = inputs(&[1000]);
+        let mut factors = BlindingFactors::new(&inputs);
+        let (blinding, first) = factors.add_output(Amount::from(300));
+        assert_eq!(blinding, first.blinding);
+        let (_, second) = factors.add_output(Amount::from(500));
+        assert_eq!(factors.outputs(), &[first, second]);
+        let last = factors.close(Amount::from(200)).unwrap();
+        let outputs = conceal(&[first, second, last]);
+        assert!(verify_balance(&conceal(&inputs), &outputs));
+        assert!(!verify_balance(&conceal(&inputs), &outputs[..2]));
+    }
+
+    #[test]
+    fn test_external_rng() {
+        let inputs = inputs(&[1000]);
+        let output = |seed: u64| {
+            let mut factors = BlindingFactors::new(&inputs);
+            let mut rng = StdRng::seed_from_u64(seed);
+            factors.add_output_with_rng(Amount::from(300), &mut rng).1
+        };
+        assert_eq!(output(1), output(1));
+        assert_ne!(output(1).blinding, output(2).blinding);
+    }
+
+    #[test]
+    fn test_zero_value_outputs() {
+        let inputs = inputs(&[500]);
+        let mut factors = BlindingFactors::new(&inputs);
+        let (_, zero) = factors.add_output(Amount::ZERO);
+        let last = factors.close(Amount::from(500)).unwrap();
+        assert!(verify_balance(&conceal(&inputs), &conceal(&[zero, last])));
+
+        let mut factors = BlindingFactors::new(&inputs);
+        let (_, output) = factors.add_output(Amount::from(500));
+        let zero = factors.close(Amount::ZERO).unwrap();
+        assert_eq!(zero.value, 0);
+        assert!(verify_balance(&conceal(&inputs), &conceal(&[output, zero])));
+    }
+
+    #[test]
+    fn test_amount_mismatch() {
+        let inputs = inputs(&[500]);
+        let mut factors = BlindingFactors::new(&inputs);
+        factors.add_output(Amount::from(300));
+        assert_eq!(
+            factors.close(Amount::from(300)),
+            Err(BlindingError::AmountMismatch {
+                inputs: Amount::from(500),
+                outputs: Amount::from(600)
+            })
+        );
+    }
+}
@@ -0,0 +1,213 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! In-process registry of the RGB20 assets known to a wallet.
+
+use std::collections::{btree_map, BTreeMap};
+use std::convert::TryFrom;
+use std::io;
+
+use strict_encoding::{StrictDecode, StrictEncode};
+
+use super::asset::{self, Asset};
+use crate::{ContractId, StateTransfer};
+
+/// Errors operating [`AssetRegistry`]
+#[derive(Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum RegistryError {
+    /// registry contains more than one asset with contract id {0}
+    DuplicateContract(ContractId),
+
+    /// registry does not contain asset with contract id {0}
+    UnknownContract(ContractId),
+
+    /// unable to update asset: {0}
+    #[from]
+    Asset(asset::Error),
+
+    /// registry data can't be encoded or decoded: {0}
+    #[from]
+    Encoding(strict_encoding::Error),
+}
+
+/// Registry of the RGB20 assets indexed by their contract ids.
+///
+/// Asset tickers are not unique, so the assets with the same ticker are kept
+/// side by side and may be distinguished only by their contract ids.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate", try_from = "Vec<Asset>", into = "Vec<Asset>")
+)]
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct AssetRegistry(BTreeMap<ContractId, Asset>);
+
+impl AssetRegistry {
+    /// Constructs empty registry
+    #[inline]
+    pub fn new() -> AssetRegistry { AssetRegistry::default() }
+
+    /// Returns number of the assets in the registry
+    #[inline]
+    pub fn len(&self) -> usize { self.0.len() }
+
+    /// Detects whether the registry has no assets
+    #[inline]
+    pub fn is_empty(&self) -> bool { self.0.is_empty() }
+
+    /// Adds asset to the registry, returning previously registered version of
+    /// the same asset, if any
+    #[inline]
+    pub fn insert(&mut self, asset: Asset) -> Option<Asset> {
+        self.0.insert(asset.contract_id(), asset)
+    }
+
+    /// Removes asset with the `contract_id` from the registry
+    #[inline]
+    pub fn remove(&mut self, contract_id: ContractId) -> Option<Asset> {
+        self.0.remove(&contract_id)
+    }
+
+    /// Returns asset with the `contract_id`, if it is registered
+    #[inline]
+    pub fn get(&self, contract_id: ContractId) -> Option<&Asset> { self.0.get(&contract_id) }
+
+    /// Iterates over the registered assets in the order of their contract ids
+    #[inline]
+    pub fn iter(&self) -> btree_map::Values<ContractId, Asset> { self.0.values() }
+
+    /// Returns all registered assets with the `ticker`, including the ones
+    /// with the same non-conforming ticker
+    pub fn find_ticker(&self, ticker: &str) -> Vec<&Asset> {
+        self.0
+            .values()
+            .filter(|asset| asset.ticker().as_str() == ticker)
+            .collect()
+    }
+
+    /// Updates allocations of the asset the `transfer` belongs to. The
+    /// consignment must be validated beforehand.
+    pub fn update_from_transfer(&mut self, transfer: &StateTransfer) -> Result<(), RegistryError> {
+        let contract_id = transfer.contract_id();
+        self.0
+            .get_mut(&contract_id)
+            .ok_or(RegistryError::UnknownContract(contract_id))?
+            .update_with_transfer(transfer)?;
+        Ok(())
+    }
+
+    /// Saves the registry with strict encoding, returning the number of
+    /// written bytes
+    pub fn save(&self, writer: impl io::Write) -> Result<usize, RegistryError> {
+        let assets = self.0.values().cloned().collect::<Vec<_>>();
+        Ok(assets.strict_encode(writer)?)
+    }
+
+    /// Loads strict-encoded registry, failing if it contains more than one
+    /// asset with the same contract id
+    pub fn load(reader: impl io::Read) -> Result<AssetRegistry, RegistryError> {
+        let assets = Vec::<Asset>::strict_decode(reader)?;
+        AssetRegistry::try_from(assets)
+    }
+}
+
+impl<'registry> IntoIterator for &'registry AssetRegistry {
+    type Item = &'registry Asset;
+    type IntoIter = btree_map::Values<'registry, ContractId, Asset>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter { self.iter() }
+}
+
+impl TryFrom<Vec<Asset>> for AssetRegistry {
+    type Error = RegistryError;
+
+    fn try_from(assets: Vec<Asset>) -> Result<Self, Self::Error> {
+        let mut registry = AssetRegistry::new();
+        for asset in assets {
+            let contract_id = asset.contract_id();
+            if registry.insert(asset).is_some() {
+                return Err(RegistryError::DuplicateContract(contract_id));
+            }
+        }
+        Ok(registry)
+    }
+}
+
+impl From<AssetRegistry> for Vec<Asset> {
+    #[inline]
+    fn from(registry: AssetRegistry) -> Self { registry.0.into_values().collect() }
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoin::hashes::Hash;
+    use bitcoin::{OutPoint, Txid};
+    use lnpbp::chain::Chain;
+
+    use super::*;
+    use crate::fungible::{validate_precision, Amount, IssueBuilder};
+    use crate::{seal, ContractState, SchemaId};
+
+    fn asset(ticker: &str, amount: u64) -> Asset {
+        let seal = seal::Revealed::from(OutPoint::new(Txid::from_inner([1u8; 32]), 0));
+        let (genesis, _) = IssueBuilder::with(SchemaId::default(), false, false)
+            .ticker(ticker.parse().unwrap())
+            .name("Test asset".parse().unwrap())
+            .precision(validate_precision(8).unwrap())
+            .allocate(seal, Amount::from(amount))
+            .finish(Chain::Testnet3)
+            .unwrap();
+        let state = ContractState::with_genesis(&genesis);
+        Asset::with_genesis_state(&genesis, state).unwrap()
+    }
+
+    #[test]
+    fn test_ticker_collisions() {
+        let mut registry = AssetRegistry::new();
+        let first = asset("TCKR", 1000);
+        let second = asset("TCKR", 2000);
+        assert!(registry.insert(first.clone()).is_none());
+        assert!(registry.insert(second.clone()).is_none());
+        assert!(registry.insert(asset("OTHER", 1000)).is_none());
+        assert_eq!(registry.len(), 3);
+
+        let mut found = registry.find_ticker("TCKR");
+        found.sort_by_key(|asset| asset.issued_supply());
+        assert_eq!(found, vec![&first, &second]);
+        assert!(registry.find_ticker("NONE").is_empty());
+        assert_eq!(registry.get(first.contract_id()), Some(&first));
+        assert_eq!(registry.iter().count(), 3);
+
+        assert_eq!(registry.remove(second.contract_id()), Some(second));
+        assert_eq!(registry.find_ticker("TCKR"), vec![&first]);
+    }
+
+    #[test]
+    fn test_save_load() {
+        let mut registry = AssetRegistry::new();
+        registry.insert(asset("TCKR", 1000));
+        registry.insert(asset("TCKR", 2000));
+        let mut data = vec![];
+        registry.save(&mut data).unwrap();
+        assert_eq!(AssetRegistry::load(data.as_slice()).unwrap(), registry);
+
+        let duplicate = asset("TCKR", 1000);
+        let contract_id = duplicate.contract_id();
+        let assets = vec![duplicate.clone(), duplicate];
+        let data = assets.strict_serialize().unwrap();
+        assert!(matches!(
+            AssetRegistry::load(data.as_slice()),
+            Err(RegistryError::DuplicateContract(id)) if id == contract_id
+        ));
+    }
+}
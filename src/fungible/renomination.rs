@@ -0,0 +1,323 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Nominal data of RGB20 assets and construction of renomination state
+//! transitions.
+
+use std::collections::BTreeMap;
+
+use amplify::Wrapper;
+#[cfg(feature = "serde")]
+use serde_with::{As, DisplayFromStr};
+
+use super::nomination::{AssetName, Nominal, NominationError, Precision, Ticker};
+use super::schema::{self, FieldType, OwnedRightType, TransitionType};
+use crate::{
+    data, seal, Assignment, AssignmentVec, Metadata, NodeId, NodeOutpoint, OwnedRights,
+    ParentOwnedRights, Schema, Transition,
+};
+
+/// Nominal data of RGB20 asset
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(crate = "serde_crate"))]
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Display)]
+#[derive(StrictEncode, StrictDecode)]
+#[display("{ticker} ({name}), precision {precision}")]
+pub struct Nomination {
+    /// Asset ticker
+    pub ticker: Nominal<Ticker>,
+
+    /// Full asset name
+    pub name: Nominal<AssetName>,
+
+    /// Decimal precision of the asset amounts
+    pub precision: Precision,
+}
+
+impl Nomination {
+    /// Constructs nominal data conforming to the RGB20 rules
+    pub fn with(ticker: Ticker, name: AssetName, precision: Precision) -> Nomination {
+        Nomination {
+            ticker: Nominal::Conforming(ticker),
+            name: Nominal::Conforming(name),
+            precision,
+        }
+    }
+
+    /// Detects whether both asset ticker and name conform to the RGB20 rules
+    #[inline]
+    pub fn is_conforming(&self) -> bool {
+        self.ticker.is_conforming() && self.name.is_conforming()
+    }
+
+    /// Returns nominal data with the ticker and name conforming to the RGB20
+    /// rules, re-validating non-conforming values. See [`validate_ticker`]
+    /// and [`validate_name`] for the rules.
+    ///
+    /// [`validate_ticker`]: super::validate_ticker
+    /// [`validate_name`]: super::validate_name
+    pub fn to_conforming(&self) -> Result<Nomination, NominationError> {
+        Ok(Nomination::with(
+            self.ticker.to_conforming()?,
+            self.name.to_conforming()?,
+            self.precision,
+        ))
+    }
+
+    /// Returns nomination updated with the ticker and name from the node
+    /// `metadata`, or `None` if the metadata do not contain any of them.
+    /// Non-conforming values are kept and marked as such.
+    pub fn renominated(&self, metadata: &BTreeMap<u16, Vec<data::Revealed>>) -> Option<Nomination> {
+        let field = |ty: FieldType| {
+            metadata
+                .get(&u16::from(ty))
+                .and_then(|values| values.first())
+        };
+        let mut nomination = self.clone();
+        let mut changed = false;
+        if let Some(data::Revealed::String(ticker)) = field(FieldType::Ticker) {
+            nomination.ticker = Nominal::with(ticker);
+            changed = true;
+        }
+        if let Some(data::Revealed::String(name)) = field(FieldType::Name) {
+            nomination.name = Nominal::with(name);
+            changed = true;
+        }
+        if changed {
+            Some(nomination)
+        } else {
+            None
+        }
+    }
+}
+
+/// Change of the asset nominal data by a renomination transition
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(crate = "serde_crate"))]
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[derive(StrictEncode, StrictDecode)]
+pub struct NominationChange {
+    /// Id of the renomination transition
+    #[cfg_attr(feature = "serde", serde(with = "As::<DisplayFromStr>"))]
+    pub node_id: NodeId,
+
+    /// Nominal data which were replaced by the transition
+    pub previous: Nomination,
+}
+
+/// Errors constructing renomination with [`RenominationBuilder`]
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum RenominationError {
+    /// schema does not define RGB20 asset renomination
+    NotFungible,
+
+    /// invalid nominal data: {0}
+    #[from]
+    InvalidNomination(NominationError),
+
+    /// renomination does not change the asset nominal data
+    NoChanges,
+
+    /// renomination may not change asset precision from {current} to
+    /// {requested}, since this changes amounts of all existing allocations
+    PrecisionChange {
+        current: Precision,
+        requested: Precision,
+    },
+}
+
+/// Builder for the RGB20 renomination state transitions.
+///
+/// Asset precision can't be changed by a renomination: the precision defines
+/// how the atomic values of all existing allocations are displayed, so its
+/// change would silently change the amounts owned by all asset holders.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RenominationBuilder {
+    right: NodeOutpoint,
+    current: Nomination,
+    nomination: Nomination,
+    next_right: Option<seal::Revealed>,
+}
+
+impl RenominationBuilder {
+    /// Constructs builder spending the renomination `right` of the asset with
+    /// the `current` nominal data
+    pub fn new(right: NodeOutpoint, current: Nomination) -> RenominationBuilder {
+        RenominationBuilder {
+            right,
+            nomination: current.clone(),
+            current,
+            next_right: None,
+        }
+    }
+
+    /// Sets new asset ticker
+    pub fn ticker(mut self, ticker: Ticker) -> Self {
+        self.nomination.ticker = Nominal::Conforming(ticker);
+        self
+    }
+
+    /// Sets new full asset name
+    pub fn name(mut self, name: AssetName) -> Self {
+        self.nomination.name = Nominal::Conforming(name);
+        self
+    }
+
+    /// Sets asset precision, which must match the current precision
+    pub fn precision(mut self, precision: Precision) -> Self {
+        self.nomination.precision = precision;
+        self
+    }
+
+    /// Assigns the renomination right onward to the `seal`, allowing future
+    /// renominations
+    pub fn assign_right(mut self, seal: seal::Revealed) -> Self {
+        self.next_right = Some(seal);
+        self
+    }
+
+    /// Constructs the renomination state transition, checking that the
+    /// `schema` defines it
+    pub fn finish(&self, schema: &Schema) -> Result<Transition, RenominationError> {
+        let rename = schema
+            .transitions
+            .get(&u16::from(TransitionType::Rename))
+            .ok_or(RenominationError::NotFungible)?;
+        if !schema::is_fungible(schema)
+            || !rename
+                .closes
+                .contains_key(&u16::from(OwnedRightType::Renomination))
+            || !rename.metadata.contains_key(&u16::from(FieldType::Ticker))
+            || !rename.metadata.contains_key(&u16::from(FieldType::Name))
+        {
+            return Err(RenominationError::NotFungible);
+        }
+        self.build()
+    }
+
+    fn build(&self) -> Result<Transition, RenominationError> {
+        // Non-conforming current ticker or name must be replaced, otherwise
+        // the renomination would propagate them further
+        let nomination = self.nomination.to_conforming()?;
+        if nomination.precision != self.current.precision {
+            return Err(RenominationError::PrecisionChange {
+                current: self.current.precision,
+                requested: nomination.precision,
+            });
+        }
+        if nomination == self.current {
+            return Err(RenominationError::NoChanges);
+        }
+
+        let renomination = u16::from(OwnedRightType::Renomination);
+        let ticker = data::Revealed::String(nomination.ticker.to_string());
+        let name = data::Revealed::String(nomination.name.to_string());
+        let metadata = bmap! {
+            u16::from(FieldType::Ticker) => vec![ticker],
+            u16::from(FieldType::Name) => vec![name]
+        };
+        let mut owned_rights = BTreeMap::new();
+        if let Some(seal) = self.next_right {
+            let right = Assignment::Revealed {
+                seal_definition: seal,
+                assigned_state: data::Void::default(),
+            };
+            owned_rights.insert(renomination, AssignmentVec::Declarative(vec![right]));
+        }
+        let parent_owned_rights = bmap! {
+            self.right.node_id => bmap! { renomination => vec![self.right.output_no] }
+        };
+
+        Ok(Transition::with(
+            u16::from(TransitionType::Rename),
+            Metadata::from_inner(metadata),
+            empty!(),
+            OwnedRights::from_inner(owned_rights),
+            empty!(),
+            ParentOwnedRights::from_inner(parent_owned_rights),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoin::hashes::{sha256t, Hash};
+    use bitcoin::{OutPoint, Txid};
+
+    use super::*;
+    use crate::fungible::validate_precision;
+    use crate::Node;
+
+    fn nomination() -> Nomination {
+        Nomination::with(
+            "TCKR".parse().unwrap(),
+            "Test asset".parse().unwrap(),
+            validate_precision(8).unwrap(),
+        )
+    }
+
+    fn right() -> NodeOutpoint {
+        NodeOutpoint::new(NodeId::from_inner(sha256t::Hash::from_inner([1u8; 32])), 4)
+    }
+
+    #[test]
+    fn test_renomination() {
+        let seal = seal::Revealed::from(OutPoint::new(Txid::from_inner([2u8; 32]), 0));
+        let builder = RenominationBuilder::new(right(), nomination());
+        assert_eq!(builder.build(), Err(RenominationError::NoChanges));
+
+        let builder = builder.ticker("NEW".parse().unwrap()).assign_right(seal);
+        let transition = builder.build().unwrap();
+        let rename = u16::from(TransitionType::Rename);
+        assert_eq!(transition.transition_type(), rename);
+        assert_eq!(transition.parent_outputs(), vec![right()]);
+
+        let metadata = transition.metadata().as_inner();
+        let expected = Nomination {
+            ticker: Nominal::with("NEW"),
+            ..nomination()
+        };
+        assert_eq!(nomination().renominated(metadata), Some(expected));
+        assert_eq!(nomination().renominated(&empty!()), None);
+        let renomination = u16::from(OwnedRightType::Renomination);
+        assert!(matches!(
+            transition.owned_rights().as_inner().get(&renomination),
+            Some(AssignmentVec::Declarative(rights)) if rights.len() == 1
+        ));
+    }
+
+    #[test]
+    fn test_renomination_errors() {
+        let builder = RenominationBuilder::new(right(), nomination());
+        let precision = validate_precision(2).unwrap();
+        assert_eq!(
+            builder.clone().precision(precision).build(),
+            Err(RenominationError::PrecisionChange {
+                current: validate_precision(8).unwrap(),
+                requested: precision
+            })
+        );
+
+        let nonconforming = Nomination {
+            name: Nominal::with("Test\u{0}asset"),
+            ..nomination()
+        };
+        assert!(!nonconforming.is_conforming());
+        let builder = RenominationBuilder::new(right(), nonconforming);
+        let err = NominationError::InvalidName(s!("Test\u{0}asset"));
+        assert_eq!(
+            builder.clone().ticker("NEW".parse().unwrap()).build(),
+            Err(RenominationError::InvalidNomination(err))
+        );
+        let transition = builder.name("Test asset".parse().unwrap()).build().unwrap();
+        let renominated = nomination().renominated(transition.metadata().as_inner());
+        assert_eq!(renominated, Some(nomination()));
+    }
+}
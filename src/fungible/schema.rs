@@ -0,0 +1,131 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Field and owned right types of the RGB20 fungible asset schema.
+
+use rgb_core::schema::{self, DataFormat, StateSchema};
+use rgb_core::Schema;
+
+/// Field types used by RGB20 schema
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Display)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(crate = "serde_crate"))]
+#[display(Debug)]
+#[repr(u16)]
+pub enum FieldType {
+    /// Asset ticker, up to 8 characters
+    Ticker = 0,
+
+    /// Full asset name
+    Name = 1,
+
+    /// Text of the asset contract
+    RicardianContract = 2,
+
+    /// Decimal precision of the asset amounts
+    Precision = 3,
+
+    /// Supply issued by the genesis or secondary issue
+    IssuedSupply = 4,
+
+    /// Supply burned by the state transition
+    BurnedSupply = 5,
+
+    /// Timestamp of the asset issue
+    Timestamp = 6,
+}
+
+impl From<FieldType> for schema::FieldType {
+    #[inline]
+    fn from(ty: FieldType) -> Self { ty as schema::FieldType }
+}
+
+/// Owned right types used by RGB20 schema
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Display)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(crate = "serde_crate"))]
+#[display(Debug)]
+#[repr(u16)]
+pub enum OwnedRightType {
+    /// Right to do a secondary issue
+    Inflation = 0,
+
+    /// Asset ownership right
+    Assets = 1,
+
+    /// Right to open a new burn & replace epoch
+    OpenEpoch = 2,
+
+    /// Right to burn & replace the asset
+    BurnReplace = 3,
+
+    /// Right to change asset name and ticker
+    Renomination = 4,
+}
+
+impl From<OwnedRightType> for schema::OwnedRightType {
+    #[inline]
+    fn from(ty: OwnedRightType) -> Self { ty as schema::OwnedRightType }
+}
+
+/// State transition types used by RGB20 schema
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Display)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(crate = "serde_crate"))]
+#[display(Debug)]
+#[repr(u16)]
+pub enum TransitionType {
+    /// Secondary issue
+    Issue = 0,
+
+    /// Asset transfer
+    Transfer = 1,
+
+    /// Opening of a new burn & replace epoch
+    Epoch = 2,
+
+    /// Asset burn
+    Burn = 3,
+
+    /// Asset burn and replacement
+    BurnAndReplace = 4,
+
+    /// Change of the asset name and ticker
+    Rename = 5,
+
+    /// Split of the owned rights without transferring the asset
+    RightsSplit = 6,
+}
+
+impl From<TransitionType> for schema::TransitionType {
+    #[inline]
+    fn from(ty: TransitionType) -> Self { ty as schema::TransitionType }
+}
+
+/// Detects whether the schema defines RGB20-style fungible asset, i.e. has
+/// ticker, name, precision and issued supply fields of the required types and
+/// asset ownership right with fungible state
+pub fn is_fungible(schema: &Schema) -> bool {
+    let has_field = |ty: FieldType, expected: fn(&DataFormat) -> bool| {
+        schema
+            .field_types
+            .get(&u16::from(ty))
+            .map(expected)
+            .unwrap_or_default()
+    };
+    has_field(FieldType::Ticker, |format| matches!(format, DataFormat::String(_)))
+        && has_field(FieldType::Name, |format| matches!(format, DataFormat::String(_)))
+        && has_field(FieldType::Precision, |format| matches!(format, DataFormat::Unsigned(..)))
+        && has_field(FieldType::IssuedSupply, |format| {
+            matches!(format, DataFormat::Unsigned(..))
+        })
+        && matches!(
+            schema.owned_right_types.get(&u16::from(OwnedRightType::Assets)),
+            Some(StateSchema::DiscreteFiniteField(_))
+        )
+}
@@ -0,0 +1,223 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Selection of the asset allocations to be spent by a state transition.
+
+use super::allocation::Allocation;
+use crate::AtomicValue;
+
+/// Strategy for choosing allocations covering the target amount.
+///
+/// Allocations with equal values are always ordered by their node id and
+/// output index, so the selection result is deterministic.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Display)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(crate = "serde_crate"))]
+#[display(lowercase)]
+pub enum SelectionStrategy {
+    /// Spend allocations starting from the smallest ones, consolidating
+    /// small allocations
+    SmallestFirst,
+
+    /// Spend allocations starting from the largest ones, minimizing the
+    /// number of inputs
+    LargestFirst,
+
+    /// Spend single allocation matching the amount exactly or, if there is
+    /// none, the smallest single allocation covering the amount. Falls back
+    /// to [`SelectionStrategy::LargestFirst`] if no single allocation
+    /// covers the amount.
+    ExactOrSmallestChange,
+}
+
+/// Errors of the allocation selection
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum SelectionError {
+    /// target amount for the allocation selection must be non-zero
+    ZeroAmount,
+
+    /// insufficient funds: {required} is required, while only {available} is
+    /// available (shortfall of {shortfall})
+    InsufficientFunds {
+        required: AtomicValue,
+        available: AtomicValue,
+        shortfall: AtomicValue,
+    },
+
+    /// total value of the selected allocations exceeds 2^64
+    Overflow,
+}
+
+/// Result of the allocation selection
+#[derive(Clone, PartialEq, Debug)]
+pub struct Selection {
+    /// Allocations chosen to be spent
+    pub allocations: Vec<Allocation>,
+
+    /// Total value of the chosen allocations
+    pub total: AtomicValue,
+
+    /// Value which has to be assigned back to the owner as change
+    pub change: AtomicValue,
+}
+
+/// Chooses allocations to be spent for covering the target `amount` using the
+/// provided `strategy`
+pub fn coin_select(
+    allocations: &[Allocation],
+    amount: AtomicValue,
+    strategy: SelectionStrategy,
+) -> Result<Selection, SelectionError> {
+    if amount == 0 {
+        return Err(SelectionError::ZeroAmount);
+    }
+
+    let mut sorted = allocations.iter().collect::<Vec<_>>();
+    sorted.sort_by_key(|a| (a.value(), *a.node_id(), *a.index()));
+
+    let available = sorted
+        .iter()
+        .try_fold(0u64, |sum, allocation| sum.checked_add(allocation.value()));
+    match available {
+        Some(available) if available < amount => {
+            return Err(SelectionError::InsufficientFunds {
+                required: amount,
+                available,
+                shortfall: amount - available,
+            })
+        }
+        _ => {}
+    }
+
+    match strategy {
+        SelectionStrategy::SmallestFirst => accumulate(sorted, amount),
+        SelectionStrategy::LargestFirst => accumulate(largest_first(sorted), amount),
+        SelectionStrategy::ExactOrSmallestChange => {
+            let covering = sorted.iter().find(|a| a.value() >= amount);
+            match covering {
+                Some(allocation) => Ok(Selection {
+                    allocations: vec![**allocation],
+                    total: allocation.value(),
+                    change: allocation.value() - amount,
+                }),
+                None => accumulate(largest_first(sorted), amount),
+            }
+        }
+    }
+}
+
+/// Reorders allocations sorted by their value such that the largest go first,
+/// keeping the node id and output index tie-breaking ascending
+fn largest_first(mut sorted: Vec<&Allocation>) -> Vec<&Allocation> {
+    sorted.sort_by(|a, b| {
+        b.value()
+            .cmp(&a.value())
+            .then_with(|| (a.node_id(), a.index()).cmp(&(b.node_id(), b.index())))
+    });
+    sorted
+}
+
+fn accumulate(ordered: Vec<&Allocation>, amount: AtomicValue) -> Result<Selection, SelectionError> {
+    let mut selection = Selection {
+        allocations: vec![],
+        total: 0,
+        change: 0,
+    };
+    for allocation in ordered {
+        if selection.total >= amount {
+            break;
+        }
+        selection.total = selection
+            .total
+            .checked_add(allocation.value())
+            .ok_or(SelectionError::Overflow)?;
+        selection.allocations.push(*allocation);
+    }
+    selection.change = selection.total - amount;
+    Ok(selection)
+}
+
+#[cfg(test)]
+mod test {
+    use amplify::Wrapper;
+    use bitcoin::hashes::{sha256t, Hash};
+    use bitcoin::secp256k1::rand::thread_rng;
+    use bitcoin::{OutPoint, Txid};
+    use rgb_core::value;
+
+    use super::*;
+    use crate::NodeId;
+
+    fn allocations(values: &[AtomicValue]) -> Vec<Allocation> {
+        let node_id = NodeId::from_inner(sha256t::Hash::from_inner([1u8; 32]));
+        values
+            .iter()
+            .enumerate()
+            .map(|(index, value)| {
+                let outpoint = OutPoint::new(Txid::from_inner([2u8; 32]), index as u32);
+                let revealed = value::Revealed::with_amount(*value, &mut thread_rng());
+                Allocation::with(node_id, index as u16, outpoint, revealed)
+            })
+            .collect()
+    }
+
+    fn indexes(selection: &Selection) -> Vec<u16> {
+        selection
+            .allocations
+            .iter()
+            .map(|allocation| *allocation.index())
+            .collect()
+    }
+
+    #[test]
+    fn test_strategies() {
+        let allocations = allocations(&[50, 10, 30, 10, 70]);
+
+        let selection = coin_select(&allocations, 25, SelectionStrategy::SmallestFirst).unwrap();
+        assert_eq!(indexes(&selection), vec![1, 3, 2]);
+        assert_eq!((selection.total, selection.change), (50, 25));
+
+        let selection = coin_select(&allocations, 100, SelectionStrategy::LargestFirst).unwrap();
+        assert_eq!(indexes(&selection), vec![4, 0]);
+        assert_eq!((selection.total, selection.change), (120, 20));
+
+        let strategy = SelectionStrategy::ExactOrSmallestChange;
+        let selection = coin_select(&allocations, 30, strategy).unwrap();
+        assert_eq!(indexes(&selection), vec![2]);
+        assert_eq!(selection.change, 0);
+
+        let selection = coin_select(&allocations, 40, strategy).unwrap();
+        assert_eq!(indexes(&selection), vec![0]);
+        assert_eq!(selection.change, 10);
+
+        let selection = coin_select(&allocations, 90, strategy).unwrap();
+        assert_eq!(indexes(&selection), vec![4, 0]);
+        assert_eq!(selection.change, 30);
+    }
+
+    #[test]
+    fn test_selection_errors() {
+        let allocations = allocations(&[50, 10]);
+        let strategy = SelectionStrategy::SmallestFirst;
+        assert_eq!(
+            coin_select(&allocations, 0, strategy),
+            Err(SelectionError::ZeroAmount)
+        );
+        assert_eq!(
+            coin_select(&allocations, 100, strategy),
+            Err(SelectionError::InsufficientFunds {
+                required: 100,
+                available: 60,
+                shortfall: 40
+            })
+        );
+    }
+}
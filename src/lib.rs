@@ -17,24 +17,71 @@ extern crate strict_encoding;
 #[macro_use]
 extern crate serde_crate as serde;
 
+#[macro_use]
+mod trace;
+
+#[cfg(feature = "bitcoind")]
+pub mod bitcoind;
 mod consignments;
 mod disclosure;
-mod stash;
+#[cfg(all(feature = "electrum", not(target_arch = "wasm32")))]
+pub mod electrum;
+mod error;
+mod method;
+mod mnemonic;
+mod proof;
+#[cfg(feature = "psbt")]
+pub mod psbt;
+mod resolver;
+pub mod stash;
 pub mod fungible;
 mod state;
+#[cfg(feature = "test_vectors")]
+pub mod test_vectors;
+mod tlv;
+mod verify;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
 pub mod prelude {
     pub use rgb_core::*;
 
+    #[cfg(feature = "bitcoind")]
+    pub use crate::bitcoind::{BitcoindConfig, BitcoindResolver, BitcoindSource};
     pub use crate::consignments::{
-        AnchoredBundles, ChainIter, ConsignmentEndpoints, ConsignmentId, ConsignmentType, Contract,
-        ContractConsignment, ExtensionList, InmemConsignment, MeshIter, StateTransfer,
-        TransferConsignment, RGB_INMEM_CONSIGNMENT_VERSION,
+        qr_capacity, AnchoredBundle, AnchoredBundles, ChainIter, ConsignmentEndpoints,
+        ConsignmentId, ConsignmentSummary, ConsignmentType, Contract, ContractConsignment,
+        ExtensionList, InmemConsignment, InvoiceUri, LazyConsignment, LazyError, MeshIter,
+        OwnedIter, SealError, SealedConsignment, StateTransfer, TransferConsignment, TransferPart,
+        TransitionIndex, UriError, WitnessedTransition, RGB_INMEM_CONSIGNMENT_VERSION,
+        RGB_SEALED_CONSIGNMENT_VERSION,
+    };
+    pub use crate::disclosure::{
+        ChannelDisclosure, ChannelDisclosureQueue, ChannelError, ChannelId, Disclosure,
+        DisclosureCommitmentProof, DisclosureError, DisclosureId, DISCLOSURE_PROTOCOL_TAG,
+        RGB_DISCLOSURE_VERSION,
     };
-    pub use crate::disclosure::{Disclosure, DisclosureId, RGB_DISCLOSURE_VERSION};
+    #[cfg(all(feature = "electrum", not(target_arch = "wasm32")))]
+    pub use crate::electrum::{ElectrumResolver, ElectrumSource, ElectrumTransport};
     pub use crate::fungible;
-    pub use crate::stash::Stash;
-    pub use crate::state::{AssignedState, ContractState, StateAtom};
+    pub use crate::method::{AnchorCloseMethod, CloseMethod};
+    pub use crate::mnemonic::{verify_mnemonic, ToMnemonic, MNEMONIC_WORDS};
+    pub use crate::proof::{OwnershipProof, ProofError, ProofStep, ProvenState, ResolveWitness};
+    #[cfg(feature = "psbt")]
+    pub use crate::psbt::{CommitError, PsbtRgbError, RgbExt};
+    pub use crate::resolver::{CachingResolver, WitnessSource};
+    pub use crate::stash::{
+        MemStash, MemStashError, MergeCount, MergeError, MergeReport, SharedStash, SnapshotId,
+        Stash, StashDiff, StashMetrics, StashObjects, StashSnapshot,
+    };
+    pub use crate::state::{
+        AppliedNode, AssignedState, AssignmentRef, Balance, BalanceOverflow, ConcealedAssignment,
+        ContractState, RollbackReport, SchemaViolation, StateApplyError, StateAtom,
+        StateConversionError, StateDiff, StateId, StateIdTag, StateKind, StateValue, TypedOutpoint,
+    };
+    pub use crate::tlv::{TlvError, TlvMap, KNOWN_TLV_TYPES};
+    pub use crate::verify::{BundleFailure, BundleReport};
 }
 
+pub use error::Error;
 pub use prelude::*;
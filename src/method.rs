@@ -0,0 +1,113 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Methods of committing to the RGB data in the witness transactions which
+//! close the single-use seals.
+
+use bitcoin::blockdata::opcodes::all::OP_RETURN;
+use bitcoin::blockdata::script::{Builder, Script};
+use bitcoin::secp256k1::{Secp256k1, XOnlyPublicKey};
+use bitcoin::util::taproot::TaprootBuilder;
+use bp::dbc::Proof;
+use commit_verify::lnpbp4;
+
+use crate::Anchor;
+
+/// Method of embedding the LNPBP-4 commitment into the witness transaction,
+/// which has to be known to the party verifying the anchor against the
+/// transaction
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(crate = "serde_crate"))]
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
+#[derive(StrictEncode, StrictDecode)]
+#[strict_encoding(by_value, repr = u8)]
+#[repr(u8)]
+pub enum CloseMethod {
+    /// Commitment is the data of the first `OP_RETURN` output
+    #[display("opret1st")]
+    #[cfg_attr(feature = "serde", serde(rename = "opret1st"))]
+    OpretFirst = 0x01,
+
+    /// Commitment is a leaf script of the first taproot output
+    #[display("tapret1st")]
+    #[cfg_attr(feature = "serde", serde(rename = "tapret1st"))]
+    TapretFirst = 0x02,
+}
+
+impl CloseMethod {
+    /// Parses single-byte representation of the method
+    pub fn with(value: &[u8]) -> Option<CloseMethod> {
+        match value {
+            [0x01] => Some(CloseMethod::OpretFirst),
+            [0x02] => Some(CloseMethod::TapretFirst),
+            _ => None,
+        }
+    }
+}
+
+impl From<&Proof> for CloseMethod {
+    fn from(proof: &Proof) -> Self {
+        match proof {
+            Proof::OpretFirst => CloseMethod::OpretFirst,
+            Proof::TapretFirst(_) => CloseMethod::TapretFirst,
+        }
+    }
+}
+
+/// Introspection of the method used by an anchor
+pub trait AnchorCloseMethod {
+    /// Returns method the anchor commits with to its witness transaction
+    fn close_method(&self) -> CloseMethod;
+}
+
+impl AnchorCloseMethod for Anchor<lnpbp4::MerkleProof> {
+    #[inline]
+    fn close_method(&self) -> CloseMethod { CloseMethod::from(&self.dbc_proof) }
+}
+
+impl AnchorCloseMethod for Anchor<lnpbp4::MerkleBlock> {
+    #[inline]
+    fn close_method(&self) -> CloseMethod { CloseMethod::from(&self.dbc_proof) }
+}
+
+/// `OP_RETURN` script with the opret commitment
+pub(crate) fn opret_script(commitment: &[u8; 32]) -> Script {
+    Builder::new()
+        .push_opcode(OP_RETURN)
+        .push_slice(commitment)
+        .into_script()
+}
+
+/// Tapret leaf script according to LNPBP-12: 29 `OP_RESERVED` followed by
+/// `OP_RETURN` and 33-byte push of the commitment with a zero nonce
+pub(crate) fn tapret_script(commitment: &[u8; 32]) -> Script {
+    let mut script = vec![0x50u8; 29];
+    script.extend([OP_RETURN.into_u8(), 33]);
+    script.extend(commitment);
+    script.push(0);
+    Script::from(script)
+}
+
+/// Constructs taproot script tree consisting of the single tapret leaf and
+/// the output script tweaked with it
+pub(crate) fn tapret_output(
+    internal_pk: XOnlyPublicKey,
+    commitment: &[u8; 32],
+) -> Option<(TaprootBuilder, Script)> {
+    let builder = TaprootBuilder::new()
+        .add_leaf(0, tapret_script(commitment))
+        .ok()?;
+    let spend_info = builder
+        .clone()
+        .finalize(&Secp256k1::verification_only(), internal_pk)
+        .ok()?;
+    let script_pubkey = Script::new_v1_p2tr_tweaked(spend_info.output_key());
+    Some((builder, script_pubkey))
+}
@@ -0,0 +1,137 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Human-memorable checksum words for contract, consignment and disclosure
+//! ids, helping users to compare long ids over the phone.
+//!
+//! The checksum consists of three words from a fixed list of 1024 words, each
+//! word encoding 10 bits of the first four bytes of the id, 30 bits in total.
+//! Two different ids have the same checksum with the probability of 2^-30, i.e.
+//! about one in a billion; a set of 2^15 (32768) ids contains at least one pair
+//! with the same checksum with the probability of about 39%. Thus the checksum
+//! detects mistakes, but does not replace comparison of the full ids when an
+//! adversary may grind ids matching a given checksum.
+//!
+//! The word list is part of the format and never changes between releases.
+
+use std::borrow::Borrow;
+
+use crate::{ConsignmentId, ContractId, DisclosureId};
+
+/// Words used in the checksums, one per line, sorted alphabetically
+const WORDLIST: &str = include_str!("mnemonic/wordlist.txt");
+
+/// Number of words in the checksum
+pub const MNEMONIC_WORDS: usize = 3;
+
+fn word(index: u32) -> &'static str {
+    WORDLIST
+        .lines()
+        .nth(index as usize)
+        .expect("word list contains 1024 words")
+}
+
+fn mnemonic<T>(id: &T) -> String
+where T: Borrow<[u8]> {
+    let bytes: &[u8] = id.borrow();
+    let bits = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    [bits >> 22, bits >> 12, bits >> 2]
+        .map(|index| word(index & 0x3FF))
+        .join("-")
+}
+
+/// Ids which may be represented by checksum words
+pub trait ToMnemonic {
+    /// Returns checksum words of the id separated with dashes, like
+    /// `ballad-scarab-floor`
+    fn to_mnemonic(&self) -> String;
+}
+
+impl ToMnemonic for ContractId {
+    #[inline]
+    fn to_mnemonic(&self) -> String { mnemonic(self) }
+}
+
+impl ToMnemonic for ConsignmentId {
+    #[inline]
+    fn to_mnemonic(&self) -> String { mnemonic(self) }
+}
+
+impl ToMnemonic for DisclosureId {
+    #[inline]
+    fn to_mnemonic(&self) -> String { mnemonic(self) }
+}
+
+/// Checks that the `phrase` contains checksum words of the `id`, ignoring the
+/// case of the letters and any separators between the words
+pub fn verify_mnemonic(id: &impl ToMnemonic, phrase: &str) -> bool {
+    let words = phrase
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase);
+    words.eq(id.to_mnemonic().split('-').map(str::to_owned))
+}
+
+#[cfg(test)]
+mod test {
+    use amplify::Wrapper;
+    use bitcoin::hashes::{sha256, sha256t, Hash};
+
+    use super::*;
+
+    fn contract_id(prefix: [u8; 4]) -> ContractId {
+        let mut bytes = [0u8; 32];
+        bytes[..4].copy_from_slice(&prefix);
+        ContractId::from_inner(sha256t::Hash::from_inner(bytes))
+    }
+
+    #[test]
+    fn test_wordlist() {
+        // The list must never change: existing checksums would not verify
+        assert_eq!(
+            sha256::Hash::hash(WORDLIST.as_bytes()).to_string(),
+            "940ad98ef9017f3252b382e7b4135595961527675cd2d0dc0a1f90d4ab7851f9"
+        );
+        let words = WORDLIST.lines().collect::<Vec<_>>();
+        assert_eq!(words.len(), 1024);
+        assert!(words.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(words
+            .iter()
+            .all(|word| word.chars().all(|c| c.is_ascii_lowercase())));
+    }
+
+    #[test]
+    fn test_mnemonic() {
+        assert_eq!(contract_id([0x00; 4]).to_mnemonic(), "abbey-abbey-abbey");
+        assert_eq!(contract_id([0xFF; 4]).to_mnemonic(), "zoo-zoo-zoo");
+        assert_eq!(
+            contract_id([0x12, 0x34, 0x56, 0x78]).to_mnemonic(),
+            "ballad-scarab-floor"
+        );
+
+        let consignment_id = ConsignmentId::from_inner(sha256t::Hash::from_inner([0x12; 32]));
+        let disclosure_id = DisclosureId::from_inner(sha256t::Hash::from_inner([0x12; 32]));
+        assert_eq!(consignment_id.to_mnemonic(), "ballad-cradle-bonus");
+        assert_eq!(disclosure_id.to_mnemonic(), consignment_id.to_mnemonic());
+        assert_eq!(consignment_id.to_mnemonic().split('-').count(), MNEMONIC_WORDS);
+    }
+
+    #[test]
+    fn test_verify_mnemonic() {
+        let id = contract_id([0x12, 0x34, 0x56, 0x78]);
+        assert!(verify_mnemonic(&id, "ballad-scarab-floor"));
+        assert!(verify_mnemonic(&id, "Ballad Scarab FLOOR"));
+        assert!(verify_mnemonic(&id, " ballad, scarab. floor\n"));
+        assert!(!verify_mnemonic(&id, "ballad scarab"));
+        assert!(!verify_mnemonic(&id, "ballad scarab floor floor"));
+        assert!(!verify_mnemonic(&id, "scarab ballad floor"));
+    }
+}
@@ -0,0 +1,323 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Ownership proofs allow to demonstrate that some contract state is assigned
+//! to a bitcoin transaction output without revealing the rest of the stash.
+//! They are smaller than consignments, but larger than disclosures, since
+//! they contain the history of the proven state.
+
+use std::collections::BTreeSet;
+use std::convert::Infallible;
+use std::error::Error as StdError;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use bitcoin::{OutPoint, Txid};
+use lnpbp_bech32::{FromBech32Str, ToBech32String};
+use rgb_core::schema::OwnedRightType;
+use rgb_core::{ContractId, Genesis, Node, NodeId, NodeOutpoint};
+
+use crate::state::{OwnedAttachment, OwnedData, OwnedRight, OwnedValue};
+use crate::{AssignmentRef, ContractState};
+
+/// Resolver of the witness transactions against the bitcoin blockchain
+pub trait ResolveWitness {
+    type Error: StdError;
+
+    /// Returns height of the block which mined the witness transaction
+    /// `txid`, or `None` if the transaction is not mined
+    fn resolve_height(&mut self, txid: Txid) -> Result<Option<u32>, Self::Error>;
+}
+
+impl<F> ResolveWitness for F
+where F: FnMut(Txid) -> Option<u32>
+{
+    type Error = Infallible;
+
+    #[inline]
+    fn resolve_height(&mut self, txid: Txid) -> Result<Option<u32>, Self::Error> { Ok(self(txid)) }
+}
+
+/// Errors happening during construction and verification of the
+/// [`OwnershipProof`]
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum ProofError {
+    /// no unspent revealed state is assigned to the outpoint {0}
+    NoState(OutPoint),
+
+    /// contract state does not contain node {0} required for the proof
+    UnknownNode(NodeId),
+
+    /// proof is made for the contract {0}, which does not match the provided
+    /// genesis
+    ContractMismatch(ContractId),
+
+    /// proof history does not start with the contract genesis
+    NoGenesis,
+
+    /// proven assignment {0} is not defined by a node from the proof history
+    Disconnected(NodeOutpoint),
+
+    /// proven assignment {0} is not assigned to the proof outpoint
+    SealMismatch(NodeOutpoint),
+
+    /// witness transaction {0} is not mined
+    Unmined(Txid),
+
+    /// unable to resolve witness transaction {0}: {1}
+    Resolver(Txid, String),
+}
+
+/// Revealed state of an assignment proven by the [`OwnershipProof`] or
+/// reported by [`Stash::outpoint_state`](crate::Stash::outpoint_state)
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[derive(StrictEncode, StrictDecode)]
+pub enum ProvenState {
+    Declarative(OwnedRight),
+    Fungible(OwnedValue),
+    Data(OwnedData),
+    Attachment(OwnedAttachment),
+}
+
+impl ProvenState {
+    /// Constructs state from the assignment, unless the assignment state is
+    /// concealed
+    pub(crate) fn with_assignment(assignment: AssignmentRef) -> Option<ProvenState> {
+        Some(match assignment {
+            AssignmentRef::Right(assigned) => ProvenState::Declarative(assigned.clone()),
+            AssignmentRef::Value(assigned) => ProvenState::Fungible(assigned.clone()),
+            AssignmentRef::Data(assigned) => ProvenState::Data(assigned.clone()),
+            AssignmentRef::Attachment(assigned) => ProvenState::Attachment(assigned.clone()),
+            AssignmentRef::Concealed(_) => return None,
+        })
+    }
+
+    /// Returns the node output defining the assignment
+    pub fn outpoint(&self) -> NodeOutpoint {
+        match self {
+            ProvenState::Declarative(assigned) => assigned.outpoint,
+            ProvenState::Fungible(assigned) => assigned.outpoint,
+            ProvenState::Data(assigned) => assigned.outpoint,
+            ProvenState::Attachment(assigned) => assigned.outpoint,
+        }
+    }
+
+    /// Returns outpoint of the assignment seal
+    pub fn seal(&self) -> OutPoint {
+        match self {
+            ProvenState::Declarative(assigned) => assigned.seal,
+            ProvenState::Fungible(assigned) => assigned.seal,
+            ProvenState::Data(assigned) => assigned.seal,
+            ProvenState::Attachment(assigned) => assigned.seal,
+        }
+    }
+}
+
+/// Node of the contract history included into the [`OwnershipProof`]
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
+#[derive(StrictEncode, StrictDecode)]
+pub struct ProofStep {
+    pub node_id: NodeId,
+
+    /// Witness transaction anchoring the node; `None` for genesis and state
+    /// extensions
+    pub witness: Option<Txid>,
+}
+
+/// Proof that the contract state is assigned to a bitcoin transaction output.
+///
+/// The proof includes the revealed state assigned to the output and the part
+/// of the contract history leading to it from genesis, represented by node
+/// ids and witness transactions anchoring them. All other state remains
+/// concealed.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[derive(StrictEncode, StrictDecode)]
+pub struct OwnershipProof {
+    pub contract_id: ContractId,
+
+    /// Transaction output holding the proven state
+    pub outpoint: OutPoint,
+
+    /// Contract history leading to the proven state, starting from genesis,
+    /// in the order of node application
+    pub history: Vec<ProofStep>,
+
+    /// Revealed state assigned to the proof outpoint, with the owned right
+    /// types of the assignments
+    pub assignments: Vec<(OwnedRightType, ProvenState)>,
+}
+
+impl OwnershipProof {
+    /// Verifies the proof against the contract `genesis`, checking that all
+    /// the witness transactions from the proof history are mined
+    pub fn verify<R: ResolveWitness>(
+        &self,
+        genesis: &Genesis,
+        resolver: &mut R,
+    ) -> Result<(), ProofError> {
+        if genesis.contract_id() != self.contract_id {
+            return Err(ProofError::ContractMismatch(self.contract_id));
+        }
+        match self.history.first() {
+            Some(step) if step.node_id == genesis.node_id() && step.witness.is_none() => {}
+            _ => return Err(ProofError::NoGenesis),
+        }
+
+        let node_ids = self
+            .history
+            .iter()
+            .map(|step| step.node_id)
+            .collect::<BTreeSet<_>>();
+        for (_, state) in &self.assignments {
+            let outpoint = state.outpoint();
+            if !node_ids.contains(&outpoint.node_id) {
+                return Err(ProofError::Disconnected(outpoint));
+            }
+            if state.seal() != self.outpoint {
+                return Err(ProofError::SealMismatch(outpoint));
+            }
+        }
+
+        for txid in self.history.iter().filter_map(|step| step.witness) {
+            match resolver.resolve_height(txid) {
+                Ok(Some(_)) => {}
+                Ok(None) => return Err(ProofError::Unmined(txid)),
+                Err(err) => return Err(ProofError::Resolver(txid, err.to_string())),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl lnpbp_bech32::Strategy for OwnershipProof {
+    const HRP: &'static str = "rgbp";
+    type Strategy = lnpbp_bech32::strategies::CompressedStrictEncoding;
+}
+
+impl Display for OwnershipProof {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result { f.write_str(&self.to_bech32_string()) }
+}
+
+impl FromStr for OwnershipProof {
+    type Err = lnpbp_bech32::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> { Self::from_bech32_str(s) }
+}
+
+impl ContractState {
+    /// Constructs proof of ownership for all unspent revealed state assigned
+    /// to the `outpoint`
+    pub fn ownership_proof(&self, outpoint: OutPoint) -> Result<OwnershipProof, ProofError> {
+        let assignments = self
+            .typed_assignments()
+            .into_iter()
+            .filter(|(_, assignment)| assignment.seal() == Some(outpoint))
+            .filter(|(output, _)| !self.is_spent(output))
+            .filter_map(|(output, assignment)| {
+                Some((output.ty, ProvenState::with_assignment(assignment)?))
+            })
+            .collect::<Vec<_>>();
+        if assignments.is_empty() {
+            return Err(ProofError::NoState(outpoint));
+        }
+
+        let mut ancestors = BTreeSet::new();
+        let mut queue = assignments
+            .iter()
+            .map(|(_, state)| state.outpoint().node_id)
+            .collect::<Vec<_>>();
+        while let Some(node_id) = queue.pop() {
+            if !ancestors.insert(node_id) {
+                continue;
+            }
+            let node = self
+                .nodes
+                .get(&node_id)
+                .ok_or(ProofError::UnknownNode(node_id))?;
+            queue.extend(node.parents.iter().copied());
+        }
+
+        let history = self
+            .history
+            .iter()
+            .filter(|node_id| ancestors.contains(node_id))
+            .map(|node_id| ProofStep {
+                node_id: *node_id,
+                witness: self.nodes[node_id].witness,
+            })
+            .collect();
+
+        Ok(OwnershipProof {
+            contract_id: self.contract_id,
+            outpoint,
+            history,
+            assignments,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use amplify::Wrapper;
+    use bitcoin::hashes::{sha256t, Hash};
+
+    use super::*;
+    use crate::{data, AppliedNode, AssignedState};
+
+    fn node_id(no: u8) -> NodeId { NodeId::from_inner(sha256t::Hash::from_inner([no; 32])) }
+
+    #[test]
+    fn test_ownership_proof() {
+        let mine = OutPoint::new(Txid::from_inner([3u8; 32]), 0);
+        let mut state = ContractState::new(ContractId::default());
+        // Genesis #1 is spent by transition #2, while transition #3 is not
+        // related to the proven state
+        for (no, parent) in [(1, None), (2, Some(1)), (3, Some(1))] {
+            let witness = parent.map(|_| Txid::from_inner([no; 32]));
+            state.nodes.insert(node_id(no), AppliedNode {
+                witness,
+                parents: parent.into_iter().map(node_id).collect(),
+                children: empty!(),
+                metadata: empty!(),
+            });
+            state.history.push(node_id(no));
+        }
+        let right = AssignedState {
+            seal: mine,
+            state: data::Void::default(),
+            outpoint: NodeOutpoint::new(node_id(2), 0),
+            witness: Some(Txid::from_inner([2u8; 32])),
+        };
+        state.owned_rights.insert(7, vec![right.clone()]);
+
+        let proof = state.ownership_proof(mine).unwrap();
+        let assignments = vec![(7, ProvenState::Declarative(right))];
+        assert_eq!(proof.assignments, assignments);
+        let history = proof
+            .history
+            .iter()
+            .map(|step| (step.node_id, step.witness))
+            .collect::<Vec<_>>();
+        let witness = Some(Txid::from_inner([2u8; 32]));
+        assert_eq!(history, vec![(node_id(1), None), (node_id(2), witness)]);
+        assert_eq!(OwnershipProof::from_str(&proof.to_string()).unwrap(), proof);
+
+        let other = OutPoint::new(Txid::from_inner([4u8; 32]), 0);
+        let err = ProofError::NoState(other);
+        assert_eq!(state.ownership_proof(other), Err(err));
+
+        state.nodes.remove(&node_id(1));
+        let err = ProofError::UnknownNode(node_id(1));
+        assert_eq!(state.ownership_proof(mine), Err(err));
+    }
+}
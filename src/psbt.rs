@@ -0,0 +1,696 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Embedding of RGB state transition bundles and their LNPBP-4 commitment
+//! into partially signed bitcoin transactions, which become witness
+//! transactions of the state transitions.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::XOnlyPublicKey;
+use bitcoin::util::psbt::raw::ProprietaryKey;
+use bitcoin::util::psbt::PartiallySignedTransaction;
+use bp::dbc::tapret::{TapretPathProof, TapretProof};
+use bp::dbc::Proof;
+use commit_verify::lnpbp4::{self, MerkleBlock, MerkleTree, MultiSource};
+use commit_verify::{CommitVerify, ConsensusCommit};
+use strict_encoding::{StrictDecode, StrictEncode};
+
+use crate::disclosure::disclosure_protocol_id;
+use crate::method::{opret_script, tapret_output};
+use crate::{
+    Anchor, CloseMethod, ContractId, Disclosure, DisclosureCommitmentProof, TransitionBundle,
+};
+
+/// Prefix of the PSBT proprietary keys holding RGB data
+pub const PSBT_RGB_PREFIX: &[u8] = b"RGB";
+
+/// Global proprietary key holding transition bundle of an RGB contract. Key
+/// data are the contract id; value is the strict-encoded bundle.
+pub const PSBT_GLOBAL_RGB_CONTRACT: u8 = 0x00;
+
+/// Output proprietary key designating the output which hosts the LNPBP-4
+/// commitment. The key has no data; value is a single byte of
+/// [`CloseMethod`].
+pub const PSBT_OUT_RGB_HOST: u8 = 0x01;
+
+/// Output proprietary key holding the 32-byte LNPBP-4 commitment embedded
+/// into the output by [`RgbExt::finalize_rgb`]. The key has no data.
+pub const PSBT_OUT_RGB_COMMITMENT: u8 = 0x02;
+
+/// Minimal depth of the LNPBP-4 merkle tree, hiding the number of committed
+/// contracts
+const LNPBP4_MIN_DEPTH: u8 = 3;
+
+/// Errors embedding RGB data into PSBT
+#[derive(Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum PsbtRgbError {
+    /// PSBT does not contain transition bundles to commit to
+    NoContracts,
+
+    /// PSBT does not designate an output for hosting the RGB commitment
+    NoHost,
+
+    /// PSBT does not have output {0}
+    UnknownOutput(usize),
+
+    /// input {0} is not known to spend a native segwit output; signing it
+    /// would change the witness transaction id committed to by the anchors
+    NonSegwitInput(usize),
+
+    /// output {0} already contains RGB commitment
+    AlreadyCommitted(usize),
+
+    /// opret commitment requires output {0} to be `OP_RETURN` output without
+    /// data
+    OpretHost(usize),
+
+    /// tapret commitment requires output {0} to be a taproot output with a
+    /// known internal key and no script tree
+    TapretHost(usize),
+
+    /// unable to construct taproot output with the tapret commitment
+    Taproot,
+
+    /// PSBT contains invalid RGB data: {0}
+    #[from]
+    Encoding(strict_encoding::Error),
+}
+
+/// Errors committing to a [`Disclosure`] in PSBT
+#[derive(Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum CommitError {
+    /// PSBT has no output which can host {0} commitment
+    NoHost(CloseMethod),
+
+    /// {0}
+    #[from]
+    Psbt(PsbtRgbError),
+}
+
+/// Extension of [`PartiallySignedTransaction`] with RGB data
+pub trait RgbExt {
+    /// Adds transition `bundle` of the contract to the PSBT, replacing the
+    /// bundle of the same contract, if any
+    fn set_rgb_contract(
+        &mut self,
+        contract_id: ContractId,
+        bundle: &TransitionBundle,
+    ) -> Result<(), PsbtRgbError>;
+
+    /// Returns ids of the contracts with transition bundles in the PSBT
+    fn rgb_contract_ids(&self) -> BTreeSet<ContractId>;
+
+    /// Returns transition bundles of all contracts in the PSBT
+    fn rgb_bundles(&self) -> Result<BTreeMap<ContractId, TransitionBundle>, PsbtRgbError>;
+
+    /// Designates output number `vout` to host the RGB commitment using the
+    /// commitment `method`
+    fn set_rgb_host(&mut self, vout: usize, method: CloseMethod) -> Result<(), PsbtRgbError>;
+
+    /// Returns output designated to host the RGB commitment together with
+    /// the commitment method
+    fn rgb_host(&self) -> Option<(usize, CloseMethod)>;
+
+    /// Commits to the transition bundles of all contracts with a LNPBP-4
+    /// multi-protocol commitment, embeds it into the host output and returns
+    /// anchors of each of the contracts to the resulting witness transaction.
+    ///
+    /// Anchors refer to the witness transaction by its id, so all the PSBT
+    /// inputs must spend native segwit outputs, known from their witness or
+    /// non-witness UTXO. The transaction must not be modified after the call
+    /// other than by signing it, otherwise the anchors become invalid.
+    fn finalize_rgb(
+        &mut self,
+    ) -> Result<BTreeMap<ContractId, Anchor<lnpbp4::MerkleProof>>, PsbtRgbError>;
+}
+
+fn rgb_key(subtype: u8, key: Vec<u8>) -> ProprietaryKey {
+    ProprietaryKey {
+        prefix: PSBT_RGB_PREFIX.to_vec(),
+        subtype,
+        key,
+    }
+}
+
+/// Checks that all inputs spend native segwit outputs, such that signing
+/// them does not change the transaction id. Signing inputs spending other
+/// outputs, including P2SH-wrapped segwit, fills in their `scriptSig`.
+fn check_segwit_inputs(psbt: &PartiallySignedTransaction) -> Result<(), PsbtRgbError> {
+    for (no, (txin, input)) in psbt.unsigned_tx.input.iter().zip(&psbt.inputs).enumerate() {
+        let spent = input.witness_utxo.as_ref().or_else(|| {
+            input
+                .non_witness_utxo
+                .as_ref()?
+                .output
+                .get(txin.previous_output.vout as usize)
+        });
+        match spent {
+            Some(txout) if txout.script_pubkey.is_witness_program() => {}
+            _ => return Err(PsbtRgbError::NonSegwitInput(no)),
+        }
+    }
+    Ok(())
+}
+
+/// Checks that the output `vout` can host a commitment using the `method`,
+/// returning the internal key of the taproot host
+fn check_host(
+    psbt: &PartiallySignedTransaction,
+    vout: usize,
+    method: CloseMethod,
+) -> Result<Option<XOnlyPublicKey>, PsbtRgbError> {
+    let (output, txout) = psbt
+        .outputs
+        .get(vout)
+        .zip(psbt.unsigned_tx.output.get(vout))
+        .ok_or(PsbtRgbError::UnknownOutput(vout))?;
+    if output
+        .proprietary
+        .contains_key(&rgb_key(PSBT_OUT_RGB_COMMITMENT, vec![]))
+    {
+        return Err(PsbtRgbError::AlreadyCommitted(vout));
+    }
+    match method {
+        CloseMethod::OpretFirst => {
+            if !txout.script_pubkey.is_op_return() || txout.script_pubkey.len() != 1 {
+                return Err(PsbtRgbError::OpretHost(vout));
+            }
+            Ok(None)
+        }
+        CloseMethod::TapretFirst => match output.tap_internal_key {
+            Some(internal_pk) if output.tap_tree.is_none() && txout.script_pubkey.is_v1_p2tr() => {
+                Ok(Some(internal_pk))
+            }
+            _ => Err(PsbtRgbError::TapretHost(vout)),
+        },
+    }
+}
+
+/// Embeds the LNPBP-4 `commitment` into the output `vout` using the
+/// commitment `method`, returning the proof of the embedding. Fails if the
+/// transaction id may change on signing.
+fn embed_commitment(
+    psbt: &mut PartiallySignedTransaction,
+    vout: usize,
+    method: CloseMethod,
+    commitment: &[u8; 32],
+) -> Result<Proof, PsbtRgbError> {
+    check_segwit_inputs(psbt)?;
+    let internal_pk = check_host(psbt, vout, method)?;
+    let output = &mut psbt.outputs[vout];
+    let txout = &mut psbt.unsigned_tx.output[vout];
+    let dbc_proof = match internal_pk {
+        None => {
+            txout.script_pubkey = opret_script(commitment);
+            Proof::OpretFirst
+        }
+        Some(internal_pk) => {
+            let (builder, script_pubkey) =
+                tapret_output(internal_pk, commitment).ok_or(PsbtRgbError::Taproot)?;
+            txout.script_pubkey = script_pubkey;
+            output.tap_tree = Some(builder);
+            Proof::TapretFirst(TapretProof {
+                path_proof: TapretPathProof::root(),
+                internal_pk,
+            })
+        }
+    };
+    let commitment_key = rgb_key(PSBT_OUT_RGB_COMMITMENT, vec![]);
+    output
+        .proprietary
+        .insert(commitment_key, commitment.to_vec());
+    Ok(dbc_proof)
+}
+
+impl RgbExt for PartiallySignedTransaction {
+    fn set_rgb_contract(
+        &mut self,
+        contract_id: ContractId,
+        bundle: &TransitionBundle,
+    ) -> Result<(), PsbtRgbError> {
+        if let Some((vout, _)) = self.rgb_host() {
+            let commitment = rgb_key(PSBT_OUT_RGB_COMMITMENT, vec![]);
+            if self.outputs[vout].proprietary.contains_key(&commitment) {
+                return Err(PsbtRgbError::AlreadyCommitted(vout));
+            }
+        }
+        let key = rgb_key(PSBT_GLOBAL_RGB_CONTRACT, contract_id.strict_serialize()?);
+        self.proprietary.insert(key, bundle.strict_serialize()?);
+        Ok(())
+    }
+
+    fn rgb_contract_ids(&self) -> BTreeSet<ContractId> {
+        self.proprietary
+            .keys()
+            .filter(|key| key.prefix == PSBT_RGB_PREFIX && key.subtype == PSBT_GLOBAL_RGB_CONTRACT)
+            .filter_map(|key| ContractId::strict_deserialize(&key.key).ok())
+            .collect()
+    }
+
+    fn rgb_bundles(&self) -> Result<BTreeMap<ContractId, TransitionBundle>, PsbtRgbError> {
+        self.proprietary
+            .iter()
+            .filter(|(key, _)| {
+                key.prefix == PSBT_RGB_PREFIX && key.subtype == PSBT_GLOBAL_RGB_CONTRACT
+            })
+            .map(|(key, value)| {
+                let contract_id = ContractId::strict_deserialize(&key.key)?;
+                Ok((contract_id, TransitionBundle::strict_deserialize(value)?))
+            })
+            .collect()
+    }
+
+    fn set_rgb_host(&mut self, vout: usize, method: CloseMethod) -> Result<(), PsbtRgbError> {
+        if vout >= self.outputs.len() {
+            return Err(PsbtRgbError::UnknownOutput(vout));
+        }
+        let host = rgb_key(PSBT_OUT_RGB_HOST, vec![]);
+        for output in &mut self.outputs {
+            output.proprietary.remove(&host);
+        }
+        self.outputs[vout]
+            .proprietary
+            .insert(host, vec![method as u8]);
+        Ok(())
+    }
+
+    fn rgb_host(&self) -> Option<(usize, CloseMethod)> {
+        let host = rgb_key(PSBT_OUT_RGB_HOST, vec![]);
+        self.outputs.iter().enumerate().find_map(|(vout, output)| {
+            let method = CloseMethod::with(output.proprietary.get(&host)?)?;
+            Some((vout, method))
+        })
+    }
+
+    fn finalize_rgb(
+        &mut self,
+    ) -> Result<BTreeMap<ContractId, Anchor<lnpbp4::MerkleProof>>, PsbtRgbError> {
+        let bundles = self.rgb_bundles()?;
+        if bundles.is_empty() {
+            return Err(PsbtRgbError::NoContracts);
+        }
+        let (vout, method) = self.rgb_host().ok_or(PsbtRgbError::NoHost)?;
+
+        let messages = bundles
+            .iter()
+            .map(|(contract_id, bundle)| {
+                let protocol_id = lnpbp4::ProtocolId::from(*contract_id);
+                (protocol_id, lnpbp4::Message::from(bundle.bundle_id()))
+            })
+            .collect();
+        let source = MultiSource {
+            min_depth: LNPBP4_MIN_DEPTH,
+            messages,
+            static_entropy: None,
+        };
+        let tree = MerkleTree::commit(&source);
+        let commitment = tree.clone().consensus_commit().into_inner();
+        let dbc_proof = embed_commitment(self, vout, method, &commitment)?;
+
+        let anchor = Anchor {
+            txid: self.unsigned_tx.txid(),
+            lnpbp4_proof: MerkleBlock::from(tree),
+            dbc_proof,
+        };
+        Ok(bundles
+            .into_keys()
+            .map(|contract_id| {
+                let protocol_id = lnpbp4::ProtocolId::from(contract_id);
+                let anchor = anchor
+                    .to_merkle_proof(protocol_id)
+                    .expect("anchor commits to all contracts of the PSBT");
+                (contract_id, anchor)
+            })
+            .collect())
+    }
+}
+
+impl Disclosure {
+    /// Commits to the disclosure id with a LNPBP-4 multi-protocol commitment
+    /// under the [`crate::DISCLOSURE_PROTOCOL_TAG`] protocol and embeds it into
+    /// the first `OP_RETURN` or taproot output of the PSBT which is able to
+    /// host it, depending on the commitment `method`. Returns proof of the
+    /// commitment, which has to accompany the disclosure.
+    ///
+    /// The host output can't be used afterwards for committing to transition
+    /// bundles. As with [`RgbExt::finalize_rgb`], all the PSBT inputs must
+    /// spend native segwit outputs, and the transaction must not be modified
+    /// after the call other than by signing it, otherwise the proof becomes
+    /// invalid.
+    pub fn commit_into_psbt(
+        &self,
+        psbt: &mut PartiallySignedTransaction,
+        method: CloseMethod,
+    ) -> Result<DisclosureCommitmentProof, CommitError> {
+        let vout = (0..psbt.outputs.len())
+            .find(|vout| check_host(psbt, *vout, method).is_ok())
+            .ok_or(CommitError::NoHost(method))?;
+
+        let protocol_id = disclosure_protocol_id();
+        let source = MultiSource {
+            min_depth: LNPBP4_MIN_DEPTH,
+            messages: bmap! { protocol_id => self.lnpbp4_message() },
+            static_entropy: None,
+        };
+        let tree = MerkleTree::commit(&source);
+        let commitment = tree.clone().consensus_commit().into_inner();
+        let dbc_proof = embed_commitment(psbt, vout, method, &commitment)?;
+
+        let anchor = Anchor {
+            txid: psbt.unsigned_tx.txid(),
+            lnpbp4_proof: MerkleBlock::from(tree),
+            dbc_proof,
+        }
+        .to_merkle_proof(protocol_id)
+        .expect("anchor commits to the disclosure");
+        Ok(DisclosureCommitmentProof { anchor })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use amplify::Wrapper;
+    use bitcoin::blockdata::opcodes::all::OP_RETURN;
+    use bitcoin::blockdata::script::{Builder, Script};
+    use bitcoin::consensus::{deserialize, serialize};
+    use bitcoin::hashes::sha256t;
+    use bitcoin::secp256k1::{KeyPair, Secp256k1, XOnlyPublicKey};
+    use bitcoin::{OutPoint, PubkeyHash, Transaction, TxIn, TxOut, Txid, WPubkeyHash};
+
+    use super::*;
+    use crate::{AnchorCloseMethod, Transition};
+
+    fn contract_id(no: u8) -> ContractId {
+        ContractId::from_inner(sha256t::Hash::from_inner([no; 32]))
+    }
+
+    fn bundle(ty: u16) -> TransitionBundle {
+        let transition = Transition::with(ty, empty!(), empty!(), empty!(), empty!(), empty!());
+        TransitionBundle::from(bmap! { transition => bset![0u16] })
+    }
+
+    fn internal_pk() -> XOnlyPublicKey {
+        let secp = Secp256k1::new();
+        let keypair = KeyPair::from_seckey_slice(&secp, &[1u8; 32]).unwrap();
+        XOnlyPublicKey::from_keypair(&keypair)
+    }
+
+    fn psbt() -> PartiallySignedTransaction {
+        let secp = Secp256k1::verification_only();
+        let tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::from_inner([1u8; 32]), 0),
+                ..TxIn::default()
+            }],
+            output: vec![
+                TxOut {
+                    value: 0,
+                    script_pubkey: Builder::new().push_opcode(OP_RETURN).into_script(),
+                },
+                TxOut {
+                    value: 10_000,
+                    script_pubkey: Script::new_v1_p2tr(&secp, internal_pk(), None),
+                },
+            ],
+        };
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].witness_utxo = Some(TxOut {
+            value: 20_000,
+            script_pubkey: Script::new_v0_p2wpkh(&WPubkeyHash::from_inner([3u8; 20])),
+        });
+        psbt.outputs[1].tap_internal_key = Some(internal_pk());
+        psbt
+    }
+
+    #[test]
+    fn test_non_segwit_inputs() {
+        let legacy = TxOut {
+            value: 20_000,
+            script_pubkey: Script::new_p2pkh(&PubkeyHash::from_inner([3u8; 20])),
+        };
+        let prev_tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![legacy.clone()],
+        };
+
+        let mut psbt = psbt();
+        psbt.set_rgb_contract(contract_id(1), &bundle(1)).unwrap();
+        psbt.set_rgb_host(0, CloseMethod::OpretFirst).unwrap();
+        psbt.unsigned_tx.input.push(TxIn {
+            previous_output: OutPoint::new(prev_tx.txid(), 0),
+            ..TxIn::default()
+        });
+        psbt.inputs.push(Default::default());
+        assert!(matches!(
+            psbt.finalize_rgb(),
+            Err(PsbtRgbError::NonSegwitInput(1))
+        ));
+        psbt.inputs[1].witness_utxo = Some(legacy);
+        assert!(matches!(
+            psbt.finalize_rgb(),
+            Err(PsbtRgbError::NonSegwitInput(1))
+        ));
+        psbt.inputs[1].witness_utxo = None;
+        psbt.inputs[1].non_witness_utxo = Some(prev_tx);
+        assert!(matches!(
+            tlv_disclosure().commit_into_psbt(&mut psbt, CloseMethod::OpretFirst),
+            Err(CommitError::Psbt(PsbtRgbError::NonSegwitInput(1)))
+        ));
+
+        // Commitment is not embedded if the inputs are rejected
+        assert_eq!(psbt.unsigned_tx.output[0].script_pubkey.len(), 1);
+        psbt.inputs[1].non_witness_utxo = None;
+        psbt.inputs[1].witness_utxo = psbt.inputs[0].witness_utxo.clone();
+        psbt.finalize_rgb().unwrap();
+    }
+
+    #[test]
+    fn test_contracts_roundtrip() {
+        let mut psbt = psbt();
+        assert!(psbt.rgb_contract_ids().is_empty());
+        psbt.set_rgb_contract(contract_id(1), &bundle(1)).unwrap();
+        psbt.set_rgb_contract(contract_id(2), &bundle(2)).unwrap();
+        psbt.set_rgb_host(0, CloseMethod::OpretFirst).unwrap();
+
+        let psbt: PartiallySignedTransaction = deserialize(&serialize(&psbt)).unwrap();
+        assert_eq!(
+            psbt.rgb_contract_ids(),
+            bset![contract_id(1), contract_id(2)]
+        );
+        assert_eq!(
+            psbt.rgb_bundles().unwrap(),
+            bmap! { contract_id(1) => bundle(1), contract_id(2) => bundle(2) }
+        );
+        assert_eq!(psbt.rgb_host(), Some((0, CloseMethod::OpretFirst)));
+    }
+
+    #[test]
+    fn test_opret_commitment() {
+        let mut psbt = psbt();
+        assert!(matches!(
+            psbt.finalize_rgb(),
+            Err(PsbtRgbError::NoContracts)
+        ));
+        psbt.set_rgb_contract(contract_id(1), &bundle(1)).unwrap();
+        assert!(matches!(psbt.finalize_rgb(), Err(PsbtRgbError::NoHost)));
+        assert!(matches!(
+            psbt.set_rgb_host(2, CloseMethod::OpretFirst),
+            Err(PsbtRgbError::UnknownOutput(2))
+        ));
+        psbt.set_rgb_host(1, CloseMethod::OpretFirst).unwrap();
+        assert!(matches!(
+            psbt.finalize_rgb(),
+            Err(PsbtRgbError::OpretHost(1))
+        ));
+
+        psbt.set_rgb_host(0, CloseMethod::OpretFirst).unwrap();
+        psbt.set_rgb_contract(contract_id(2), &bundle(2)).unwrap();
+        let anchors = psbt.finalize_rgb().unwrap();
+        assert_eq!(
+            anchors.keys().copied().collect::<BTreeSet<_>>(),
+            bset![contract_id(1), contract_id(2)]
+        );
+        let txid = psbt.unsigned_tx.txid();
+        assert!(anchors.values().all(|anchor| anchor.txid == txid));
+        assert!(anchors
+            .values()
+            .all(|anchor| anchor.close_method() == CloseMethod::OpretFirst));
+
+        let script = &psbt.unsigned_tx.output[0].script_pubkey;
+        assert!(script.is_op_return());
+        assert_eq!(script.len(), 34);
+        let commitment = psbt.outputs[0]
+            .proprietary
+            .get(&rgb_key(PSBT_OUT_RGB_COMMITMENT, vec![]))
+            .unwrap();
+        assert_eq!(&script[2..], commitment.as_slice());
+
+        assert!(matches!(
+            psbt.finalize_rgb(),
+            Err(PsbtRgbError::AlreadyCommitted(0))
+        ));
+        assert!(matches!(
+            psbt.set_rgb_contract(contract_id(3), &bundle(3)),
+            Err(PsbtRgbError::AlreadyCommitted(0))
+        ));
+    }
+
+    #[test]
+    fn test_tapret_commitment() {
+        let mut psbt = psbt();
+        psbt.set_rgb_contract(contract_id(1), &bundle(1)).unwrap();
+        psbt.set_rgb_host(0, CloseMethod::TapretFirst).unwrap();
+        assert!(matches!(
+            psbt.finalize_rgb(),
+            Err(PsbtRgbError::TapretHost(0))
+        ));
+
+        psbt.set_rgb_host(1, CloseMethod::TapretFirst).unwrap();
+        assert_eq!(psbt.rgb_host(), Some((1, CloseMethod::TapretFirst)));
+        let original = psbt.unsigned_tx.output[1].script_pubkey.clone();
+        let anchors = psbt.finalize_rgb().unwrap();
+        assert_eq!(anchors.len(), 1);
+        assert_eq!(anchors[&contract_id(1)].txid, psbt.unsigned_tx.txid());
+        assert_eq!(
+            anchors[&contract_id(1)].close_method(),
+            CloseMethod::TapretFirst
+        );
+
+        let script = &psbt.unsigned_tx.output[1].script_pubkey;
+        assert!(script.is_v1_p2tr());
+        assert_ne!(script, &original);
+        assert!(psbt.outputs[1].tap_tree.is_some());
+
+        let psbt: PartiallySignedTransaction = deserialize(&serialize(&psbt)).unwrap();
+        let bundles = psbt.rgb_bundles().unwrap();
+        assert_eq!(bundles, bmap! { contract_id(1) => bundle(1) });
+    }
+
+    fn tlv_disclosure() -> Disclosure {
+        let mut disclosure = Disclosure::default();
+        disclosure.insert_tlv_record(7, vec![1, 2, 3]).unwrap();
+        disclosure
+    }
+
+    #[test]
+    fn test_disclosure_opret() {
+        let mut psbt = psbt();
+        let mut disclosure = tlv_disclosure();
+        let proof = disclosure
+            .commit_into_psbt(&mut psbt, CloseMethod::OpretFirst)
+            .unwrap();
+        let tx = psbt.unsigned_tx.clone();
+        assert_eq!(proof.txid(), tx.txid());
+        assert_eq!(proof.anchor.close_method(), CloseMethod::OpretFirst);
+        assert!(proof.verify(&disclosure, &tx));
+
+        let proof =
+            DisclosureCommitmentProof::strict_deserialize(proof.strict_serialize().unwrap())
+                .unwrap();
+        assert!(proof.verify(&disclosure, &tx));
+
+        // Comments are not committed to
+        disclosure.change_comment(s!("comment"));
+        assert!(proof.verify(&disclosure, &tx));
+        disclosure.insert_tlv_record(9, vec![4]).unwrap();
+        assert!(!proof.verify(&disclosure, &tx));
+
+        assert!(!proof.verify(&Disclosure::default(), &tx));
+        assert!(!proof.verify(&tlv_disclosure(), &psbt().unsigned_tx));
+        assert!(matches!(
+            tlv_disclosure().commit_into_psbt(&mut psbt, CloseMethod::OpretFirst),
+            Err(CommitError::NoHost(CloseMethod::OpretFirst))
+        ));
+    }
+
+    #[test]
+    fn test_disclosure_tapret() {
+        let mut psbt = psbt();
+        let mut disclosure = tlv_disclosure();
+        let original = psbt.unsigned_tx.output[1].script_pubkey.clone();
+        let proof = disclosure
+            .commit_into_psbt(&mut psbt, CloseMethod::TapretFirst)
+            .unwrap();
+        let tx = psbt.unsigned_tx.clone();
+        assert_ne!(tx.output[1].script_pubkey, original);
+        assert_eq!(proof.anchor.close_method(), CloseMethod::TapretFirst);
+        assert!(proof.verify(&disclosure, &tx));
+
+        disclosure.remove_tlv_record(7);
+        assert!(!proof.verify(&disclosure, &tx));
+
+        psbt.set_rgb_contract(contract_id(1), &bundle(1)).unwrap();
+        psbt.set_rgb_host(1, CloseMethod::TapretFirst).unwrap();
+        assert!(matches!(
+            psbt.finalize_rgb(),
+            Err(PsbtRgbError::AlreadyCommitted(1))
+        ));
+    }
+
+    #[test]
+    fn test_disclosure_host_search() {
+        let mut psbt = psbt();
+        let secp = Secp256k1::verification_only();
+        // Taproot output with unknown internal key can't host the commitment
+        psbt.unsigned_tx.output.insert(0, TxOut {
+            value: 10_000,
+            script_pubkey: Script::new_v1_p2tr(&secp, internal_pk(), None),
+        });
+        psbt.outputs.insert(0, Default::default());
+        psbt.unsigned_tx.output.push(TxOut {
+            value: 0,
+            script_pubkey: Builder::new().push_opcode(OP_RETURN).into_script(),
+        });
+        psbt.outputs.push(Default::default());
+
+        // Outputs hosting other commitments are skipped
+        let disclosure = tlv_disclosure();
+        let proof = disclosure
+            .commit_into_psbt(&mut psbt, CloseMethod::OpretFirst)
+            .unwrap();
+        assert_eq!(psbt.unsigned_tx.output[1].script_pubkey.len(), 34);
+        assert!(proof.verify(&disclosure, &psbt.unsigned_tx));
+        let proof = disclosure
+            .commit_into_psbt(&mut psbt, CloseMethod::OpretFirst)
+            .unwrap();
+        assert_eq!(psbt.unsigned_tx.output[3].script_pubkey.len(), 34);
+        assert!(proof.verify(&disclosure, &psbt.unsigned_tx));
+
+        let proof = disclosure
+            .commit_into_psbt(&mut psbt, CloseMethod::TapretFirst)
+            .unwrap();
+        assert_eq!(
+            psbt.unsigned_tx.output[0].script_pubkey,
+            Script::new_v1_p2tr(&secp, internal_pk(), None)
+        );
+        assert!(psbt.outputs[2].tap_tree.is_some());
+        assert!(proof.verify(&disclosure, &psbt.unsigned_tx));
+    }
+
+    #[test]
+    fn test_disclosure_no_host() {
+        let mut psbt = psbt();
+        psbt.unsigned_tx.output.remove(0);
+        psbt.outputs.remove(0);
+        assert!(matches!(
+            tlv_disclosure().commit_into_psbt(&mut psbt, CloseMethod::OpretFirst),
+            Err(CommitError::NoHost(CloseMethod::OpretFirst))
+        ));
+    }
+}
@@ -0,0 +1,236 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Caching layer shared by the resolvers of the witness transactions.
+//!
+//! Resolved transactions and heights of the mined transactions are cached in
+//! memory; failed, unknown and unconfirmed lookups are not cached and are
+//! retried on the next request. Source failures are reported as unresolved
+//! transactions rather than as validation errors, so the validation can
+//! continue and report which of the witnesses were not checked.
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error as StdError;
+
+use bitcoin::{Transaction, Txid};
+use rgb_core::validation::{ResolveTx, TxResolverError};
+
+use crate::{ConsignmentType, InmemConsignment, ResolveWitness};
+
+/// Source of the witness transactions and their mining heights, like a
+/// blockchain indexer or a bitcoin node
+pub trait WitnessSource {
+    type Error: StdError + 'static;
+
+    /// Retrieves transactions with the given ids. Transactions unknown to the
+    /// source are omitted from the result.
+    fn fetch_txs(&self, txids: &[Txid]) -> Result<Vec<Transaction>, Self::Error>;
+
+    /// Retrieves heights of the blocks mining the transactions with the given
+    /// ids. Unknown and unconfirmed transactions are omitted from the result.
+    /// Already retrieved transactions are provided in `txs`.
+    fn fetch_heights(
+        &self,
+        txids: &[Txid],
+        txs: &BTreeMap<Txid, Transaction>,
+    ) -> Result<BTreeMap<Txid, u32>, Self::Error>;
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+struct Cache {
+    txs: BTreeMap<Txid, Transaction>,
+    heights: BTreeMap<Txid, u32>,
+}
+
+/// Resolver of the witness transactions and their mining heights with
+/// in-memory cache over a [`WitnessSource`]
+#[derive(Debug)]
+pub struct CachingResolver<S>
+where S: WitnessSource
+{
+    source: S,
+    cache: RefCell<Cache>,
+}
+
+impl<S> CachingResolver<S>
+where S: WitnessSource
+{
+    /// Constructs resolver over the `source` with an empty cache
+    pub fn with(source: S) -> Self {
+        CachingResolver {
+            source,
+            cache: none!(),
+        }
+    }
+
+    /// Returns source used by the resolver
+    #[inline]
+    pub fn source(&self) -> &S { &self.source }
+
+    /// Removes all cached transactions and heights
+    pub fn clear_cache(&self) { *self.cache.borrow_mut() = none!(); }
+
+    fn fetch_txs(&self, txids: &BTreeSet<Txid>) -> Result<(), S::Error> {
+        let mut cache = self.cache.borrow_mut();
+        let missing = txids
+            .iter()
+            .filter(|txid| !cache.txs.contains_key(txid))
+            .copied()
+            .collect::<Vec<_>>();
+        if missing.is_empty() {
+            return Ok(());
+        }
+        let txs = self.source.fetch_txs(&missing)?;
+        cache.txs.extend(txs.into_iter().map(|tx| (tx.txid(), tx)));
+        Ok(())
+    }
+
+    fn fetch_heights(&self, txids: &BTreeSet<Txid>) -> Result<(), S::Error> {
+        let mut cache = self.cache.borrow_mut();
+        let unmined = txids
+            .iter()
+            .filter(|txid| !cache.heights.contains_key(txid))
+            .copied()
+            .collect::<Vec<_>>();
+        if unmined.is_empty() {
+            return Ok(());
+        }
+        let heights = self.source.fetch_heights(&unmined, &cache.txs)?;
+        cache.heights.extend(heights);
+        Ok(())
+    }
+
+    /// Retrieves all transactions which are not cached yet and heights of
+    /// those of them which are not known to be mined, letting the source to
+    /// batch the requests
+    pub fn prefetch(&self, txids: impl IntoIterator<Item = Txid>) -> Result<(), S::Error> {
+        let txids = txids.into_iter().collect();
+        self.fetch_txs(&txids)?;
+        self.fetch_heights(&txids)
+    }
+
+    /// Prefetches all witness transactions of the consignment; see
+    /// [`CachingResolver::prefetch`]
+    #[inline]
+    pub fn prefetch_consignment<C>(
+        &self,
+        consignment: &InmemConsignment<C>,
+    ) -> Result<(), S::Error>
+    where
+        C: ConsignmentType,
+    {
+        self.prefetch(consignment.txids())
+    }
+
+    /// Returns witness transaction with the `txid`, or `None` if it is not
+    /// known to the source
+    pub fn transaction(&self, txid: Txid) -> Result<Option<Transaction>, S::Error> {
+        self.fetch_txs(&bset![txid])?;
+        Ok(self.cache.borrow().txs.get(&txid).cloned())
+    }
+
+    /// Returns height of the block which mined the witness transaction, or
+    /// `None` if the transaction is unconfirmed or unknown
+    pub fn height(&self, txid: Txid) -> Result<Option<u32>, S::Error> {
+        self.prefetch([txid])?;
+        Ok(self.cache.borrow().heights.get(&txid).copied())
+    }
+}
+
+impl<S> ResolveTx for CachingResolver<S>
+where S: WitnessSource
+{
+    fn resolve_tx(&self, txid: Txid) -> Result<Transaction, TxResolverError> {
+        match self.transaction(txid) {
+            Ok(Some(tx)) => Ok(tx),
+            Ok(None) => Err(TxResolverError { txid, err: None }),
+            Err(err) => {
+                warn_event!(%txid, %err, "witness transaction is not resolved");
+                Err(TxResolverError {
+                    txid,
+                    err: Some(Box::new(err)),
+                })
+            }
+        }
+    }
+}
+
+impl<S> ResolveWitness for CachingResolver<S>
+where S: WitnessSource
+{
+    type Error = S::Error;
+
+    #[inline]
+    fn resolve_height(&mut self, txid: Txid) -> Result<Option<u32>, Self::Error> {
+        self.height(txid)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use bitcoin::{Script, TxIn, TxOut};
+
+    use super::*;
+
+    /// Transaction with `no` inputs, which the test sources consider mined at
+    /// the height `no`, or unconfirmed for zero `no`
+    pub(crate) fn tx(no: u8) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn::default(); no as usize],
+            output: vec![TxOut {
+                value: no as u64,
+                script_pubkey: Script::from(vec![0x51, no]),
+            }],
+        }
+    }
+
+    /// Checks behaviour which must be common for all [`WitnessSource`]
+    /// implementations. The source must know transactions [`tx`] with numbers
+    /// from 0 to 3 and must fail all requests while `set_offline` is on.
+    pub(crate) fn resolver_conformance<S>(
+        mut resolver: CachingResolver<S>,
+        set_offline: impl Fn(&S, bool),
+    ) where
+        S: WitnessSource,
+    {
+        let txids = (0..4).map(|no| tx(no).txid()).collect::<Vec<_>>();
+        resolver.prefetch(txids.iter().copied()).unwrap();
+        for (no, txid) in txids.iter().enumerate() {
+            assert_eq!(resolver.resolve_tx(*txid).unwrap().txid(), *txid);
+            let height = resolver.resolve_height(*txid).unwrap();
+            assert_eq!(height, Some(no as u32).filter(|height| *height > 0));
+        }
+
+        let unknown = tx(4).txid();
+        let err = resolver.resolve_tx(unknown).unwrap_err();
+        assert_eq!(err.txid, unknown);
+        assert!(err.err.is_none());
+        assert_eq!(resolver.resolve_height(unknown).unwrap(), None);
+
+        // Mined transactions are served from the cache, while the unconfirmed
+        // ones are requested again
+        set_offline(resolver.source(), true);
+        assert_eq!(resolver.resolve_tx(txids[1]).unwrap().txid(), txids[1]);
+        assert_eq!(resolver.resolve_height(txids[1]).unwrap(), Some(1));
+        assert!(resolver.resolve_height(txids[0]).is_err());
+        let err = resolver.resolve_tx(unknown).unwrap_err();
+        assert_eq!(err.txid, unknown);
+        assert!(err.err.is_some());
+
+        resolver.clear_cache();
+        assert!(resolver.resolve_tx(txids[1]).is_err());
+        set_offline(resolver.source(), false);
+        assert_eq!(resolver.resolve_height(txids[3]).unwrap(), Some(3));
+    }
+}
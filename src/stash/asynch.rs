@@ -0,0 +1,317 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Asynchronous version of the [`Stash`] API for backends which are
+//! inherently async, like remote databases or HTTP storage.
+//!
+//! # Cancellation safety
+//!
+//! Futures returned by [`AsyncStash`] methods may be dropped at any await
+//! point. Implementations of the mutating operations (`accept`, `enclose`,
+//! `process_witness`, `restore`) must not leave the stash partially modified
+//! in that case: all required data must be fetched and the changes prepared
+//! before the first modification, and the modification itself must be applied
+//! as a single atomic step (for instance, a database transaction) after which
+//! no more await points follow. [`SyncAsAsync`] adapter satisfies this
+//! requirement trivially, since the wrapped synchronous operations never
+//! yield.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use async_trait::async_trait;
+use bitcoin::{OutPoint, Txid};
+use commit_verify::lnpbp4;
+
+use super::{Stash, StashDiff, StashMetrics, StashSnapshot};
+use crate::{
+    seal, Anchor, CloseMethod, ContractId, Disclosure, DisclosureId, ProvenState, SealEndpoint,
+    StateTransfer, TransitionBundle,
+};
+
+/// Asynchronous counterpart of the [`Stash`] trait. See [`Stash`] for the
+/// description of the individual methods and the module-level documentation
+/// for the cancellation safety requirements.
+#[async_trait]
+pub trait AsyncStash {
+    type Error: std::error::Error + Send;
+
+    async fn consign(
+        &self,
+        contract_id: ContractId,
+        bundle: TransitionBundle,
+        anchor: Option<&Anchor<lnpbp4::MerkleProof>>,
+        endpoints: &BTreeSet<SealEndpoint>,
+        close_method: Option<CloseMethod>,
+    ) -> Result<StateTransfer, Self::Error>;
+
+    async fn accept(
+        &mut self,
+        consignment: &StateTransfer,
+        known_seals: &[seal::Revealed],
+    ) -> Result<(), Self::Error>;
+
+    async fn enclose(&mut self, disclosure: &Disclosure) -> Result<(), Self::Error>;
+
+    async fn defer_disclosure(
+        &mut self,
+        witness_txid: Txid,
+        disclosure: Disclosure,
+    ) -> Result<(), Self::Error>;
+
+    async fn pending_disclosures(&self) -> Result<BTreeMap<Txid, Vec<DisclosureId>>, Self::Error>;
+
+    async fn process_witness(&mut self, txid: Txid) -> Result<Vec<DisclosureId>, Self::Error>;
+
+    async fn snapshot(&self) -> Result<StashSnapshot, Self::Error>;
+
+    async fn restore(&mut self, snapshot: StashSnapshot) -> Result<(), Self::Error>;
+
+    async fn diff(&self, snapshot: &StashSnapshot) -> Result<StashDiff, Self::Error>;
+
+    async fn metrics(&self) -> Result<StashMetrics, Self::Error>;
+
+    async fn outpoint_state(
+        &self,
+        outpoints: &BTreeSet<OutPoint>,
+    ) -> Result<BTreeMap<OutPoint, Vec<(ContractId, ProvenState)>>, Self::Error>;
+
+    async fn is_rgb_colored(&self, outpoint: OutPoint) -> Result<bool, Self::Error>;
+}
+
+/// Adapter making any synchronous [`Stash`] implementation usable through the
+/// [`AsyncStash`] API
+#[derive(Wrapper, Clone, PartialEq, Debug, Default, From)]
+pub struct SyncAsAsync<S>(S)
+where S: Stash;
+
+#[async_trait]
+impl<S> AsyncStash for SyncAsAsync<S>
+where
+    S: Stash + Send + Sync,
+    S::Error: Send,
+{
+    type Error = S::Error;
+
+    async fn consign(
+        &self,
+        contract_id: ContractId,
+        bundle: TransitionBundle,
+        anchor: Option<&Anchor<lnpbp4::MerkleProof>>,
+        endpoints: &BTreeSet<SealEndpoint>,
+        close_method: Option<CloseMethod>,
+    ) -> Result<StateTransfer, Self::Error> {
+        self.0
+            .consign(contract_id, bundle, anchor, endpoints, close_method)
+    }
+
+    async fn accept(
+        &mut self,
+        consignment: &StateTransfer,
+        known_seals: &[seal::Revealed],
+    ) -> Result<(), Self::Error> {
+        self.0.accept(consignment, known_seals)
+    }
+
+    async fn enclose(&mut self, disclosure: &Disclosure) -> Result<(), Self::Error> {
+        self.0.enclose(disclosure)
+    }
+
+    async fn defer_disclosure(
+        &mut self,
+        witness_txid: Txid,
+        disclosure: Disclosure,
+    ) -> Result<(), Self::Error> {
+        self.0.defer_disclosure(witness_txid, disclosure)
+    }
+
+    async fn pending_disclosures(&self) -> Result<BTreeMap<Txid, Vec<DisclosureId>>, Self::Error> {
+        self.0.pending_disclosures()
+    }
+
+    async fn process_witness(&mut self, txid: Txid) -> Result<Vec<DisclosureId>, Self::Error> {
+        self.0.process_witness(txid)
+    }
+
+    async fn snapshot(&self) -> Result<StashSnapshot, Self::Error> { self.0.snapshot() }
+
+    async fn restore(&mut self, snapshot: StashSnapshot) -> Result<(), Self::Error> {
+        self.0.restore(snapshot)
+    }
+
+    async fn diff(&self, snapshot: &StashSnapshot) -> Result<StashDiff, Self::Error> {
+        self.0.diff(snapshot)
+    }
+
+    async fn metrics(&self) -> Result<StashMetrics, Self::Error> { self.0.metrics() }
+
+    async fn outpoint_state(
+        &self,
+        outpoints: &BTreeSet<OutPoint>,
+    ) -> Result<BTreeMap<OutPoint, Vec<(ContractId, ProvenState)>>, Self::Error> {
+        self.0.outpoint_state(outpoints)
+    }
+
+    async fn is_rgb_colored(&self, outpoint: OutPoint) -> Result<bool, Self::Error> {
+        self.0.is_rgb_colored(outpoint)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    use bitcoin::hashes::Hash;
+    use futures::executor::block_on;
+    use futures::poll;
+
+    use super::*;
+    use crate::{MemStash, MemStashError};
+
+    /// Future yielding control to the executor once before completing
+    struct YieldNow(bool);
+
+    impl Future for YieldNow {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.0 {
+                return Poll::Ready(());
+            }
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+
+    /// Mock backend which yields between preparing changes and applying them
+    #[derive(Default)]
+    struct YieldingStash(MemStash);
+
+    #[async_trait]
+    impl AsyncStash for YieldingStash {
+        type Error = MemStashError;
+
+        async fn consign(
+            &self,
+            contract_id: ContractId,
+            bundle: TransitionBundle,
+            anchor: Option<&Anchor<lnpbp4::MerkleProof>>,
+            endpoints: &BTreeSet<SealEndpoint>,
+            close_method: Option<CloseMethod>,
+        ) -> Result<StateTransfer, Self::Error> {
+            YieldNow(false).await;
+            self.0
+                .consign(contract_id, bundle, anchor, endpoints, close_method)
+        }
+
+        async fn accept(
+            &mut self,
+            consignment: &StateTransfer,
+            known_seals: &[seal::Revealed],
+        ) -> Result<(), Self::Error> {
+            let mut staged = self.0.clone();
+            staged.accept(consignment, known_seals)?;
+            YieldNow(false).await;
+            self.0 = staged;
+            Ok(())
+        }
+
+        async fn enclose(&mut self, disclosure: &Disclosure) -> Result<(), Self::Error> {
+            let mut staged = self.0.clone();
+            staged.enclose(disclosure)?;
+            YieldNow(false).await;
+            self.0 = staged;
+            Ok(())
+        }
+
+        async fn defer_disclosure(
+            &mut self,
+            witness_txid: Txid,
+            disclosure: Disclosure,
+        ) -> Result<(), Self::Error> {
+            self.0.defer_disclosure(witness_txid, disclosure)
+        }
+
+        async fn pending_disclosures(
+            &self,
+        ) -> Result<BTreeMap<Txid, Vec<DisclosureId>>, Self::Error> {
+            self.0.pending_disclosures()
+        }
+
+        async fn process_witness(&mut self, txid: Txid) -> Result<Vec<DisclosureId>, Self::Error> {
+            let mut staged = self.0.clone();
+            let enclosed = staged.process_witness(txid)?;
+            YieldNow(false).await;
+            self.0 = staged;
+            Ok(enclosed)
+        }
+
+        async fn snapshot(&self) -> Result<StashSnapshot, Self::Error> { self.0.snapshot() }
+
+        async fn restore(&mut self, snapshot: StashSnapshot) -> Result<(), Self::Error> {
+            self.0.restore(snapshot)
+        }
+
+        async fn diff(&self, snapshot: &StashSnapshot) -> Result<StashDiff, Self::Error> {
+            self.0.diff(snapshot)
+        }
+
+        async fn metrics(&self) -> Result<StashMetrics, Self::Error> { Ok(self.0.metrics()) }
+
+        async fn outpoint_state(
+            &self,
+            outpoints: &BTreeSet<OutPoint>,
+        ) -> Result<BTreeMap<OutPoint, Vec<(ContractId, ProvenState)>>, Self::Error> {
+            self.0.outpoint_state(outpoints)
+        }
+
+        async fn is_rgb_colored(&self, outpoint: OutPoint) -> Result<bool, Self::Error> {
+            self.0.is_rgb_colored(outpoint)
+        }
+    }
+
+    #[test]
+    fn test_sync_adapter() {
+        let mut stash = SyncAsAsync::from(MemStash::new());
+        let txid = Txid::from_inner([1u8; 32]);
+        block_on(async {
+            stash
+                .defer_disclosure(txid, Disclosure::default())
+                .await
+                .unwrap();
+            assert_eq!(stash.pending_disclosures().await.unwrap().len(), 1);
+            assert_eq!(stash.process_witness(txid).await.unwrap().len(), 1);
+            assert!(stash.pending_disclosures().await.unwrap().is_empty());
+        });
+    }
+
+    #[test]
+    fn test_cancellation_safety() {
+        let mut stash = YieldingStash::default();
+        let txid = Txid::from_inner([1u8; 32]);
+        block_on(async {
+            stash
+                .defer_disclosure(txid, Disclosure::default())
+                .await
+                .unwrap();
+
+            let mut future = stash.process_witness(txid);
+            assert!(poll!(&mut future).is_pending());
+            drop(future);
+            assert_eq!(stash.pending_disclosures().await.unwrap().len(), 1);
+
+            assert_eq!(stash.process_witness(txid).await.unwrap().len(), 1);
+            assert!(stash.pending_disclosures().await.unwrap().is_empty());
+        });
+    }
+}
@@ -0,0 +1,158 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Contract history extraction shared by the stash backends.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use commit_verify::lnpbp4;
+use rgb_core::{
+    Anchor, BundleId, ContractId, Extension, Genesis, Node, NodeId, Schema, SchemaId, SealEndpoint,
+    TransitionBundle,
+};
+use strict_encoding::LargeVec;
+
+use super::MemStashError;
+use crate::{AnchorCloseMethod, CloseMethod, StateTransfer};
+
+/// Storage of the contract history data, which can be used for constructing
+/// consignments. Lookups return owned data, such that the storage may
+/// deserialize it on demand.
+pub(super) trait HistorySource {
+    type Error: From<MemStashError>;
+
+    fn load_schema(&self, schema_id: SchemaId) -> Result<Option<Schema>, Self::Error>;
+
+    fn load_genesis(&self, contract_id: ContractId) -> Result<Option<Genesis>, Self::Error>;
+
+    /// Returns id of the transition bundle containing state transition with
+    /// the given id, if the transition is known
+    fn load_bundle_id(&self, node_id: NodeId) -> Result<Option<BundleId>, Self::Error>;
+
+    fn load_anchored_bundle(
+        &self,
+        contract_id: ContractId,
+        bundle_id: BundleId,
+    ) -> Result<(Anchor<lnpbp4::MerkleProof>, TransitionBundle), Self::Error>;
+
+    fn load_extension(
+        &self,
+        contract_id: ContractId,
+        node_id: NodeId,
+    ) -> Result<Option<Extension>, Self::Error>;
+}
+
+/// Constructs consignment containing the whole contract history required to
+/// validate the transition `bundle`. See [`super::Stash::consign`].
+///
+/// The `close_method` applies only to the `anchor` of the new bundle: history
+/// anchored with other methods is still included into the consignment.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        name = "consign",
+        level = "debug",
+        skip_all,
+        fields(contract_id = %contract_id, bundles = tracing::field::Empty)
+    )
+)]
+pub(super) fn consign_history<S>(
+    source: &S,
+    contract_id: ContractId,
+    bundle: TransitionBundle,
+    anchor: Option<&Anchor<lnpbp4::MerkleProof>>,
+    endpoints: &BTreeSet<SealEndpoint>,
+    close_method: Option<CloseMethod>,
+) -> Result<StateTransfer, S::Error>
+where S: HistorySource {
+    if let (Some(required), Some(anchor)) = (close_method, anchor) {
+        let used = anchor.close_method();
+        if used != required {
+            return Err(MemStashError::CloseMethodMismatch { required, used }.into());
+        }
+    }
+
+    let genesis = source
+        .load_genesis(contract_id)?
+        .ok_or(MemStashError::UnknownContract(contract_id))?;
+    let schema_id = genesis.schema_id();
+    let schema = source
+        .load_schema(schema_id)?
+        .ok_or(MemStashError::UnknownSchema(schema_id))?;
+    let root_schema = source.load_schema(schema.root_id)?;
+    let genesis_id = genesis.node_id();
+
+    let mut anchored_bundles = BTreeMap::<BundleId, _>::new();
+    let mut extensions = BTreeMap::<NodeId, Extension>::new();
+    let mut visited = bset![genesis_id];
+    let mut queue = bundle
+        .known_transitions()
+        .flat_map(|transition| transition.parent_outputs())
+        .map(|output| output.node_id)
+        .collect::<Vec<_>>();
+
+    while let Some(node_id) = queue.pop() {
+        if !visited.insert(node_id) {
+            continue;
+        }
+        if let Some(bundle_id) = source.load_bundle_id(node_id)? {
+            if !anchored_bundles.contains_key(&bundle_id) {
+                let anchored_bundle = source.load_anchored_bundle(contract_id, bundle_id)?;
+                anchored_bundles.insert(bundle_id, anchored_bundle);
+                debug_event!(%bundle_id, "bundle added to the consignment");
+            }
+            let (_, known_bundle) = &anchored_bundles[&bundle_id];
+            let transition = known_bundle
+                .known_transitions()
+                .find(|transition| transition.node_id() == node_id)
+                .ok_or(MemStashError::UnknownNode(node_id))?;
+            queue.extend(
+                transition
+                    .parent_outputs()
+                    .into_iter()
+                    .map(|output| output.node_id),
+            );
+        } else if let Some(extension) = source.load_extension(contract_id, node_id)? {
+            queue.extend(
+                extension
+                    .parent_outputs()
+                    .into_iter()
+                    .map(|output| output.node_id),
+            );
+            queue.extend(extension.parent_public_rights().keys().copied());
+            extensions.insert(node_id, extension);
+        } else {
+            return Err(MemStashError::UnknownNode(node_id).into());
+        }
+    }
+
+    let bundle_id = bundle.bundle_id();
+    if let Some(anchor) = anchor {
+        anchored_bundles.insert(bundle_id, (anchor.clone(), bundle));
+    }
+    let endpoints = endpoints
+        .iter()
+        .map(|endpoint| (bundle_id, *endpoint))
+        .collect();
+    #[cfg(feature = "tracing")]
+    tracing::Span::current().record("bundles", &anchored_bundles.len());
+
+    Ok(StateTransfer::with(
+        schema,
+        root_schema,
+        genesis,
+        endpoints,
+        LargeVec::try_from(anchored_bundles.into_values().collect::<Vec<_>>())
+            .map_err(|_| MemStashError::Oversized)?,
+        LargeVec::try_from(extensions.into_values().collect::<Vec<_>>())
+            .map_err(|_| MemStashError::Oversized)?,
+    ))
+}
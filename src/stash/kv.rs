@@ -0,0 +1,920 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Persistent stash backed by the `sled` embedded key-value database.
+//!
+//! Each class of the stash objects is kept in a separate sled tree. Keys are
+//! raw bytes of the object ids (prefixed with the contract id for the objects
+//! which belong to a specific contract) and values are strict-encoded objects.
+//! All changes made by a single stash operation are applied in one
+//! transaction spanning all trees, so they are atomic.
+//!
+//! The index of the unspent state by outpoints is kept in its own tree and is
+//! updated by each merge for the affected contracts only.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::Infallible;
+use std::path::Path;
+
+use bitcoin::{OutPoint, Txid};
+use commit_verify::{lnpbp4, CommitConceal};
+use rgb_core::{
+    seal, Anchor, AnchorId, BundleId, ContractId, Extension, Genesis, NodeId, Schema, SchemaId,
+    SealEndpoint, TransitionBundle,
+};
+use sled::transaction::{ConflictableTransactionError, TransactionError, Transactional};
+use sled::{Batch, Db, Tree};
+use strict_encoding::{StrictDecode, StrictEncode};
+
+use super::history::{consign_history, HistorySource};
+use super::mem::MemStashData;
+use super::outpoints::{revealed_state, OutpointIndex, OutputStates};
+use super::{
+    ByteCounter, ContractMetrics, MemStash, MemStashError, ObjectMetrics, Stash, StashDiff,
+    StashMetrics, StashObjects, StashSnapshot,
+};
+use crate::{CloseMethod, Disclosure, DisclosureId, ProvenState, StateTransfer};
+
+/// Version of the database layout created by this version of the library
+pub const SLED_LAYOUT_VERSION: u16 = 3;
+
+const META_TREE: &[u8] = b"meta";
+const META_VERSION_KEY: &[u8] = b"version";
+
+/// Errors happening during operations with [`SledStash`]
+#[derive(Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum SledStashError {
+    /// database error: {0}
+    #[from]
+    Sled(sled::Error),
+
+    /// stash data can't be encoded or decoded: {0}
+    #[from]
+    Encoding(strict_encoding::Error),
+
+    /// the database has layout version {0}, which is not supported by this
+    /// version of the library
+    UnsupportedVersion(u16),
+
+    /// the database layout version record is corrupted
+    CorruptedVersion,
+
+    #[display(inner)]
+    #[from]
+    Stash(MemStashError),
+}
+
+/// Persistent implementation of the [`Stash`] using `sled` database.
+///
+/// Opening the stash checks the layout version stored in the database meta
+/// tree, such that the databases created by the future versions of the
+/// library are rejected and the older ones can be migrated.
+#[derive(Clone, Debug)]
+pub struct SledStash {
+    db: Db,
+    schemata: Tree,
+    geneses: Tree,
+    anchors: Tree,
+    /// Keyed by contract id followed by the bundle id; values are pairs of
+    /// the anchor id and the bundle
+    bundles: Tree,
+    /// Keyed by contract id followed by the node id
+    extensions: Tree,
+    /// Maps transition node ids to the contract and bundle ids
+    transition_index: Tree,
+    /// Keyed by the concealed form of the seal
+    seal_secrets: Tree,
+    deferred_disclosures: Tree,
+    /// Keyed by the witness txid; values are empty
+    processed_witnesses: Tree,
+    /// Keyed by the seal outpoint followed by the contract id; values are
+    /// unspent states assigned to the outpoint, `None` for concealed ones
+    outpoint_index: Tree,
+}
+
+/// Changes to all of the stash trees which are applied atomically
+#[derive(Default)]
+struct SledBatch {
+    schemata: Batch,
+    geneses: Batch,
+    anchors: Batch,
+    bundles: Batch,
+    extensions: Batch,
+    transition_index: Batch,
+    seal_secrets: Batch,
+    deferred_disclosures: Batch,
+    processed_witnesses: Batch,
+    outpoint_index: Batch,
+}
+
+impl SledBatch {
+    /// Adds all objects from the in-memory stash, replacing existing objects
+    /// with the same ids
+    fn insert_all(&mut self, stash: &MemStash) -> Result<(), SledStashError> {
+        for (schema_id, schema) in &stash.schemata {
+            self.schemata
+                .insert(schema_id.strict_serialize()?, schema.strict_serialize()?);
+        }
+        for (contract_id, genesis) in &stash.geneses {
+            self.geneses
+                .insert(contract_id.strict_serialize()?, genesis.strict_serialize()?);
+        }
+        for (anchor_id, anchor) in &stash.anchors {
+            self.anchors
+                .insert(anchor_id.strict_serialize()?, anchor.strict_serialize()?);
+        }
+        for (contract_id, bundles) in &stash.bundles {
+            for (bundle_id, anchored_bundle) in bundles {
+                self.bundles.insert(
+                    (*contract_id, *bundle_id).strict_serialize()?,
+                    anchored_bundle.strict_serialize()?,
+                );
+            }
+        }
+        for (contract_id, extensions) in &stash.extensions {
+            for (node_id, extension) in extensions {
+                self.extensions.insert(
+                    (*contract_id, *node_id).strict_serialize()?,
+                    extension.strict_serialize()?,
+                );
+            }
+        }
+        for (node_id, location) in &stash.transition_index {
+            self.transition_index
+                .insert(node_id.strict_serialize()?, location.strict_serialize()?);
+        }
+        for seal in &stash.seal_secrets {
+            self.seal_secrets.insert(
+                seal.commit_conceal().strict_serialize()?,
+                seal.strict_serialize()?,
+            );
+        }
+        for (id, deferred) in &stash.deferred_disclosures {
+            self.deferred_disclosures
+                .insert(id.strict_serialize()?, deferred.strict_serialize()?);
+        }
+        for txid in &stash.processed_witnesses {
+            self.processed_witnesses
+                .insert(txid.strict_serialize()?, Vec::<u8>::new());
+        }
+        Ok(())
+    }
+
+    /// Adds all records of the outpoint index, replacing existing records
+    /// for the same outpoints and contracts
+    fn insert_outpoints(&mut self, index: &OutpointIndex) -> Result<(), SledStashError> {
+        for (outpoint, contracts) in index {
+            for (contract_id, states) in contracts {
+                self.outpoint_index.insert(
+                    (*outpoint, *contract_id).strict_serialize()?,
+                    states.strict_serialize()?,
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+impl SledStash {
+    /// Opens or creates stash database at the given path
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, SledStashError> {
+        SledStash::with(sled::open(path)?)
+    }
+
+    /// Opens stash using already opened database, initializing it if
+    /// necessary
+    pub fn with(db: Db) -> Result<Self, SledStashError> {
+        let meta = db.open_tree(META_TREE)?;
+        let version = match meta.get(META_VERSION_KEY)? {
+            None => None,
+            Some(data) => {
+                let version = <[u8; 2]>::try_from(data.as_ref())
+                    .map(u16::from_le_bytes)
+                    .map_err(|_| SledStashError::CorruptedVersion)?;
+                if version == 0 || version > SLED_LAYOUT_VERSION {
+                    return Err(SledStashError::UnsupportedVersion(version));
+                }
+                Some(version)
+            }
+        };
+
+        let stash = SledStash {
+            schemata: db.open_tree(b"schemata")?,
+            geneses: db.open_tree(b"geneses")?,
+            anchors: db.open_tree(b"anchors")?,
+            bundles: db.open_tree(b"bundles")?,
+            extensions: db.open_tree(b"extensions")?,
+            transition_index: db.open_tree(b"transition_index")?,
+            seal_secrets: db.open_tree(b"seal_secrets")?,
+            deferred_disclosures: db.open_tree(b"deferred_disclosures")?,
+            processed_witnesses: db.open_tree(b"processed_witnesses")?,
+            outpoint_index: db.open_tree(b"outpoint_index")?,
+            db,
+        };
+
+        // Migrations from the older layout versions
+        match version {
+            Some(SLED_LAYOUT_VERSION) => return Ok(stash),
+            // Version 1 has no index of the unspent state by outpoints, and
+            // version 2 indexes the state by node outputs without the owned
+            // right types
+            Some(1) | Some(2) => stash.rebuild_outpoint_index()?,
+            _ => {}
+        }
+        meta.insert(META_VERSION_KEY, &SLED_LAYOUT_VERSION.to_le_bytes()[..])?;
+        Ok(stash)
+    }
+
+    /// Rebuilds index of the unspent state by outpoints from all the data
+    /// stored in the database
+    pub fn rebuild_outpoint_index(&self) -> Result<(), SledStashError> {
+        let mut batch = SledBatch::default();
+        for key in self.outpoint_index.iter().keys() {
+            batch.outpoint_index.remove(key?);
+        }
+        batch.insert_outpoints(&self.load()?.scan_outpoints())?;
+        self.apply(batch)
+    }
+
+    /// Flushes all pending changes to the disk
+    pub fn flush(&self) -> Result<(), SledStashError> {
+        self.db.flush()?;
+        Ok(())
+    }
+
+    fn trees(&self) -> [&Tree; 10] {
+        [
+            &self.schemata,
+            &self.geneses,
+            &self.anchors,
+            &self.bundles,
+            &self.extensions,
+            &self.transition_index,
+            &self.seal_secrets,
+            &self.deferred_disclosures,
+            &self.processed_witnesses,
+            &self.outpoint_index,
+        ]
+    }
+
+    /// Applies batch to all trees in a single transaction
+    fn apply(&self, batch: SledBatch) -> Result<(), SledStashError> {
+        let batches = [
+            batch.schemata,
+            batch.geneses,
+            batch.anchors,
+            batch.bundles,
+            batch.extensions,
+            batch.transition_index,
+            batch.seal_secrets,
+            batch.deferred_disclosures,
+            batch.processed_witnesses,
+            batch.outpoint_index,
+        ];
+        let trees: &[&Tree] = &self.trees();
+        trees
+            .transaction(|views| {
+                for (view, batch) in views.iter().zip(&batches) {
+                    view.apply_batch(batch)?;
+                }
+                Ok::<_, ConflictableTransactionError<Infallible>>(())
+            })
+            .map_err(|err| match err {
+                TransactionError::Abort(never) => match never {},
+                TransactionError::Storage(err) => SledStashError::Sled(err),
+            })
+    }
+
+    /// Loads the whole stash content into memory
+    fn load(&self) -> Result<MemStash, SledStashError> {
+        let mut stash = MemStash::from(MemStashData {
+            schemata: load_map(&self.schemata)?,
+            geneses: load_map(&self.geneses)?,
+            anchors: load_map(&self.anchors)?,
+            deferred_disclosures: load_map(&self.deferred_disclosures)?,
+            processed_witnesses: load_keys(&self.processed_witnesses)?,
+            ..MemStashData::default()
+        });
+        for item in self.bundles.iter() {
+            let (key, value) = item?;
+            let (contract_id, bundle_id) = <(ContractId, BundleId)>::strict_deserialize(key)?;
+            stash
+                .bundles
+                .entry(contract_id)
+                .or_default()
+                .insert(bundle_id, StrictDecode::strict_deserialize(value)?);
+        }
+        for item in self.extensions.iter() {
+            let (key, value) = item?;
+            let (contract_id, node_id) = <(ContractId, NodeId)>::strict_deserialize(key)?;
+            stash
+                .extensions
+                .entry(contract_id)
+                .or_default()
+                .insert(node_id, Extension::strict_deserialize(value)?);
+        }
+        for value in self.seal_secrets.iter().values() {
+            stash
+                .seal_secrets
+                .insert(seal::Revealed::strict_deserialize(value?)?);
+        }
+        for item in self.outpoint_index.iter() {
+            let (key, value) = item?;
+            let (outpoint, contract_id) = <(OutPoint, ContractId)>::strict_deserialize(key)?;
+            stash
+                .outpoint_index
+                .entry(outpoint)
+                .or_default()
+                .insert(contract_id, StrictDecode::strict_deserialize(value)?);
+        }
+        stash.reindex();
+        Ok(stash)
+    }
+
+    /// Loads all data of the given contracts, together with all the known
+    /// seal secrets
+    fn load_contracts(
+        &self,
+        contract_ids: &BTreeSet<ContractId>,
+    ) -> Result<MemStash, SledStashError> {
+        let mut stash = MemStash::default();
+        for contract_id in contract_ids {
+            if let Some(genesis) = load(&self.geneses, contract_id)? {
+                stash.geneses.insert(*contract_id, genesis);
+            }
+            let prefix = contract_id.strict_serialize()?;
+            for item in self.bundles.scan_prefix(&prefix) {
+                let (key, value) = item?;
+                let (_, bundle_id) = <(ContractId, BundleId)>::strict_deserialize(key)?;
+                let (anchor_id, bundle) =
+                    <(AnchorId, TransitionBundle)>::strict_deserialize(value)?;
+                if let Some(anchor) = load(&self.anchors, &anchor_id)? {
+                    stash.anchors.insert(anchor_id, anchor);
+                }
+                stash
+                    .bundles
+                    .entry(*contract_id)
+                    .or_default()
+                    .insert(bundle_id, (anchor_id, bundle));
+            }
+            for item in self.extensions.scan_prefix(&prefix) {
+                let (key, value) = item?;
+                let (_, node_id) = <(ContractId, NodeId)>::strict_deserialize(key)?;
+                stash
+                    .extensions
+                    .entry(*contract_id)
+                    .or_default()
+                    .insert(node_id, Extension::strict_deserialize(value)?);
+            }
+        }
+        for value in self.seal_secrets.iter().values() {
+            stash
+                .seal_secrets
+                .insert(seal::Revealed::strict_deserialize(value?)?);
+        }
+        Ok(stash)
+    }
+
+    /// Loads objects which are already present in the database under the
+    /// same ids as the objects of the `other` stash
+    fn load_overlapping(&self, other: &MemStash) -> Result<MemStash, SledStashError> {
+        let mut known = MemStash::default();
+        for schema_id in other.schemata.keys() {
+            if let Some(schema) = load(&self.schemata, schema_id)? {
+                known.schemata.insert(*schema_id, schema);
+            }
+        }
+        for contract_id in other.geneses.keys() {
+            if let Some(genesis) = load(&self.geneses, contract_id)? {
+                known.geneses.insert(*contract_id, genesis);
+            }
+        }
+        for anchor_id in other.anchors.keys() {
+            if let Some(anchor) = load(&self.anchors, anchor_id)? {
+                known.anchors.insert(*anchor_id, anchor);
+            }
+        }
+        for (contract_id, bundles) in &other.bundles {
+            for bundle_id in bundles.keys() {
+                if let Some(bundle) = load(&self.bundles, &(*contract_id, *bundle_id))? {
+                    known
+                        .bundles
+                        .entry(*contract_id)
+                        .or_default()
+                        .insert(*bundle_id, bundle);
+                }
+            }
+        }
+        for (contract_id, extensions) in &other.extensions {
+            for node_id in extensions.keys() {
+                if let Some(extension) = load(&self.extensions, &(*contract_id, *node_id))? {
+                    known
+                        .extensions
+                        .entry(*contract_id)
+                        .or_default()
+                        .insert(*node_id, extension);
+                }
+            }
+        }
+        for id in other.deferred_disclosures.keys() {
+            if let Some(deferred) = load(&self.deferred_disclosures, id)? {
+                known.deferred_disclosures.insert(*id, deferred);
+            }
+        }
+        known.reindex();
+        Ok(known)
+    }
+
+    /// Merges `other` stash data with the data already present in the
+    /// database and adds the result to the batch
+    fn stage_merge(&self, other: MemStash, batch: &mut SledBatch) -> Result<(), SledStashError> {
+        // New seal secrets may reveal seals of any contract, otherwise only
+        // the state of the merged contracts changes
+        let mut contract_ids = other.affected_contracts();
+        for seal in &other.seal_secrets {
+            if !self
+                .seal_secrets
+                .contains_key(seal.commit_conceal().strict_serialize()?)?
+            {
+                contract_ids.extend(load_keys::<ContractId>(&self.geneses)?);
+                break;
+            }
+        }
+
+        let mut known = self.load_overlapping(&other)?;
+        known.merge(other).map_err(MemStashError::from)?;
+        batch.insert_all(&known)?;
+        self.stage_outpoints(&contract_ids, &known, batch)
+    }
+
+    /// Replaces outpoint index records of the given contracts with the ones
+    /// computed from the contract data in the database updated with the
+    /// `merged` objects
+    fn stage_outpoints(
+        &self,
+        contract_ids: &BTreeSet<ContractId>,
+        merged: &MemStash,
+        batch: &mut SledBatch,
+    ) -> Result<(), SledStashError> {
+        for key in self.outpoint_index.iter().keys() {
+            let key = key?;
+            let (_, contract_id) = <(OutPoint, ContractId)>::strict_deserialize(&key)?;
+            if contract_ids.contains(&contract_id) {
+                batch.outpoint_index.remove(key);
+            }
+        }
+
+        // Merged objects replace their stored versions
+        let mut contracts = self.load_contracts(contract_ids)?;
+        contracts.geneses.extend(merged.geneses.clone());
+        contracts.anchors.extend(merged.anchors.clone());
+        for (contract_id, bundles) in &merged.bundles {
+            contracts
+                .bundles
+                .entry(*contract_id)
+                .or_default()
+                .extend(bundles.clone());
+        }
+        for (contract_id, extensions) in &merged.extensions {
+            contracts
+                .extensions
+                .entry(*contract_id)
+                .or_default()
+                .extend(extensions.clone());
+        }
+        contracts
+            .seal_secrets
+            .extend(merged.seal_secrets.iter().cloned());
+        batch.insert_outpoints(&contracts.scan_outpoints())
+    }
+
+    /// Returns ids of all objects contained in the stash
+    pub fn objects(&self) -> Result<StashObjects, SledStashError> {
+        Ok(StashObjects {
+            schemata: load_keys(&self.schemata)?,
+            geneses: load_keys(&self.geneses)?,
+            anchors: load_keys(&self.anchors)?,
+            bundles: load_contract_keys(&self.bundles)?,
+            transitions: load_keys(&self.transition_index)?,
+            extensions: load_contract_keys(&self.extensions)?,
+            seal_secrets: self
+                .seal_secrets
+                .iter()
+                .values()
+                .map(|value| Ok(seal::Revealed::strict_deserialize(value?)?))
+                .collect::<Result<_, SledStashError>>()?,
+            deferred_disclosures: load_keys(&self.deferred_disclosures)?,
+        })
+    }
+}
+
+fn load<T>(tree: &Tree, key: &impl StrictEncode) -> Result<Option<T>, SledStashError>
+where T: StrictDecode {
+    tree.get(key.strict_serialize()?)?
+        .map(|value| T::strict_deserialize(value).map_err(SledStashError::from))
+        .transpose()
+}
+
+fn load_map<K, V>(tree: &Tree) -> Result<BTreeMap<K, V>, SledStashError>
+where
+    K: StrictDecode + Ord,
+    V: StrictDecode,
+{
+    tree.iter()
+        .map(|item| {
+            let (key, value) = item?;
+            Ok((K::strict_deserialize(key)?, V::strict_deserialize(value)?))
+        })
+        .collect()
+}
+
+fn load_keys<K>(tree: &Tree) -> Result<BTreeSet<K>, SledStashError>
+where K: StrictDecode + Ord {
+    tree.iter()
+        .keys()
+        .map(|key| Ok(K::strict_deserialize(key?)?))
+        .collect()
+}
+
+/// Loads ids from the keys prefixed with the contract id
+fn load_contract_keys<K>(tree: &Tree) -> Result<BTreeSet<K>, SledStashError>
+where K: StrictDecode + Ord {
+    tree.iter()
+        .keys()
+        .map(|key| Ok(<(ContractId, K)>::strict_deserialize(key?)?.1))
+        .collect()
+}
+
+/// Computes metrics of a tree from the size of the stored values
+fn tree_metrics(tree: &Tree) -> Result<ObjectMetrics, SledStashError> {
+    let mut metrics = ObjectMetrics::default();
+    for value in tree.iter().values() {
+        metrics.add_len(value?.len());
+    }
+    Ok(metrics)
+}
+
+impl HistorySource for SledStash {
+    type Error = SledStashError;
+
+    fn load_schema(&self, schema_id: SchemaId) -> Result<Option<Schema>, Self::Error> {
+        load(&self.schemata, &schema_id)
+    }
+
+    fn load_genesis(&self, contract_id: ContractId) -> Result<Option<Genesis>, Self::Error> {
+        load(&self.geneses, &contract_id)
+    }
+
+    fn load_bundle_id(&self, node_id: NodeId) -> Result<Option<BundleId>, Self::Error> {
+        Ok(load::<(ContractId, BundleId)>(&self.transition_index, &node_id)?
+            .map(|(_, bundle_id)| bundle_id))
+    }
+
+    fn load_anchored_bundle(
+        &self,
+        contract_id: ContractId,
+        bundle_id: BundleId,
+    ) -> Result<(Anchor<lnpbp4::MerkleProof>, TransitionBundle), Self::Error> {
+        let (anchor_id, bundle) =
+            load::<(AnchorId, TransitionBundle)>(&self.bundles, &(contract_id, bundle_id))?
+                .ok_or(MemStashError::UnknownContract(contract_id))?;
+        let anchor = load::<Anchor<lnpbp4::MerkleBlock>>(&self.anchors, &anchor_id)?
+            .ok_or(MemStashError::UnknownAnchor(anchor_id))?
+            .to_merkle_proof(contract_id)
+            .map_err(|_| MemStashError::UnrelatedAnchor(bundle_id))?;
+        Ok((anchor, bundle))
+    }
+
+    fn load_extension(
+        &self,
+        contract_id: ContractId,
+        node_id: NodeId,
+    ) -> Result<Option<Extension>, Self::Error> {
+        load(&self.extensions, &(contract_id, node_id))
+    }
+}
+
+impl Stash for SledStash {
+    type Error = SledStashError;
+
+    fn consign(
+        &self,
+        contract_id: ContractId,
+        bundle: TransitionBundle,
+        anchor: Option<&Anchor<lnpbp4::MerkleProof>>,
+        endpoints: &BTreeSet<SealEndpoint>,
+        close_method: Option<CloseMethod>,
+    ) -> Result<StateTransfer, Self::Error> {
+        consign_history(self, contract_id, bundle, anchor, endpoints, close_method)
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(
+                contract_id = %consignment.contract_id(),
+                bundles = consignment.anchored_bundles.len(),
+                extensions = consignment.state_extensions.len(),
+            )
+        )
+    )]
+    fn accept(
+        &mut self,
+        consignment: &StateTransfer,
+        known_seals: &[seal::Revealed],
+    ) -> Result<(), Self::Error> {
+        let mut consignment = consignment.clone();
+        consignment.reveal_seals(known_seals.iter());
+
+        let mut other = MemStash::with_consignment(&consignment)?;
+        // The stored index is updated when the data are staged for merging
+        other.seal_secrets.extend(known_seals.iter().cloned());
+
+        let mut batch = SledBatch::default();
+        self.stage_merge(other, &mut batch)?;
+        self.apply(batch)
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(anchors = disclosure.anchored_bundles().len())
+        )
+    )]
+    fn enclose(&mut self, disclosure: &Disclosure) -> Result<(), Self::Error> {
+        let other = MemStash::with_disclosure(disclosure)?;
+
+        let mut batch = SledBatch::default();
+        self.stage_merge(other, &mut batch)?;
+        self.apply(batch)
+    }
+
+    fn defer_disclosure(
+        &mut self,
+        witness_txid: Txid,
+        disclosure: Disclosure,
+    ) -> Result<(), Self::Error> {
+        let id = disclosure.id();
+        let (disclosure, mut txids) = load(&self.deferred_disclosures, &id)?
+            .unwrap_or_else(|| (disclosure, BTreeSet::<Txid>::new()));
+        txids.extend(disclosure.txids());
+        txids.insert(witness_txid);
+        for txid in txids.clone() {
+            if self
+                .processed_witnesses
+                .contains_key(txid.strict_serialize()?)?
+            {
+                txids.remove(&txid);
+            }
+        }
+        // All the witness transactions may be already processed
+        if txids.is_empty() {
+            let mut batch = SledBatch::default();
+            batch.deferred_disclosures.remove(id.strict_serialize()?);
+            self.stage_merge(MemStash::with_disclosure(&disclosure)?, &mut batch)?;
+            return self.apply(batch);
+        }
+        self.deferred_disclosures.insert(
+            id.strict_serialize()?,
+            (disclosure, txids).strict_serialize()?,
+        )?;
+        Ok(())
+    }
+
+    fn pending_disclosures(&self) -> Result<BTreeMap<Txid, Vec<DisclosureId>>, Self::Error> {
+        let mut pending = BTreeMap::<Txid, Vec<DisclosureId>>::new();
+        let deferred: BTreeMap<DisclosureId, (Disclosure, BTreeSet<Txid>)> =
+            load_map(&self.deferred_disclosures)?;
+        for (id, (_, txids)) in deferred {
+            for txid in txids {
+                pending.entry(txid).or_default().push(id);
+            }
+        }
+        Ok(pending)
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    fn process_witness(&mut self, txid: Txid) -> Result<Vec<DisclosureId>, Self::Error> {
+        let mut enclosed = vec![];
+        let mut other = MemStash::default();
+        let mut batch = SledBatch::default();
+        for item in self.deferred_disclosures.iter() {
+            let (key, value) = item?;
+            let id = DisclosureId::strict_deserialize(&key)?;
+            let (disclosure, mut txids) =
+                <(Disclosure, BTreeSet<Txid>)>::strict_deserialize(value)?;
+            if txids.iter().all(|t| *t == txid) {
+                other
+                    .merge(MemStash::with_disclosure(&disclosure)?)
+                    .map_err(MemStashError::from)?;
+                batch.deferred_disclosures.remove(key);
+                enclosed.push(id);
+            } else if txids.remove(&txid) {
+                batch
+                    .deferred_disclosures
+                    .insert(key, (disclosure, txids).strict_serialize()?);
+            }
+        }
+        if enclosed.is_empty() && !self.deferred_disclosures.is_empty() {
+            warn_event!(%txid, "witness transaction does not complete any deferred disclosure");
+        }
+        batch
+            .processed_witnesses
+            .insert(txid.strict_serialize()?, Vec::<u8>::new());
+        self.stage_merge(other, &mut batch)?;
+        self.apply(batch)?;
+        Ok(enclosed)
+    }
+
+    fn snapshot(&self) -> Result<StashSnapshot, Self::Error> {
+        Ok(StashSnapshot::with(self.load()?))
+    }
+
+    fn restore(&mut self, snapshot: StashSnapshot) -> Result<(), Self::Error> {
+        let mut batch = SledBatch::default();
+        for (tree, tree_batch) in self.trees().into_iter().zip([
+            &mut batch.schemata,
+            &mut batch.geneses,
+            &mut batch.anchors,
+            &mut batch.bundles,
+            &mut batch.extensions,
+            &mut batch.transition_index,
+            &mut batch.seal_secrets,
+            &mut batch.deferred_disclosures,
+            &mut batch.processed_witnesses,
+            &mut batch.outpoint_index,
+        ]) {
+            for key in tree.iter().keys() {
+                tree_batch.remove(key?);
+            }
+        }
+        // Insertions override removals of the same keys within the batch
+        batch.insert_all(snapshot.as_stash())?;
+        batch.insert_outpoints(&snapshot.as_stash().outpoint_index)?;
+        self.apply(batch)
+    }
+
+    fn diff(&self, snapshot: &StashSnapshot) -> Result<StashDiff, Self::Error> {
+        Ok(StashDiff::between(
+            &snapshot.as_stash().objects(),
+            &self.objects()?,
+        ))
+    }
+
+    fn metrics(&self) -> Result<StashMetrics, Self::Error> {
+        let mut metrics = StashMetrics {
+            schemata: tree_metrics(&self.schemata)?,
+            anchors: tree_metrics(&self.anchors)?,
+            seal_secrets: tree_metrics(&self.seal_secrets)?,
+            deferred_disclosures: tree_metrics(&self.deferred_disclosures)?,
+            ..StashMetrics::default()
+        };
+
+        let mut contracts = BTreeMap::<ContractId, ContractMetrics>::new();
+        for item in self.geneses.iter() {
+            let (key, value) = item?;
+            let contract = contracts
+                .entry(ContractId::strict_deserialize(key)?)
+                .or_default();
+            contract.genesis.add_len(value.len());
+        }
+        let mut anchor_ids = BTreeSet::new();
+        for item in self.bundles.iter() {
+            let (key, value) = item?;
+            let (contract_id, _) = <(ContractId, BundleId)>::strict_deserialize(key)?;
+            let (anchor_id, bundle) = <(AnchorId, TransitionBundle)>::strict_deserialize(value)?;
+            let contract = contracts.entry(contract_id).or_default();
+            contract.bundles.add_len(ByteCounter::strict_len(&bundle));
+            for transition in bundle.known_transitions() {
+                contract.transitions.add(transition);
+            }
+            if anchor_ids.insert((contract_id, anchor_id)) {
+                if let Some(anchor) = self.anchors.get(anchor_id.strict_serialize()?)? {
+                    contract.anchors.add_len(anchor.len());
+                }
+            }
+        }
+        for item in self.extensions.iter() {
+            let (key, value) = item?;
+            let (contract_id, _) = <(ContractId, NodeId)>::strict_deserialize(key)?;
+            let contract = contracts.entry(contract_id).or_default();
+            contract.extensions.add_len(value.len());
+        }
+
+        for contract in contracts.values() {
+            metrics.geneses += contract.genesis;
+            metrics.bundles += contract.bundles;
+            metrics.transitions += contract.transitions;
+            metrics.extensions += contract.extensions;
+        }
+        metrics.contracts = contracts;
+        Ok(metrics)
+    }
+
+    fn outpoint_state(
+        &self,
+        outpoints: &BTreeSet<OutPoint>,
+    ) -> Result<BTreeMap<OutPoint, Vec<(ContractId, ProvenState)>>, Self::Error> {
+        let mut state = BTreeMap::new();
+        for outpoint in outpoints {
+            let mut contracts = BTreeMap::<ContractId, OutputStates>::new();
+            for item in self
+                .outpoint_index
+                .scan_prefix(outpoint.strict_serialize()?)
+            {
+                let (key, value) = item?;
+                let (_, contract_id) = <(OutPoint, ContractId)>::strict_deserialize(key)?;
+                contracts.insert(contract_id, StrictDecode::strict_deserialize(value)?);
+            }
+            let revealed = revealed_state(&contracts);
+            if !revealed.is_empty() {
+                state.insert(*outpoint, revealed);
+            }
+        }
+        Ok(state)
+    }
+
+    fn is_rgb_colored(&self, outpoint: OutPoint) -> Result<bool, Self::Error> {
+        Ok(self
+            .outpoint_index
+            .scan_prefix(outpoint.strict_serialize()?)
+            .next()
+            .transpose()?
+            .is_some())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::stash::test::{outpoint_conformance, stash_conformance};
+
+    fn temporary_db() -> Db { sled::Config::new().temporary(true).open().unwrap() }
+
+    #[test]
+    fn test_sled_stash_conformance() {
+        stash_conformance(SledStash::with(temporary_db()).unwrap());
+    }
+
+    #[test]
+    fn test_sled_stash_outpoints() {
+        outpoint_conformance(SledStash::with(temporary_db()).unwrap());
+    }
+
+    #[test]
+    fn test_outpoint_index_migration() {
+        let db = temporary_db();
+        let mut stash = SledStash::with(db.clone()).unwrap();
+        stash
+            .accept(&crate::verify::test::consignment(2), &[])
+            .unwrap();
+        let index = stash.load().unwrap().outpoint_index;
+        assert!(!index.is_empty());
+
+        // Databases of the layout version 1 have no outpoint index, and the
+        // index of the version 2 is rebuilt
+        let meta = db.open_tree(META_TREE).unwrap();
+        for version in [1u16, 2] {
+            stash.outpoint_index.clear().unwrap();
+            meta.insert(META_VERSION_KEY, &version.to_le_bytes()[..])
+                .unwrap();
+            stash = SledStash::with(db.clone()).unwrap();
+            assert_eq!(stash.load().unwrap().outpoint_index, index);
+            assert_eq!(
+                meta.get(META_VERSION_KEY).unwrap().as_deref(),
+                Some(&SLED_LAYOUT_VERSION.to_le_bytes()[..])
+            );
+        }
+    }
+
+    #[test]
+    fn test_layout_version() {
+        let db = temporary_db();
+        SledStash::with(db.clone()).unwrap();
+        // Reopening the database with the same layout must succeed
+        SledStash::with(db.clone()).unwrap();
+
+        let version = SLED_LAYOUT_VERSION + 1;
+        db.open_tree(META_TREE)
+            .unwrap()
+            .insert(META_VERSION_KEY, &version.to_le_bytes()[..])
+            .unwrap();
+        assert!(matches!(
+            SledStash::with(db),
+            Err(SledStashError::UnsupportedVersion(v)) if v == version
+        ));
+    }
+}
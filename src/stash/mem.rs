@@ -0,0 +1,494 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+
+use bitcoin::{OutPoint, Txid};
+use commit_verify::lnpbp4;
+use rgb_core::{
+    seal, Anchor, AnchorId, BundleId, ContractId, Extension, Genesis, Node, NodeId, Schema,
+    SchemaId, SealEndpoint, TransitionBundle,
+};
+use strict_encoding::{StrictDecode, StrictEncode};
+
+use super::history::{consign_history, HistorySource};
+use super::outpoints::{revealed_state, OutpointIndex};
+use super::{MergeError, Stash, StashDiff, StashMetrics, StashSnapshot};
+use crate::{
+    CloseMethod, ConsignmentType, Disclosure, DisclosureId, InmemConsignment, ProvenState,
+    StateTransfer,
+};
+
+/// Errors happening during operations with [`MemStash`]
+#[derive(Clone, PartialEq, Eq, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum MemStashError {
+    /// contract {0} is not known to the stash
+    UnknownContract(ContractId),
+
+    /// schema {0} used by the contract is not known to the stash
+    UnknownSchema(SchemaId),
+
+    /// node {0} is referenced by the contract history but is not known to the
+    /// stash
+    UnknownNode(NodeId),
+
+    /// anchor {0} is referenced by a transition bundle but is not known to the
+    /// stash
+    UnknownAnchor(AnchorId),
+
+    /// anchor for the transition bundle {0} does not commit to the contract
+    UnrelatedAnchor(BundleId),
+
+    /// consignment requires {required} close method, while the anchor of the
+    /// transition bundle commits with {used}
+    CloseMethodMismatch {
+        required: CloseMethod,
+        used: CloseMethod,
+    },
+
+    /// the amount of data exceeds the maximum size of the consignment
+    /// collections
+    Oversized,
+
+    /// stash data can't be merged
+    #[display(inner)]
+    #[from]
+    Merge(MergeError),
+}
+
+/// Data kept by the [`MemStash`]
+#[derive(Clone, PartialEq, Debug, Default, StrictEncode, StrictDecode)]
+pub struct MemStashData {
+    pub(super) schemata: BTreeMap<SchemaId, Schema>,
+
+    pub(super) geneses: BTreeMap<ContractId, Genesis>,
+
+    pub(super) anchors: BTreeMap<AnchorId, Anchor<lnpbp4::MerkleBlock>>,
+
+    /// Transition bundles organized by RGB contract, each of them linked to
+    /// the anchor committing to it
+    pub(super) bundles: BTreeMap<ContractId, BTreeMap<BundleId, (AnchorId, TransitionBundle)>>,
+
+    /// State extensions organized by RGB contract
+    pub(super) extensions: BTreeMap<ContractId, BTreeMap<NodeId, Extension>>,
+
+    /// Index of all known state transitions pointing to the contract and
+    /// bundle containing them
+    pub(super) transition_index: BTreeMap<NodeId, (ContractId, BundleId)>,
+
+    /// Revealed seal definitions owned by the stash owner
+    pub(super) seal_secrets: BTreeSet<seal::Revealed>,
+
+    /// Disclosures waiting to be enclosed, with the set of witness
+    /// transactions which are still not mined
+    pub(super) deferred_disclosures: BTreeMap<DisclosureId, (Disclosure, BTreeSet<Txid>)>,
+
+    /// Witness transactions which were already processed, such that the
+    /// disclosures deferred later do not wait for them
+    pub(super) processed_witnesses: BTreeSet<Txid>,
+
+    /// Index of the unspent state of all contracts by the transaction outputs
+    /// of the assignment seals
+    pub(super) outpoint_index: OutpointIndex,
+}
+
+/// In-memory implementation of the [`Stash`]. All data are kept in ordered
+/// maps, and the whole stash can be persisted with strict encoding.
+///
+/// Clones of the stash (including its snapshots) share the same data, which
+/// are copied only once one of the clones gets modified.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct MemStash(Arc<MemStashData>);
+
+impl Deref for MemStash {
+    type Target = MemStashData;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target { &self.0 }
+}
+
+impl DerefMut for MemStash {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target { Arc::make_mut(&mut self.0) }
+}
+
+impl From<MemStashData> for MemStash {
+    #[inline]
+    fn from(data: MemStashData) -> Self { MemStash(Arc::new(data)) }
+}
+
+impl StrictEncode for MemStash {
+    #[inline]
+    fn strict_encode<E: io::Write>(&self, e: E) -> Result<usize, strict_encoding::Error> {
+        self.0.strict_encode(e)
+    }
+}
+
+impl StrictDecode for MemStash {
+    #[inline]
+    fn strict_decode<D: io::Read>(d: D) -> Result<Self, strict_encoding::Error> {
+        MemStashData::strict_decode(d).map(MemStash::from)
+    }
+}
+
+impl MemStash {
+    /// Constructs empty in-memory stash
+    #[inline]
+    pub fn new() -> Self { MemStash::default() }
+
+    /// Takes the stash data, copying them only if they are shared with other
+    /// clones of the stash
+    pub(super) fn into_data(self) -> MemStashData {
+        Arc::try_unwrap(self.0).unwrap_or_else(|data| (*data).clone())
+    }
+
+    /// Constructs in-memory stash containing all the data from the provided
+    /// consignment
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub fn with_consignment<T>(consignment: &InmemConsignment<T>) -> Result<Self, MemStashError>
+    where T: ConsignmentType {
+        let mut stash = MemStash::default();
+        let contract_id = consignment.contract_id();
+
+        stash
+            .schemata
+            .insert(consignment.schema.schema_id(), consignment.schema.clone());
+        if let Some(ref root_schema) = consignment.root_schema {
+            stash
+                .schemata
+                .insert(root_schema.schema_id(), root_schema.clone());
+        }
+        stash
+            .geneses
+            .insert(contract_id, consignment.genesis.clone());
+
+        for (anchor, bundle) in consignment.anchored_bundles.iter() {
+            let bundle_id = bundle.bundle_id();
+            let anchor = anchor
+                .to_merkle_block(contract_id, bundle_id)
+                .map_err(|_| MemStashError::UnrelatedAnchor(bundle_id))?;
+            stash.insert_bundle(contract_id, anchor, bundle.clone())?;
+            debug_event!(%bundle_id, "bundle processed");
+        }
+
+        for extension in consignment.state_extensions.iter() {
+            stash
+                .extensions
+                .entry(contract_id)
+                .or_default()
+                .insert(extension.node_id(), extension.clone());
+        }
+
+        stash.rebuild_outpoint_index();
+        Ok(stash)
+    }
+
+    /// Constructs in-memory stash containing all the data from the provided
+    /// disclosure
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub fn with_disclosure(disclosure: &Disclosure) -> Result<Self, MemStashError> {
+        let mut stash = MemStash::default();
+
+        for (anchor, bundles) in disclosure.anchored_bundles().values() {
+            for (contract_id, bundle) in bundles {
+                stash.insert_bundle(*contract_id, anchor.clone(), bundle.clone())?;
+            }
+        }
+
+        for (contract_id, extensions) in disclosure.extensions() {
+            stash.extensions.entry(*contract_id).or_default().extend(
+                extensions
+                    .iter()
+                    .map(|extension| (extension.node_id(), extension.clone())),
+            );
+        }
+
+        Ok(stash)
+    }
+
+    /// Returns ids of all contracts known to the stash
+    #[inline]
+    pub fn contract_ids(&self) -> BTreeSet<ContractId> { self.geneses.keys().copied().collect() }
+
+    /// Returns schema with a given id, if known
+    #[inline]
+    pub fn schema(&self, schema_id: SchemaId) -> Option<&Schema> { self.schemata.get(&schema_id) }
+
+    /// Returns genesis of a given contract, if the contract is known
+    #[inline]
+    pub fn genesis(&self, contract_id: ContractId) -> Option<&Genesis> {
+        self.geneses.get(&contract_id)
+    }
+
+    /// Returns revealed seal definitions known to the stash
+    #[inline]
+    pub fn seal_secrets(&self) -> &BTreeSet<seal::Revealed> { &self.seal_secrets }
+
+    /// Adds seal definitions to the set of the known seal secrets. Returns
+    /// number of seals which were not known before.
+    ///
+    /// New seal secrets may reveal seals of any contract, so the outpoint
+    /// index is rebuilt if any of the seals were not known.
+    pub fn add_seal_secrets(&mut self, seals: impl IntoIterator<Item = seal::Revealed>) -> usize {
+        let count = self.seal_secrets.len();
+        self.seal_secrets.extend(seals);
+        let added = self.seal_secrets.len() - count;
+        if added > 0 {
+            self.rebuild_outpoint_index();
+        }
+        added
+    }
+
+    pub(super) fn insert_bundle(
+        &mut self,
+        contract_id: ContractId,
+        anchor: Anchor<lnpbp4::MerkleBlock>,
+        bundle: TransitionBundle,
+    ) -> Result<(), MemStashError> {
+        let anchor_id = anchor.anchor_id();
+        let anchor = match self.anchors.get(&anchor_id) {
+            Some(known) => anchor
+                .merge_reveal(known.clone())
+                .map_err(|_| MergeError::AnchorConflict(anchor_id))?,
+            None => anchor,
+        };
+        self.anchors.insert(anchor_id, anchor);
+
+        let bundle_id = bundle.bundle_id();
+        for node_id in bundle.known_node_ids() {
+            self.transition_index
+                .insert(node_id, (contract_id, bundle_id));
+        }
+        self.bundles
+            .entry(contract_id)
+            .or_default()
+            .insert(bundle_id, (anchor_id, bundle));
+        Ok(())
+    }
+
+    /// Rebuilds index of the state transitions from the stored transition
+    /// bundles
+    pub(super) fn reindex(&mut self) {
+        self.transition_index = self
+            .bundles
+            .iter()
+            .flat_map(|(contract_id, bundles)| {
+                bundles.iter().flat_map(move |(bundle_id, (_, bundle))| {
+                    bundle
+                        .known_node_ids()
+                        .into_iter()
+                        .map(move |node_id| (node_id, (*contract_id, *bundle_id)))
+                })
+            })
+            .collect();
+    }
+}
+
+impl HistorySource for MemStash {
+    type Error = MemStashError;
+
+    fn load_schema(&self, schema_id: SchemaId) -> Result<Option<Schema>, Self::Error> {
+        Ok(self.schemata.get(&schema_id).cloned())
+    }
+
+    fn load_genesis(&self, contract_id: ContractId) -> Result<Option<Genesis>, Self::Error> {
+        Ok(self.geneses.get(&contract_id).cloned())
+    }
+
+    fn load_bundle_id(&self, node_id: NodeId) -> Result<Option<BundleId>, Self::Error> {
+        Ok(self
+            .transition_index
+            .get(&node_id)
+            .map(|(_, bundle_id)| *bundle_id))
+    }
+
+    fn load_anchored_bundle(
+        &self,
+        contract_id: ContractId,
+        bundle_id: BundleId,
+    ) -> Result<(Anchor<lnpbp4::MerkleProof>, TransitionBundle), Self::Error> {
+        let (anchor_id, bundle) = self
+            .bundles
+            .get(&contract_id)
+            .and_then(|bundles| bundles.get(&bundle_id))
+            .ok_or(MemStashError::UnknownContract(contract_id))?;
+        let anchor = self
+            .anchors
+            .get(anchor_id)
+            .ok_or(MemStashError::UnknownAnchor(*anchor_id))?
+            .to_merkle_proof(contract_id)
+            .map_err(|_| MemStashError::UnrelatedAnchor(bundle_id))?;
+        Ok((anchor, bundle.clone()))
+    }
+
+    fn load_extension(
+        &self,
+        contract_id: ContractId,
+        node_id: NodeId,
+    ) -> Result<Option<Extension>, Self::Error> {
+        Ok(self
+            .extensions
+            .get(&contract_id)
+            .and_then(|extensions| extensions.get(&node_id))
+            .cloned())
+    }
+}
+
+impl Stash for MemStash {
+    type Error = MemStashError;
+
+    fn consign(
+        &self,
+        contract_id: ContractId,
+        bundle: TransitionBundle,
+        anchor: Option<&Anchor<lnpbp4::MerkleProof>>,
+        endpoints: &BTreeSet<SealEndpoint>,
+        close_method: Option<CloseMethod>,
+    ) -> Result<StateTransfer, Self::Error> {
+        consign_history(self, contract_id, bundle, anchor, endpoints, close_method)
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(
+                contract_id = %consignment.contract_id(),
+                bundles = consignment.anchored_bundles.len(),
+                extensions = consignment.state_extensions.len(),
+            )
+        )
+    )]
+    fn accept(
+        &mut self,
+        consignment: &StateTransfer,
+        known_seals: &[seal::Revealed],
+    ) -> Result<(), Self::Error> {
+        let mut consignment = consignment.clone();
+        consignment.reveal_seals(known_seals.iter());
+
+        let mut other = MemStash::with_consignment(&consignment)?;
+        // The index of the merged stash is updated by the merge procedure
+        other.seal_secrets.extend(known_seals.iter().cloned());
+        self.merge(other)?;
+        Ok(())
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(anchors = disclosure.anchored_bundles().len())
+        )
+    )]
+    fn enclose(&mut self, disclosure: &Disclosure) -> Result<(), Self::Error> {
+        let other = MemStash::with_disclosure(disclosure)?;
+        self.merge(other)?;
+        Ok(())
+    }
+
+    fn defer_disclosure(
+        &mut self,
+        witness_txid: Txid,
+        disclosure: Disclosure,
+    ) -> Result<(), Self::Error> {
+        let mut txids = disclosure.txids();
+        txids.insert(witness_txid);
+        txids.retain(|txid| !self.processed_witnesses.contains(txid));
+        let id = disclosure.id();
+        let (disclosure, pending) = self
+            .deferred_disclosures
+            .entry(id)
+            .or_insert_with(|| (disclosure, empty!()));
+        pending.extend(txids);
+        // All the witness transactions may be already processed
+        if pending.is_empty() {
+            let disclosure = disclosure.clone();
+            self.enclose(&disclosure)?;
+            self.deferred_disclosures.remove(&id);
+        }
+        Ok(())
+    }
+
+    fn pending_disclosures(&self) -> Result<BTreeMap<Txid, Vec<DisclosureId>>, Self::Error> {
+        let mut pending = BTreeMap::<Txid, Vec<DisclosureId>>::new();
+        for (id, (_, txids)) in &self.deferred_disclosures {
+            for txid in txids {
+                pending.entry(*txid).or_default().push(*id);
+            }
+        }
+        Ok(pending)
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    fn process_witness(&mut self, txid: Txid) -> Result<Vec<DisclosureId>, Self::Error> {
+        let mut enclosed = vec![];
+        // All ready disclosures are collected first, such that a failure of
+        // one of them does not leave the stash with the others enclosed
+        let mut other = MemStash::default();
+        for (id, (disclosure, txids)) in &self.deferred_disclosures {
+            if txids.iter().all(|t| *t == txid) {
+                other.merge(MemStash::with_disclosure(disclosure)?)?;
+                enclosed.push(*id);
+            }
+        }
+        self.merge(other)?;
+        if enclosed.is_empty() && !self.deferred_disclosures.is_empty() {
+            warn_event!(%txid, "witness transaction does not complete any deferred disclosure");
+        }
+
+        self.deferred_disclosures
+            .retain(|id, _| !enclosed.contains(id));
+        for (_, txids) in self.deferred_disclosures.values_mut() {
+            txids.remove(&txid);
+        }
+        self.processed_witnesses.insert(txid);
+        Ok(enclosed)
+    }
+
+    fn snapshot(&self) -> Result<StashSnapshot, Self::Error> {
+        Ok(StashSnapshot::with(self.clone()))
+    }
+
+    fn restore(&mut self, snapshot: StashSnapshot) -> Result<(), Self::Error> {
+        *self = snapshot.into_stash();
+        Ok(())
+    }
+
+    fn diff(&self, snapshot: &StashSnapshot) -> Result<StashDiff, Self::Error> {
+        Ok(StashDiff::with(snapshot.as_stash(), self))
+    }
+
+    fn metrics(&self) -> Result<StashMetrics, Self::Error> { Ok(MemStash::metrics(self)) }
+
+    fn outpoint_state(
+        &self,
+        outpoints: &BTreeSet<OutPoint>,
+    ) -> Result<BTreeMap<OutPoint, Vec<(ContractId, ProvenState)>>, Self::Error> {
+        Ok(outpoints
+            .iter()
+            .filter_map(|outpoint| {
+                let state = revealed_state(self.outpoint_index.get(outpoint)?);
+                (!state.is_empty()).then(|| (*outpoint, state))
+            })
+            .collect())
+    }
+
+    fn is_rgb_colored(&self, outpoint: OutPoint) -> Result<bool, Self::Error> {
+        Ok(self.outpoint_index.contains_key(&outpoint))
+    }
+}
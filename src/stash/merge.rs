@@ -0,0 +1,405 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Merging of the data from two stashes.
+
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+
+use rgb_core::{
+    AnchorId, BundleId, ContractId, MergeReveal, Node, NodeId, SchemaId, TransitionBundle,
+};
+
+use super::MemStash;
+
+/// Errors happening during stash merge procedure. All of them are detected
+/// before any data inside the stash get modified.
+#[derive(Clone, PartialEq, Eq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum MergeError {
+    /// schema {0} has a different content in the merged stash
+    SchemaConflict(SchemaId),
+
+    /// genesis of contract {0} has conflicting revealed data in the merged
+    /// stash
+    GenesisConflict(ContractId),
+
+    /// anchor {0} has conflicting revealed data in the merged stash
+    AnchorConflict(AnchorId),
+
+    /// transition bundle {0} is committed with a different anchor in the
+    /// merged stash
+    BundleAnchorConflict(BundleId),
+
+    /// state transition {0} has conflicting revealed data in the merged stash
+    TransitionConflict(NodeId),
+
+    /// state extension {0} has conflicting revealed data in the merged stash
+    ExtensionConflict(NodeId),
+
+    /// transition bundle {0} can't be reconstructed from the merged data
+    BundleConflict(BundleId),
+}
+
+/// Number of objects of a specific class processed during the merge
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default, Display)]
+#[display("{new} new, {merged} merged, {identical} identical")]
+pub struct MergeCount {
+    /// Objects which were not known before the merge
+    pub new: usize,
+
+    /// Objects which were known, but were revealed with more data
+    pub merged: usize,
+
+    /// Objects which were known with exactly the same data
+    pub identical: usize,
+}
+
+/// Report on the data merged into a stash by [`MemStash::merge`]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct MergeReport {
+    pub schemata: MergeCount,
+    pub geneses: MergeCount,
+    pub anchors: MergeCount,
+    pub bundles: MergeCount,
+    pub transitions: MergeCount,
+    pub extensions: MergeCount,
+    pub seal_secrets: MergeCount,
+}
+
+impl Display for MergeReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "schemata: {}", self.schemata)?;
+        writeln!(f, "geneses: {}", self.geneses)?;
+        writeln!(f, "anchors: {}", self.anchors)?;
+        writeln!(f, "bundles: {}", self.bundles)?;
+        writeln!(f, "transitions: {}", self.transitions)?;
+        writeln!(f, "extensions: {}", self.extensions)?;
+        write!(f, "seal secrets: {}", self.seal_secrets)
+    }
+}
+
+impl MemStash {
+    /// Merges all data from the `other` stash into this one: copies unknown
+    /// schemata and geneses, merge-reveals anchors, transition bundles and
+    /// extensions sharing the same ids and unions seal secrets.
+    ///
+    /// If any of the objects under the same id contains conflicting revealed
+    /// data the procedure fails and the stash is left untouched.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub fn merge(&mut self, other: MemStash) -> Result<MergeReport, MergeError> {
+        let mut report = MergeReport::default();
+        let contract_ids = other.affected_contracts();
+        // We work on a copy so that a conflict detected in the middle of the
+        // procedure does not leave the stash partially updated
+        let mut stash = self.clone();
+        let other = other.into_data();
+
+        for (schema_id, schema) in other.schemata {
+            match stash.schemata.entry(schema_id) {
+                Entry::Vacant(entry) => {
+                    entry.insert(schema);
+                    report.schemata.new += 1;
+                }
+                Entry::Occupied(entry) if entry.get() == &schema => report.schemata.identical += 1,
+                Entry::Occupied(_) => return Err(MergeError::SchemaConflict(schema_id)),
+            }
+        }
+
+        for (contract_id, genesis) in other.geneses {
+            match stash.geneses.entry(contract_id) {
+                Entry::Vacant(entry) => {
+                    entry.insert(genesis);
+                    report.geneses.new += 1;
+                }
+                Entry::Occupied(entry) if entry.get() == &genesis => report.geneses.identical += 1,
+                Entry::Occupied(mut entry) => {
+                    let merged = entry
+                        .get()
+                        .clone()
+                        .merge_reveal(genesis)
+                        .map_err(|_| MergeError::GenesisConflict(contract_id))?;
+                    warn_event!(%contract_id, "differing genesis data merged by reveal");
+                    entry.insert(merged);
+                    report.geneses.merged += 1;
+                }
+            }
+        }
+
+        for (anchor_id, anchor) in other.anchors {
+            match stash.anchors.entry(anchor_id) {
+                Entry::Vacant(entry) => {
+                    entry.insert(anchor);
+                    report.anchors.new += 1;
+                }
+                Entry::Occupied(entry) if entry.get() == &anchor => report.anchors.identical += 1,
+                Entry::Occupied(mut entry) => {
+                    let merged = anchor
+                        .merge_reveal(entry.get().clone())
+                        .map_err(|_| MergeError::AnchorConflict(anchor_id))?;
+                    warn_event!(%anchor_id, "differing anchor data merged by reveal");
+                    entry.insert(merged);
+                    report.anchors.merged += 1;
+                }
+            }
+        }
+
+        for (contract_id, bundles) in other.bundles {
+            let known_bundles = stash.bundles.entry(contract_id).or_default();
+            for (bundle_id, (anchor_id, bundle)) in bundles {
+                match known_bundles.entry(bundle_id) {
+                    Entry::Vacant(entry) => {
+                        report.transitions.new += bundle.known_node_ids().len();
+                        entry.insert((anchor_id, bundle));
+                        report.bundles.new += 1;
+                    }
+                    Entry::Occupied(entry) if entry.get().0 != anchor_id => {
+                        return Err(MergeError::BundleAnchorConflict(bundle_id))
+                    }
+                    Entry::Occupied(entry) if entry.get().1 == bundle => {
+                        report.transitions.identical += bundle.known_node_ids().len();
+                        report.bundles.identical += 1;
+                    }
+                    Entry::Occupied(mut entry) => {
+                        let merged = merge_bundles(
+                            bundle_id,
+                            &entry.get().1,
+                            bundle,
+                            &mut report.transitions,
+                        )?;
+                        warn_event!(%bundle_id, "differing bundle data merged by reveal");
+                        entry.get_mut().1 = merged;
+                        report.bundles.merged += 1;
+                    }
+                }
+            }
+        }
+
+        for (contract_id, extensions) in other.extensions {
+            let known_extensions = stash.extensions.entry(contract_id).or_default();
+            for (node_id, extension) in extensions {
+                match known_extensions.entry(node_id) {
+                    Entry::Vacant(entry) => {
+                        entry.insert(extension);
+                        report.extensions.new += 1;
+                    }
+                    Entry::Occupied(entry) if entry.get() == &extension => {
+                        report.extensions.identical += 1
+                    }
+                    Entry::Occupied(mut entry) => {
+                        let merged = entry
+                            .get()
+                            .clone()
+                            .merge_reveal(extension)
+                            .map_err(|_| MergeError::ExtensionConflict(node_id))?;
+                        warn_event!(%node_id, "differing extension data merged by reveal");
+                        entry.insert(merged);
+                        report.extensions.merged += 1;
+                    }
+                }
+            }
+        }
+
+        for (id, (disclosure, txids)) in other.deferred_disclosures {
+            stash
+                .deferred_disclosures
+                .entry(id)
+                .or_insert_with(|| (disclosure, empty!()))
+                .1
+                .extend(txids);
+        }
+
+        stash.processed_witnesses.extend(other.processed_witnesses);
+
+        let known_secrets = other.seal_secrets.len();
+        report.seal_secrets.new = stash.add_seal_secrets(other.seal_secrets);
+        report.seal_secrets.identical = known_secrets - report.seal_secrets.new;
+
+        stash.reindex();
+        // New seal secrets have already caused rebuild of the whole index
+        if report.seal_secrets.new == 0 {
+            stash.update_outpoints(self, &contract_ids);
+        }
+        *self = stash;
+        Ok(report)
+    }
+}
+
+/// Merges two copies of the same transition bundle. Transitions revealed in
+/// any of the copies are revealed in the result; transitions concealed in
+/// both copies are kept concealed.
+fn merge_bundles(
+    bundle_id: BundleId,
+    known: &TransitionBundle,
+    other: TransitionBundle,
+    count: &mut MergeCount,
+) -> Result<TransitionBundle, MergeError> {
+    let mut transitions = known
+        .revealed_iter()
+        .map(|(transition, inputs)| (transition.node_id(), (transition.clone(), inputs.clone())))
+        .collect::<BTreeMap<_, _>>();
+
+    for (transition, inputs) in other.revealed_iter() {
+        let node_id = transition.node_id();
+        match transitions.entry(node_id) {
+            Entry::Vacant(entry) => {
+                entry.insert((transition.clone(), inputs.clone()));
+                count.new += 1;
+            }
+            Entry::Occupied(entry) if &entry.get().0 == transition => count.identical += 1,
+            Entry::Occupied(mut entry) => {
+                let merged = entry
+                    .get()
+                    .0
+                    .clone()
+                    .merge_reveal(transition.clone())
+                    .map_err(|_| MergeError::TransitionConflict(node_id))?;
+                entry.get_mut().0 = merged;
+                count.merged += 1;
+            }
+        }
+    }
+
+    let concealed = known
+        .concealed_iter()
+        .chain(other.concealed_iter())
+        .filter(|(node_id, _)| !transitions.contains_key(node_id))
+        .map(|(node_id, inputs)| (*node_id, inputs.clone()))
+        .collect::<BTreeMap<_, _>>();
+    let revealed = transitions.into_values().collect::<BTreeMap<_, _>>();
+    TransitionBundle::with(revealed, concealed).map_err(|_| MergeError::BundleConflict(bundle_id))
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+
+    use bitcoin::hashes::Hash;
+    use bitcoin::{OutPoint, Txid};
+    use commit_verify::CommitConceal;
+    use rgb_core::{seal, ConcealSeals};
+
+    use super::*;
+    use crate::verify::test::consignment;
+    use crate::Transition;
+
+    /// Returns a few transitions together with their inputs
+    fn transitions() -> Vec<(Transition, BTreeSet<u16>)> {
+        consignment(4)
+            .anchored_bundles
+            .iter()
+            .flat_map(|(_, bundle)| bundle.revealed_iter())
+            .map(|(transition, inputs)| (transition.clone(), inputs.clone()))
+            .collect()
+    }
+
+    /// Constructs copy of the bundle revealing only the transitions selected
+    /// by the bit `mask`
+    fn partially_concealed(
+        transitions: &[(Transition, BTreeSet<u16>)],
+        mask: u8,
+    ) -> TransitionBundle {
+        let mut revealed = BTreeMap::new();
+        let mut concealed = BTreeMap::new();
+        for (no, (transition, inputs)) in transitions.iter().enumerate() {
+            if mask & (1 << no) != 0 {
+                revealed.insert(transition.clone(), inputs.clone());
+            } else {
+                concealed.insert(transition.node_id(), inputs.clone());
+            }
+        }
+        TransitionBundle::with(revealed, concealed).unwrap()
+    }
+
+    #[test]
+    fn test_merge_concealed_copy() {
+        let transitions = transitions();
+        let full = (1u8 << transitions.len()) - 1;
+        let bundle = partially_concealed(&transitions, full);
+        let bundle_id = bundle.bundle_id();
+        let concealed = partially_concealed(&transitions, 0);
+        assert_eq!(concealed.bundle_id(), bundle_id);
+
+        let mut count = MergeCount::default();
+        let merged = merge_bundles(bundle_id, &bundle, concealed.clone(), &mut count).unwrap();
+        assert_eq!(merged, bundle);
+        let merged = merge_bundles(bundle_id, &concealed, bundle.clone(), &mut count).unwrap();
+        assert_eq!(merged, bundle);
+        let merged = merge_bundles(bundle_id, &concealed, concealed.clone(), &mut count).unwrap();
+        assert_eq!(merged, concealed);
+    }
+
+    #[test]
+    fn test_merge_partially_concealed() {
+        // Checks all combinations of the revealed transitions in both copies
+        let transitions = transitions();
+        let count = 1u8 << transitions.len();
+        for left in 0..count {
+            for right in 0..count {
+                let known = partially_concealed(&transitions, left);
+                let other = partially_concealed(&transitions, right);
+                let merged =
+                    merge_bundles(known.bundle_id(), &known, other, &mut MergeCount::default())
+                        .unwrap();
+                assert_eq!(merged, partially_concealed(&transitions, left | right));
+                assert_eq!(merged.bundle_id(), known.bundle_id());
+            }
+        }
+    }
+
+    #[test]
+    fn test_merge_concealed_stash() {
+        let seal = seal::Revealed::from(OutPoint::new(Txid::from_inner([1u8; 32]), 0));
+        let seals = vec![seal.commit_conceal()];
+        let mut original = MemStash::with_consignment(&consignment(3)).unwrap();
+        original.add_seal_secrets(vec![seal]);
+
+        // The copy does not know the seal secrets, has the transitions of the
+        // first bundle concealed and the seals of the other nodes concealed
+        let mut data = original.clone().into_data();
+        data.seal_secrets = empty!();
+        for genesis in data.geneses.values_mut() {
+            genesis.conceal_seals(&seals);
+        }
+        for bundles in data.bundles.values_mut() {
+            for (no, (_, bundle)) in bundles.values_mut().enumerate() {
+                let mut revealed = BTreeMap::new();
+                let mut concealed = BTreeMap::new();
+                for (transition, inputs) in bundle.revealed_iter() {
+                    if no == 0 {
+                        concealed.insert(transition.node_id(), inputs.clone());
+                    } else {
+                        let mut transition = transition.clone();
+                        transition.conceal_seals(&seals);
+                        revealed.insert(transition, inputs.clone());
+                    }
+                }
+                *bundle = TransitionBundle::with(revealed, concealed).unwrap();
+            }
+        }
+        let mut copy = MemStash::from(data);
+        copy.reindex();
+        copy.rebuild_outpoint_index();
+        assert_ne!(copy, original);
+
+        let mut merged = original.clone();
+        merged.merge(copy.clone()).unwrap();
+        assert_eq!(merged, original);
+
+        let mut merged = copy;
+        merged.merge(original.clone()).unwrap();
+        assert_eq!(merged.transition_index, original.transition_index);
+        assert_eq!(merged.outpoint_index, original.outpoint_index);
+        assert_eq!(merged.seal_secrets, original.seal_secrets);
+        assert_eq!(merged, original);
+    }
+}
@@ -0,0 +1,253 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Size accounting for the stash data.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::ops::AddAssign;
+
+use rgb_core::ContractId;
+use strict_encoding::StrictEncode;
+
+use super::MemStash;
+
+/// Writer which discards all the data and just counts number of bytes
+/// written. Used for computing the size of strict-encoded data without
+/// allocating buffers.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct ByteCounter(usize);
+
+impl ByteCounter {
+    /// Constructs counter with zero bytes written
+    #[inline]
+    pub fn new() -> Self { ByteCounter(0) }
+
+    /// Returns number of bytes written to the counter
+    #[inline]
+    pub fn count(self) -> usize { self.0 }
+
+    /// Computes size of the strict-encoded data
+    pub fn strict_len(data: &impl StrictEncode) -> usize {
+        let mut counter = ByteCounter::new();
+        data.strict_encode(&mut counter)
+            .expect("byte counter writer does not error");
+        counter.count()
+    }
+}
+
+impl io::Write for ByteCounter {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> { Ok(()) }
+}
+
+/// Number and total strict-encoded size of objects of some class
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(crate = "serde_crate"))]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default, Display)]
+#[display("{count} ({size} bytes)")]
+pub struct ObjectMetrics {
+    /// Number of objects
+    pub count: usize,
+
+    /// Total strict-encoded size of the objects, in bytes
+    pub size: usize,
+}
+
+impl ObjectMetrics {
+    /// Accounts a single object
+    #[inline]
+    pub fn add(&mut self, object: &impl StrictEncode) {
+        self.add_len(ByteCounter::strict_len(object))
+    }
+
+    /// Accounts a single object with already known strict-encoded size
+    #[inline]
+    pub fn add_len(&mut self, len: usize) {
+        self.count += 1;
+        self.size += len;
+    }
+}
+
+impl AddAssign for ObjectMetrics {
+    fn add_assign(&mut self, rhs: Self) {
+        self.count += rhs.count;
+        self.size += rhs.size;
+    }
+}
+
+/// Metrics of the data related to a single contract
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(crate = "serde_crate"))]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct ContractMetrics {
+    pub genesis: ObjectMetrics,
+    /// Anchors committing to the contract bundles. The same anchor may be
+    /// accounted in the metrics of multiple contracts.
+    pub anchors: ObjectMetrics,
+    pub bundles: ObjectMetrics,
+    pub transitions: ObjectMetrics,
+    pub extensions: ObjectMetrics,
+}
+
+impl ContractMetrics {
+    /// Total size of all contract data, in bytes. Transitions are not
+    /// accounted separately since they are a part of the bundle data.
+    pub fn size(&self) -> usize {
+        self.genesis.size + self.anchors.size + self.bundles.size + self.extensions.size
+    }
+}
+
+/// Stash size metrics, overall and for each of the known contracts.
+///
+/// Seal secrets are not related to a specific contract and are accounted only
+/// in the overall metrics.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(crate = "serde_crate"))]
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct StashMetrics {
+    pub schemata: ObjectMetrics,
+    pub geneses: ObjectMetrics,
+    pub anchors: ObjectMetrics,
+    pub bundles: ObjectMetrics,
+    pub transitions: ObjectMetrics,
+    pub extensions: ObjectMetrics,
+    pub seal_secrets: ObjectMetrics,
+    pub deferred_disclosures: ObjectMetrics,
+    pub contracts: BTreeMap<ContractId, ContractMetrics>,
+}
+
+impl Display for StashMetrics {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:<24}{:>12}{:>16}", "Objects", "Count", "Size, bytes")?;
+        for (name, metrics) in [
+            ("schemata", self.schemata),
+            ("geneses", self.geneses),
+            ("anchors", self.anchors),
+            ("bundles", self.bundles),
+            ("transitions", self.transitions),
+            ("extensions", self.extensions),
+            ("seal secrets", self.seal_secrets),
+            ("deferred disclosures", self.deferred_disclosures),
+        ] {
+            writeln!(f, "{:<24}{:>12}{:>16}", name, metrics.count, metrics.size)?;
+        }
+
+        for (contract_id, metrics) in &self.contracts {
+            writeln!(f)?;
+            writeln!(f, "{}", contract_id)?;
+            for (name, metrics) in [
+                ("genesis", metrics.genesis),
+                ("anchors", metrics.anchors),
+                ("bundles", metrics.bundles),
+                ("transitions", metrics.transitions),
+                ("extensions", metrics.extensions),
+            ] {
+                writeln!(f, "  {:<22}{:>12}{:>16}", name, metrics.count, metrics.size)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl MemStash {
+    /// Computes metrics of the stash data without cloning the stored objects
+    pub fn metrics(&self) -> StashMetrics {
+        let mut metrics = StashMetrics::default();
+
+        for schema in self.schemata.values() {
+            metrics.schemata.add(schema);
+        }
+        for anchor in self.anchors.values() {
+            metrics.anchors.add(anchor);
+        }
+        for seal in &self.seal_secrets {
+            metrics.seal_secrets.add(seal);
+        }
+        for (disclosure, _) in self.deferred_disclosures.values() {
+            metrics.deferred_disclosures.add(disclosure);
+        }
+
+        let contract_ids = self
+            .geneses
+            .keys()
+            .chain(self.bundles.keys())
+            .chain(self.extensions.keys())
+            .collect::<BTreeSet<_>>();
+        for contract_id in contract_ids {
+            let mut contract = ContractMetrics::default();
+            if let Some(genesis) = self.geneses.get(contract_id) {
+                contract.genesis.add(genesis);
+            }
+            let mut anchor_ids = BTreeSet::new();
+            for (anchor_id, bundle) in self
+                .bundles
+                .get(contract_id)
+                .into_iter()
+                .flat_map(|bundles| bundles.values())
+            {
+                contract.bundles.add(bundle);
+                for transition in bundle.known_transitions() {
+                    contract.transitions.add(transition);
+                }
+                if anchor_ids.insert(anchor_id) {
+                    if let Some(anchor) = self.anchors.get(anchor_id) {
+                        contract.anchors.add(anchor);
+                    }
+                }
+            }
+            for extension in self
+                .extensions
+                .get(contract_id)
+                .into_iter()
+                .flat_map(|extensions| extensions.values())
+            {
+                contract.extensions.add(extension);
+            }
+
+            metrics.geneses += contract.genesis;
+            metrics.bundles += contract.bundles;
+            metrics.transitions += contract.transitions;
+            metrics.extensions += contract.extensions;
+            metrics.contracts.insert(*contract_id, contract);
+        }
+
+        metrics
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use strict_encoding::StrictEncode;
+
+    use super::*;
+    use crate::seal;
+
+    #[test]
+    fn test_seal_secrets_metrics() {
+        let seal = seal::Revealed::from(bitcoin::OutPoint::default());
+        let mut stash = MemStash::new();
+        stash.add_seal_secrets(vec![seal.clone()]);
+
+        let metrics = stash.metrics();
+        assert_eq!(metrics.seal_secrets.count, 1);
+        assert_eq!(
+            metrics.seal_secrets.size,
+            seal.strict_serialize().unwrap().len()
+        );
+        assert_eq!(metrics.anchors, ObjectMetrics::default());
+        assert!(metrics.contracts.is_empty());
+    }
+}
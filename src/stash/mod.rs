@@ -0,0 +1,372 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+#[cfg(feature = "async")]
+pub mod asynch;
+mod history;
+#[cfg(feature = "sled")]
+mod kv;
+mod mem;
+mod merge;
+mod metrics;
+mod outpoints;
+mod snapshot;
+mod shared;
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use bitcoin::{OutPoint, Txid};
+use commit_verify::lnpbp4;
+
+#[cfg(feature = "sled")]
+pub use self::kv::{SledStash, SledStashError, SLED_LAYOUT_VERSION};
+pub use self::mem::{MemStash, MemStashError};
+pub use self::merge::{MergeCount, MergeError, MergeReport};
+pub use self::metrics::{ByteCounter, ContractMetrics, ObjectMetrics, StashMetrics};
+pub use self::shared::SharedStash;
+pub use self::snapshot::{SnapshotId, SnapshotIdTag, StashDiff, StashObjects, StashSnapshot};
+use crate::{
+    seal, Anchor, CloseMethod, ContractId, Disclosure, DisclosureId, ProvenState, SealEndpoint,
+    StateTransfer, TransitionBundle,
+};
+
+pub trait Stash {
+    type Error: std::error::Error;
+
+    /// When we need to send over to somebody else an update (like we have
+    /// transferred him some state, for instance an asset) for each transfer we
+    /// ask [`Stash`] to create a new [`Consignment`] for the given set of seals
+    /// (`endpoints`) under some specific [`ContractId`], starting from a graph
+    /// vertex `node`. If the node is state transition, we must also include
+    /// `anchor` information. If `close_method` is given, the anchor must
+    /// commit to the witness transaction with this method.
+    fn consign(
+        &self,
+        contract_id: ContractId,
+        bundle: TransitionBundle,
+        anchor: Option<&Anchor<lnpbp4::MerkleProof>>,
+        endpoints: &BTreeSet<SealEndpoint>,
+        close_method: Option<CloseMethod>,
+    ) -> Result<StateTransfer, Self::Error>;
+
+    /// When we have received data from other peer (which usually relate to our
+    /// newly owned state, like assets) we do `accept` a [`Consignment`],
+    /// and it gets into the known data.
+    fn accept(
+        &mut self,
+        consignment: &StateTransfer,
+        known_seals: &[seal::Revealed],
+    ) -> Result<(), Self::Error>;
+
+    /// Acquire knowledge from a given disclosure (**enclose** procedure)
+    fn enclose(&mut self, disclosure: &Disclosure) -> Result<(), Self::Error>;
+
+    /// Puts disclosure into a queue of disclosures which will be enclosed into
+    /// the stash once the witness transaction with `witness_txid` is mined.
+    /// If the disclosure anchors are spanning other witness transactions, the
+    /// disclosure will wait for all of them. Witness transactions which were
+    /// already processed are not waited for; if none remains, the disclosure
+    /// is enclosed at once.
+    fn defer_disclosure(
+        &mut self,
+        witness_txid: Txid,
+        disclosure: Disclosure,
+    ) -> Result<(), Self::Error>;
+
+    /// Lists ids of the deferred disclosures awaiting for each of the witness
+    /// transactions
+    fn pending_disclosures(&self) -> Result<BTreeMap<Txid, Vec<DisclosureId>>, Self::Error>;
+
+    /// Marks witness transaction as mined and encloses all deferred
+    /// disclosures which were not waiting for other witness transactions.
+    /// Returns ids of the enclosed disclosures, which are removed from the
+    /// queue.
+    fn process_witness(&mut self, txid: Txid) -> Result<Vec<DisclosureId>, Self::Error>;
+
+    /// Creates a rollback point capturing the current content of the stash
+    fn snapshot(&self) -> Result<StashSnapshot, Self::Error>;
+
+    /// Restores stash content to the state captured by a snapshot, discarding
+    /// all changes made since the snapshot creation
+    fn restore(&mut self, snapshot: StashSnapshot) -> Result<(), Self::Error>;
+
+    /// Lists objects added to or removed from the stash since the snapshot
+    /// creation
+    fn diff(&self, snapshot: &StashSnapshot) -> Result<StashDiff, Self::Error>;
+
+    /// Reports number and strict-encoded size of the stored objects, overall
+    /// and for each of the known contracts
+    fn metrics(&self) -> Result<StashMetrics, Self::Error>;
+
+    /// Returns unspent revealed state of all known contracts assigned to each
+    /// of the `outpoints`. Concealed seals are resolved with the known seal
+    /// secrets; outpoints without any revealed state are omitted.
+    ///
+    /// The state is read from the index maintained by the stash, so the call
+    /// does not reconstruct the contract states.
+    ///
+    /// Each state is returned as [`ProvenState`] wrapping the
+    /// [`crate::AssignedState`] of its kind, since assigned state is generic
+    /// over the state kind.
+    fn outpoint_state(
+        &self,
+        outpoints: &BTreeSet<OutPoint>,
+    ) -> Result<BTreeMap<OutPoint, Vec<(ContractId, ProvenState)>>, Self::Error>;
+
+    /// Detects whether any of the known contracts assigns unspent state to the
+    /// `outpoint`, including state with a concealed value
+    fn is_rgb_colored(&self, outpoint: OutPoint) -> Result<bool, Self::Error>;
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::fmt::Debug;
+
+    use bitcoin::hashes::Hash;
+    use commit_verify::CommitConceal;
+    use strict_encoding::{StrictDecode, StrictEncode};
+
+    use super::*;
+
+    /// Constructs disclosure of all the transition bundles of the consignment
+    pub(crate) fn disclosure(consignment: &StateTransfer) -> Disclosure {
+        let contract_id = consignment.contract_id();
+        let mut disclosure = Disclosure::default();
+        for (anchor, bundle) in consignment.anchored_bundles.iter() {
+            let anchor = anchor
+                .to_merkle_block(contract_id, bundle.bundle_id())
+                .unwrap();
+            disclosure
+                .insert_anchored_bundles(anchor, bmap! { contract_id => bundle.clone() })
+                .unwrap();
+        }
+        disclosure
+    }
+
+    /// Checks behaviour which must be common for all [`Stash`] implementations.
+    /// Requires an empty stash.
+    pub(crate) fn stash_conformance<S>(mut stash: S)
+    where
+        S: Stash,
+        S::Error: Debug,
+    {
+        let accepted = crate::verify::test::consignment(2);
+        let disclosed = crate::verify::test::consignment(1);
+        let disclosure = disclosure(&disclosed);
+        let id = disclosure.id();
+        let bundle_ids = |consignment: &StateTransfer| {
+            consignment
+                .anchored_bundles
+                .iter()
+                .map(|(_, bundle)| bundle.bundle_id())
+                .collect::<BTreeSet<_>>()
+        };
+        let witness = Txid::from_inner([3u8; 32]);
+        let other_witness = Txid::from_inner([4u8; 32]);
+        assert_eq!(disclosure.txids().len(), 1);
+        let anchor_txid = *disclosure.txids().iter().next().unwrap();
+
+        assert!(stash.pending_disclosures().unwrap().is_empty());
+        let snapshot = stash.snapshot().unwrap();
+        let snapshot_id = snapshot.snapshot_id();
+        assert!(stash.diff(&snapshot).unwrap().is_empty());
+
+        stash.accept(&accepted, &[]).unwrap();
+        let diff = stash.diff(&snapshot).unwrap();
+        assert!(diff.removed.is_empty());
+        assert_eq!(diff.added.geneses, bset![accepted.contract_id()]);
+        assert_eq!(diff.added.bundles, bundle_ids(&accepted));
+        assert_eq!(diff.added.transitions.len(), 2);
+        let metrics = stash.metrics().unwrap();
+        assert_eq!(metrics.geneses.count, 1);
+        assert_eq!(metrics.bundles.count, 2);
+
+        // Enclosing already known data does not change the stash
+        stash.enclose(&self::disclosure(&accepted)).unwrap();
+        assert_eq!(stash.diff(&snapshot).unwrap(), diff);
+
+        // The same disclosure deferred for two witnesses must wait for both
+        // of them and for the witness transaction of its anchor
+        stash.defer_disclosure(witness, disclosure.clone()).unwrap();
+        stash
+            .defer_disclosure(other_witness, disclosure.clone())
+            .unwrap();
+        let pending = stash.pending_disclosures().unwrap();
+        assert_eq!(
+            pending,
+            bmap! { witness => vec![id], other_witness => vec![id], anchor_txid => vec![id] }
+        );
+
+        let diff = stash.diff(&snapshot).unwrap();
+        assert_eq!(diff.added.deferred_disclosures, bset![id]);
+        assert!(diff.removed.is_empty());
+        assert_eq!(stash.metrics().unwrap().deferred_disclosures.count, 1);
+
+        assert!(stash.process_witness(witness).unwrap().is_empty());
+        assert!(stash.process_witness(other_witness).unwrap().is_empty());
+        let pending = stash.pending_disclosures().unwrap();
+        assert_eq!(pending, bmap! { anchor_txid => vec![id] });
+        // Deferred disclosure data are not enclosed until all the witness
+        // transactions are mined
+        let diff = stash.diff(&snapshot).unwrap();
+        assert_eq!(diff.added.bundles, bundle_ids(&accepted));
+
+        assert_eq!(stash.process_witness(anchor_txid).unwrap(), vec![id]);
+        assert!(stash.pending_disclosures().unwrap().is_empty());
+        assert!(stash.process_witness(anchor_txid).unwrap().is_empty());
+        let diff = stash.diff(&snapshot).unwrap();
+        assert!(diff.removed.is_empty());
+        // Disclosures do not bring contract geneses
+        assert_eq!(diff.added.geneses, bset![accepted.contract_id()]);
+        let mut expected = bundle_ids(&accepted);
+        expected.extend(bundle_ids(&disclosed));
+        assert_eq!(diff.added.bundles, expected);
+        assert!(diff.added.deferred_disclosures.is_empty());
+        assert_eq!(stash.metrics().unwrap().bundles.count, 3);
+
+        stash
+            .defer_disclosure(Txid::from_inner([5u8; 32]), disclosure.clone())
+            .unwrap();
+        stash.restore(snapshot).unwrap();
+        assert!(stash.pending_disclosures().unwrap().is_empty());
+        assert_eq!(stash.snapshot().unwrap().snapshot_id(), snapshot_id);
+        assert_eq!(stash.metrics().unwrap(), StashMetrics::default());
+
+        // Disclosures deferred after some of their witness transactions were
+        // processed wait only for the remaining ones
+        let snapshot = stash.snapshot().unwrap();
+        assert!(stash.process_witness(witness).unwrap().is_empty());
+        stash.defer_disclosure(witness, disclosure.clone()).unwrap();
+        let pending = stash.pending_disclosures().unwrap();
+        assert_eq!(pending, bmap! { anchor_txid => vec![id] });
+        assert_eq!(stash.process_witness(anchor_txid).unwrap(), vec![id]);
+        let diff = stash.diff(&snapshot).unwrap();
+        assert_eq!(diff.added.bundles, bundle_ids(&disclosed));
+
+        // and are enclosed at once if all of them were processed
+        stash.restore(snapshot).unwrap();
+        assert!(stash.process_witness(witness).unwrap().is_empty());
+        assert!(stash.process_witness(anchor_txid).unwrap().is_empty());
+        let snapshot = stash.snapshot().unwrap();
+        stash.defer_disclosure(witness, disclosure).unwrap();
+        assert!(stash.pending_disclosures().unwrap().is_empty());
+        let diff = stash.diff(&snapshot).unwrap();
+        assert_eq!(diff.added.bundles, bundle_ids(&disclosed));
+        assert!(diff.added.deferred_disclosures.is_empty());
+    }
+
+    /// Checks that the outpoint index of a [`Stash`] implementation agrees
+    /// with the state computed from all the stash data. Requires an empty
+    /// stash.
+    pub(crate) fn outpoint_conformance<S>(mut stash: S)
+    where
+        S: Stash,
+        S::Error: Debug,
+    {
+        let outpoint = OutPoint::new(Txid::from_inner([1u8; 32]), 0);
+        let consignment = crate::verify::test::consignment(3);
+        assert!(!stash.is_rgb_colored(outpoint).unwrap());
+
+        stash.accept(&consignment, &[]).unwrap();
+        let state = stash.outpoint_state(&bset![outpoint]).unwrap();
+        assert_eq!(state.len(), 1);
+        assert_eq!(state[&outpoint].len(), 1);
+        assert_eq!(state[&outpoint][0].0, consignment.contract_id());
+        assert!(stash.is_rgb_colored(outpoint).unwrap());
+        assert!(!stash.is_rgb_colored(OutPoint::default()).unwrap());
+
+        let snapshot = stash.snapshot().unwrap();
+        let scanned = snapshot.as_stash().scan_outpoints();
+        assert_eq!(snapshot.as_stash().outpoint_index, scanned);
+        let revealed = scanned
+            .iter()
+            .map(|(outpoint, contracts)| (*outpoint, outpoints::revealed_state(contracts)))
+            .collect::<BTreeMap<_, _>>();
+        let owned = revealed.keys().copied().collect();
+        assert_eq!(stash.outpoint_state(&owned).unwrap(), revealed);
+
+        // Repeated acceptance must not change the index
+        stash.accept(&consignment, &[]).unwrap();
+        assert_eq!(stash.outpoint_state(&owned).unwrap(), revealed);
+    }
+
+    #[test]
+    fn test_mem_stash_conformance() { stash_conformance(MemStash::new()); }
+
+    #[test]
+    fn test_deferred_disclosure() {
+        let consignment = crate::verify::test::consignment(2);
+        let contract_id = consignment.contract_id();
+        let disclosure = disclosure(&consignment);
+        let id = disclosure.id();
+        assert_eq!(disclosure.txids().len(), 1);
+        let anchor_txid = *disclosure.txids().iter().next().unwrap();
+        let witness = Txid::from_inner([3u8; 32]);
+
+        // The disclosure also waits for the witness transaction of its anchors
+        let mut stash = MemStash::new();
+        stash.defer_disclosure(witness, disclosure.clone()).unwrap();
+        let pending = stash.pending_disclosures().unwrap();
+        assert_eq!(
+            pending,
+            bmap! { witness => vec![id], anchor_txid => vec![id] }
+        );
+
+        // The queue is persisted together with the stash
+        let data = stash.strict_serialize().unwrap();
+        let mut stash = MemStash::strict_deserialize(data).unwrap();
+        assert_eq!(stash.pending_disclosures().unwrap(), pending);
+
+        assert!(stash.process_witness(witness).unwrap().is_empty());
+        assert!(stash.bundles.is_empty());
+        assert_eq!(
+            stash.pending_disclosures().unwrap(),
+            bmap! { anchor_txid => vec![id] }
+        );
+
+        assert_eq!(stash.process_witness(anchor_txid).unwrap(), vec![id]);
+        assert!(stash.pending_disclosures().unwrap().is_empty());
+        assert!(stash.process_witness(anchor_txid).unwrap().is_empty());
+        let enclosed = MemStash::with_disclosure(&disclosure).unwrap();
+        assert_eq!(stash.bundles[&contract_id].len(), 2);
+        assert_eq!(stash.bundles, enclosed.bundles);
+        assert_eq!(stash.anchors, enclosed.anchors);
+    }
+
+    #[test]
+    fn test_mem_stash_outpoints() { outpoint_conformance(MemStash::new()); }
+
+    #[test]
+    fn test_consign_close_method() {
+        let consignment = crate::verify::test::consignment(2);
+        assert_eq!(consignment.close_methods(), bset![CloseMethod::OpretFirst]);
+        assert!(!consignment.has_mixed_close_methods());
+
+        let mut stash = MemStash::new();
+        stash.accept(&consignment, &[]).unwrap();
+        let (anchor, bundle) = consignment.anchored_bundles.iter().last().unwrap();
+        let seal = seal::Revealed::from(bitcoin::OutPoint::default());
+        let endpoints = bset![SealEndpoint::ConcealedUtxo(seal.commit_conceal())];
+        assert!(matches!(
+            stash.consign(
+                consignment.contract_id(),
+                bundle.clone(),
+                Some(anchor),
+                &endpoints,
+                Some(CloseMethod::TapretFirst)
+            ),
+            Err(MemStashError::CloseMethodMismatch {
+                required: CloseMethod::TapretFirst,
+                used: CloseMethod::OpretFirst
+            })
+        ));
+    }
+}
@@ -0,0 +1,733 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Index of the unspent contract state by the transaction outputs of the
+//! assignment seals, giving wallets a view of the state of all contracts
+//! assigned to their outputs without reconstructing the contract states on
+//! each query.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use bitcoin::{OutPoint, Txid};
+use rgb_core::{seal, ContractId, Extension, Node, NodeId, Transition};
+
+use super::MemStash;
+use crate::{ContractState, ProvenState, StateApplyError, TypedOutpoint};
+
+/// Unspent state of a contract assigned to a transaction output, keyed by the
+/// node outputs and owned right types defining the assignments. Assignments
+/// with concealed state are kept as `None`, such that the index also tracks
+/// the outputs whose state value is not known.
+pub(super) type OutputStates = BTreeMap<TypedOutpoint, Option<ProvenState>>;
+
+/// Unspent state of the contracts assigned to each of the transaction outputs
+pub(super) type OutpointIndex = BTreeMap<OutPoint, BTreeMap<ContractId, OutputStates>>;
+
+/// Returns copy of the node with the seals revealed by the known seal secrets
+fn revealed<N>(node: &N, seal_secrets: &BTreeSet<seal::Revealed>) -> N
+where N: Node + Clone {
+    let mut node = node.clone();
+    for (_, assignment) in node.owned_rights_mut().iter_mut() {
+        assignment.reveal_seals(seal_secrets.iter());
+    }
+    node
+}
+
+/// Collects revealed state of the index entries for a single outpoint
+pub(super) fn revealed_state<'index>(
+    contracts: impl IntoIterator<Item = (&'index ContractId, &'index OutputStates)>,
+) -> Vec<(ContractId, ProvenState)> {
+    contracts
+        .into_iter()
+        .flat_map(|(contract_id, states)| {
+            states
+                .values()
+                .flatten()
+                .map(move |state| (*contract_id, state.clone()))
+        })
+        .collect()
+}
+
+/// Contract node stored in the stash which can be applied to the contract
+/// state, with its seals revealed
+#[derive(Clone, PartialEq, Debug)]
+enum StashedNode {
+    /// State transition together with the id of its witness transaction
+    Transition(Txid, Transition),
+    Extension(Extension),
+}
+
+impl StashedNode {
+    fn parents(&self) -> BTreeSet<NodeId> {
+        match self {
+            StashedNode::Transition(_, transition) => ContractState::node_parents(transition),
+            StashedNode::Extension(extension) => ContractState::node_parents(extension),
+        }
+    }
+
+    fn parent_outputs(&self) -> Vec<TypedOutpoint> {
+        match self {
+            StashedNode::Transition(_, transition) => ContractState::spent_outputs(transition),
+            StashedNode::Extension(extension) => ContractState::spent_outputs(extension),
+        }
+    }
+
+    /// Applies the node to the state, checking that its parents are known
+    fn apply(&self, state: &mut ContractState) -> Result<(), StateApplyError> {
+        match self {
+            StashedNode::Transition(txid, transition) => {
+                state.check_parents(transition)?;
+                state.extend(*txid, transition)
+            }
+            StashedNode::Extension(extension) => state.apply_extension(extension),
+        }
+    }
+
+    /// Adds state of the node without checking its parents
+    fn extend(&self, state: &mut ContractState) -> Result<(), StateApplyError> {
+        match self {
+            StashedNode::Transition(txid, transition) => state.extend(*txid, transition),
+            StashedNode::Extension(extension) => state.extend_node(extension, None),
+        }
+    }
+}
+
+/// Orders nodes such that each node follows all its parents; ties are
+/// resolved by the node ids. Nodes with cyclic dependencies are omitted.
+fn topological_order(nodes: &BTreeMap<NodeId, StashedNode>) -> Vec<NodeId> {
+    let mut pending = BTreeMap::<NodeId, usize>::new();
+    let mut children = BTreeMap::<NodeId, BTreeSet<NodeId>>::new();
+    for (node_id, node) in nodes {
+        let parents = node
+            .parents()
+            .into_iter()
+            .filter(|parent| nodes.contains_key(parent))
+            .collect::<BTreeSet<_>>();
+        pending.insert(*node_id, parents.len());
+        for parent in parents {
+            children.entry(parent).or_default().insert(*node_id);
+        }
+    }
+
+    let mut ready = pending
+        .iter()
+        .filter(|(_, count)| **count == 0)
+        .map(|(node_id, _)| *node_id)
+        .collect::<BTreeSet<_>>();
+    let mut order = Vec::with_capacity(nodes.len());
+    while let Some(node_id) = ready.iter().next().copied() {
+        ready.remove(&node_id);
+        for child in children.get(&node_id).into_iter().flatten() {
+            let count = pending.get_mut(child).expect("child is always pending");
+            *count -= 1;
+            if *count == 0 {
+                ready.insert(*child);
+            }
+        }
+        order.push(node_id);
+    }
+    order
+}
+
+impl MemStash {
+    /// Collects nodes of the contract which can be applied to its state:
+    /// state transitions with known anchors, since without the anchor the
+    /// witness transaction is not known, and state extensions
+    fn contract_nodes(&self, contract_id: ContractId) -> BTreeMap<NodeId, StashedNode> {
+        let mut nodes = BTreeMap::new();
+        let bundles = self
+            .bundles
+            .get(&contract_id)
+            .into_iter()
+            .flat_map(BTreeMap::values);
+        for (anchor_id, bundle) in bundles {
+            let txid = match self.anchors.get(anchor_id) {
+                Some(anchor) => anchor.txid,
+                None => continue,
+            };
+            for transition in bundle.known_transitions() {
+                let transition = revealed(transition, &self.seal_secrets);
+                nodes.insert(
+                    transition.node_id(),
+                    StashedNode::Transition(txid, transition),
+                );
+            }
+        }
+        let extensions = self
+            .extensions
+            .get(&contract_id)
+            .into_iter()
+            .flat_map(BTreeMap::values);
+        for extension in extensions {
+            let extension = revealed(extension, &self.seal_secrets);
+            nodes.insert(extension.node_id(), StashedNode::Extension(extension));
+        }
+        nodes
+    }
+
+    /// Constructs state of the contract from all the data known to the stash,
+    /// revealing concealed seals with the known seal secrets. Returns `None`
+    /// if the contract genesis is not known.
+    ///
+    /// Nodes are applied in topological order once all their parents are
+    /// applied; ties are resolved by the node ids. Nodes depending on the
+    /// parts of the contract history missing from the stash, as well as the
+    /// ones spending outputs already spent by a preceding node, do not
+    /// contribute to the state; they are returned together with the state and
+    /// the errors preventing their application.
+    pub fn contract_state(
+        &self,
+        contract_id: ContractId,
+    ) -> Option<(ContractState, BTreeMap<NodeId, StateApplyError>)> {
+        let genesis = revealed(self.geneses.get(&contract_id)?, &self.seal_secrets);
+        let mut state = ContractState::with_genesis(&genesis);
+        let mut skipped = BTreeMap::new();
+        let nodes = self.contract_nodes(contract_id);
+        for node_id in topological_order(&nodes) {
+            if let Err(err) = nodes[&node_id].apply(&mut state) {
+                debug_event!(%node_id, %err, "node is not applied to the contract state");
+                skipped.insert(node_id, err);
+            }
+        }
+        Some((state, skipped))
+    }
+
+    /// Collects unspent assignments of the contract with known seals, indexed
+    /// by the seal outpoints
+    pub(super) fn contract_outpoints(
+        &self,
+        contract_id: ContractId,
+    ) -> BTreeMap<OutPoint, OutputStates> {
+        let state = match self.contract_state(contract_id) {
+            Some((state, _)) => state,
+            None => return empty!(),
+        };
+        unspent_outpoints(&state)
+    }
+
+    /// Computes index of the unspent state by outpoints scanning all the
+    /// contracts known to the stash
+    pub(super) fn scan_outpoints(&self) -> OutpointIndex {
+        let mut index = OutpointIndex::new();
+        for contract_id in self.geneses.keys() {
+            for (outpoint, states) in self.contract_outpoints(*contract_id) {
+                index
+                    .entry(outpoint)
+                    .or_default()
+                    .insert(*contract_id, states);
+            }
+        }
+        index
+    }
+
+    /// Rebuilds index of the unspent state by outpoints from all the data
+    /// known to the stash
+    pub fn rebuild_outpoint_index(&mut self) { self.outpoint_index = self.scan_outpoints(); }
+
+    /// Recomputes index of the unspent state by outpoints for the given
+    /// contracts only
+    pub(super) fn reindex_outpoints(&mut self, contract_ids: &BTreeSet<ContractId>) {
+        if contract_ids.is_empty() {
+            return;
+        }
+        for contracts in self.outpoint_index.values_mut() {
+            contracts.retain(|contract_id, _| !contract_ids.contains(contract_id));
+        }
+        self.outpoint_index
+            .retain(|_, contracts| !contracts.is_empty());
+        for contract_id in contract_ids {
+            for (outpoint, states) in self.contract_outpoints(*contract_id) {
+                self.outpoint_index
+                    .entry(outpoint)
+                    .or_default()
+                    .insert(*contract_id, states);
+            }
+        }
+    }
+
+    /// Updates index of the unspent state by outpoints for the given
+    /// contracts after the data were merged into the `known` stash, which
+    /// keeps index of the data before the merge.
+    ///
+    /// The index is updated incrementally from the nodes accepted by the
+    /// merge: outputs spent by them are removed and their assignments are
+    /// added. Contracts for which the accepted nodes do not simply extend the
+    /// applied contract history are recomputed with
+    /// [`MemStash::reindex_outpoints`]; this happens for new contracts, for
+    /// nodes revealed by the merge, for nodes spending outputs whose seals are
+    /// not known and for accepted nodes which are parents of the nodes known
+    /// before.
+    pub(super) fn update_outpoints(
+        &mut self,
+        known: &MemStash,
+        contract_ids: &BTreeSet<ContractId>,
+    ) {
+        let reindexed = contract_ids
+            .iter()
+            .filter(|contract_id| !self.index_accepted(known, **contract_id))
+            .copied()
+            .collect();
+        self.reindex_outpoints(&reindexed);
+    }
+
+    /// Collects nodes of the contract which can be applied to the contract
+    /// state in this stash but not in the `known` one. Returns `None` if the
+    /// genesis or any of the nodes known to both stashes differs, or if a
+    /// transition known before is now a part of a new bundle.
+    fn accepted_nodes(
+        &self,
+        known: &MemStash,
+        contract_id: ContractId,
+    ) -> Option<BTreeMap<NodeId, StashedNode>> {
+        let genesis = self.geneses.get(&contract_id)?;
+        if known.geneses.get(&contract_id) != Some(genesis) {
+            return None;
+        }
+
+        let mut accepted = BTreeMap::new();
+        let bundles = self.bundles.get(&contract_id).into_iter().flatten();
+        let known_bundles = known.bundles.get(&contract_id);
+        for (bundle_id, (anchor_id, bundle)) in bundles {
+            let txid = match self.anchors.get(anchor_id) {
+                Some(anchor) => anchor.txid,
+                None => continue,
+            };
+            match known_bundles.and_then(|bundles| bundles.get(bundle_id)) {
+                Some((known_anchor_id, known_bundle))
+                    if known_anchor_id == anchor_id
+                        && known_bundle == bundle
+                        && known.anchors.contains_key(anchor_id) =>
+                {
+                    continue
+                }
+                Some(_) => return None,
+                None => {}
+            }
+            for transition in bundle.known_transitions() {
+                if known.transition_index.contains_key(&transition.node_id()) {
+                    return None;
+                }
+                let transition = revealed(transition, &self.seal_secrets);
+                accepted.insert(
+                    transition.node_id(),
+                    StashedNode::Transition(txid, transition),
+                );
+            }
+        }
+
+        let known_extensions = known.extensions.get(&contract_id);
+        for (node_id, extension) in self.extensions.get(&contract_id).into_iter().flatten() {
+            match known_extensions.and_then(|extensions| extensions.get(node_id)) {
+                Some(known_extension) if known_extension == extension => {}
+                Some(_) => return None,
+                None => {
+                    let extension = revealed(extension, &self.seal_secrets);
+                    accepted.insert(*node_id, StashedNode::Extension(extension));
+                }
+            }
+        }
+        Some(accepted)
+    }
+
+    /// Returns state containing only the assignments of the node of the
+    /// contract, if the node is known to the stash and can be applied
+    fn node_state(&self, contract_id: ContractId, node_id: NodeId) -> Option<ContractState> {
+        let genesis = self.geneses.get(&contract_id)?;
+        if genesis.node_id() == node_id {
+            return Some(ContractState::with_genesis(&revealed(
+                genesis,
+                &self.seal_secrets,
+            )));
+        }
+        let node = match self.transition_index.get(&node_id) {
+            Some((id, bundle_id)) if *id == contract_id => {
+                let (anchor_id, bundle) = self.bundles.get(&contract_id)?.get(bundle_id)?;
+                let txid = self.anchors.get(anchor_id)?.txid;
+                let transition = bundle
+                    .known_transitions()
+                    .find(|transition| transition.node_id() == node_id)?;
+                StashedNode::Transition(txid, revealed(transition, &self.seal_secrets))
+            }
+            Some(_) => return None,
+            None => {
+                let extension = self.extensions.get(&contract_id)?.get(&node_id)?;
+                StashedNode::Extension(revealed(extension, &self.seal_secrets))
+            }
+        };
+        let mut state = ContractState::new(contract_id);
+        node.extend(&mut state).ok()?;
+        Some(state)
+    }
+
+    /// Updates the index with the nodes of the contract accepted by the merge
+    /// into the `known` stash. Returns `false` leaving the index untouched if
+    /// the accepted nodes do not simply extend the applied contract history,
+    /// such that the index has to be recomputed.
+    fn index_accepted(&mut self, known: &MemStash, contract_id: ContractId) -> bool {
+        let accepted = match self.accepted_nodes(known, contract_id) {
+            Some(accepted) => accepted,
+            None => return false,
+        };
+        if accepted.is_empty() {
+            return true;
+        }
+
+        // Nodes known before which depend on the accepted ones were not
+        // applied, but may become applicable now; nodes known before which
+        // spend the same outputs as the accepted ones may take precedence over
+        // them once applied in topological order
+        let mut dependencies = BTreeSet::new();
+        let mut known_outputs = BTreeSet::new();
+        for (node_id, node) in self.contract_nodes(contract_id) {
+            if !accepted.contains_key(&node_id) {
+                dependencies.extend(node.parents());
+                known_outputs.extend(node.parent_outputs());
+            }
+        }
+        if dependencies
+            .iter()
+            .any(|parent| accepted.contains_key(parent))
+        {
+            return false;
+        }
+
+        let order = topological_order(&accepted);
+        if order.len() < accepted.len() {
+            return false;
+        }
+        let genesis_id = self.geneses[&contract_id].node_id();
+        let mut delta = ContractState::new(contract_id);
+        let mut spent = Vec::<(OutPoint, TypedOutpoint)>::new();
+        let mut parent_states = BTreeMap::<NodeId, Option<ContractState>>::new();
+        for node_id in order {
+            let node = &accepted[&node_id];
+            let outputs = node.parent_outputs();
+            // Nodes known before must be applied, which is proven by the
+            // unspent outputs in the index; state extensions may depend only
+            // on genesis
+            for parent_id in node.parents() {
+                if parent_id != genesis_id
+                    && !accepted.contains_key(&parent_id)
+                    && !outputs.iter().any(|output| output.node_id == parent_id)
+                {
+                    return false;
+                }
+            }
+            for output in outputs
+                .iter()
+                .filter(|output| !accepted.contains_key(&output.node_id))
+            {
+                let parent_state = parent_states
+                    .entry(output.node_id)
+                    .or_insert_with(|| self.node_state(contract_id, output.node_id));
+                let seal = match parent_state.as_ref().and_then(|state| {
+                    state
+                        .typed_assignments()
+                        .get(output)
+                        .and_then(|assignment| assignment.seal())
+                }) {
+                    Some(seal) => seal,
+                    None => return false,
+                };
+                let indexed = self
+                    .outpoint_index
+                    .get(&seal)
+                    .and_then(|contracts| contracts.get(&contract_id))
+                    .map_or(false, |states| states.contains_key(output));
+                if !indexed || known_outputs.contains(output) {
+                    return false;
+                }
+                spent.push((seal, *output));
+            }
+            if node.extend(&mut delta).is_err() {
+                return false;
+            }
+        }
+
+        for (seal, output) in spent {
+            if let Some(contracts) = self.outpoint_index.get_mut(&seal) {
+                if let Some(states) = contracts.get_mut(&contract_id) {
+                    states.remove(&output);
+                    if states.is_empty() {
+                        contracts.remove(&contract_id);
+                    }
+                }
+                if contracts.is_empty() {
+                    self.outpoint_index.remove(&seal);
+                }
+            }
+        }
+        for (outpoint, states) in unspent_outpoints(&delta) {
+            self.outpoint_index
+                .entry(outpoint)
+                .or_default()
+                .entry(contract_id)
+                .or_default()
+                .extend(states);
+        }
+        true
+    }
+
+    /// Returns ids of the contracts having any data in the stash
+    pub(super) fn affected_contracts(&self) -> BTreeSet<ContractId> {
+        self.geneses
+            .keys()
+            .chain(self.bundles.keys())
+            .chain(self.extensions.keys())
+            .copied()
+            .collect()
+    }
+}
+
+/// Collects unspent assignments of the contract state with known seals,
+/// indexed by the seal outpoints
+fn unspent_outpoints(state: &ContractState) -> BTreeMap<OutPoint, OutputStates> {
+    let mut outpoints = BTreeMap::<_, OutputStates>::new();
+    for (outpoint, assignment) in state.typed_assignments() {
+        if state.is_spent(&outpoint) {
+            continue;
+        }
+        if let Some(seal) = assignment.seal() {
+            outpoints
+                .entry(seal)
+                .or_default()
+                .insert(outpoint, ProvenState::with_assignment(assignment));
+        }
+    }
+    outpoints
+}
+
+#[cfg(test)]
+mod test {
+    use amplify::Wrapper;
+    use bitcoin::hashes::Hash;
+    use bitcoin::secp256k1::rand::thread_rng;
+    use commit_verify::CommitConceal;
+    use lnpbp::chain::Chain;
+    use rgb_core::{
+        value, Assignment, AssignmentVec, ConcealSeals, Genesis, OwnedRights, ParentOwnedRights,
+        SchemaId, TransitionBundle,
+    };
+
+    use super::*;
+    use crate::stash::Stash;
+    use crate::verify::test::consignment;
+    use crate::StateTransfer;
+
+    fn outpoint() -> OutPoint { OutPoint::new(Txid::from_inner([1u8; 32]), 0) }
+
+    /// Returns copy of the consignment with the bundles at the given indexes
+    fn with_bundles(consignment: &StateTransfer, indexes: &[usize]) -> StateTransfer {
+        let mut consignment = consignment.clone();
+        consignment.anchored_bundles = consignment
+            .anchored_bundles
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| indexes.contains(index))
+            .map(|(_, anchored)| anchored.clone())
+            .collect::<Vec<_>>()
+            .try_into()
+            .unwrap();
+        consignment
+    }
+
+    fn node_ids(consignment: &StateTransfer) -> Vec<NodeId> {
+        consignment
+            .anchored_bundles
+            .iter()
+            .flat_map(|(_, bundle)| bundle.known_transitions())
+            .map(Transition::node_id)
+            .collect()
+    }
+
+    #[test]
+    fn test_unspent_state() {
+        let first = consignment(3);
+        let second = consignment(1);
+        let mut stash = MemStash::new();
+        stash.accept(&first, &[]).unwrap();
+        stash.accept(&second, &[]).unwrap();
+        assert_eq!(stash.outpoint_index, stash.scan_outpoints());
+
+        // All but the last transition of each chain are spent
+        let state = stash.outpoint_state(&bset![outpoint()]).unwrap();
+        let mut contract_ids = state[&outpoint()]
+            .iter()
+            .map(|(contract_id, _)| *contract_id)
+            .collect::<Vec<_>>();
+        contract_ids.sort();
+        let mut expected = vec![first.contract_id(), second.contract_id()];
+        expected.sort();
+        assert_eq!(contract_ids, expected);
+        assert!(stash.is_rgb_colored(outpoint()).unwrap());
+
+        assert!(!stash.is_rgb_colored(OutPoint::default()).unwrap());
+        assert!(stash
+            .outpoint_state(&bset![OutPoint::default()])
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_typed_outputs() {
+        // Genesis assigns both owned right types to the first output
+        let value = |amount| {
+            AssignmentVec::Fungible(vec![Assignment::Revealed {
+                seal_definition: seal::Revealed::from(outpoint()),
+                assigned_state: value::Revealed::with_amount(amount, &mut thread_rng()),
+            }])
+        };
+        let genesis = Genesis::with(
+            SchemaId::default(),
+            Chain::Testnet3,
+            empty!(),
+            OwnedRights::from_inner(bmap! { 0 => value(1000), 1 => value(100) }),
+            empty!(),
+        );
+        let contract_id = genesis.contract_id();
+        let inflation = TypedOutpoint::new(genesis.node_id(), 0, 0);
+        let assets = TypedOutpoint::new(genesis.node_id(), 1, 0);
+
+        let mut stash = MemStash::new();
+        stash.geneses.insert(contract_id, genesis.clone());
+        stash.rebuild_outpoint_index();
+        assert!(stash.is_rgb_colored(outpoint()).unwrap());
+        let states = &stash.outpoint_index[&outpoint()][&contract_id];
+        assert_eq!(states.keys().copied().collect::<Vec<_>>(), vec![inflation, assets]);
+        assert_eq!(
+            stash.outpoint_state(&bset![outpoint()]).unwrap()[&outpoint()].len(),
+            2
+        );
+
+        // Spending the inflation right leaves the assets on the output
+        let parents = bmap! { genesis.node_id() => bmap! { 0 => vec![0] } };
+        let transition = Transition::with(
+            1,
+            empty!(),
+            empty!(),
+            empty!(),
+            empty!(),
+            ParentOwnedRights::from_inner(parents),
+        );
+        let mut state = ContractState::with_genesis(&genesis);
+        state
+            .extend(Txid::from_inner([2u8; 32]), &transition)
+            .unwrap();
+        let index = unspent_outpoints(&state);
+        assert_eq!(index[&outpoint()].keys().copied().collect::<Vec<_>>(), vec![assets]);
+    }
+
+    #[test]
+    fn test_concealed_seals() {
+        let seal = seal::Revealed::from(outpoint());
+        let mut consignment = consignment(2);
+        let concealed = vec![seal.commit_conceal()];
+        consignment.anchored_bundles = consignment
+            .anchored_bundles
+            .iter()
+            .map(|(anchor, bundle)| {
+                let bundle = bundle
+                    .revealed_iter()
+                    .map(|(transition, inputs)| {
+                        let mut transition = transition.clone();
+                        transition.conceal_seals(&concealed);
+                        (transition, inputs.clone())
+                    })
+                    .collect::<BTreeMap<_, _>>();
+                (anchor.clone(), TransitionBundle::from(bundle))
+            })
+            .collect::<Vec<_>>()
+            .try_into()
+            .unwrap();
+
+        // Genesis assignment is spent, and the seals of the transitions are
+        // not known
+        let mut stash = MemStash::new();
+        stash.accept(&consignment, &[]).unwrap();
+        assert!(!stash.is_rgb_colored(outpoint()).unwrap());
+
+        stash.add_seal_secrets(vec![seal.clone()]);
+        assert!(stash.is_rgb_colored(outpoint()).unwrap());
+        assert_eq!(stash.outpoint_index, stash.scan_outpoints());
+
+        let mut other = MemStash::new();
+        other.accept(&consignment, &[seal]).unwrap();
+        assert_eq!(other.outpoint_index, stash.outpoint_index);
+        assert_eq!(
+            other.outpoint_state(&bset![outpoint()]).unwrap()[&outpoint()].len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_incremental_index() {
+        let consignment = consignment(3);
+        let contract_id = consignment.contract_id();
+        let mut known = MemStash::new();
+        known
+            .accept(&with_bundles(&consignment, &[0, 1]), &[])
+            .unwrap();
+        assert_eq!(known.outpoint_index, known.scan_outpoints());
+
+        let mut stash = known.clone();
+        stash.accept(&consignment, &[]).unwrap();
+        assert_eq!(stash.outpoint_index, stash.scan_outpoints());
+
+        // The accepted transition spends the output indexed before
+        stash.outpoint_index = known.outpoint_index.clone();
+        assert!(stash.index_accepted(&known, contract_id));
+        assert_eq!(stash.outpoint_index, stash.scan_outpoints());
+    }
+
+    #[test]
+    fn test_reindex_fallback() {
+        let consignment = consignment(3);
+        let contract_id = consignment.contract_id();
+        let node_ids = node_ids(&consignment);
+        let mut known = MemStash::new();
+        known
+            .accept(&with_bundles(&consignment, &[0, 2]), &[])
+            .unwrap();
+        assert_eq!(known.outpoint_index, known.scan_outpoints());
+
+        // The last transition is not applied since its parent is not known
+        let (state, skipped) = known.contract_state(contract_id).unwrap();
+        assert_eq!(skipped, bmap! {
+            node_ids[2] => StateApplyError::UnknownParents {
+                node_id: node_ids[2],
+                missing: bset![node_ids[1]]
+            }
+        });
+        assert_eq!(
+            state.spending_transition(&TypedOutpoint::new(node_ids[0], 1, 0)),
+            None
+        );
+        assert!(!state
+            .typed_assignments()
+            .contains_key(&TypedOutpoint::new(node_ids[2], 1, 0)));
+
+        // The accepted transition is a parent of the transition known before,
+        // so the index is recomputed
+        let mut stash = known.clone();
+        stash.accept(&consignment, &[]).unwrap();
+        assert_eq!(stash.outpoint_index, stash.scan_outpoints());
+        stash.outpoint_index = known.outpoint_index.clone();
+        assert!(!stash.index_accepted(&known, contract_id));
+        assert_eq!(stash.outpoint_index, known.outpoint_index);
+
+        let (state, skipped) = stash.contract_state(contract_id).unwrap();
+        assert!(skipped.is_empty());
+        assert_eq!(
+            state.spending_transition(&TypedOutpoint::new(node_ids[1], 1, 0)),
+            Some(node_ids[2])
+        );
+    }
+}
@@ -0,0 +1,325 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Thread-safe wrapper around [`Stash`] implementations.
+//!
+//! Stash operations which do not modify the stash (consignment creation,
+//! queries) are performed under a read lock and can run concurrently;
+//! operations modifying the stash data take a write lock. Since
+//! [`Stash::consign`] requires only `&self`, a long-running consignment
+//! creation never blocks other readers.
+//!
+//! If `parking_lot` feature is enabled, locks are provided by the
+//! `parking_lot` crate; otherwise standard library locks are used.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+use bitcoin::{OutPoint, Txid};
+use commit_verify::lnpbp4;
+
+use super::{Stash, StashDiff, StashMetrics, StashSnapshot};
+use crate::{
+    seal, Anchor, CloseMethod, ContractId, Disclosure, DisclosureId, ProvenState, SealEndpoint,
+    StateTransfer, TransitionBundle,
+};
+
+#[cfg(feature = "parking_lot")]
+type Lock<S> = parking_lot::RwLock<S>;
+#[cfg(feature = "parking_lot")]
+type ReadGuard<'a, S> = parking_lot::RwLockReadGuard<'a, S>;
+#[cfg(feature = "parking_lot")]
+type WriteGuard<'a, S> = parking_lot::RwLockWriteGuard<'a, S>;
+
+#[cfg(not(feature = "parking_lot"))]
+type Lock<S> = std::sync::RwLock<S>;
+#[cfg(not(feature = "parking_lot"))]
+type ReadGuard<'a, S> = std::sync::RwLockReadGuard<'a, S>;
+#[cfg(not(feature = "parking_lot"))]
+type WriteGuard<'a, S> = std::sync::RwLockWriteGuard<'a, S>;
+
+/// Stash which can be shared between multiple threads. Cloning the shared
+/// stash produces a new handle to the same underlying stash.
+#[derive(Debug)]
+pub struct SharedStash<S>
+where S: Stash
+{
+    inner: Arc<Lock<S>>,
+}
+
+impl<S> Clone for SharedStash<S>
+where S: Stash
+{
+    fn clone(&self) -> Self {
+        SharedStash {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<S> From<S> for SharedStash<S>
+where S: Stash
+{
+    fn from(stash: S) -> Self { SharedStash::new(stash) }
+}
+
+impl<S> SharedStash<S>
+where S: Stash
+{
+    /// Wraps the stash into a thread-safe shared handle
+    #[inline]
+    pub fn new(stash: S) -> Self {
+        SharedStash {
+            inner: Arc::new(Lock::new(stash)),
+        }
+    }
+
+    #[cfg(feature = "parking_lot")]
+    fn read(&self) -> ReadGuard<S> { self.inner.read() }
+
+    #[cfg(feature = "parking_lot")]
+    fn write(&self) -> WriteGuard<S> { self.inner.write() }
+
+    // A panic in another thread holding the lock does not corrupt stash data,
+    // since stash implementations apply changes atomically, so we recover
+    // from lock poisoning
+    #[cfg(not(feature = "parking_lot"))]
+    fn read(&self) -> ReadGuard<S> {
+        self.inner
+            .read()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    #[cfg(not(feature = "parking_lot"))]
+    fn write(&self) -> WriteGuard<S> {
+        self.inner
+            .write()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// Runs multiple read-only operations under a single read lock, such that
+    /// all of them observe the same stash state
+    pub fn read_with<T>(&self, f: impl FnOnce(&S) -> T) -> T { f(&self.read()) }
+
+    /// Runs multiple operations under a single write lock, such that no other
+    /// thread can observe or modify the stash in between them
+    pub fn write_with<T>(&self, f: impl FnOnce(&mut S) -> T) -> T { f(&mut self.write()) }
+
+    /// Creates consignment under a read lock; see [`Stash::consign`]
+    pub fn consign(
+        &self,
+        contract_id: ContractId,
+        bundle: TransitionBundle,
+        anchor: Option<&Anchor<lnpbp4::MerkleProof>>,
+        endpoints: &BTreeSet<SealEndpoint>,
+        close_method: Option<CloseMethod>,
+    ) -> Result<StateTransfer, S::Error> {
+        self.read()
+            .consign(contract_id, bundle, anchor, endpoints, close_method)
+    }
+
+    /// Accepts consignment under a write lock; see [`Stash::accept`]
+    pub fn accept(
+        &self,
+        consignment: &StateTransfer,
+        known_seals: &[seal::Revealed],
+    ) -> Result<(), S::Error> {
+        self.write().accept(consignment, known_seals)
+    }
+
+    /// Encloses disclosure under a write lock; see [`Stash::enclose`]
+    pub fn enclose(&self, disclosure: &Disclosure) -> Result<(), S::Error> {
+        self.write().enclose(disclosure)
+    }
+
+    /// Defers disclosure under a write lock; see [`Stash::defer_disclosure`]
+    pub fn defer_disclosure(
+        &self,
+        witness_txid: Txid,
+        disclosure: Disclosure,
+    ) -> Result<(), S::Error> {
+        self.write().defer_disclosure(witness_txid, disclosure)
+    }
+
+    /// Lists deferred disclosures under a read lock; see
+    /// [`Stash::pending_disclosures`]
+    pub fn pending_disclosures(&self) -> Result<BTreeMap<Txid, Vec<DisclosureId>>, S::Error> {
+        self.read().pending_disclosures()
+    }
+
+    /// Processes mined witness under a write lock; see
+    /// [`Stash::process_witness`]
+    pub fn process_witness(&self, txid: Txid) -> Result<Vec<DisclosureId>, S::Error> {
+        self.write().process_witness(txid)
+    }
+
+    /// Creates stash snapshot under a read lock; see [`Stash::snapshot`]
+    pub fn snapshot(&self) -> Result<StashSnapshot, S::Error> { self.read().snapshot() }
+
+    /// Restores stash from a snapshot under a write lock; see
+    /// [`Stash::restore`]
+    pub fn restore(&self, snapshot: StashSnapshot) -> Result<(), S::Error> {
+        self.write().restore(snapshot)
+    }
+
+    /// Compares stash with a snapshot under a read lock; see [`Stash::diff`]
+    pub fn diff(&self, snapshot: &StashSnapshot) -> Result<StashDiff, S::Error> {
+        self.read().diff(snapshot)
+    }
+
+    /// Computes stash metrics under a read lock; see [`Stash::metrics`]
+    pub fn metrics(&self) -> Result<StashMetrics, S::Error> { self.read().metrics() }
+
+    /// Returns state assigned to the outpoints under a read lock; see
+    /// [`Stash::outpoint_state`]
+    pub fn outpoint_state(
+        &self,
+        outpoints: &BTreeSet<OutPoint>,
+    ) -> Result<BTreeMap<OutPoint, Vec<(ContractId, ProvenState)>>, S::Error> {
+        self.read().outpoint_state(outpoints)
+    }
+
+    /// Checks whether the outpoint holds any state under a read lock; see
+    /// [`Stash::is_rgb_colored`]
+    pub fn is_rgb_colored(&self, outpoint: OutPoint) -> Result<bool, S::Error> {
+        self.read().is_rgb_colored(outpoint)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{mpsc, Barrier};
+    use std::thread;
+    use std::time::Duration;
+
+    use bitcoin::hashes::Hash;
+    use commit_verify::CommitConceal;
+
+    use super::*;
+    use crate::verify::test::consignment;
+    use crate::MemStash;
+
+    #[test]
+    fn test_readers_are_not_blocked() {
+        let stash = SharedStash::new(MemStash::new());
+        let (tx, rx) = mpsc::channel();
+
+        let reader = stash.clone();
+        let handle = thread::spawn(move || {
+            reader.read_with(|_| {
+                tx.send(()).unwrap();
+                // Emulate long-running consignment creation
+                thread::sleep(Duration::from_millis(200));
+            })
+        });
+
+        rx.recv().unwrap();
+        // Must succeed while the other thread holds a read lock
+        assert!(stash.pending_disclosures().unwrap().is_empty());
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_concurrent_access() {
+        let stash = SharedStash::new(MemStash::new());
+
+        let handles = (0u8..8)
+            .map(|no| {
+                let stash = stash.clone();
+                thread::spawn(move || {
+                    let txid = Txid::from_inner([no; 32]);
+                    stash.defer_disclosure(txid, Disclosure::default()).unwrap();
+                    assert!(stash.pending_disclosures().unwrap().contains_key(&txid));
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(stash.pending_disclosures().unwrap().len(), 8);
+        let enclosed = stash.process_witness(Txid::from_inner([0u8; 32])).unwrap();
+        assert!(enclosed.is_empty());
+    }
+
+    #[test]
+    fn test_concurrent_consign() {
+        let history = consignment(16);
+        let contract_id = history.contract_id();
+        let (anchor, bundle) = history.anchored_bundles.iter().last().unwrap().clone();
+        let seal = seal::Revealed::from(OutPoint::default());
+        let endpoints = bset![SealEndpoint::ConcealedUtxo(seal.commit_conceal())];
+
+        let mut inner = MemStash::new();
+        inner.accept(&history, &[]).unwrap();
+        let expected = inner
+            .consign(contract_id, bundle.clone(), Some(&anchor), &endpoints, None)
+            .unwrap();
+        let stash = SharedStash::new(inner);
+        let others = (1..=4).map(consignment).collect::<Vec<_>>();
+        let barrier = Arc::new(Barrier::new(3));
+
+        let consigner = {
+            let stash = stash.clone();
+            let barrier = barrier.clone();
+            let endpoints = endpoints.clone();
+            thread::spawn(move || {
+                barrier.wait();
+                for _ in 0..8 {
+                    let transfer = stash
+                        .consign(contract_id, bundle.clone(), Some(&anchor), &endpoints, None)
+                        .unwrap();
+                    // Contracts accepted in between must not affect the result
+                    assert_eq!(transfer, expected);
+                }
+            })
+        };
+        let acceptor = {
+            let stash = stash.clone();
+            let barrier = barrier.clone();
+            let others = others.clone();
+            thread::spawn(move || {
+                barrier.wait();
+                for other in &others {
+                    stash.accept(other, &[]).unwrap();
+                }
+            })
+        };
+        let querier = {
+            let stash = stash.clone();
+            let outpoint = OutPoint::new(Txid::from_inner([1u8; 32]), 0);
+            thread::spawn(move || {
+                barrier.wait();
+                for _ in 0..8 {
+                    // The outpoint always holds at least the state of the
+                    // initially accepted contract
+                    let state = stash.outpoint_state(&bset![outpoint]).unwrap();
+                    assert!(state[&outpoint].iter().any(|(id, _)| *id == contract_id));
+                    assert!(stash.is_rgb_colored(outpoint).unwrap());
+                    assert!(stash
+                        .metrics()
+                        .unwrap()
+                        .contracts
+                        .contains_key(&contract_id));
+                }
+            })
+        };
+        for handle in [consigner, acceptor, querier] {
+            handle.join().unwrap();
+        }
+
+        let contract_ids = stash.read_with(MemStash::contract_ids);
+        assert_eq!(contract_ids.len(), 5);
+        for other in &others {
+            assert!(contract_ids.contains(&other.contract_id()));
+        }
+    }
+}
@@ -0,0 +1,284 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Snapshots of the stash content used as rollback points.
+
+use std::collections::BTreeSet;
+use std::str::FromStr;
+
+use bitcoin::hashes::{sha256, sha256t};
+use commit_verify::{commit_encode, CommitVerify, ConsensusCommit, PrehashedProtocol, TaggedHash};
+use lnpbp_bech32::{FromBech32Str, ToBech32String};
+use rgb_core::{seal, AnchorId, BundleId, ContractId, NodeId, SchemaId};
+
+use super::MemStash;
+use crate::DisclosureId;
+
+// "rgb:snapshot"
+static MIDSTATE_SNAPSHOT_ID: [u8; 32] = [
+    213, 37, 19, 173, 115, 76, 120, 79, 22, 99, 228, 104, 107, 93, 137, 10, 246, 14, 90, 143, 183,
+    7, 166, 17, 8, 80, 235, 121, 12, 57, 138, 148,
+];
+
+/// Tag used for [`SnapshotId`] hash types
+pub struct SnapshotIdTag;
+
+impl sha256t::Tag for SnapshotIdTag {
+    #[inline]
+    fn engine() -> sha256::HashEngine {
+        let midstate = sha256::Midstate::from_inner(MIDSTATE_SNAPSHOT_ID);
+        sha256::HashEngine::from_midstate(midstate, 64)
+    }
+}
+
+/// Unique stash snapshot identifier equivalent to the commitment hash
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(crate = "serde_crate"))]
+#[derive(Wrapper, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Debug, Display, From)]
+#[derive(StrictEncode, StrictDecode)]
+#[wrapper(LowerHex, BorrowSlice)]
+#[display(SnapshotId::to_bech32_string)]
+pub struct SnapshotId(sha256t::Hash<SnapshotIdTag>);
+
+impl<Msg> CommitVerify<Msg, PrehashedProtocol> for SnapshotId
+where Msg: AsRef<[u8]>
+{
+    #[inline]
+    fn commit(msg: &Msg) -> SnapshotId { SnapshotId::hash(msg) }
+}
+
+impl commit_encode::Strategy for SnapshotId {
+    type Strategy = commit_encode::strategies::UsingStrict;
+}
+
+impl lnpbp_bech32::Strategy for SnapshotId {
+    const HRP: &'static str = "id";
+    type Strategy = lnpbp_bech32::strategies::UsingStrictEncoding;
+}
+
+impl FromStr for SnapshotId {
+    type Err = lnpbp_bech32::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> { SnapshotId::from_bech32_str(s) }
+}
+
+/// Capture of the stash content which can be used to restore the stash to
+/// the state at the moment of the snapshot creation.
+///
+/// Snapshot stores stash data in the form of [`MemStash`], which serves as an
+/// interchange format between different stash backends. Backends supporting
+/// copy-on-write may construct snapshot lazily, at the moment when the data
+/// are modified. In particular, a snapshot of [`MemStash`] shares the data
+/// with the stash, which are copied only once the stash gets modified.
+#[derive(Clone, PartialEq, Debug, StrictEncode, StrictDecode)]
+pub struct StashSnapshot(MemStash);
+
+impl commit_encode::Strategy for StashSnapshot {
+    type Strategy = commit_encode::strategies::UsingStrict;
+}
+
+impl ConsensusCommit for StashSnapshot {
+    type Commitment = SnapshotId;
+}
+
+impl StashSnapshot {
+    #[inline]
+    pub fn with(stash: MemStash) -> Self { StashSnapshot(stash) }
+
+    #[inline]
+    pub fn snapshot_id(&self) -> SnapshotId { self.clone().consensus_commit() }
+
+    #[inline]
+    pub fn as_stash(&self) -> &MemStash { &self.0 }
+
+    #[inline]
+    pub fn into_stash(self) -> MemStash { self.0 }
+}
+
+/// Ids of objects contained in the stash
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct StashObjects {
+    pub schemata: BTreeSet<SchemaId>,
+    pub geneses: BTreeSet<ContractId>,
+    pub anchors: BTreeSet<AnchorId>,
+    pub bundles: BTreeSet<BundleId>,
+    pub transitions: BTreeSet<NodeId>,
+    pub extensions: BTreeSet<NodeId>,
+    pub seal_secrets: BTreeSet<seal::Revealed>,
+    pub deferred_disclosures: BTreeSet<DisclosureId>,
+}
+
+impl StashObjects {
+    /// Detects whether there are no objects of any class
+    pub fn is_empty(&self) -> bool {
+        self.schemata.is_empty()
+            && self.geneses.is_empty()
+            && self.anchors.is_empty()
+            && self.bundles.is_empty()
+            && self.transitions.is_empty()
+            && self.extensions.is_empty()
+            && self.seal_secrets.is_empty()
+            && self.deferred_disclosures.is_empty()
+    }
+
+    /// Returns objects present in `self` but absent in `other`
+    pub fn difference(&self, other: &StashObjects) -> StashObjects {
+        StashObjects {
+            schemata: self.schemata.difference(&other.schemata).copied().collect(),
+            geneses: self.geneses.difference(&other.geneses).copied().collect(),
+            anchors: self.anchors.difference(&other.anchors).copied().collect(),
+            bundles: self.bundles.difference(&other.bundles).copied().collect(),
+            transitions: self
+                .transitions
+                .difference(&other.transitions)
+                .copied()
+                .collect(),
+            extensions: self
+                .extensions
+                .difference(&other.extensions)
+                .copied()
+                .collect(),
+            seal_secrets: self
+                .seal_secrets
+                .difference(&other.seal_secrets)
+                .cloned()
+                .collect(),
+            deferred_disclosures: self
+                .deferred_disclosures
+                .difference(&other.deferred_disclosures)
+                .copied()
+                .collect(),
+        }
+    }
+}
+
+/// Difference between the current stash content and a snapshot
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct StashDiff {
+    /// Objects added to the stash since the snapshot
+    pub added: StashObjects,
+
+    /// Objects removed from the stash since the snapshot
+    pub removed: StashObjects,
+}
+
+impl StashDiff {
+    /// Computes difference between the `snapshot` and `current` stash data
+    #[inline]
+    pub fn with(snapshot: &MemStash, current: &MemStash) -> Self {
+        StashDiff::between(&snapshot.objects(), &current.objects())
+    }
+
+    /// Computes difference between the sets of objects contained in the stash
+    /// `before` and `after` some changes
+    pub fn between(before: &StashObjects, after: &StashObjects) -> Self {
+        StashDiff {
+            added: after.difference(before),
+            removed: before.difference(after),
+        }
+    }
+
+    /// Detects whether the stash was not changed since the snapshot
+    #[inline]
+    pub fn is_empty(&self) -> bool { self.added.is_empty() && self.removed.is_empty() }
+}
+
+impl MemStash {
+    /// Returns ids of all objects contained in the stash
+    pub fn objects(&self) -> StashObjects {
+        StashObjects {
+            schemata: self.schemata.keys().copied().collect(),
+            geneses: self.geneses.keys().copied().collect(),
+            anchors: self.anchors.keys().copied().collect(),
+            bundles: self
+                .bundles
+                .values()
+                .flat_map(|bundles| bundles.keys().copied())
+                .collect(),
+            transitions: self.transition_index.keys().copied().collect(),
+            extensions: self
+                .extensions
+                .values()
+                .flat_map(|extensions| extensions.keys().copied())
+                .collect(),
+            seal_secrets: self.seal_secrets.clone(),
+            deferred_disclosures: self.deferred_disclosures.keys().copied().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use amplify::Wrapper;
+    use bitcoin::Txid;
+    use commit_verify::tagged_hash;
+    use strict_encoding::StrictEncode;
+
+    use super::*;
+    use crate::verify::test::consignment;
+    use crate::{Disclosure, Stash};
+
+    #[test]
+    fn test_snapshot_id_midstate() {
+        let midstate = tagged_hash::Midstate::with(b"rgb:snapshot");
+        assert_eq!(midstate.into_inner().into_inner(), MIDSTATE_SNAPSHOT_ID);
+    }
+
+    #[test]
+    fn test_restore() {
+        let mut stash = MemStash::new();
+        stash.add_seal_secrets(vec![seal::Revealed::from(bitcoin::OutPoint::default())]);
+        let before = stash.strict_serialize().unwrap();
+
+        let snapshot = stash.snapshot().unwrap();
+        stash
+            .defer_disclosure(Txid::default(), Disclosure::default())
+            .unwrap();
+        let diff = stash.diff(&snapshot).unwrap();
+        assert_eq!(diff.added.deferred_disclosures.len(), 1);
+        assert!(diff.removed.is_empty());
+
+        stash.restore(snapshot).unwrap();
+        assert_eq!(stash.strict_serialize().unwrap(), before);
+    }
+
+    #[test]
+    fn test_restore_accepted() {
+        let mut stash = MemStash::new();
+        let first = consignment(1);
+        stash.accept(&first, &[]).unwrap();
+        let before = stash.strict_serialize().unwrap();
+        let objects = stash.objects();
+
+        let snapshot = stash.snapshot().unwrap();
+        let second = consignment(2);
+        stash.accept(&second, &[]).unwrap();
+
+        let diff = stash.diff(&snapshot).unwrap();
+        assert!(diff.removed.is_empty());
+        assert!(diff.added.geneses.contains(&second.contract_id()));
+        let bundle_ids = second
+            .anchored_bundles
+            .iter()
+            .map(|(_, bundle)| bundle.bundle_id())
+            .collect::<BTreeSet<_>>();
+        assert_eq!(diff.added.bundles, bundle_ids);
+        assert_eq!(diff.added.transitions.len(), 2);
+        // Snapshot data are not affected by the stash modification
+        assert_eq!(snapshot.as_stash().objects(), objects);
+        assert_eq!(snapshot.as_stash().strict_serialize().unwrap(), before);
+
+        stash.restore(snapshot).unwrap();
+        assert_eq!(stash.objects(), objects);
+        assert!(stash.genesis(second.contract_id()).is_none());
+        assert!(stash.contract_state(second.contract_id()).is_none());
+        assert_eq!(stash.strict_serialize().unwrap(), before);
+    }
+}
@@ -9,21 +9,32 @@
 // You should have received a copy of the MIT License along with this software.
 // If not, see <https://opensource.org/licenses/MIT>.
 
-use std::collections::BTreeMap;
-use std::fmt::{Debug, Display};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Debug, Display, Formatter};
 use std::hash::Hash;
+use std::io;
 use std::ops::Deref;
-use std::slice;
+use std::{slice, str};
 
+use amplify::Wrapper;
+use bitcoin::hashes::{sha256, sha256t};
 use bitcoin::{OutPoint, Txid};
 use bp::seals::txout::TxoSeal;
-use commit_verify::CommitConceal;
-use rgb_core::contract::attachment;
-use rgb_core::schema::{FieldType, OwnedRightType};
+use commit_verify::{
+    commit_encode, lnpbp4, CommitConceal, CommitEncode, CommitVerify, ConsensusCommit,
+    PrehashedProtocol, TaggedHash,
+};
+use lnpbp_bech32::{FromBech32Str, ToBech32String};
+use rgb_core::contract::attachment::{self, AttachmentId};
+use rgb_core::schema::{DataFormat, FieldType, OwnedRightType, StateSchema};
 use rgb_core::{
-    data, seal, Assignment, AssignmentVec, AtomicValue, AttachmentStrategy, ContractId,
-    DeclarativeStrategy, HashStrategy, Node, NodeId, NodeOutpoint, PedersenStrategy, State,
+    data, seal, Anchor, Assignment, AssignmentVec, AtomicValue, AttachmentStrategy, ConcealSeals,
+    ConcealState, ContractId, DeclarativeStrategy, Extension, Genesis, HashStrategy, Node, NodeId,
+    NodeOutpoint, PedersenStrategy, Schema, State, Transition,
 };
+#[cfg(feature = "serde")]
+use serde_with::{As, DisplayFromStr, Same};
 use strict_encoding::{StrictDecode, StrictEncode};
 
 pub trait StateAtom:
@@ -38,29 +49,285 @@ pub trait StateAtom:
     + From<<Self::StateType as State>::Revealed>
 {
     type StateType: State;
+
+    /// Kind of the state represented by the type
+    const KIND: StateKind;
+
+    /// Interprets numeric state as an unsigned 64-bit integer. Fails if the
+    /// value can't be represented without loss of information.
+    fn as_u64(&self) -> Result<u64, StateConversionError> {
+        Err(StateConversionError::WrongKind(Self::KIND, "u64"))
+    }
+
+    /// Returns amount of the fungible state
+    fn as_amount(&self) -> Result<u64, StateConversionError> {
+        Err(StateConversionError::WrongKind(Self::KIND, "amount"))
+    }
+
+    /// Returns binary representation of the data state
+    fn as_bytes(&self) -> Result<&[u8], StateConversionError> {
+        Err(StateConversionError::WrongKind(Self::KIND, "bytes"))
+    }
+
+    /// Returns data state as a string, checking that it is a valid UTF-8
+    fn as_string(&self) -> Result<&str, StateConversionError> {
+        Err(StateConversionError::WrongKind(Self::KIND, "string"))
+    }
+
+    /// Detects whether the state value is concealed
+    #[inline]
+    fn is_confidential(&self) -> bool { false }
+
+    /// Returns the state value in a form independent of the state type
+    fn to_value(&self) -> StateValue;
 }
 impl StateAtom for data::Void {
     type StateType = DeclarativeStrategy;
+    const KIND: StateKind = StateKind::Declarative;
+
+    #[inline]
+    fn to_value(&self) -> StateValue { StateValue::Declarative }
 }
 impl StateAtom for AtomicValue {
     type StateType = PedersenStrategy;
+    const KIND: StateKind = StateKind::Fungible;
+
+    #[inline]
+    fn to_value(&self) -> StateValue { StateValue::Fungible(self.value) }
+
+    #[inline]
+    fn as_u64(&self) -> Result<u64, StateConversionError> { Ok(self.value) }
+
+    #[inline]
+    fn as_amount(&self) -> Result<u64, StateConversionError> { Ok(self.value) }
 }
 impl StateAtom for data::Revealed {
     type StateType = HashStrategy;
+    const KIND: StateKind = StateKind::Data;
+
+    fn to_value(&self) -> StateValue {
+        StateValue::Data(
+            self.strict_serialize()
+                .expect("in-memory encoding of the data state must not fail"),
+        )
+    }
+
+    fn as_u64(&self) -> Result<u64, StateConversionError> {
+        let lossy = |_| StateConversionError::Lossy("u64");
+        match self {
+            data::Revealed::U8(val) => Ok(*val as u64),
+            data::Revealed::U16(val) => Ok(*val as u64),
+            data::Revealed::U32(val) => Ok(*val as u64),
+            data::Revealed::U64(val) => Ok(*val),
+            data::Revealed::U128(val) => u64::try_from(*val).map_err(lossy),
+            data::Revealed::I8(val) => u64::try_from(*val).map_err(lossy),
+            data::Revealed::I16(val) => u64::try_from(*val).map_err(lossy),
+            data::Revealed::I32(val) => u64::try_from(*val).map_err(lossy),
+            data::Revealed::I64(val) => u64::try_from(*val).map_err(lossy),
+            data::Revealed::I128(val) => u64::try_from(*val).map_err(lossy),
+            data::Revealed::F32(val) => float_to_u64(*val as f64),
+            data::Revealed::F64(val) => float_to_u64(*val),
+            _ => Err(StateConversionError::WrongType("u64")),
+        }
+    }
+
+    fn as_bytes(&self) -> Result<&[u8], StateConversionError> {
+        match self {
+            data::Revealed::Bytes(bytes) => Ok(bytes),
+            data::Revealed::String(s) => Ok(s.as_bytes()),
+            _ => Err(StateConversionError::WrongType("bytes")),
+        }
+    }
+
+    fn as_string(&self) -> Result<&str, StateConversionError> {
+        match self {
+            data::Revealed::String(s) => Ok(s),
+            data::Revealed::Bytes(bytes) => {
+                str::from_utf8(bytes).map_err(|_| StateConversionError::InvalidUtf8)
+            }
+            _ => Err(StateConversionError::WrongType("string")),
+        }
+    }
 }
 impl StateAtom for attachment::Revealed {
     type StateType = AttachmentStrategy;
+    const KIND: StateKind = StateKind::Attachment;
+
+    fn to_value(&self) -> StateValue {
+        StateValue::Attachment {
+            id: self.id,
+            mime: self.mime.clone(),
+        }
+    }
+}
+
+/// Tag of the concealed [`StateValue`] in its strict encoding; revealed values
+/// are tagged with their [`StateKind`]
+const CONCEALED_STATE_TAG: u8 = 0x80;
+
+/// Value of the state of any kind, independent of the state type holding it
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate", rename_all = "lowercase")
+)]
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
+pub enum StateValue {
+    /// Declarative state, which has no value
+    Declarative,
+
+    /// Amount of the fungible state
+    Fungible(u64),
+
+    /// Strict encoding of the data state
+    Data(Vec<u8>),
+
+    /// File attachment, identified by its id and MIME type
+    Attachment { id: AttachmentId, mime: String },
+
+    /// State of the given kind whose value is concealed
+    Concealed(StateKind),
+}
+
+impl StateValue {
+    /// Returns kind of the state
+    pub fn kind(&self) -> StateKind {
+        match self {
+            StateValue::Declarative => StateKind::Declarative,
+            StateValue::Fungible(_) => StateKind::Fungible,
+            StateValue::Data(_) => StateKind::Data,
+            StateValue::Attachment { .. } => StateKind::Attachment,
+            StateValue::Concealed(kind) => *kind,
+        }
+    }
+
+    /// Detects whether the state value is concealed
+    #[inline]
+    pub fn is_confidential(&self) -> bool { matches!(self, StateValue::Concealed(_)) }
+
+    /// Returns concealed form of the value. Declarative state has no value to
+    /// conceal and is returned as is.
+    pub fn conceal(&self) -> StateValue {
+        match self {
+            StateValue::Declarative => StateValue::Declarative,
+            value => StateValue::Concealed(value.kind()),
+        }
+    }
+}
+
+impl StrictEncode for StateValue {
+    fn strict_encode<E: io::Write>(&self, mut e: E) -> Result<usize, strict_encoding::Error> {
+        Ok(match self {
+            StateValue::Declarative => StateKind::Declarative.strict_encode(e)?,
+            StateValue::Fungible(amount) => strict_encode_list!(e; StateKind::Fungible, amount),
+            StateValue::Data(data) => strict_encode_list!(e; StateKind::Data, data),
+            StateValue::Attachment { id, mime } => {
+                strict_encode_list!(e; StateKind::Attachment, id, mime)
+            }
+            StateValue::Concealed(kind) => strict_encode_list!(e; CONCEALED_STATE_TAG, kind),
+        })
+    }
+}
+
+impl StrictDecode for StateValue {
+    fn strict_decode<D: io::Read>(mut d: D) -> Result<Self, strict_encoding::Error> {
+        let tag = u8::strict_decode(&mut d)?;
+        Ok(match tag {
+            tag if tag == StateKind::Declarative as u8 => StateValue::Declarative,
+            tag if tag == StateKind::Fungible as u8 => {
+                StateValue::Fungible(StrictDecode::strict_decode(d)?)
+            }
+            tag if tag == StateKind::Data as u8 => {
+                StateValue::Data(StrictDecode::strict_decode(d)?)
+            }
+            tag if tag == StateKind::Attachment as u8 => StateValue::Attachment {
+                id: StrictDecode::strict_decode(&mut d)?,
+                mime: StrictDecode::strict_decode(&mut d)?,
+            },
+            CONCEALED_STATE_TAG => StateValue::Concealed(StrictDecode::strict_decode(d)?),
+            tag => {
+                return Err(strict_encoding::Error::DataIntegrityError(format!(
+                    "unknown state value tag {:#04x}",
+                    tag
+                )))
+            }
+        })
+    }
+}
+
+/// Converts floating-point value to an integer only if it has no fractional
+/// part and fits into 64 bits
+fn float_to_u64(val: f64) -> Result<u64, StateConversionError> {
+    // 2^64 is exactly representable by f64, unlike `u64::MAX`
+    if val.fract() == 0.0 && (0.0..18446744073709551616.0).contains(&val) {
+        Ok(val as u64)
+    } else {
+        Err(StateConversionError::Lossy("u64"))
+    }
+}
+
+/// Errors happening when the state is interpreted as a value of some type
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum StateConversionError {
+    /// {0} state can't be interpreted as {1}
+    WrongKind(StateKind, &'static str),
+
+    /// data state has a type which can't be interpreted as {0}
+    WrongType(&'static str),
+
+    /// numeric state value can't be represented as {0} without loss of
+    /// information
+    Lossy(&'static str),
+
+    /// data state is not a valid UTF-8 string
+    InvalidUtf8,
+
+    /// the state is concealed and its value is not known
+    Concealed,
 }
 
-#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
+/// State assigned to a seal.
+///
+/// Assignments are ordered by their witness transaction ids, such that the
+/// state assigned by genesis and state extensions goes first, then by the ids
+/// of the nodes defining them and by output index within the node. Use
+/// [`ContractState::assignments_ordered`] for ordering by the height of the
+/// witness transactions.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(crate = "serde_crate"))]
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Display)]
 #[derive(StrictEncode, StrictDecode)]
 #[display("{state}@{seal}")]
 pub struct AssignedState<State>
 where State: StateAtom
 {
+    #[cfg_attr(feature = "serde", serde(with = "As::<DisplayFromStr>"))]
     pub seal: OutPoint,
     pub state: State,
     pub outpoint: NodeOutpoint,
+    /// Witness transaction of the state transition which created the state;
+    /// `None` for the state assigned by genesis and state extensions
+    pub witness: Option<Txid>,
+}
+
+impl<State> Ord for AssignedState<State>
+where State: StateAtom
+{
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.witness
+            .cmp(&other.witness)
+            .then_with(|| self.outpoint.node_id.cmp(&other.outpoint.node_id))
+            .then_with(|| self.outpoint.output_no.cmp(&other.outpoint.output_no))
+            .then_with(|| self.seal.cmp(&other.seal))
+            .then_with(|| self.state.cmp(&other.state))
+    }
+}
+
+impl<State> PartialOrd for AssignedState<State>
+where State: StateAtom
+{
+    #[inline]
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(self.cmp(other)) }
 }
 
 impl<State> AssignedState<State>
@@ -77,6 +344,7 @@ where State: StateAtom
             seal: seal.outpoint_or(witness_txid),
             state,
             outpoint: NodeOutpoint::new(node_id, no),
+            witness: Some(witness_txid),
         }
     }
 }
@@ -86,118 +354,3221 @@ pub type OwnedValue = AssignedState<AtomicValue>;
 pub type OwnedData = AssignedState<data::Revealed>;
 pub type OwnedAttachment = AssignedState<attachment::Revealed>;
 
-#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
+/// Node output of a specific owned right type. Outputs of a node are numbered
+/// separately for each of its owned right types, so the node output alone
+/// does not identify an assignment.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(crate = "serde_crate"))]
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
 #[derive(StrictEncode, StrictDecode)]
-pub struct ContractState {
-    pub contract_id: ContractId,
-    pub metadata: BTreeMap<FieldType, Vec<data::Revealed>>,
-    pub owned_rights: BTreeMap<OwnedRightType, Vec<OwnedRight>>,
-    pub owned_values: BTreeMap<OwnedRightType, Vec<OwnedValue>>,
-    pub owned_data: BTreeMap<OwnedRightType, Vec<OwnedData>>,
-    pub owned_attachments: BTreeMap<OwnedRightType, Vec<OwnedAttachment>>,
+#[display("{node_id}/{ty}/{output_no}")]
+pub struct TypedOutpoint {
+    pub node_id: NodeId,
+    pub ty: OwnedRightType,
+    pub output_no: u16,
 }
 
-impl ContractState {
-    pub fn new(contract_id: ContractId) -> Self {
-        ContractState {
-            contract_id,
-            metadata: empty!(),
-            owned_rights: empty!(),
-            owned_values: empty!(),
-            owned_data: empty!(),
-            owned_attachments: empty!(),
+impl TypedOutpoint {
+    #[inline]
+    pub fn new(node_id: NodeId, ty: OwnedRightType, output_no: u16) -> Self {
+        TypedOutpoint {
+            node_id,
+            ty,
+            output_no,
         }
     }
 
-    pub fn extend(&mut self, txid: Txid, node: &impl Node) {
-        let node_id = node.node_id();
+    /// Constructs typed outpoint from the node output of the owned right type
+    #[inline]
+    pub fn with(outpoint: NodeOutpoint, ty: OwnedRightType) -> Self {
+        TypedOutpoint::new(outpoint.node_id, ty, outpoint.output_no)
+    }
 
-        for (ty, meta) in node.metadata() {
-            self.metadata
-                .entry(*ty)
-                .or_default()
-                .extend(meta.iter().cloned());
+    /// Returns node output without the owned right type
+    #[inline]
+    pub fn node_outpoint(&self) -> NodeOutpoint { NodeOutpoint::new(self.node_id, self.output_no) }
+}
+
+/// Errors happening when contract nodes are applied to the [`ContractState`]
+#[derive(Clone, PartialEq, Eq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum StateApplyError {
+    /// node {node_id} depends on nodes {missing:?} which are not known to the
+    /// contract state
+    UnknownParents {
+        node_id: NodeId,
+        missing: BTreeSet<NodeId>,
+    },
+
+    /// anchor does not correspond to the witness transaction {0}
+    WitnessMismatch(Txid),
+
+    /// anchor does not commit to the contract {0}
+    UnrelatedAnchor(ContractId),
+
+    /// state extension belongs to a different contract {0}
+    ContractMismatch(ContractId),
+
+    /// output {output} is already spent by node {spender}
+    DoubleSpend {
+        output: TypedOutpoint,
+        spender: NodeId,
+    },
+}
+
+/// Error indicating that the sum of fungible state does not fit into 64 bits
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default, Display, Error)]
+#[display("fungible state balance exceeds 2^64")]
+pub struct BalanceOverflow;
+
+/// Balance of the fungible state
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default, Display)]
+#[display("{visible} (+{concealed} concealed)")]
+pub struct Balance {
+    /// Sum of the revealed fungible state
+    pub visible: u64,
+
+    /// Number of assignments with concealed amount, which are not accounted
+    /// in the `visible` balance
+    pub concealed: usize,
+}
+
+impl Balance {
+    /// Detects whether the actual balance may be larger than the visible one
+    #[inline]
+    pub fn is_lower_bound(&self) -> bool { self.concealed > 0 }
+}
+
+/// Reference to an assignment of any state kind
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, From)]
+pub enum AssignmentRef<'state> {
+    #[from]
+    Right(&'state OwnedRight),
+
+    #[from]
+    Value(&'state OwnedValue),
+
+    #[from]
+    Data(&'state OwnedData),
+
+    #[from]
+    Attachment(&'state OwnedAttachment),
+
+    #[from]
+    Concealed(&'state ConcealedAssignment),
+}
+
+impl<'state> AssignmentRef<'state> {
+    /// Returns the node output defining the assignment
+    pub fn outpoint(self) -> NodeOutpoint {
+        match self {
+            AssignmentRef::Right(assigned) => assigned.outpoint,
+            AssignmentRef::Value(assigned) => assigned.outpoint,
+            AssignmentRef::Data(assigned) => assigned.outpoint,
+            AssignmentRef::Attachment(assigned) => assigned.outpoint,
+            AssignmentRef::Concealed(assignment) => assignment.outpoint,
         }
+    }
 
-        fn process<S: StateAtom>(
-            fields: &mut Vec<AssignedState<S>>,
-            assignments: &[Assignment<S::StateType>],
-            node_id: NodeId,
-            txid: Txid,
-        ) where
-            <S::StateType as State>::Confidential: Eq
-                + From<<<S::StateType as State>::Revealed as CommitConceal>::ConcealedCommitment>,
-        {
-            for (no, seal, state) in assignments
-                .iter()
-                .enumerate()
-                .filter_map(|(n, a)| a.to_revealed().map(|(seal, state)| (n, seal, state)))
-            {
-                let assigned_state =
-                    AssignedState::with(seal, txid, state.into(), node_id, no as u16);
-                fields.push(assigned_state);
+    /// Returns outpoint of the assignment seal, if the seal is known
+    pub fn seal(self) -> Option<OutPoint> {
+        match self {
+            AssignmentRef::Right(assigned) => Some(assigned.seal),
+            AssignmentRef::Value(assigned) => Some(assigned.seal),
+            AssignmentRef::Data(assigned) => Some(assigned.seal),
+            AssignmentRef::Attachment(assigned) => Some(assigned.seal),
+            AssignmentRef::Concealed(assignment) => assignment.seal,
+        }
+    }
+
+    /// Returns witness transaction of the state transition which created the
+    /// assignment
+    pub fn witness(self) -> Option<Txid> {
+        match self {
+            AssignmentRef::Right(assigned) => assigned.witness,
+            AssignmentRef::Value(assigned) => assigned.witness,
+            AssignmentRef::Data(assigned) => assigned.witness,
+            AssignmentRef::Attachment(assigned) => assigned.witness,
+            AssignmentRef::Concealed(assignment) => assignment.witness,
+        }
+    }
+
+    /// Returns kind of the assigned state
+    pub fn kind(self) -> StateKind {
+        match self {
+            AssignmentRef::Right(_) => StateKind::Declarative,
+            AssignmentRef::Value(_) => StateKind::Fungible,
+            AssignmentRef::Data(_) => StateKind::Data,
+            AssignmentRef::Attachment(_) => StateKind::Attachment,
+            AssignmentRef::Concealed(assignment) => assignment.kind,
+        }
+    }
+
+    /// Returns value of the assigned state, which is concealed for the
+    /// assignments with unknown state
+    pub fn value(self) -> StateValue {
+        match self {
+            AssignmentRef::Right(assigned) => assigned.state.to_value(),
+            AssignmentRef::Value(assigned) => assigned.state.to_value(),
+            AssignmentRef::Data(assigned) => assigned.state.to_value(),
+            AssignmentRef::Attachment(assigned) => assigned.state.to_value(),
+            AssignmentRef::Concealed(assignment) => assignment
+                .state
+                .clone()
+                .unwrap_or(StateValue::Concealed(assignment.kind)),
+        }
+    }
+
+    /// Detects whether the assignment is concealed. Concealed assignments
+    /// with unknown seals may still have known state value, which is returned
+    /// by [`AssignmentRef::value`].
+    pub fn is_confidential(self) -> bool {
+        match self {
+            AssignmentRef::Right(assigned) => assigned.state.is_confidential(),
+            AssignmentRef::Value(assigned) => assigned.state.is_confidential(),
+            AssignmentRef::Data(assigned) => assigned.state.is_confidential(),
+            AssignmentRef::Attachment(assigned) => assigned.state.is_confidential(),
+            AssignmentRef::Concealed(_) => true,
+        }
+    }
+
+    /// Interprets numeric state as an unsigned 64-bit integer.
+    /// See [`StateAtom::as_u64`].
+    pub fn as_u64(self) -> Result<u64, StateConversionError> {
+        match self {
+            AssignmentRef::Right(assigned) => assigned.state.as_u64(),
+            AssignmentRef::Value(assigned) => assigned.state.as_u64(),
+            AssignmentRef::Data(assigned) => assigned.state.as_u64(),
+            AssignmentRef::Attachment(assigned) => assigned.state.as_u64(),
+            AssignmentRef::Concealed(assignment) => match assignment.state {
+                Some(StateValue::Fungible(amount)) => Ok(amount),
+                _ => assignment.concealed_as(&[StateKind::Fungible, StateKind::Data], "u64"),
+            },
+        }
+    }
+
+    /// Returns amount of the fungible state. See [`StateAtom::as_amount`].
+    pub fn as_amount(self) -> Result<u64, StateConversionError> {
+        match self {
+            AssignmentRef::Right(assigned) => assigned.state.as_amount(),
+            AssignmentRef::Value(assigned) => assigned.state.as_amount(),
+            AssignmentRef::Data(assigned) => assigned.state.as_amount(),
+            AssignmentRef::Attachment(assigned) => assigned.state.as_amount(),
+            AssignmentRef::Concealed(assignment) => match assignment.state {
+                Some(StateValue::Fungible(amount)) => Ok(amount),
+                _ => assignment.concealed_as(&[StateKind::Fungible], "amount"),
+            },
+        }
+    }
+
+    /// Returns binary representation of the data state.
+    /// See [`StateAtom::as_bytes`].
+    pub fn as_bytes(self) -> Result<&'state [u8], StateConversionError> {
+        match self {
+            AssignmentRef::Right(assigned) => assigned.state.as_bytes(),
+            AssignmentRef::Value(assigned) => assigned.state.as_bytes(),
+            AssignmentRef::Data(assigned) => assigned.state.as_bytes(),
+            AssignmentRef::Attachment(assigned) => assigned.state.as_bytes(),
+            AssignmentRef::Concealed(assignment) => {
+                assignment.concealed_as(&[StateKind::Data], "bytes")
             }
         }
+    }
 
-        for (ty, assignments) in node.owned_rights().iter() {
-            match assignments {
-                AssignmentVec::Declarative(assignments) => {
-                    let fields = self.owned_rights.entry(*ty).or_default();
-                    process(fields, assignments, node_id, txid)
-                }
-                AssignmentVec::Fungible(assignments) => {
-                    let fields = self.owned_values.entry(*ty).or_default();
-                    process(fields, assignments, node_id, txid)
-                }
-                AssignmentVec::NonFungible(assignments) => {
-                    let fields = self.owned_data.entry(*ty).or_default();
-                    process(fields, assignments, node_id, txid)
-                }
-                AssignmentVec::Attachment(assignments) => {
-                    let fields = self.owned_attachments.entry(*ty).or_default();
-                    process(fields, assignments, node_id, txid)
+    /// Returns data state as a string. See [`StateAtom::as_string`].
+    pub fn as_string(self) -> Result<&'state str, StateConversionError> {
+        match self {
+            AssignmentRef::Right(assigned) => assigned.state.as_string(),
+            AssignmentRef::Value(assigned) => assigned.state.as_string(),
+            AssignmentRef::Data(assigned) => assigned.state.as_string(),
+            AssignmentRef::Attachment(assigned) => assigned.state.as_string(),
+            AssignmentRef::Concealed(assignment) => {
+                assignment.concealed_as(&[StateKind::Data], "string")
+            }
+        }
+    }
+}
+
+impl Display for AssignmentRef<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            AssignmentRef::Right(assigned) => Display::fmt(assigned, f),
+            AssignmentRef::Value(assigned) => Display::fmt(assigned, f),
+            AssignmentRef::Data(assigned) => Display::fmt(assigned, f),
+            AssignmentRef::Attachment(assigned) => Display::fmt(assigned, f),
+            AssignmentRef::Concealed(assignment) => {
+                f.write_str("~confidential~")?;
+                if let Some(seal) = assignment.seal {
+                    write!(f, "@{}", seal)?;
                 }
+                Ok(())
             }
         }
     }
+}
 
-    pub fn metadata(&self, ty: FieldType) -> slice::Iter<data::Revealed> {
-        self.metadata
-            .get(&ty)
-            .map(Vec::deref)
-            .map(<[_]>::iter)
-            .unwrap_or_else(|| [].iter())
+/// Kind of the state assigned to a seal
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate", rename_all = "lowercase")
+)]
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
+#[derive(StrictEncode, StrictDecode)]
+#[strict_encoding(by_value, repr = u8)]
+#[display(lowercase)]
+#[repr(u8)]
+pub enum StateKind {
+    Declarative = 0,
+    Fungible = 1,
+    Data = 2,
+    Attachment = 3,
+}
+
+/// Assignment whose state or seal is not known to the contract state owner
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(crate = "serde_crate"))]
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
+#[derive(StrictEncode, StrictDecode)]
+pub struct ConcealedAssignment {
+    pub kind: StateKind,
+    /// Seal of the assignment, if it is revealed
+    #[cfg_attr(feature = "serde", serde(with = "As::<Option<DisplayFromStr>>"))]
+    pub seal: Option<OutPoint>,
+    /// Value of the assigned state, if it is revealed. Only the assignments
+    /// with unknown seals may have it, for instance the ones with concealed
+    /// seals or the seals of genesis and state extensions which do not
+    /// specify the transaction id.
+    pub state: Option<StateValue>,
+    pub outpoint: NodeOutpoint,
+    /// Witness transaction of the state transition which created the
+    /// assignment; `None` for genesis and state extensions
+    pub witness: Option<Txid>,
+}
+
+impl ConcealedAssignment {
+    /// Reports the reason why the concealed state can't be converted: either
+    /// its kind is not one of the `compatible` kinds, or the value is unknown
+    fn concealed_as<T>(
+        &self,
+        compatible: &[StateKind],
+        target: &'static str,
+    ) -> Result<T, StateConversionError> {
+        if compatible.contains(&self.kind) {
+            Err(StateConversionError::Concealed)
+        } else {
+            Err(StateConversionError::WrongKind(self.kind, target))
+        }
     }
+}
 
-    pub fn owned_rights(&self, ty: OwnedRightType) -> slice::Iter<OwnedRight> {
-        self.owned_rights
-            .get(&ty)
-            .map(Vec::deref)
-            .map(<[_]>::iter)
-            .unwrap_or_else(|| [].iter())
+/// Contract node which was applied to the [`ContractState`]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(crate = "serde_crate"))]
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
+#[derive(StrictEncode, StrictDecode)]
+pub struct AppliedNode {
+    /// Witness transaction of the state transition; `None` for genesis and
+    /// state extensions
+    pub witness: Option<Txid>,
+
+    /// Nodes whose owned or public rights were used by this node
+    pub parents: BTreeSet<NodeId>,
+
+    /// Applied nodes which use owned or public rights of this node
+    pub children: BTreeSet<NodeId>,
+
+    /// Metadata defined by the node
+    pub metadata: BTreeMap<FieldType, Vec<data::Revealed>>,
+}
+
+/// Report on the state rolled back with [`ContractState::rollback_witness`]
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct RollbackReport {
+    /// State transitions anchored to the rolled back witness transaction
+    pub rolled_back: BTreeSet<NodeId>,
+
+    /// Nodes which were rolled back since they depended on the state created
+    /// by the rolled back transitions
+    pub cascaded: BTreeSet<NodeId>,
+}
+
+impl RollbackReport {
+    /// Detects whether no state was rolled back
+    #[inline]
+    pub fn is_empty(&self) -> bool { self.rolled_back.is_empty() }
+
+    /// Detects whether rollback affected nodes not anchored to the witness
+    /// transaction
+    #[inline]
+    pub fn is_cascading(&self) -> bool { !self.cascaded.is_empty() }
+
+    /// Returns ids of all rolled back nodes
+    pub fn affected(&self) -> BTreeSet<NodeId> {
+        self.rolled_back.union(&self.cascaded).copied().collect()
     }
+}
 
-    pub fn owned_values(&self, ty: OwnedRightType) -> slice::Iter<OwnedValue> {
-        self.owned_values
-            .get(&ty)
-            .map(Vec::deref)
-            .map(<[_]>::iter)
-            .unwrap_or_else(|| [].iter())
+/// Difference between two contract states, see [`ContractState::diff`].
+/// Assignments are identified by the node output and owned right type
+/// defining them.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct StateDiff {
+    /// Assignments known only to the other state
+    pub added: BTreeSet<TypedOutpoint>,
+
+    /// Assignments known only to this state
+    pub removed: BTreeSet<TypedOutpoint>,
+
+    /// Assignments which are known to both states and differ only in which
+    /// parts of them (state value and seal) are concealed
+    pub concealment: BTreeSet<TypedOutpoint>,
+
+    /// Assignments which are known to both states but have conflicting
+    /// state kind, value or seal
+    pub conflicts: BTreeSet<TypedOutpoint>,
+
+    /// Types of the metadata fields whose values differ
+    pub metadata: BTreeSet<FieldType>,
+
+    /// Nodes applied only to the other state
+    pub added_nodes: BTreeSet<NodeId>,
+
+    /// Nodes applied only to this state
+    pub removed_nodes: BTreeSet<NodeId>,
+}
+
+impl StateDiff {
+    /// Detects whether both states are identical
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.concealment.is_empty()
+            && self.conflicts.is_empty()
+            && self.metadata.is_empty()
+            && self.added_nodes.is_empty()
+            && self.removed_nodes.is_empty()
     }
 
-    pub fn owned_data(&self, ty: OwnedRightType) -> slice::Iter<OwnedData> {
-        self.owned_data
-            .get(&ty)
-            .map(Vec::deref)
-            .map(<[_]>::iter)
-            .unwrap_or_else(|| [].iter())
+    /// Detects whether the states contain contradicting information, i.e.
+    /// the difference can't be resolved by revealing or concealing state
+    #[inline]
+    pub fn has_conflicts(&self) -> bool { !self.conflicts.is_empty() || !self.metadata.is_empty() }
+}
+
+impl Display for StateDiff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return f.write_str("states are identical\n");
+        }
+        fn section<T: Display>(
+            f: &mut Formatter<'_>,
+            title: &str,
+            items: &BTreeSet<T>,
+        ) -> fmt::Result {
+            if items.is_empty() {
+                return Ok(());
+            }
+            writeln!(f, "{}:", title)?;
+            for item in items {
+                writeln!(f, "  {}", item)?;
+            }
+            Ok(())
+        }
+        section(f, "added nodes", &self.added_nodes)?;
+        section(f, "removed nodes", &self.removed_nodes)?;
+        section(f, "added assignments", &self.added)?;
+        section(f, "removed assignments", &self.removed)?;
+        section(f, "conflicting assignments", &self.conflicts)?;
+        section(f, "assignments differing in concealment", &self.concealment)?;
+        section(f, "differing metadata fields", &self.metadata)
     }
+}
 
-    pub fn owned_attachments(&self, ty: OwnedRightType) -> slice::Iter<OwnedAttachment> {
-        self.owned_attachments
-            .get(&ty)
-            .map(Vec::deref)
-            .map(<[_]>::iter)
-            .unwrap_or_else(|| [].iter())
+/// Inconsistency between the contract state and its schema, detected by
+/// [`ContractState::check_schema`]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum SchemaViolation {
+    /// node {node_id} assigns state of owned right type {ty}, which is not
+    /// defined by the schema
+    UnknownOwnedRight { node_id: NodeId, ty: OwnedRightType },
+
+    /// node {node_id} assigns {found} state to owned right type {ty}, while
+    /// the schema requires {expected} state
+    StateKindMismatch {
+        node_id: NodeId,
+        ty: OwnedRightType,
+        expected: StateKind,
+        found: StateKind,
+    },
+
+    /// node {node_id} defines metadata field of type {ty}, which is not
+    /// defined by the schema
+    UnknownField { node_id: NodeId, ty: FieldType },
+
+    /// node {node_id} defines metadata field of type {ty} with a value not
+    /// matching the data format required by the schema
+    FieldFormatMismatch { node_id: NodeId, ty: FieldType },
+
+    /// genesis {node_id} defines {count} values of metadata field {ty},
+    /// which violates the number of occurrences required by the schema
+    FieldOccurrences {
+        node_id: NodeId,
+        ty: FieldType,
+        count: usize,
+    },
+    /// node {node_id} is listed in the contract state history, but the
+    /// contract state has no data for it
+    UnknownNode { node_id: NodeId },
+}
+
+impl StateKind {
+    /// Returns kind of the state defined by the schema for an owned right
+    /// type
+    pub fn with_schema(schema: &StateSchema) -> StateKind {
+        match schema {
+            StateSchema::Declarative => StateKind::Declarative,
+            StateSchema::DiscreteFiniteField(_) => StateKind::Fungible,
+            StateSchema::CustomData(..) => StateKind::Data,
+            StateSchema::DataContainer => StateKind::Attachment,
+        }
+    }
+}
+
+/// Detects whether the primitive type of a metadata value matches the data
+/// format. Only the primitive type is checked; formats without a direct
+/// primitive counterpart are not checked at all.
+fn matches_format(value: &data::Revealed, format: &DataFormat) -> bool {
+    match format {
+        DataFormat::Unsigned(..) => matches!(
+            value,
+            data::Revealed::U8(_)
+                | data::Revealed::U16(_)
+                | data::Revealed::U32(_)
+                | data::Revealed::U64(_)
+                | data::Revealed::U128(_)
+        ),
+        DataFormat::Integer(..) => matches!(
+            value,
+            data::Revealed::I8(_)
+                | data::Revealed::I16(_)
+                | data::Revealed::I32(_)
+                | data::Revealed::I64(_)
+                | data::Revealed::I128(_)
+        ),
+        DataFormat::Float(..) => {
+            matches!(value, data::Revealed::F32(_) | data::Revealed::F64(_))
+        }
+        DataFormat::String(_) => matches!(value, data::Revealed::String(_)),
+        DataFormat::Bytes(_) => matches!(value, data::Revealed::Bytes(_)),
+        _ => true,
+    }
+}
+
+/// Result of comparing two versions of the same assignment
+enum AssignmentCmp {
+    Equal,
+    Concealment,
+    Conflict,
+}
+
+impl AssignmentCmp {
+    /// Compares assignments defined by the same node output. Concealed
+    /// assignment records do not keep state commitments, so revealed and
+    /// concealed versions of the assignment are matched by the state kind,
+    /// and by the seal and state value when they are not concealed.
+    fn with(left: AssignmentRef, right: AssignmentRef) -> Self {
+        let seals_match = match (left.seal(), right.seal()) {
+            (Some(left), Some(right)) => left == right,
+            _ => true,
+        };
+        let (left_value, right_value) = (left.value(), right.value());
+        let values_match = left_value.is_confidential()
+            || right_value.is_confidential()
+            || left_value == right_value;
+        if left == right {
+            AssignmentCmp::Equal
+        } else if left.kind() != right.kind() || !seals_match || !values_match {
+            AssignmentCmp::Conflict
+        } else if left.is_confidential() || right.is_confidential() {
+            AssignmentCmp::Concealment
+        } else {
+            AssignmentCmp::Conflict
+        }
+    }
+}
+
+// "rgb:state"
+static MIDSTATE_STATE_ID: [u8; 32] = [
+    194, 124, 53, 198, 228, 171, 107, 252, 216, 31, 25, 197, 204, 55, 191, 253, 201, 31, 26, 150,
+    100, 222, 0, 246, 188, 88, 107, 245, 232, 153, 98, 223,
+];
+
+/// Tag used for [`StateId`] hash types
+pub struct StateIdTag;
+
+impl sha256t::Tag for StateIdTag {
+    #[inline]
+    fn engine() -> sha256::HashEngine {
+        let midstate = sha256::Midstate::from_inner(MIDSTATE_STATE_ID);
+        sha256::HashEngine::from_midstate(midstate, 64)
+    }
+}
+
+/// Content-addressed identifier of the [`ContractState`], equivalent to the
+/// commitment hash
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(crate = "serde_crate"))]
+#[derive(Wrapper, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Display, From)]
+#[derive(StrictEncode, StrictDecode)]
+#[wrapper(LowerHex, BorrowSlice)]
+#[display(StateId::to_bech32_string)]
+pub struct StateId(sha256t::Hash<StateIdTag>);
+
+impl<Msg> CommitVerify<Msg, PrehashedProtocol> for StateId
+where Msg: AsRef<[u8]>
+{
+    #[inline]
+    fn commit(msg: &Msg) -> StateId { StateId::hash(msg) }
+}
+
+impl commit_encode::Strategy for StateId {
+    type Strategy = commit_encode::strategies::UsingStrict;
+}
+
+impl lnpbp_bech32::Strategy for StateId {
+    const HRP: &'static str = "id";
+    type Strategy = lnpbp_bech32::strategies::UsingStrictEncoding;
+}
+
+impl str::FromStr for StateId {
+    type Err = lnpbp_bech32::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> { StateId::from_bech32_str(s) }
+}
+
+/// Magic bytes starting the versioned strict encoding of the [`ContractState`].
+/// The encoding of version 0 did not have them and started directly with the
+/// contract id; the chance of a contract id starting with the same bytes is
+/// negligible.
+const CONTRACT_STATE_MAGIC: [u8; 8] = *b"RGBSTATE";
+
+/// Current version of the [`ContractState`] strict encoding. Version 1 adds
+/// concealed assignments, applied nodes with their history, witness index,
+/// spent outputs and seal commitments, as well as witness transactions of
+/// the assigned state. Contract states of version 0 are still decoded, with
+/// this data left empty.
+///
+/// Version 2 keys spent outputs and seal commitments by the owned right type
+/// and keeps known state values of the concealed assignments. In the states
+/// of version 1 these records are attributed to all owned right types
+/// assigning state to the same node output, and records for the outputs
+/// without known assignments are dropped.
+pub const CONTRACT_STATE_VERSION: u8 = 2;
+
+/// Layout of the [`AssignedState`] in the contract state encoding of version
+/// 0, which did not keep witness transactions
+#[derive(Clone, StrictEncode, StrictDecode)]
+struct LegacyAssignedState<State>
+where State: StateAtom
+{
+    seal: OutPoint,
+    state: State,
+    outpoint: NodeOutpoint,
+}
+
+impl<State> From<LegacyAssignedState<State>> for AssignedState<State>
+where State: StateAtom
+{
+    fn from(legacy: LegacyAssignedState<State>) -> Self {
+        AssignedState {
+            seal: legacy.seal,
+            state: legacy.state,
+            outpoint: legacy.outpoint,
+            witness: None,
+        }
+    }
+}
+
+/// Layout of the [`ConcealedAssignment`] in the contract state encoding of
+/// version 1, which did not keep known state values
+#[derive(Clone, StrictEncode, StrictDecode)]
+struct LegacyConcealedAssignment {
+    kind: StateKind,
+    seal: Option<OutPoint>,
+    outpoint: NodeOutpoint,
+    witness: Option<Txid>,
+}
+
+impl From<LegacyConcealedAssignment> for ConcealedAssignment {
+    fn from(legacy: LegacyConcealedAssignment) -> Self {
+        ConcealedAssignment {
+            kind: legacy.kind,
+            seal: legacy.seal,
+            state: None,
+            outpoint: legacy.outpoint,
+            witness: legacy.witness,
+        }
+    }
+}
+
+/// Decodes assignments from the contract state encoding of version 0
+fn decode_legacy_assignments<State>(
+    d: impl io::Read,
+) -> Result<BTreeMap<OwnedRightType, Vec<AssignedState<State>>>, strict_encoding::Error>
+where State: StateAtom {
+    let legacy = BTreeMap::<OwnedRightType, Vec<LegacyAssignedState<State>>>::strict_decode(d)?;
+    Ok(legacy
+        .into_iter()
+        .map(|(ty, items)| (ty, items.into_iter().map(AssignedState::from).collect()))
+        .collect())
+}
+
+/// Decodes concealed assignments from the contract state encoding of version 1
+fn decode_legacy_concealed(
+    d: impl io::Read,
+) -> Result<BTreeMap<OwnedRightType, Vec<ConcealedAssignment>>, strict_encoding::Error> {
+    let legacy = BTreeMap::<OwnedRightType, Vec<LegacyConcealedAssignment>>::strict_decode(d)?;
+    Ok(legacy
+        .into_iter()
+        .map(|(ty, items)| (ty, items.into_iter().map(ConcealedAssignment::from).collect()))
+        .collect())
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(crate = "serde_crate"))]
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
+pub struct ContractState {
+    #[cfg_attr(feature = "serde", serde(with = "As::<DisplayFromStr>"))]
+    pub contract_id: ContractId,
+    pub metadata: BTreeMap<FieldType, Vec<data::Revealed>>,
+    /// Revealed declarative assignments. Here and below, assignments are
+    /// serialized in their sort order
+    #[cfg_attr(feature = "serde", serde(with = "serde_ordered"))]
+    pub owned_rights: BTreeMap<OwnedRightType, Vec<OwnedRight>>,
+    #[cfg_attr(feature = "serde", serde(with = "serde_ordered"))]
+    pub owned_values: BTreeMap<OwnedRightType, Vec<OwnedValue>>,
+    #[cfg_attr(feature = "serde", serde(with = "serde_ordered"))]
+    pub owned_data: BTreeMap<OwnedRightType, Vec<OwnedData>>,
+    #[cfg_attr(feature = "serde", serde(with = "serde_ordered"))]
+    pub owned_attachments: BTreeMap<OwnedRightType, Vec<OwnedAttachment>>,
+    /// Assignments with concealed state
+    pub concealed: BTreeMap<OwnedRightType, Vec<ConcealedAssignment>>,
+    /// Nodes applied to the state
+    pub nodes: BTreeMap<NodeId, AppliedNode>,
+    /// Ids of the applied nodes in the order of their application
+    pub history: Vec<NodeId>,
+    /// Index of the applied state transitions by their witness transactions
+    pub witness_index: BTreeMap<Txid, BTreeSet<NodeId>>,
+    /// Assignments which were spent by state transitions, with the ids of the
+    /// spending transitions
+    #[cfg_attr(feature = "serde", serde(with = "As::<Vec<(Same, Same)>>"))]
+    pub spent: BTreeMap<TypedOutpoint, NodeId>,
+    /// Confidential forms of the seals of all known assignments, used for
+    /// matching assignments against the lists of concealed seals
+    #[cfg_attr(feature = "serde", serde(with = "As::<Vec<(Same, Same)>>"))]
+    pub seal_commitments: BTreeMap<TypedOutpoint, seal::Confidential>,
+}
+
+impl StrictEncode for ContractState {
+    fn strict_encode<E: io::Write>(&self, mut e: E) -> Result<usize, strict_encoding::Error> {
+        e.write_all(&CONTRACT_STATE_MAGIC)?;
+        Ok(CONTRACT_STATE_MAGIC.len()
+            + strict_encode_list!(e;
+                CONTRACT_STATE_VERSION,
+                self.contract_id,
+                self.metadata,
+                self.owned_rights,
+                self.owned_values,
+                self.owned_data,
+                self.owned_attachments,
+                self.concealed,
+                self.nodes,
+                self.history,
+                self.witness_index,
+                self.spent,
+                self.seal_commitments
+            ))
+    }
+}
+
+impl StrictDecode for ContractState {
+    fn strict_decode<D: io::Read>(mut d: D) -> Result<Self, strict_encoding::Error> {
+        let mut prefix = [0u8; 32];
+        let (magic, rest) = prefix.split_at_mut(CONTRACT_STATE_MAGIC.len());
+        d.read_exact(magic)?;
+        if magic != CONTRACT_STATE_MAGIC {
+            // Encoding of version 0 starts with the contract id
+            d.read_exact(rest)?;
+            let contract_id = ContractId::strict_decode(&prefix[..])?;
+            return Ok(ContractState {
+                contract_id,
+                metadata: StrictDecode::strict_decode(&mut d)?,
+                owned_rights: decode_legacy_assignments(&mut d)?,
+                owned_values: decode_legacy_assignments(&mut d)?,
+                owned_data: decode_legacy_assignments(&mut d)?,
+                owned_attachments: decode_legacy_assignments(&mut d)?,
+                ..ContractState::new(contract_id)
+            });
+        }
+
+        let version = u8::strict_decode(&mut d)?;
+        if version == 0 || version > CONTRACT_STATE_VERSION {
+            return Err(strict_encoding::Error::UnsupportedDataStructure(
+                "Contract state encoding version is not supported",
+            ));
+        }
+        if version == 1 {
+            let contract_id = ContractId::strict_decode(&mut d)?;
+            let mut state = ContractState {
+                contract_id,
+                metadata: StrictDecode::strict_decode(&mut d)?,
+                owned_rights: StrictDecode::strict_decode(&mut d)?,
+                owned_values: StrictDecode::strict_decode(&mut d)?,
+                owned_data: StrictDecode::strict_decode(&mut d)?,
+                owned_attachments: StrictDecode::strict_decode(&mut d)?,
+                concealed: decode_legacy_concealed(&mut d)?,
+                nodes: StrictDecode::strict_decode(&mut d)?,
+                history: StrictDecode::strict_decode(&mut d)?,
+                witness_index: StrictDecode::strict_decode(&mut d)?,
+                ..ContractState::new(contract_id)
+            };
+            let spent = BTreeMap::<NodeOutpoint, NodeId>::strict_decode(&mut d)?;
+            let seal_commitments = BTreeMap::<NodeOutpoint, seal::Confidential>::strict_decode(d)?;
+            state.spent = state.type_legacy_outputs(spent);
+            state.seal_commitments = state.type_legacy_outputs(seal_commitments);
+            return Ok(state);
+        }
+        Ok(ContractState {
+            contract_id: StrictDecode::strict_decode(&mut d)?,
+            metadata: StrictDecode::strict_decode(&mut d)?,
+            owned_rights: StrictDecode::strict_decode(&mut d)?,
+            owned_values: StrictDecode::strict_decode(&mut d)?,
+            owned_data: StrictDecode::strict_decode(&mut d)?,
+            owned_attachments: StrictDecode::strict_decode(&mut d)?,
+            concealed: StrictDecode::strict_decode(&mut d)?,
+            nodes: StrictDecode::strict_decode(&mut d)?,
+            history: StrictDecode::strict_decode(&mut d)?,
+            witness_index: StrictDecode::strict_decode(&mut d)?,
+            spent: StrictDecode::strict_decode(&mut d)?,
+            seal_commitments: StrictDecode::strict_decode(&mut d)?,
+        })
+    }
+}
+
+impl CommitEncode for ContractState {
+    fn commit_encode<E: io::Write>(&self, mut e: E) -> usize {
+        // Canonical form of the state, which does not depend on the order in
+        // which the nodes were applied:
+        // 1. Lists of metadata values and assignments are sorted;
+        // 2. Application history and witness index are not included, since
+        //    they are either order-dependent or derived from the nodes;
+        // 3. Concealed and revealed assignments are committed separately, so
+        //    concealing any state changes the commitment.
+        fn sorted<T: Ord + Clone>(map: &BTreeMap<u16, Vec<T>>) -> BTreeMap<u16, Vec<T>> {
+            map.iter()
+                .map(|(ty, items)| {
+                    let mut items = items.clone();
+                    items.sort();
+                    (*ty, items)
+                })
+                .collect()
+        }
+
+        let metadata = sorted(&self.metadata);
+        let owned_rights = sorted(&self.owned_rights);
+        let owned_values = sorted(&self.owned_values);
+        let owned_data = sorted(&self.owned_data);
+        let owned_attachments = sorted(&self.owned_attachments);
+        let concealed = sorted(&self.concealed);
+        (|| -> Result<usize, strict_encoding::Error> {
+            Ok(strict_encode_list!(e;
+                self.contract_id,
+                metadata,
+                owned_rights,
+                owned_values,
+                owned_data,
+                owned_attachments,
+                concealed,
+                self.nodes,
+                self.spent,
+                self.seal_commitments
+            ))
+        })()
+        .expect("Commit encoding is in-memory encoding and must not fail")
+    }
+}
+
+impl ConsensusCommit for ContractState {
+    type Commitment = StateId;
+}
+
+/// Serialization of the assignment lists in the sort order of the
+/// assignments, which does not depend on the order of node application
+#[cfg(feature = "serde")]
+mod serde_ordered {
+    use std::collections::BTreeMap;
+
+    use rgb_core::schema::OwnedRightType;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S, T>(
+        map: &BTreeMap<OwnedRightType, Vec<T>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        T: Ord + Serialize,
+    {
+        let mut ordered = BTreeMap::<OwnedRightType, Vec<&T>>::new();
+        for (ty, items) in map {
+            let mut items = items.iter().collect::<Vec<_>>();
+            items.sort();
+            ordered.insert(*ty, items);
+        }
+        ordered.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D, T>(
+        deserializer: D,
+    ) -> Result<BTreeMap<OwnedRightType, Vec<T>>, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: Deserialize<'de>,
+    {
+        BTreeMap::deserialize(deserializer)
+    }
+}
+
+impl Display for ContractState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        fn unspent<'state, T>(
+            state: &'state ContractState,
+            map: &'state BTreeMap<OwnedRightType, Vec<AssignedState<T>>>,
+        ) -> impl Iterator<Item = (OwnedRightType, &'state AssignedState<T>)>
+        where T: StateAtom {
+            let mut assignments = map
+                .iter()
+                .flat_map(|(ty, items)| items.iter().map(move |assigned| (*ty, assigned)))
+                .filter(|(ty, assigned)| {
+                    !state.is_spent(&TypedOutpoint::with(assigned.outpoint, *ty))
+                })
+                .collect::<Vec<_>>();
+            assignments.sort_by_key(|(_, assigned)| *assigned);
+            assignments.into_iter()
+        }
+
+        fn section(
+            f: &mut Formatter<'_>,
+            title: &str,
+            items: BTreeMap<OutPoint, Vec<String>>,
+        ) -> fmt::Result {
+            if items.is_empty() {
+                return Ok(());
+            }
+            writeln!(f, "{}:", title)?;
+            for (outpoint, lines) in items {
+                writeln!(f, "  {}", outpoint)?;
+                for line in lines {
+                    writeln!(f, "    {}", line)?;
+                }
+            }
+            Ok(())
+        }
+
+        writeln!(f, "contract {}", self.contract_id)?;
+
+        if !self.metadata.is_empty() {
+            writeln!(f, "metadata:")?;
+            for (ty, values) in &self.metadata {
+                let values = values
+                    .iter()
+                    .map(data::Revealed::to_string)
+                    .collect::<Vec<_>>();
+                writeln!(f, "  #{}: {}", ty, values.join(", "))?;
+            }
+        }
+
+        let mut assignments = BTreeMap::<OutPoint, Vec<String>>::new();
+        for (ty, assigned) in unspent(self, &self.owned_values) {
+            let line = format!("#{} amount {}", ty, assigned.state.value);
+            assignments.entry(assigned.seal).or_default().push(line);
+        }
+        for (ty, assigned) in unspent(self, &self.owned_data) {
+            let line = format!("#{} data {}", ty, assigned.state);
+            assignments.entry(assigned.seal).or_default().push(line);
+        }
+        for (ty, assigned) in unspent(self, &self.owned_attachments) {
+            let attachment = &assigned.state;
+            let line = format!("#{} attachment {} ({})", ty, attachment.id, attachment.mime);
+            assignments.entry(assigned.seal).or_default().push(line);
+        }
+        section(f, "assignments", assignments)?;
+
+        let mut rights = BTreeMap::<OutPoint, Vec<String>>::new();
+        for (ty, assigned) in unspent(self, &self.owned_rights) {
+            let line = format!("#{}", ty);
+            rights.entry(assigned.seal).or_default().push(line);
+        }
+        section(f, "rights", rights)?;
+
+        let concealed = self.concealed.values().map(Vec::len).sum::<usize>();
+        writeln!(f, "concealed: {}", concealed)
+    }
+}
+
+impl ContractState {
+    pub fn new(contract_id: ContractId) -> Self {
+        ContractState {
+            contract_id,
+            metadata: empty!(),
+            owned_rights: empty!(),
+            owned_values: empty!(),
+            owned_data: empty!(),
+            owned_attachments: empty!(),
+            concealed: empty!(),
+            nodes: empty!(),
+            history: empty!(),
+            witness_index: empty!(),
+            spent: empty!(),
+            seal_commitments: empty!(),
+        }
+    }
+
+    /// Returns content-addressed identifier of the state. Logically equal
+    /// states have the same id regardless of the order of node application,
+    /// while concealing any part of the state changes the id.
+    #[inline]
+    pub fn state_id(&self) -> StateId { self.clone().consensus_commit() }
+
+    /// Constructs contract state containing the state assigned by the
+    /// contract genesis
+    pub fn with_genesis(genesis: &Genesis) -> Self {
+        let mut state = ContractState::new(genesis.contract_id());
+        state
+            .apply_node(genesis, None)
+            .expect("genesis does not spend any outputs");
+        state
+    }
+
+    /// Adds state from a node without checking that the node parents are
+    /// known. Seals of the node assignments which do not specify a
+    /// transaction are resolved to the `txid`.
+    ///
+    /// Fails if the node spends an output already spent by another node,
+    /// leaving the state untouched.
+    pub fn extend(&mut self, txid: Txid, node: &impl Node) -> Result<(), StateApplyError> {
+        self.apply_node(node, Some(txid))
+    }
+
+    /// Adds state from a node without checking that the node parents are
+    /// known, like [`ContractState::extend`], for the nodes which may have no
+    /// witness transaction
+    pub(crate) fn extend_node(
+        &mut self,
+        node: &impl Node,
+        witness: Option<Txid>,
+    ) -> Result<(), StateApplyError> {
+        self.apply_node(node, witness)
+    }
+
+    /// Applies state transition to the contract state, marking the parent
+    /// assignments as spent. All parent nodes must be already applied;
+    /// applying already known transition does nothing.
+    pub fn apply_transition(
+        &mut self,
+        transition: &Transition,
+        anchor: &Anchor<lnpbp4::MerkleBlock>,
+        witness_txid: Txid,
+    ) -> Result<(), StateApplyError> {
+        if self.nodes.contains_key(&transition.node_id()) {
+            return Ok(());
+        }
+        if anchor.txid != witness_txid {
+            return Err(StateApplyError::WitnessMismatch(witness_txid));
+        }
+        anchor
+            .to_merkle_proof(self.contract_id)
+            .map_err(|_| StateApplyError::UnrelatedAnchor(self.contract_id))?;
+        self.check_parents(transition)?;
+        self.apply_node(transition, Some(witness_txid))
+    }
+
+    /// Applies state extension to the contract state. All parent nodes must be
+    /// already applied; applying already known extension does nothing.
+    ///
+    /// Seals assigned by state extensions must specify transaction id, since
+    /// extensions do not have a witness transaction; assignments to seals
+    /// without it are kept as concealed assignments with known state.
+    pub fn apply_extension(&mut self, extension: &Extension) -> Result<(), StateApplyError> {
+        if self.nodes.contains_key(&extension.node_id()) {
+            return Ok(());
+        }
+        if extension.contract_id() != self.contract_id {
+            return Err(StateApplyError::ContractMismatch(extension.contract_id()));
+        }
+        self.check_parents(extension)?;
+        self.apply_node(extension, None)
+    }
+
+    /// Returns ids of the nodes whose owned or public rights are used by the
+    /// `node`
+    pub(crate) fn node_parents(node: &impl Node) -> BTreeSet<NodeId> {
+        node.parent_outputs()
+            .into_iter()
+            .map(|output| output.node_id)
+            .chain(node.parent_public_rights().keys().copied())
+            .collect()
+    }
+
+    /// Checks that all the nodes whose rights are used by the `node` are
+    /// already applied to the state
+    pub(crate) fn check_parents(&self, node: &impl Node) -> Result<(), StateApplyError> {
+        let missing = ContractState::node_parents(node)
+            .into_iter()
+            .filter(|node_id| !self.nodes.contains_key(node_id))
+            .collect::<BTreeSet<_>>();
+        if !missing.is_empty() {
+            return Err(StateApplyError::UnknownParents {
+                node_id: node.node_id(),
+                missing,
+            });
+        }
+        Ok(())
+    }
+
+    /// Applies node to the state unless it is already known. All checks are
+    /// performed before the state is modified.
+    fn apply_node(
+        &mut self,
+        node: &impl Node,
+        witness: Option<Txid>,
+    ) -> Result<(), StateApplyError> {
+        let node_id = node.node_id();
+        if self.nodes.contains_key(&node_id) {
+            return Ok(());
+        }
+
+        let outputs = ContractState::spent_outputs(node);
+        for output in &outputs {
+            if let Some(spender) = self.spent.get(output) {
+                return Err(StateApplyError::DoubleSpend {
+                    output: *output,
+                    spender: *spender,
+                });
+            }
+        }
+        for output in outputs {
+            self.spent.insert(output, node_id);
+        }
+        let parents = ContractState::node_parents(node);
+        for parent_id in &parents {
+            if let Some(parent) = self.nodes.get_mut(parent_id) {
+                parent.children.insert(node_id);
+            }
+        }
+        let mut metadata = BTreeMap::<FieldType, Vec<data::Revealed>>::new();
+        for (ty, meta) in node.metadata() {
+            metadata
+                .entry(*ty)
+                .or_default()
+                .extend(meta.iter().cloned());
+            self.metadata
+                .entry(*ty)
+                .or_default()
+                .extend(meta.iter().cloned());
+        }
+        self.nodes.insert(node_id, AppliedNode {
+            witness,
+            parents,
+            children: empty!(),
+            metadata,
+        });
+        self.history.push(node_id);
+        if let Some(txid) = witness {
+            self.witness_index.entry(txid).or_default().insert(node_id);
+        }
+
+        fn process<S: StateAtom>(
+            fields: &mut Vec<AssignedState<S>>,
+            concealed: &mut Vec<ConcealedAssignment>,
+            seal_commitments: &mut BTreeMap<TypedOutpoint, seal::Confidential>,
+            ty: OwnedRightType,
+            assignments: &[Assignment<S::StateType>],
+            node_id: NodeId,
+            witness: Option<Txid>,
+        ) where
+            <S::StateType as State>::Confidential: Eq
+                + From<<<S::StateType as State>::Revealed as CommitConceal>::ConcealedCommitment>,
+        {
+            // Seals of genesis and state extensions can't be resolved without
+            // the transaction id; their assignments are kept concealed
+            let resolve = |seal: seal::Revealed| match witness {
+                Some(txid) => Some(seal.outpoint_or(txid)),
+                None => seal.outpoint(),
+            };
+            for (no, assignment) in assignments.iter().enumerate() {
+                let outpoint = NodeOutpoint::new(node_id, no as u16);
+                let typed = TypedOutpoint::with(outpoint, ty);
+                seal_commitments.insert(typed, assignment.to_confidential_seal());
+                let state = match assignment {
+                    Assignment::Revealed { assigned_state, .. }
+                    | Assignment::ConfidentialSeal { assigned_state, .. } => {
+                        Some(S::from(assigned_state.clone()))
+                    }
+                    _ => None,
+                };
+                match (assignment.revealed_seal().and_then(resolve), state) {
+                    (Some(seal), Some(state)) => fields.push(AssignedState {
+                        seal,
+                        state,
+                        outpoint,
+                        witness,
+                    }),
+                    (seal, state) => concealed.push(ConcealedAssignment {
+                        kind: S::KIND,
+                        seal,
+                        state: state.as_ref().map(S::to_value),
+                        outpoint,
+                        witness,
+                    }),
+                }
+            }
+        }
+
+        for (ty, assignments) in node.owned_rights().iter() {
+            let concealed = self.concealed.entry(*ty).or_default();
+            let commitments = &mut self.seal_commitments;
+            match assignments {
+                AssignmentVec::Declarative(assignments) => {
+                    let fields = self.owned_rights.entry(*ty).or_default();
+                    process(fields, concealed, commitments, *ty, assignments, node_id, witness)
+                }
+                AssignmentVec::Fungible(assignments) => {
+                    let fields = self.owned_values.entry(*ty).or_default();
+                    process(fields, concealed, commitments, *ty, assignments, node_id, witness)
+                }
+                AssignmentVec::NonFungible(assignments) => {
+                    let fields = self.owned_data.entry(*ty).or_default();
+                    process(fields, concealed, commitments, *ty, assignments, node_id, witness)
+                }
+                AssignmentVec::Attachment(assignments) => {
+                    let fields = self.owned_attachments.entry(*ty).or_default();
+                    process(fields, concealed, commitments, *ty, assignments, node_id, witness)
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Attributes records keyed by the node outputs in the contract state
+    /// encoding of version 1 to all owned right types assigning state to the
+    /// same node output
+    fn type_legacy_outputs<T: Copy>(
+        &self,
+        legacy: BTreeMap<NodeOutpoint, T>,
+    ) -> BTreeMap<TypedOutpoint, T> {
+        self.assignments_typed()
+            .filter_map(|(ty, assignment)| {
+                let outpoint = assignment.outpoint();
+                let record = legacy.get(&outpoint)?;
+                Some((TypedOutpoint::with(outpoint, ty), *record))
+            })
+            .collect()
+    }
+
+    /// Returns node outputs of the owned rights spent by the `node`
+    pub(crate) fn spent_outputs(node: &impl Node) -> Vec<TypedOutpoint> {
+        node.parent_owned_rights()
+            .as_inner()
+            .iter()
+            .flat_map(|(node_id, types)| {
+                types.iter().flat_map(move |(ty, output_nos)| {
+                    output_nos
+                        .iter()
+                        .map(move |no| TypedOutpoint::new(*node_id, *ty, *no))
+                })
+            })
+            .collect()
+    }
+
+    /// Removes all state created by the state transitions anchored to the
+    /// witness transaction `txid` and, recursively, by all nodes depending on
+    /// them. Assignments spent by the removed transitions become unspent
+    /// again.
+    pub fn rollback_witness(&mut self, txid: Txid) -> RollbackReport {
+        let rolled_back = self.nodes_by_witness(txid);
+
+        let mut removed = BTreeSet::new();
+        let mut queue = rolled_back.iter().copied().collect::<Vec<_>>();
+        while let Some(node_id) = queue.pop() {
+            if !removed.insert(node_id) {
+                continue;
+            }
+            if let Some(node) = self.nodes.get(&node_id) {
+                queue.extend(node.children.iter().copied());
+            }
+        }
+
+        for node_id in &removed {
+            if let Some(node) = self.nodes.remove(node_id) {
+                if let Some(txid) = node.witness {
+                    if let Some(node_ids) = self.witness_index.get_mut(&txid) {
+                        node_ids.remove(node_id);
+                        if node_ids.is_empty() {
+                            self.witness_index.remove(&txid);
+                        }
+                    }
+                }
+                for parent_id in node.parents {
+                    if let Some(parent) = self.nodes.get_mut(&parent_id) {
+                        parent.children.remove(node_id);
+                    }
+                }
+            }
+        }
+        self.history.retain(|node_id| !removed.contains(node_id));
+        // Outputs of the removed nodes may be spent by nodes which were applied
+        // before their parents and thus are not known as their children; such
+        // spendings are kept
+        self.spent.retain(|_, spender| !removed.contains(spender));
+        self.seal_commitments
+            .retain(|outpoint, _| !removed.contains(&outpoint.node_id));
+
+        fn remove_nodes<T>(
+            map: &mut BTreeMap<OwnedRightType, Vec<T>>,
+            removed: &BTreeSet<NodeId>,
+            outpoint: impl Fn(&T) -> NodeOutpoint,
+        ) {
+            for items in map.values_mut() {
+                items.retain(|item| !removed.contains(&outpoint(item).node_id));
+            }
+        }
+        remove_nodes(&mut self.owned_rights, &removed, |a| a.outpoint);
+        remove_nodes(&mut self.owned_values, &removed, |a| a.outpoint);
+        remove_nodes(&mut self.owned_data, &removed, |a| a.outpoint);
+        remove_nodes(&mut self.owned_attachments, &removed, |a| a.outpoint);
+        remove_nodes(&mut self.concealed, &removed, |a| a.outpoint);
+
+        self.metadata = empty!();
+        let nodes = self
+            .history
+            .iter()
+            .filter_map(|node_id| self.nodes.get(node_id));
+        for node in nodes {
+            for (ty, meta) in &node.metadata {
+                self.metadata
+                    .entry(*ty)
+                    .or_default()
+                    .extend(meta.iter().cloned());
+            }
+        }
+
+        let cascaded = removed.difference(&rolled_back).copied().collect();
+        RollbackReport {
+            rolled_back,
+            cascaded,
+        }
+    }
+
+    /// Re-derives the contract state as of the block at `max_height`, using
+    /// the `heights` lookup to get the heights at which witness transactions
+    /// were mined. State transitions whose witness transactions are not mined
+    /// (the lookup returns `None`) or are mined above `max_height` are
+    /// treated as not applied, together with all nodes depending on them.
+    ///
+    /// Genesis and state extensions do not have witness transactions and are
+    /// always included, unless they depend on an excluded state transition.
+    pub fn snapshot_at(
+        &self,
+        max_height: u32,
+        heights: &impl Fn(Txid) -> Option<u32>,
+    ) -> ContractState {
+        let mut state = self.clone();
+        for txid in self.witness_index.keys() {
+            if !matches!(heights(*txid), Some(height) if height <= max_height) {
+                state.rollback_witness(*txid);
+            }
+        }
+        state
+    }
+
+    /// Returns ids of the state transitions anchored to the witness
+    /// transaction `txid`
+    pub fn nodes_by_witness(&self, txid: Txid) -> BTreeSet<NodeId> {
+        self.witness_index.get(&txid).cloned().unwrap_or_default()
+    }
+
+    /// Returns all assignments, including concealed ones, created by the
+    /// state transitions anchored to the witness transaction `txid`
+    pub fn assignments_by_witness(&self, txid: Txid) -> Vec<AssignmentRef> {
+        if !self.witness_index.contains_key(&txid) {
+            return vec![];
+        }
+        let witness = Some(txid);
+        self.assignments()
+            .filter(|assignment| assignment.witness() == witness)
+            .collect()
+    }
+
+    /// Compares this state with the `other` state of the same contract.
+    /// Both states may be produced from the same contract history by
+    /// different devices, such that the same assignment may be revealed in
+    /// one of them and concealed in the other; these cases are reported
+    /// separately from the conflicting assignments.
+    pub fn diff(&self, other: &ContractState) -> StateDiff {
+        let ours = self.typed_assignments();
+        let theirs = other.typed_assignments();
+
+        let mut diff = StateDiff {
+            added: theirs
+                .keys()
+                .filter(|outpoint| !ours.contains_key(outpoint))
+                .copied()
+                .collect(),
+            removed: ours
+                .keys()
+                .filter(|outpoint| !theirs.contains_key(outpoint))
+                .copied()
+                .collect(),
+            added_nodes: other
+                .nodes
+                .keys()
+                .filter(|node_id| !self.nodes.contains_key(node_id))
+                .copied()
+                .collect(),
+            removed_nodes: self
+                .nodes
+                .keys()
+                .filter(|node_id| !other.nodes.contains_key(node_id))
+                .copied()
+                .collect(),
+            ..StateDiff::default()
+        };
+
+        for (outpoint, assignment) in &ours {
+            let other_assignment = match theirs.get(outpoint) {
+                Some(assignment) => assignment,
+                None => continue,
+            };
+            match AssignmentCmp::with(*assignment, *other_assignment) {
+                AssignmentCmp::Equal => {}
+                AssignmentCmp::Concealment => {
+                    diff.concealment.insert(*outpoint);
+                }
+                AssignmentCmp::Conflict => {
+                    diff.conflicts.insert(*outpoint);
+                }
+            }
+        }
+
+        diff.metadata = self
+            .metadata
+            .keys()
+            .chain(other.metadata.keys())
+            .filter(|ty| self.metadata.get(ty) != other.metadata.get(ty))
+            .copied()
+            .collect();
+
+        diff
+    }
+
+    /// Checks that the state types and metadata fields of the contract state
+    /// are defined by the `schema` and that genesis metadata respects the
+    /// number of field occurrences required by the schema.
+    ///
+    /// This is a sanity check of the contract state and not a replacement
+    /// for the contract validation; in particular, it does not check the
+    /// state transition rules and does not access the bitcoin blockchain.
+    pub fn check_schema(&self, schema: &Schema) -> Vec<SchemaViolation> {
+        let mut violations = vec![];
+
+        for (ty, assignment) in self.assignments_typed() {
+            let node_id = assignment.outpoint().node_id;
+            match schema.owned_right_types.get(&ty) {
+                None => violations.push(SchemaViolation::UnknownOwnedRight { node_id, ty }),
+                Some(state_schema) => {
+                    let expected = StateKind::with_schema(state_schema);
+                    let found = assignment.kind();
+                    if expected != found {
+                        violations.push(SchemaViolation::StateKindMismatch {
+                            node_id,
+                            ty,
+                            expected,
+                            found,
+                        });
+                    }
+                }
+            }
+        }
+
+        for node_id in &self.history {
+            let node = match self.nodes.get(node_id) {
+                Some(node) => node,
+                None => {
+                    violations.push(SchemaViolation::UnknownNode { node_id: *node_id });
+                    continue;
+                }
+            };
+            for (ty, values) in &node.metadata {
+                let node_id = *node_id;
+                let ty = *ty;
+                match schema.field_types.get(&ty) {
+                    None => violations.push(SchemaViolation::UnknownField { node_id, ty }),
+                    Some(format) if !values.iter().all(|value| matches_format(value, format)) => {
+                        violations.push(SchemaViolation::FieldFormatMismatch { node_id, ty })
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+
+        let genesis = self.history.first().and_then(|node_id| {
+            self.nodes
+                .get(node_id)
+                .filter(|node| node.witness.is_none())
+                .map(|node| (node_id, node))
+        });
+        if let Some((node_id, node)) = genesis {
+            let metadata = &node.metadata;
+            for (ty, occurrences) in &schema.genesis.metadata {
+                let count = metadata.get(ty).map(Vec::len).unwrap_or_default();
+                if count < occurrences.min_value() as usize
+                    || count > occurrences.max_value() as usize
+                {
+                    violations.push(SchemaViolation::FieldOccurrences {
+                        node_id: *node_id,
+                        ty: *ty,
+                        count,
+                    });
+                }
+            }
+        }
+
+        violations
+    }
+
+    /// Reveals concealed assignments and seals of this state which are known
+    /// to the `other` state of the same contract, returning the number of
+    /// revealed items. This is an inverse of concealment with [`ConcealState`]
+    /// and [`ConcealSeals`] when the `other` is the original state.
+    pub fn merge_reveal(&mut self, other: &ContractState) -> usize {
+        let known = other.typed_assignments();
+        let mut count = 0usize;
+        for (ty, items) in self.concealed.iter_mut() {
+            let mut remaining = Vec::with_capacity(items.len());
+            for item in items.drain(..) {
+                let revealed = match known.get(&TypedOutpoint::with(item.outpoint, *ty)) {
+                    Some(revealed) => *revealed,
+                    None => {
+                        remaining.push(item);
+                        continue;
+                    }
+                };
+                match revealed {
+                    AssignmentRef::Right(assigned) if item.kind == StateKind::Declarative => {
+                        self.owned_rights.entry(*ty).or_default().push(assigned.clone())
+                    }
+                    AssignmentRef::Value(assigned) if item.kind == StateKind::Fungible => {
+                        self.owned_values.entry(*ty).or_default().push(assigned.clone())
+                    }
+                    AssignmentRef::Data(assigned) if item.kind == StateKind::Data => {
+                        self.owned_data.entry(*ty).or_default().push(assigned.clone())
+                    }
+                    AssignmentRef::Attachment(assigned) if item.kind == StateKind::Attachment => {
+                        self.owned_attachments
+                            .entry(*ty)
+                            .or_default()
+                            .push(assigned.clone())
+                    }
+                    AssignmentRef::Concealed(assignment)
+                        if assignment.kind == item.kind
+                            && ((item.seal.is_none() && assignment.seal.is_some())
+                                || (item.state.is_none() && assignment.state.is_some())) =>
+                    {
+                        remaining.push(ConcealedAssignment {
+                            seal: item.seal.or(assignment.seal),
+                            state: item.state.clone().or_else(|| assignment.state.clone()),
+                            ..item
+                        })
+                    }
+                    _ => {
+                        remaining.push(item);
+                        continue;
+                    }
+                }
+                count += 1;
+            }
+            *items = remaining;
+        }
+
+        if count > 0 {
+            // Restore the order in which assignments are added by the nodes
+            let positions = self
+                .history
+                .iter()
+                .enumerate()
+                .map(|(pos, node_id)| (*node_id, pos))
+                .collect::<BTreeMap<_, _>>();
+            let key = |outpoint: NodeOutpoint| {
+                let pos = positions.get(&outpoint.node_id).copied();
+                (pos.unwrap_or(usize::MAX), outpoint.output_no)
+            };
+            for items in self.owned_rights.values_mut() {
+                items.sort_by_key(|a| key(a.outpoint));
+            }
+            for items in self.owned_values.values_mut() {
+                items.sort_by_key(|a| key(a.outpoint));
+            }
+            for items in self.owned_data.values_mut() {
+                items.sort_by_key(|a| key(a.outpoint));
+            }
+            for items in self.owned_attachments.values_mut() {
+                items.sort_by_key(|a| key(a.outpoint));
+            }
+        }
+        count
+    }
+
+    /// Returns all assignments, including concealed ones, indexed by the
+    /// node output and owned right type defining them
+    pub(crate) fn typed_assignments(&self) -> BTreeMap<TypedOutpoint, AssignmentRef> {
+        self.assignments_typed()
+            .map(|(ty, assignment)| (TypedOutpoint::with(assignment.outpoint(), ty), assignment))
+            .collect()
+    }
+
+    /// Returns all assignments with revealed state ordered by the heights of
+    /// their witness transactions, taken from the `heights` lookup. State
+    /// assigned by genesis and state extensions goes first, and unconfirmed
+    /// assignments, for which the lookup returns `None`, go last. Assignments
+    /// with the same height are ordered as defined by [`AssignedState`].
+    pub fn assignments_ordered(
+        &self,
+        heights: &impl Fn(Txid) -> Option<u32>,
+    ) -> Vec<AssignmentRef> {
+        let mut assignments = self
+            .assignments()
+            .filter(|assignment| !assignment.is_confidential())
+            .collect::<Vec<_>>();
+        assignments.sort_by_cached_key(|assignment| {
+            let witness = assignment.witness();
+            let rank = match witness.map(heights) {
+                None => (0u8, 0u32),
+                Some(Some(height)) => (1, height),
+                Some(None) => (2, 0),
+            };
+            let outpoint = assignment.outpoint();
+            (rank, witness, outpoint.node_id, outpoint.output_no)
+        });
+        assignments
+    }
+
+    /// Iterates over all assignments of all kinds, including concealed ones
+    fn assignments(&self) -> impl Iterator<Item = AssignmentRef> {
+        self.assignments_typed().map(|(_, assignment)| assignment)
+    }
+
+    /// Iterates over all assignments of all kinds, including concealed ones,
+    /// together with their owned right types
+    fn assignments_typed(&self) -> impl Iterator<Item = (OwnedRightType, AssignmentRef)> {
+        fn typed<'state, T>(
+            map: &'state BTreeMap<OwnedRightType, Vec<T>>,
+        ) -> impl Iterator<Item = (OwnedRightType, AssignmentRef<'state>)>
+        where &'state T: Into<AssignmentRef<'state>> {
+            map.iter()
+                .flat_map(|(ty, items)| items.iter().map(move |item| (*ty, item.into())))
+        }
+
+        typed(&self.owned_rights)
+            .chain(typed(&self.owned_values))
+            .chain(typed(&self.owned_data))
+            .chain(typed(&self.owned_attachments))
+            .chain(typed(&self.concealed))
+    }
+
+    /// Iterates over assignments with revealed state whose seals are defined
+    /// by one of the `outpoints`
+    pub fn owned_assignments<'state>(
+        &'state self,
+        outpoints: &'state BTreeSet<OutPoint>,
+    ) -> impl Iterator<Item = AssignmentRef<'state>> + 'state {
+        self.assignments().filter(move |assignment| {
+            !assignment.is_confidential()
+                && matches!(assignment.seal(), Some(seal) if outpoints.contains(&seal))
+        })
+    }
+
+    /// Constructs reduced contract state containing only assignments with
+    /// revealed state whose seals are defined by one of the `outpoints`. The
+    /// contract metadata and records of the nodes which created the retained
+    /// assignments are preserved.
+    #[inline]
+    pub fn filter_owned(&self, outpoints: &BTreeSet<OutPoint>) -> ContractState {
+        self.filter_owned_inner(outpoints, false)
+    }
+
+    /// Works like [`ContractState::filter_owned`], but also retains concealed
+    /// assignments whose seals are defined by one of the `outpoints`
+    #[inline]
+    pub fn filter_owned_with_concealed(&self, outpoints: &BTreeSet<OutPoint>) -> ContractState {
+        self.filter_owned_inner(outpoints, true)
+    }
+
+    fn filter_owned_inner(
+        &self,
+        outpoints: &BTreeSet<OutPoint>,
+        include_concealed: bool,
+    ) -> ContractState {
+        fn filter<T: Clone>(
+            map: &BTreeMap<OwnedRightType, Vec<T>>,
+            keep: impl Fn(&T) -> bool,
+        ) -> BTreeMap<OwnedRightType, Vec<T>> {
+            map.iter()
+                .map(|(ty, items)| {
+                    let items = items.iter().filter(|item| keep(item)).cloned();
+                    (*ty, items.collect::<Vec<_>>())
+                })
+                .filter(|(_, items)| !items.is_empty())
+                .collect()
+        }
+
+        let mut state = ContractState::new(self.contract_id);
+        state.metadata = self.metadata.clone();
+        state.owned_rights = filter(&self.owned_rights, |a| outpoints.contains(&a.seal));
+        state.owned_values = filter(&self.owned_values, |a| outpoints.contains(&a.seal));
+        state.owned_data = filter(&self.owned_data, |a| outpoints.contains(&a.seal));
+        state.owned_attachments = filter(&self.owned_attachments, |a| outpoints.contains(&a.seal));
+        if include_concealed {
+            let owned =
+                |a: &ConcealedAssignment| matches!(a.seal, Some(seal) if outpoints.contains(&seal));
+            state.concealed = filter(&self.concealed, owned);
+        }
+
+        let retained = state
+            .assignments_typed()
+            .map(|(ty, assignment)| TypedOutpoint::with(assignment.outpoint(), ty))
+            .collect::<BTreeSet<_>>();
+        let node_ids = retained
+            .iter()
+            .map(|outpoint| outpoint.node_id)
+            .collect::<BTreeSet<_>>();
+        state.nodes = self
+            .nodes
+            .iter()
+            .filter(|(node_id, _)| node_ids.contains(node_id))
+            .map(|(node_id, node)| (*node_id, node.clone()))
+            .collect();
+        state.history = self
+            .history
+            .iter()
+            .filter(|node_id| node_ids.contains(node_id))
+            .copied()
+            .collect();
+        state.spent = self
+            .spent
+            .iter()
+            .filter(|(outpoint, _)| retained.contains(outpoint))
+            .map(|(outpoint, spender)| (*outpoint, *spender))
+            .collect();
+        state.seal_commitments = self
+            .seal_commitments
+            .iter()
+            .filter(|(outpoint, _)| retained.contains(outpoint))
+            .map(|(outpoint, commitment)| (*outpoint, *commitment))
+            .collect();
+        state.rebuild_witness_index();
+        state
+    }
+
+    /// Returns witness transactions of all state transitions applied to the
+    /// state; these are the transactions which must be monitored for chain
+    /// reorganizations
+    pub fn witness_txids(&self) -> BTreeSet<Txid> { self.witness_index.keys().copied().collect() }
+
+    /// Reconstructs index of the state transitions by their witness
+    /// transactions from the applied node records
+    pub fn rebuild_witness_index(&mut self) {
+        self.witness_index = empty!();
+        for (node_id, node) in &self.nodes {
+            if let Some(txid) = node.witness {
+                self.witness_index.entry(txid).or_default().insert(*node_id);
+            }
+        }
+    }
+
+    /// Detects whether the assignment was spent by a known state transition
+    #[inline]
+    pub fn is_spent(&self, outpoint: &TypedOutpoint) -> bool { self.spent.contains_key(outpoint) }
+
+    /// Returns id of the known state transition which spent the assignment
+    #[inline]
+    pub fn spending_transition(&self, outpoint: &TypedOutpoint) -> Option<NodeId> {
+        self.spent.get(outpoint).copied()
+    }
+
+    /// Iterates over unspent revealed fungible assignments of all types
+    fn unspent_values(&self) -> impl Iterator<Item = &OwnedValue> {
+        self.owned_values.iter().flat_map(move |(ty, items)| {
+            items.iter().filter(move |assigned| {
+                !self.is_spent(&TypedOutpoint::with(assigned.outpoint, *ty))
+            })
+        })
+    }
+
+    /// Computes sum of the revealed unspent fungible state of all types
+    /// assigned to seals on the given outpoints
+    pub fn balance(&self, outpoints: &BTreeSet<OutPoint>) -> Result<u64, BalanceOverflow> {
+        self.unspent_values()
+            .filter(|assigned| outpoints.contains(&assigned.seal))
+            .try_fold(0u64, |sum, assigned| {
+                sum.checked_add(assigned.state.value).ok_or(BalanceOverflow)
+            })
+    }
+
+    /// Computes balance of the revealed unspent fungible state for each of
+    /// the outpoints holding it
+    pub fn balances_by_outpoint(&self) -> Result<BTreeMap<OutPoint, u64>, BalanceOverflow> {
+        let mut balances = BTreeMap::<OutPoint, u64>::new();
+        for assigned in self.unspent_values() {
+            let balance = balances.entry(assigned.seal).or_default();
+            *balance = balance
+                .checked_add(assigned.state.value)
+                .ok_or(BalanceOverflow)?;
+        }
+        Ok(balances)
+    }
+
+    /// Computes balance on the given outpoints like [`ContractState::balance`]
+    /// and counts unspent fungible assignments on the same outpoints with
+    /// concealed amount, which are not included into the balance
+    pub fn balance_report(
+        &self,
+        outpoints: &BTreeSet<OutPoint>,
+    ) -> Result<Balance, BalanceOverflow> {
+        let concealed = self
+            .concealed
+            .iter()
+            .flat_map(|(ty, items)| items.iter().map(move |assignment| (*ty, assignment)))
+            .filter(|(_, assignment)| assignment.kind == StateKind::Fungible)
+            .filter(|(ty, assignment)| {
+                !self.is_spent(&TypedOutpoint::with(assignment.outpoint, *ty))
+            })
+            .filter(|(_, assignment)| {
+                matches!(assignment.seal, Some(seal) if outpoints.contains(&seal))
+            })
+            .count();
+        Ok(Balance {
+            visible: self.balance(outpoints)?,
+            concealed,
+        })
+    }
+
+    /// Iterates over metadata of the given type defined by genesis and all
+    /// applied nodes, in the order of node application
+    pub fn metadata(&self, ty: FieldType) -> slice::Iter<data::Revealed> {
+        self.metadata
+            .get(&ty)
+            .map(Vec::deref)
+            .map(<[_]>::iter)
+            .unwrap_or_else(|| [].iter())
+    }
+
+    /// Returns the latest metadata value of the given type, interpreted as
+    /// an unsigned 64-bit integer. Returns `None` if the field is absent or
+    /// its latest value is not an integer which fits into 64 bits.
+    pub fn metadata_u64(&self, ty: FieldType) -> Option<u64> {
+        self.metadata(ty).last()?.as_u64().ok()
+    }
+
+    /// Returns the latest metadata value of the given type as a string.
+    /// Returns `None` if the field is absent or its latest value is not a
+    /// valid UTF-8 string.
+    pub fn metadata_string(&self, ty: FieldType) -> Option<&str> {
+        self.metadata(ty).last()?.as_string().ok()
+    }
+
+    /// Iterates over metadata of the given type defined by a specific node
+    pub fn metadata_at(&self, node_id: NodeId, ty: FieldType) -> slice::Iter<data::Revealed> {
+        self.nodes
+            .get(&node_id)
+            .and_then(|node| node.metadata.get(&ty))
+            .map(Vec::deref)
+            .map(<[_]>::iter)
+            .unwrap_or_else(|| [].iter())
+    }
+
+    pub fn owned_rights(&self, ty: OwnedRightType) -> slice::Iter<OwnedRight> {
+        self.owned_rights
+            .get(&ty)
+            .map(Vec::deref)
+            .map(<[_]>::iter)
+            .unwrap_or_else(|| [].iter())
+    }
+
+    pub fn owned_values(&self, ty: OwnedRightType) -> slice::Iter<OwnedValue> {
+        self.owned_values
+            .get(&ty)
+            .map(Vec::deref)
+            .map(<[_]>::iter)
+            .unwrap_or_else(|| [].iter())
+    }
+
+    pub fn owned_data(&self, ty: OwnedRightType) -> slice::Iter<OwnedData> {
+        self.owned_data
+            .get(&ty)
+            .map(Vec::deref)
+            .map(<[_]>::iter)
+            .unwrap_or_else(|| [].iter())
+    }
+
+    pub fn owned_attachments(&self, ty: OwnedRightType) -> slice::Iter<OwnedAttachment> {
+        self.owned_attachments
+            .get(&ty)
+            .map(Vec::deref)
+            .map(<[_]>::iter)
+            .unwrap_or_else(|| [].iter())
+    }
+
+    /// Iterates over all revealed attachments of all types, returning ids
+    /// of the nodes which assigned the attachment, attachment ids and MIME
+    /// types
+    pub fn attachments(&self) -> impl Iterator<Item = (NodeId, AttachmentId, &str)> {
+        self.owned_attachments.values().flatten().map(|assigned| {
+            (
+                assigned.outpoint.node_id,
+                assigned.state.id,
+                assigned.state.mime.as_str(),
+            )
+        })
+    }
+
+    /// Returns values of all assignments, including spent and concealed ones,
+    /// indexed by the node outputs and owned right types defining them
+    pub fn state_values(&self) -> BTreeMap<TypedOutpoint, StateValue> {
+        self.typed_assignments()
+            .into_iter()
+            .map(|(outpoint, assignment)| (outpoint, assignment.value()))
+            .collect()
+    }
+
+    pub fn concealed(&self, ty: OwnedRightType) -> slice::Iter<ConcealedAssignment> {
+        self.concealed
+            .get(&ty)
+            .map(Vec::deref)
+            .map(<[_]>::iter)
+            .unwrap_or_else(|| [].iter())
+    }
+}
+
+/// Moves assignments defined by the node outputs matching the `predicate`
+/// into the list of concealed assignments, keeping either their seals
+/// revealed if `keep_seal` is set, or their state values otherwise
+fn conceal_where<S: StateAtom>(
+    map: &mut BTreeMap<OwnedRightType, Vec<AssignedState<S>>>,
+    concealed: &mut BTreeMap<OwnedRightType, Vec<ConcealedAssignment>>,
+    keep_seal: bool,
+    predicate: impl Fn(&TypedOutpoint) -> bool,
+) -> usize {
+    let mut count = 0usize;
+    for (ty, items) in map.iter_mut() {
+        let (hidden, kept): (Vec<_>, Vec<_>) = items
+            .drain(..)
+            .partition(|a| predicate(&TypedOutpoint::with(a.outpoint, *ty)));
+        *items = kept;
+        if hidden.is_empty() {
+            continue;
+        }
+        count += hidden.len();
+        let hidden = hidden.into_iter().map(|assigned| ConcealedAssignment {
+            kind: S::KIND,
+            seal: Some(assigned.seal).filter(|_| keep_seal),
+            state: Some(assigned.state.to_value()).filter(|_| !keep_seal),
+            outpoint: assigned.outpoint,
+            witness: assigned.witness,
+        });
+        concealed.entry(*ty).or_default().extend(hidden);
+    }
+    count
+}
+
+impl ConcealSeals for ContractState {
+    /// Conceals seals from the list. Since revealed assignments in the
+    /// contract state always have their seals known, assignments with
+    /// concealed seals are moved to the concealed assignments.
+    fn conceal_seals(&mut self, seals: &[seal::Confidential]) -> usize {
+        let commitments = &self.seal_commitments;
+        let listed = |outpoint: &TypedOutpoint| {
+            commitments
+                .get(outpoint)
+                .map(|commitment| seals.contains(commitment))
+                .unwrap_or_default()
+        };
+
+        let mut count = 0usize;
+        let concealed = &mut self.concealed;
+        count += conceal_where(&mut self.owned_rights, concealed, false, &listed);
+        count += conceal_where(&mut self.owned_values, concealed, false, &listed);
+        count += conceal_where(&mut self.owned_data, concealed, false, &listed);
+        count += conceal_where(&mut self.owned_attachments, concealed, false, &listed);
+        for (ty, items) in concealed.iter_mut() {
+            for assignment in items {
+                let outpoint = TypedOutpoint::with(assignment.outpoint, *ty);
+                if assignment.seal.is_some() && listed(&outpoint) {
+                    assignment.seal = None;
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+}
+
+impl ConcealState for ContractState {
+    /// Conceals state of all fungible, data and attachment assignments except
+    /// those assigned to the listed seals. Declarative state has no value to
+    /// conceal and is kept as is.
+    fn conceal_state_except(&mut self, seals: &[seal::Confidential]) -> usize {
+        let commitments = &self.seal_commitments;
+        let conceal = |outpoint: &TypedOutpoint| {
+            commitments
+                .get(outpoint)
+                .map(|commitment| !seals.contains(commitment))
+                .unwrap_or(true)
+        };
+
+        let mut count = 0usize;
+        let concealed = &mut self.concealed;
+        count += conceal_where(&mut self.owned_values, concealed, true, &conceal);
+        count += conceal_where(&mut self.owned_data, concealed, true, &conceal);
+        count += conceal_where(&mut self.owned_attachments, concealed, true, &conceal);
+        for (ty, items) in concealed.iter_mut() {
+            for assignment in items {
+                let outpoint = TypedOutpoint::with(assignment.outpoint, *ty);
+                if assignment.kind != StateKind::Declarative
+                    && assignment.state.is_some()
+                    && conceal(&outpoint)
+                {
+                    assignment.state = None;
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoin::hashes::{sha256t, Hash};
+    use bitcoin::secp256k1::rand::thread_rng;
+    use commit_verify::tagged_hash;
+    use lnpbp::chain::Chain;
+    use rgb_core::schema::{Bits, DiscreteFiniteFieldFormat, Occurrences};
+    use rgb_core::{value, OwnedRights, ParentOwnedRights, ParentPublicRights, SchemaId};
+
+    use super::*;
+
+    fn assigned<State: StateAtom>(state: State) -> AssignedState<State> {
+        AssignedState {
+            seal: OutPoint::default(),
+            state,
+            outpoint: NodeOutpoint::new(node_id(1), 0),
+            witness: None,
+        }
+    }
+
+    fn node_id(no: u8) -> NodeId { NodeId::from_inner(sha256t::Hash::from_inner([no; 32])) }
+
+    #[test]
+    fn test_witness_index_rebuild() {
+        let txid = Txid::from_inner([1u8; 32]);
+        let other_txid = Txid::from_inner([2u8; 32]);
+        let mut state = ContractState::new(ContractId::default());
+        state.nodes.insert(node_id(1), AppliedNode {
+            witness: Some(txid),
+            parents: empty!(),
+            children: bset![node_id(2)],
+            metadata: empty!(),
+        });
+        state.nodes.insert(node_id(2), AppliedNode {
+            witness: Some(other_txid),
+            parents: bset![node_id(1)],
+            children: empty!(),
+            metadata: empty!(),
+        });
+        state.history = vec![node_id(1), node_id(2)];
+
+        state.rebuild_witness_index();
+        assert_eq!(state.witness_txids(), bset![txid, other_txid]);
+        assert_eq!(state.nodes_by_witness(other_txid), bset![node_id(2)]);
+
+        let report = state.rollback_witness(txid);
+        assert_eq!(report.rolled_back, bset![node_id(1)]);
+        assert_eq!(report.cascaded, bset![node_id(2)]);
+
+        let incremental = state.witness_index.clone();
+        state.rebuild_witness_index();
+        assert_eq!(state.witness_index, incremental);
+        assert!(state.witness_txids().is_empty());
+        assert!(state.history.is_empty());
+    }
+
+    #[test]
+    fn test_filter_owned() {
+        let mine = OutPoint::new(Txid::from_inner([1u8; 32]), 0);
+        let other = OutPoint::new(Txid::from_inner([2u8; 32]), 1);
+        let right = |seal, no| AssignedState {
+            seal,
+            state: data::Void::default(),
+            outpoint: NodeOutpoint::new(node_id(no), 0),
+            witness: None,
+        };
+
+        let mut state = ContractState::new(ContractId::default());
+        state.metadata.insert(1, vec![data::Revealed::U8(1)]);
+        state
+            .owned_rights
+            .insert(1, vec![right(mine, 1), right(other, 2)]);
+        state.owned_rights.insert(2, vec![right(other, 3)]);
+        state.concealed.insert(1, vec![ConcealedAssignment {
+            kind: StateKind::Declarative,
+            seal: Some(mine),
+            state: None,
+            outpoint: NodeOutpoint::new(node_id(4), 0),
+            witness: None,
+        }]);
+        for no in 1..=4 {
+            state.nodes.insert(node_id(no), AppliedNode {
+                witness: None,
+                parents: empty!(),
+                children: empty!(),
+                metadata: empty!(),
+            });
+            state.history.push(node_id(no));
+        }
+
+        let outpoints = bset![mine];
+        assert_eq!(state.owned_assignments(&outpoints).count(), 1);
+
+        let filtered = state.filter_owned(&outpoints);
+        assert_eq!(filtered.metadata, state.metadata);
+        assert_eq!(filtered.owned_rights, bmap! { 1 => vec![right(mine, 1)] });
+        assert!(filtered.concealed.is_empty());
+        assert_eq!(filtered.history, vec![node_id(1)]);
+        assert_eq!(filtered.nodes.len(), 1);
+        assert!(filtered.nodes.contains_key(&node_id(1)));
+
+        let serialized = filtered.strict_serialize().unwrap();
+        let deserialized = ContractState::strict_deserialize(serialized).unwrap();
+        assert_eq!(deserialized, filtered);
+
+        let filtered = state.filter_owned_with_concealed(&outpoints);
+        assert_eq!(filtered.concealed, state.concealed);
+        assert_eq!(filtered.history, vec![node_id(1), node_id(4)]);
+    }
+
+    #[test]
+    fn test_balances() {
+        let mine = OutPoint::new(Txid::from_inner([1u8; 32]), 0);
+        let other = OutPoint::new(Txid::from_inner([2u8; 32]), 1);
+        let value = |seal: OutPoint, no: u8, amount: u64| AssignedState {
+            seal,
+            state: value::Revealed::with_amount(amount, &mut thread_rng()),
+            outpoint: NodeOutpoint::new(node_id(no), 0),
+            witness: None,
+        };
+        let concealed = |seal: OutPoint, no: u8, kind: StateKind| ConcealedAssignment {
+            kind,
+            seal: Some(seal),
+            state: None,
+            outpoint: NodeOutpoint::new(node_id(no), 0),
+            witness: None,
+        };
+        let mut state = ContractState::new(ContractId::default());
+        state.owned_values.insert(1, vec![
+            value(mine, 1, 600),
+            value(mine, 2, 400),
+            value(other, 3, 100),
+        ]);
+        state
+            .owned_values
+            .insert(2, vec![value(mine, 4, 50), value(other, 5, 1000)]);
+        state.concealed.insert(1, vec![
+            concealed(mine, 6, StateKind::Fungible),
+            concealed(mine, 7, StateKind::Fungible),
+            concealed(mine, 8, StateKind::Declarative),
+            concealed(other, 9, StateKind::Fungible),
+        ]);
+        // One of the revealed and one of the concealed assignments are spent
+        state
+            .spent
+            .insert(TypedOutpoint::new(node_id(2), 1, 0), node_id(10));
+        state
+            .spent
+            .insert(TypedOutpoint::new(node_id(7), 1, 0), node_id(10));
+        // Outputs of other types with the same number are not spent
+        state
+            .spent
+            .insert(TypedOutpoint::new(node_id(4), 1, 0), node_id(10));
+
+        let wallet = bset![mine];
+        assert_eq!(state.balance(&wallet), Ok(650));
+        assert_eq!(state.balance(&bset![mine, other]), Ok(1750));
+        assert_eq!(state.balance(&bset![OutPoint::default()]), Ok(0));
+        assert_eq!(
+            state.balances_by_outpoint(),
+            Ok(bmap! { mine => 650, other => 1100 })
+        );
+
+        let report = state.balance_report(&wallet).unwrap();
+        assert_eq!(report, Balance {
+            visible: 650,
+            concealed: 1
+        });
+        assert!(report.is_lower_bound());
+        assert_eq!(report.to_string(), "650 (+1 concealed)");
+        let report = state.balance_report(&bset![other]).unwrap();
+        assert_eq!(report, Balance {
+            visible: 1100,
+            concealed: 1
+        });
+        let report = state.balance_report(&bset![OutPoint::default()]).unwrap();
+        assert_eq!(report, Balance::default());
+        assert!(!report.is_lower_bound());
+
+        state
+            .owned_values
+            .insert(3, vec![value(other, 11, u64::MAX)]);
+        assert_eq!(state.balance(&wallet), Ok(650));
+        assert_eq!(state.balance(&bset![other]), Err(BalanceOverflow));
+        assert_eq!(state.balances_by_outpoint(), Err(BalanceOverflow));
+        assert_eq!(state.balance_report(&bset![other]), Err(BalanceOverflow));
+    }
+
+    #[test]
+    fn test_metadata_latest_wins() {
+        let mut state = ContractState::new(ContractId::default());
+        let ticker = data::Revealed::String(s!("A"));
+        let precision = vec![data::Revealed::U8(8)];
+        let supply = vec![data::Revealed::U64(1), data::Revealed::U32(2)];
+        for (no, meta) in [
+            (1, bmap! { 1 => precision, 2 => vec![ticker.clone()] }),
+            (2, bmap! { 1 => supply }),
+            (3, bmap! { 2 => vec![data::Revealed::Bytes(vec![0xFF])] }),
+        ] {
+            for (ty, values) in &meta {
+                let aggregated = state.metadata.entry(*ty).or_default();
+                aggregated.extend(values.iter().cloned());
+            }
+            state.nodes.insert(node_id(no), AppliedNode {
+                witness: None,
+                parents: empty!(),
+                children: empty!(),
+                metadata: meta,
+            });
+            state.history.push(node_id(no));
+        }
+
+        assert_eq!(state.metadata(1).count(), 3);
+        assert_eq!(state.metadata_u64(1), Some(2));
+        assert_eq!(state.metadata_u64(3), None);
+        assert_eq!(state.metadata_string(2), None);
+        assert_eq!(state.metadata_at(node_id(1), 2).next(), Some(&ticker));
+        assert_eq!(state.metadata_at(node_id(2), 2).next(), None);
+        assert_eq!(state.metadata_at(node_id(9), 1).next(), None);
+    }
+
+    #[test]
+    fn test_state_diff() {
+        let seal = OutPoint::new(Txid::from_inner([1u8; 32]), 0);
+        let other_seal = OutPoint::new(Txid::from_inner([2u8; 32]), 0);
+        let right = |seal, no| AssignedState {
+            seal,
+            state: data::Void::default(),
+            outpoint: NodeOutpoint::new(node_id(no), 0),
+            witness: None,
+        };
+        let concealed = |seal, no| ConcealedAssignment {
+            kind: StateKind::Declarative,
+            seal,
+            state: None,
+            outpoint: NodeOutpoint::new(node_id(no), 0),
+            witness: None,
+        };
+
+        let mut ours = ContractState::new(ContractId::default());
+        let rights = vec![right(seal, 1), right(seal, 2), right(seal, 3)];
+        ours.owned_rights.insert(1, rights);
+        ours.concealed.insert(1, vec![concealed(None, 4)]);
+        ours.metadata.insert(1, vec![data::Revealed::U8(1)]);
+
+        let mut theirs = ours.clone();
+        assert!(ours.diff(&theirs).is_empty());
+        assert_eq!(ours.diff(&theirs).to_string(), "states are identical\n");
+
+        let rights = vec![right(seal, 1), right(other_seal, 3)];
+        theirs.owned_rights.insert(1, rights);
+        // Assignments of other types at the same node outputs are distinct
+        theirs.owned_rights.insert(2, vec![right(seal, 1), right(seal, 5)]);
+        let concealed = vec![concealed(Some(seal), 2), concealed(None, 4)];
+        theirs.concealed.insert(1, concealed);
+        theirs.metadata.insert(1, vec![data::Revealed::U8(2)]);
+
+        let diff = ours.diff(&theirs);
+        assert_eq!(
+            diff.added,
+            bset![TypedOutpoint::new(node_id(1), 2, 0), TypedOutpoint::new(node_id(5), 2, 0)]
+        );
+        assert!(diff.removed.is_empty());
+        assert_eq!(diff.concealment, bset![TypedOutpoint::new(node_id(2), 1, 0)]);
+        assert_eq!(diff.conflicts, bset![TypedOutpoint::new(node_id(3), 1, 0)]);
+        assert_eq!(diff.metadata, bset![1]);
+        assert!(diff.has_conflicts());
+
+        let reverse = theirs.diff(&ours);
+        assert_eq!(reverse.removed, diff.added);
+        assert_eq!(reverse.concealment, diff.concealment);
+        assert_eq!(reverse.conflicts, diff.conflicts);
+    }
+
+    #[test]
+    fn test_snapshot_at() {
+        let txid = |no| Txid::from_inner([no; 32]);
+        let heights = |txid: Txid| match txid.into_inner()[0] {
+            2 => Some(100),
+            3 => Some(120),
+            4 => Some(110),
+            _ => None,
+        };
+
+        // Genesis #1 is followed by a chain of transitions #2 -> #3 and a
+        // transition #4 together with an unmined transition #5 spending
+        // genesis outputs
+        let mut state = ContractState::new(ContractId::default());
+        for (no, parent) in [(1, None), (2, Some(1)), (3, Some(2)), (4, Some(1)), (5, Some(1))] {
+            let witness = parent.map(|_| txid(no));
+            let assigned = AssignedState {
+                seal: OutPoint::new(txid(no), 0),
+                state: data::Void::default(),
+                outpoint: NodeOutpoint::new(node_id(no), 0),
+                witness,
+            };
+            state.owned_rights.entry(1).or_default().push(assigned);
+            state.nodes.insert(node_id(no), AppliedNode {
+                witness,
+                parents: parent.into_iter().map(node_id).collect(),
+                children: empty!(),
+                metadata: empty!(),
+            });
+            if let Some(parent) = parent {
+                let parent_node = state.nodes.get_mut(&node_id(parent)).unwrap();
+                parent_node.children.insert(node_id(no));
+                let spent = TypedOutpoint::new(node_id(parent), 1, no as u16);
+                state.spent.insert(spent, node_id(no));
+            }
+            state.history.push(node_id(no));
+        }
+        state.rebuild_witness_index();
+
+        let nodes = |state: &ContractState| state.nodes.keys().copied().collect::<BTreeSet<_>>();
+        let snapshot = state.snapshot_at(115, &heights);
+        assert_eq!(nodes(&snapshot), bset![node_id(1), node_id(2), node_id(4)]);
+        assert_eq!(snapshot.witness_txids(), bset![txid(2), txid(4)]);
+        assert_eq!(state.snapshot_at(u32::MAX, &heights).history.len(), 4);
+        let snapshot = state.snapshot_at(105, &heights);
+        assert_eq!(nodes(&snapshot), bset![node_id(1), node_id(2)]);
+
+        let snapshot = state.snapshot_at(99, &heights);
+        assert_eq!(nodes(&snapshot), bset![node_id(1)]);
+        assert!(snapshot.spent.is_empty());
+        assert_eq!(snapshot.owned_rights[&1].len(), 1);
+    }
+
+    fn display_fixture() -> ContractState {
+        let seal = OutPoint::new(Txid::from_inner([1u8; 32]), 0);
+        let other_seal = OutPoint::new(Txid::from_inner([2u8; 32]), 3);
+        let mut state = ContractState::new(ContractId::default());
+        state.metadata.insert(1, vec![data::Revealed::String(s!("TCKR"))]);
+        let value = AssignedState {
+            seal,
+            state: value::Revealed::with_amount(1000, &mut thread_rng()),
+            outpoint: NodeOutpoint::new(node_id(1), 0),
+            witness: None,
+        };
+        state.owned_values.insert(2, vec![value]);
+        let right = AssignedState {
+            seal: other_seal,
+            state: data::Void::default(),
+            outpoint: NodeOutpoint::new(node_id(1), 1),
+            witness: None,
+        };
+        state.owned_rights.insert(3, vec![right]);
+        state.concealed.insert(2, vec![ConcealedAssignment {
+            kind: StateKind::Fungible,
+            seal: None,
+            state: None,
+            outpoint: NodeOutpoint::new(node_id(1), 2),
+            witness: None,
+        }]);
+        state.nodes.insert(node_id(1), AppliedNode {
+            witness: None,
+            parents: empty!(),
+            children: empty!(),
+            metadata: state.metadata.clone(),
+        });
+        state.history.push(node_id(1));
+        state
+            .spent
+            .insert(TypedOutpoint::new(node_id(1), 2, 5), node_id(2));
+        state
+    }
+
+    #[test]
+    fn test_display() {
+        let state = display_fixture();
+        let ticker = data::Revealed::String(s!("TCKR"));
+        let expected = format!(
+            "contract {}
+metadata:
+  #1: {}
+assignments:
+  {}
+    #2 amount 1000
+rights:
+  {}
+    #3
+concealed: 1
+",
+            ContractId::default(),
+            ticker,
+            OutPoint::new(Txid::from_inner([1u8; 32]), 0),
+            OutPoint::new(Txid::from_inner([2u8; 32]), 3),
+        );
+        assert_eq!(state.to_string(), expected);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde_roundtrip() {
+        let state = display_fixture();
+        let json = serde_json::to_string(&state).unwrap();
+        assert!(json.contains(&ContractId::default().to_string()));
+        let deserialized: ContractState = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, state);
+    }
+
+    fn schema() -> Schema {
+        let mut schema = Schema::default();
+        schema.field_types = bmap! { 1 => DataFormat::String(16) };
+        schema.owned_right_types = bmap! {
+            2 => StateSchema::DiscreteFiniteField(DiscreteFiniteFieldFormat::Unsigned64bit),
+            3 => StateSchema::Declarative
+        };
+        schema.genesis.metadata = bmap! { 1 => Occurrences::Once };
+        schema
+    }
+
+    #[test]
+    fn test_check_schema() {
+        let node_id = node_id(1);
+        let state = display_fixture();
+        assert!(state.check_schema(&schema()).is_empty());
+
+        let mut schema = schema();
+        schema.owned_right_types.remove(&3);
+        schema
+            .owned_right_types
+            .insert(2, StateSchema::DataContainer);
+        schema
+            .field_types
+            .insert(1, DataFormat::Unsigned(Bits::Bit8, 0, 255));
+        schema.genesis.metadata.insert(4, Occurrences::Once);
+        assert_eq!(state.check_schema(&schema), vec![
+            SchemaViolation::StateKindMismatch {
+                node_id,
+                ty: 2,
+                expected: StateKind::Attachment,
+                found: StateKind::Fungible,
+            },
+            SchemaViolation::UnknownOwnedRight { node_id, ty: 3 },
+            SchemaViolation::StateKindMismatch {
+                node_id,
+                ty: 2,
+                expected: StateKind::Attachment,
+                found: StateKind::Fungible,
+            },
+            SchemaViolation::FieldFormatMismatch { node_id, ty: 1 },
+            SchemaViolation::FieldOccurrences {
+                node_id,
+                ty: 4,
+                count: 0,
+            },
+        ]);
+
+        let mut schema = self::schema();
+        schema.field_types.clear();
+        assert_eq!(state.check_schema(&schema), vec![
+            SchemaViolation::UnknownField { node_id, ty: 1 }
+        ]);
+    }
+
+    #[test]
+    fn test_check_schema_unknown_nodes() {
+        // History entries without node data are reported instead of failing
+        // the check, and genesis metadata is not checked without genesis data
+        let mut state = display_fixture();
+        state.nodes.clear();
+        state.history.push(node_id(2));
+        assert_eq!(state.check_schema(&schema()), vec![
+            SchemaViolation::UnknownNode {
+                node_id: node_id(1)
+            },
+            SchemaViolation::UnknownNode {
+                node_id: node_id(2)
+            },
+        ]);
+    }
+
+    #[test]
+    fn test_conceal_merge_reveal() {
+        let value_seal = OutPoint::new(Txid::from_inner([1u8; 32]), 0);
+        let right_seal = OutPoint::new(Txid::from_inner([2u8; 32]), 3);
+        let value_commitment = seal::Revealed::from(value_seal).commit_conceal();
+        let right_commitment = seal::Revealed::from(right_seal).commit_conceal();
+        let mut state = display_fixture();
+        // Contract state has concealed assignment lists for all owned right
+        // types of the applied nodes
+        state.concealed.insert(3, vec![]);
+        let commitments = &mut state.seal_commitments;
+        commitments.insert(TypedOutpoint::new(node_id(1), 2, 0), value_commitment);
+        commitments.insert(TypedOutpoint::new(node_id(1), 3, 1), right_commitment);
+        let original = state.clone();
+
+        assert_eq!(state.conceal_state_except(&[value_commitment]), 0);
+        assert_eq!(state.conceal_state_except(&[]), 1);
+        assert!(state.owned_values[&2].is_empty());
+        assert_eq!(state.concealed[&2].len(), 2);
+        assert_eq!(state.nodes, original.nodes);
+
+        assert_eq!(state.conceal_seals(&[right_commitment]), 1);
+        assert_eq!(state.conceal_seals(&[value_commitment]), 1);
+        assert!(state.owned_rights[&3].is_empty());
+        assert_eq!(state.concealed[&3][0].seal, None);
+
+        assert_eq!(state.merge_reveal(&original), 2);
+        assert_eq!(state, original);
+        assert_eq!(state.merge_reveal(&original), 0);
+    }
+
+    #[test]
+    fn test_state_id_midstate() {
+        let midstate = tagged_hash::Midstate::with(b"rgb:state");
+        assert_eq!(midstate.into_inner().into_inner(), MIDSTATE_STATE_ID);
+    }
+
+    #[test]
+    fn test_state_id() {
+        let empty = ContractState::new(ContractId::default());
+        let expected = [
+            28, 127, 74, 85, 17, 44, 55, 36, 250, 24, 242, 252, 162, 181, 184, 241, 198, 203, 63,
+            174, 240, 246, 219, 237, 129, 65, 255, 170, 182, 100, 80, 157,
+        ];
+        assert_eq!(empty.state_id().into_inner().into_inner(), expected);
+
+        let mut state = display_fixture();
+        let other = AssignedState {
+            seal: OutPoint::new(Txid::from_inner([4u8; 32]), 1),
+            state: value::Revealed::with_amount(500, &mut thread_rng()),
+            outpoint: NodeOutpoint::new(node_id(1), 3),
+            witness: None,
+        };
+        state.owned_values.get_mut(&2).unwrap().push(other);
+        let id = state.state_id();
+        assert_ne!(id, empty.state_id());
+        assert_eq!(id.to_string().parse::<StateId>().unwrap(), id);
+
+        let mut reordered = state.clone();
+        reordered.history.reverse();
+        for items in reordered.owned_values.values_mut() {
+            items.reverse();
+        }
+        assert_eq!(reordered.state_id(), id);
+
+        let value_seal = OutPoint::new(Txid::from_inner([1u8; 32]), 0);
+        let value_commitment = seal::Revealed::from(value_seal).commit_conceal();
+        state
+            .seal_commitments
+            .insert(TypedOutpoint::new(node_id(1), 2, 0), value_commitment);
+        let id = state.state_id();
+        assert_eq!(state.conceal_seals(&[value_commitment]), 1);
+        assert_ne!(state.state_id(), id);
+    }
+
+    #[test]
+    fn test_assignments_ordered() {
+        let heights = |txid: Txid| match txid.into_inner()[0] {
+            2 => Some(200),
+            3 => Some(100),
+            _ => None,
+        };
+        let assigned = |node: u8, no: u16, witness: u8| AssignedState {
+            seal: OutPoint::new(Txid::from_inner([witness; 32]), no as u32),
+            state: data::Void::default(),
+            outpoint: NodeOutpoint::new(node_id(node), no),
+            witness: Some(Txid::from_inner([witness; 32])).filter(|_| witness > 0),
+        };
+        let items = vec![
+            assigned(4, 0, 4),
+            assigned(2, 1, 2),
+            assigned(1, 0, 0),
+            assigned(3, 0, 3),
+            assigned(2, 0, 2),
+            assigned(5, 0, 3),
+        ];
+
+        let mut first = ContractState::new(ContractId::default());
+        first.owned_rights.insert(1, items.clone());
+        let mut second = ContractState::new(ContractId::default());
+        for item in items.into_iter().rev() {
+            second.owned_rights.entry(1).or_default().push(item);
+        }
+        assert_ne!(first, second);
+
+        let ordered = first.assignments_ordered(&heights);
+        assert_eq!(ordered, second.assignments_ordered(&heights));
+        let order = ordered
+            .into_iter()
+            .map(|assignment| assignment.outpoint())
+            .collect::<Vec<_>>();
+        let expected = [(1, 0), (3, 0), (5, 0), (2, 0), (2, 1), (4, 0)]
+            .into_iter()
+            .map(|(node, no)| NodeOutpoint::new(node_id(node), no))
+            .collect::<Vec<_>>();
+        assert_eq!(order, expected);
+        assert_eq!(first.to_string(), second.to_string());
+
+        #[cfg(feature = "serde")]
+        assert_eq!(
+            serde_json::to_string(&first).unwrap(),
+            serde_json::to_string(&second).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_declarative_conversions() {
+        let right = assigned(data::Void::default());
+        let state = AssignmentRef::from(&right);
+        assert!(!state.is_confidential());
+        let err = StateConversionError::WrongKind(StateKind::Declarative, "u64");
+        assert_eq!(state.as_u64(), Err(err));
+        let err = StateConversionError::WrongKind(StateKind::Declarative, "amount");
+        assert_eq!(state.as_amount(), Err(err));
+        let err = StateConversionError::WrongKind(StateKind::Declarative, "bytes");
+        assert_eq!(state.as_bytes(), Err(err));
+        let err = StateConversionError::WrongKind(StateKind::Declarative, "string");
+        assert_eq!(state.as_string(), Err(err));
+    }
+
+    #[test]
+    fn test_fungible_conversions() {
+        let value = assigned(value::Revealed::with_amount(42, &mut thread_rng()));
+        let state = AssignmentRef::from(&value);
+        assert!(!state.is_confidential());
+        assert_eq!(state.as_u64(), Ok(42));
+        assert_eq!(state.as_amount(), Ok(42));
+        let err = StateConversionError::WrongKind(StateKind::Fungible, "string");
+        assert_eq!(state.as_string(), Err(err));
+    }
+
+    #[test]
+    fn test_data_conversions() {
+        let lossy = Err(StateConversionError::Lossy("u64"));
+        assert_eq!(data::Revealed::U8(7).as_u64(), Ok(7));
+        assert_eq!(data::Revealed::U64(u64::MAX).as_u64(), Ok(u64::MAX));
+        let max = u64::MAX as u128;
+        assert_eq!(data::Revealed::U128(max).as_u64(), Ok(u64::MAX));
+        assert_eq!(data::Revealed::U128(max + 1).as_u64(), lossy);
+        assert_eq!(data::Revealed::I64(5).as_u64(), Ok(5));
+        assert_eq!(data::Revealed::I8(-1).as_u64(), lossy);
+        assert_eq!(data::Revealed::F64(3.0).as_u64(), Ok(3));
+        assert_eq!(data::Revealed::F64(3.5).as_u64(), lossy);
+        assert_eq!(data::Revealed::F32(-1.0).as_u64(), lossy);
+        assert_eq!(data::Revealed::F64(f64::NAN).as_u64(), lossy);
+        assert_eq!(data::Revealed::F64(18446744073709551616.0).as_u64(), lossy);
+
+        let string = data::Revealed::String(s!("asset"));
+        assert_eq!(string.as_string(), Ok("asset"));
+        assert_eq!(string.as_bytes(), Ok(&b"asset"[..]));
+        assert_eq!(string.as_u64(), Err(StateConversionError::WrongType("u64")));
+        assert_eq!(
+            string.as_amount(),
+            Err(StateConversionError::WrongKind(StateKind::Data, "amount"))
+        );
+
+        let bytes = data::Revealed::Bytes(vec![0xF0, 0x9F, 0x92, 0x96]);
+        assert_eq!(bytes.as_string(), Ok("\u{1F496}"));
+        let invalid = data::Revealed::Bytes(vec![0xFF, 0xFE]);
+        assert_eq!(invalid.as_bytes(), Ok(&[0xFF, 0xFE][..]));
+        assert_eq!(invalid.as_string(), Err(StateConversionError::InvalidUtf8));
+        assert_eq!(
+            data::Revealed::U8(1).as_bytes(),
+            Err(StateConversionError::WrongType("bytes"))
+        );
+    }
+
+    #[test]
+    fn test_concealed_conversions() {
+        let concealed = ConcealedAssignment {
+            kind: StateKind::Fungible,
+            seal: Some(OutPoint::default()),
+            state: None,
+            outpoint: NodeOutpoint::new(node_id(1), 0),
+            witness: None,
+        };
+        let state = AssignmentRef::from(&concealed);
+        assert!(state.is_confidential());
+        assert_eq!(state.as_amount(), Err(StateConversionError::Concealed));
+        assert_eq!(state.as_u64(), Err(StateConversionError::Concealed));
+        let err = StateConversionError::WrongKind(StateKind::Fungible, "string");
+        assert_eq!(state.as_string(), Err(err));
+        let display = format!("~confidential~@{}", OutPoint::default());
+        assert_eq!(state.to_string(), display);
+
+        let concealed = ConcealedAssignment {
+            seal: None,
+            kind: StateKind::Data,
+            ..concealed
+        };
+        let state = AssignmentRef::from(&concealed);
+        assert_eq!(state.as_bytes(), Err(StateConversionError::Concealed));
+        assert_eq!(state.to_string(), "~confidential~");
+
+        // Assignments with unknown seals may have known state
+        let concealed = ConcealedAssignment {
+            kind: StateKind::Fungible,
+            state: Some(StateValue::Fungible(42)),
+            ..concealed
+        };
+        let state = AssignmentRef::from(&concealed);
+        assert!(state.is_confidential());
+        assert_eq!(state.value(), StateValue::Fungible(42));
+        assert_eq!(state.as_amount(), Ok(42));
+        assert_eq!(state.as_u64(), Ok(42));
+    }
+
+    /// Returns genesis of the test contract together with a chain of state
+    /// transitions and their anchors
+    fn history(count: usize) -> (Genesis, Vec<(Transition, Anchor<lnpbp4::MerkleBlock>)>) {
+        let consignment = crate::verify::test::consignment(count);
+        let contract_id = consignment.contract_id();
+        let transitions = consignment
+            .anchored_bundles
+            .iter()
+            .flat_map(|(anchor, bundle)| {
+                let anchor = anchor
+                    .to_merkle_block(contract_id, bundle.bundle_id())
+                    .unwrap();
+                bundle
+                    .known_transitions()
+                    .map(move |transition| (transition.clone(), anchor.clone()))
+            })
+            .collect();
+        (consignment.genesis, transitions)
+    }
+
+    #[test]
+    fn test_apply_transition() {
+        let (genesis, transitions) = history(2);
+        let (first, anchor) = &transitions[0];
+        let (second, second_anchor) = &transitions[1];
+        let txid = anchor.txid;
+        let mut state = ContractState::with_genesis(&genesis);
+
+        // Parents must be applied first
+        assert_eq!(
+            state.apply_transition(second, second_anchor, txid),
+            Err(StateApplyError::UnknownParents {
+                node_id: second.node_id(),
+                missing: bset![first.node_id()]
+            })
+        );
+        assert_eq!(state, ContractState::with_genesis(&genesis));
+
+        state.apply_transition(first, anchor, txid).unwrap();
+        let applied = state.clone();
+        state.apply_transition(first, anchor, txid).unwrap();
+        assert_eq!(state, applied);
+
+        state.apply_transition(second, second_anchor, txid).unwrap();
+        assert_eq!(
+            state.history,
+            vec![genesis.node_id(), first.node_id(), second.node_id()]
+        );
+        assert!(state.is_spent(&TypedOutpoint::new(first.node_id(), 1, 0)));
+        assert!(!state.is_spent(&TypedOutpoint::new(second.node_id(), 1, 0)));
+    }
+
+    #[test]
+    fn test_double_spend() {
+        let (genesis, transitions) = history(1);
+        let (transition, anchor) = &transitions[0];
+        let mut state = ContractState::with_genesis(&genesis);
+        state
+            .apply_transition(transition, anchor, anchor.txid)
+            .unwrap();
+        let applied = state.clone();
+
+        let output = TypedOutpoint::new(genesis.node_id(), 1, 0);
+        let conflicting = Transition::with(
+            1,
+            empty!(),
+            empty!(),
+            empty!(),
+            empty!(),
+            ParentOwnedRights::from_inner(bmap! { genesis.node_id() => bmap! { 1 => vec![0] } }),
+        );
+        let err = StateApplyError::DoubleSpend {
+            output,
+            spender: transition.node_id(),
+        };
+        assert_eq!(
+            state.apply_transition(&conflicting, anchor, anchor.txid),
+            Err(err.clone())
+        );
+        assert_eq!(state.extend(anchor.txid, &conflicting), Err(err));
+        assert_eq!(state, applied);
+        assert_eq!(state.spent[&output], transition.node_id());
+    }
+
+    #[test]
+    fn test_apply_extension() {
+        let (genesis, _) = history(0);
+        let contract_id = genesis.contract_id();
+        let extension = |contract_id: ContractId, parent: NodeId| {
+            Extension::with(
+                1,
+                contract_id,
+                empty!(),
+                ParentPublicRights::from_inner(bmap! { parent => bset![1] }),
+                empty!(),
+                empty!(),
+            )
+        };
+        let mut state = ContractState::with_genesis(&genesis);
+
+        let orphan = extension(contract_id, node_id(1));
+        assert_eq!(
+            state.apply_extension(&orphan),
+            Err(StateApplyError::UnknownParents {
+                node_id: orphan.node_id(),
+                missing: bset![node_id(1)]
+            })
+        );
+        let foreign = extension(ContractId::default(), genesis.node_id());
+        assert_eq!(
+            state.apply_extension(&foreign),
+            Err(StateApplyError::ContractMismatch(ContractId::default()))
+        );
+        assert_eq!(state, ContractState::with_genesis(&genesis));
+
+        let valid = extension(contract_id, genesis.node_id());
+        state.apply_extension(&valid).unwrap();
+        let applied = state.clone();
+        state.apply_extension(&valid).unwrap();
+        assert_eq!(state, applied);
+        assert_eq!(
+            state.nodes[&genesis.node_id()].children,
+            bset![valid.node_id()]
+        );
+        assert_eq!(state.history, vec![genesis.node_id(), valid.node_id()]);
+    }
+
+    #[test]
+    fn test_rollback_conflicting() {
+        let (genesis, transitions) = history(1);
+        let (transition, anchor) = &transitions[0];
+        let output = TypedOutpoint::new(genesis.node_id(), 1, 0);
+        let conflicting = Transition::with(
+            1,
+            empty!(),
+            empty!(),
+            empty!(),
+            empty!(),
+            ParentOwnedRights::from_inner(bmap! { genesis.node_id() => bmap! { 1 => vec![0] } }),
+        );
+        let txid = Txid::from_inner([3u8; 32]);
+        let other_txid = Txid::from_inner([4u8; 32]);
+        let initial = ContractState::with_genesis(&genesis);
+
+        let mut state = initial.clone();
+        state.extend(txid, transition).unwrap();
+        assert!(state.extend(other_txid, &conflicting).is_err());
+        // Rolling back witness of the rejected transition changes nothing
+        let report = state.rollback_witness(other_txid);
+        assert!(report.rolled_back.is_empty());
+        assert_eq!(state.spent[&output], transition.node_id());
+
+        let report = state.rollback_witness(txid);
+        assert_eq!(report.rolled_back, bset![transition.node_id()]);
+        assert!(!state.is_spent(&output));
+        assert_eq!(state, initial);
+
+        // Once the first spending is rolled back, the output can be spent
+        // by the other transition
+        state.extend(other_txid, &conflicting).unwrap();
+        assert_eq!(state.spent[&output], conflicting.node_id());
+        state.rollback_witness(other_txid);
+        assert_eq!(state, initial);
+    }
+
+    #[test]
+    fn test_rollback_unordered() {
+        let (genesis, transitions) = history(2);
+        let (first, _) = &transitions[0];
+        let (second, _) = &transitions[1];
+        let txid = Txid::from_inner([3u8; 32]);
+        let other_txid = Txid::from_inner([4u8; 32]);
+        let genesis_output = TypedOutpoint::new(genesis.node_id(), 1, 0);
+        let first_output = TypedOutpoint::new(first.node_id(), 1, 0);
+
+        // The second transition is applied before its parent, so it is not
+        // known as the parent child and is not rolled back together with it
+        let mut state = ContractState::with_genesis(&genesis);
+        state.extend(other_txid, second).unwrap();
+        state.extend(txid, first).unwrap();
+        let report = state.rollback_witness(txid);
+        assert_eq!(report.rolled_back, bset![first.node_id()]);
+        assert!(report.cascaded.is_empty());
+        assert!(!state.is_spent(&genesis_output));
+        assert_eq!(state.spent[&first_output], second.node_id());
+        assert_eq!(state.history, vec![genesis.node_id(), second.node_id()]);
+
+        // Nodes missing from the history index are skipped
+        state.history.push(node_id(9));
+        let report = state.rollback_witness(other_txid);
+        assert_eq!(report.rolled_back, bset![second.node_id()]);
+        assert!(!state.is_spent(&first_output));
+        assert_eq!(state.history, vec![genesis.node_id(), node_id(9)]);
+        assert_eq!(state.nodes.len(), 1);
+    }
+
+    #[test]
+    fn test_typed_spending() {
+        // Outputs are numbered separately for each owned right type, so the
+        // genesis defines both Inflation#0 and Assets#0
+        const INFLATION: OwnedRightType = 0;
+        const ASSETS: OwnedRightType = 1;
+        let outpoint = OutPoint::new(Txid::from_inner([1u8; 32]), 0);
+        let value = |amount| {
+            AssignmentVec::Fungible(vec![Assignment::Revealed {
+                seal_definition: seal::Revealed::from(outpoint),
+                assigned_state: value::Revealed::with_amount(amount, &mut thread_rng()),
+            }])
+        };
+        let genesis = Genesis::with(
+            SchemaId::default(),
+            Chain::Testnet3,
+            empty!(),
+            OwnedRights::from_inner(bmap! { INFLATION => value(1000), ASSETS => value(100) }),
+            empty!(),
+        );
+        let spending = |transition_type, ty: OwnedRightType| {
+            let parents = bmap! { genesis.node_id() => bmap! { ty => vec![0] } };
+            Transition::with(
+                transition_type,
+                empty!(),
+                empty!(),
+                empty!(),
+                empty!(),
+                ParentOwnedRights::from_inner(parents),
+            )
+        };
+        let inflation = TypedOutpoint::new(genesis.node_id(), INFLATION, 0);
+        let assets = TypedOutpoint::new(genesis.node_id(), ASSETS, 0);
+        let txid = Txid::from_inner([3u8; 32]);
+        let mut state = ContractState::with_genesis(&genesis);
+        assert_eq!(state.balance(&bset![outpoint]), Ok(1100));
+
+        // Spending the inflation right leaves the assets unspent
+        let issue = spending(1, INFLATION);
+        state.extend(txid, &issue).unwrap();
+        assert_eq!(state.spending_transition(&inflation), Some(issue.node_id()));
+        assert!(!state.is_spent(&assets));
+        assert_eq!(state.balance(&bset![outpoint]), Ok(100));
+        assert_eq!(
+            state.extend(txid, &spending(2, INFLATION)),
+            Err(StateApplyError::DoubleSpend {
+                output: inflation,
+                spender: issue.node_id()
+            })
+        );
+
+        let transfer = spending(2, ASSETS);
+        state.extend(txid, &transfer).unwrap();
+        assert_eq!(state.spending_transition(&assets), Some(transfer.node_id()));
+        assert_eq!(state.balance(&bset![outpoint]), Ok(0));
+    }
+
+    #[test]
+    fn test_concealed_known_state() {
+        // Genesis seal without the transaction id and a concealed seal can't
+        // be resolved, but their amounts are known
+        let mut seal = seal::Revealed::from(OutPoint::new(Txid::from_inner([1u8; 32]), 0));
+        seal.txid = None;
+        let confidential =
+            seal::Revealed::from(OutPoint::new(Txid::from_inner([2u8; 32]), 1)).commit_conceal();
+        let assignments = AssignmentVec::Fungible(vec![
+            Assignment::Revealed {
+                seal_definition: seal,
+                assigned_state: value::Revealed::with_amount(10, &mut thread_rng()),
+            },
+            Assignment::ConfidentialSeal {
+                seal_definition: confidential,
+                assigned_state: value::Revealed::with_amount(20, &mut thread_rng()),
+            },
+        ]);
+        let genesis = Genesis::with(
+            SchemaId::default(),
+            Chain::Testnet3,
+            empty!(),
+            OwnedRights::from_inner(bmap! { 1 => assignments }),
+            empty!(),
+        );
+        let state = ContractState::with_genesis(&genesis);
+        assert!(state.owned_values(1).next().is_none());
+        let amounts = state
+            .concealed(1)
+            .map(|assignment| (assignment.seal, AssignmentRef::from(assignment).as_amount()))
+            .collect::<Vec<_>>();
+        assert_eq!(amounts, vec![(None, Ok(10)), (None, Ok(20))]);
+        assert_eq!(state.balances_by_outpoint(), Ok(empty!()));
+
+        // Concealing the state drops the known amounts, and merging with the
+        // original state recovers them
+        let mut concealed = state.clone();
+        assert_eq!(concealed.conceal_state_except(&[]), 2);
+        assert!(concealed.concealed(1).all(|assignment| assignment.state.is_none()));
+        assert!(!concealed.diff(&state).has_conflicts());
+        assert_eq!(concealed.merge_reveal(&state), 2);
+        assert_eq!(concealed, state);
+    }
+
+    fn attachment(mime: &str) -> attachment::Revealed {
+        attachment::Revealed {
+            id: AttachmentId::from_inner(sha256t::Hash::from_inner([7u8; 32])),
+            mime: mime.to_owned(),
+            salt: 0,
+        }
+    }
+
+    #[test]
+    fn test_state_values() {
+        let data = data::Revealed::String(s!("data"));
+        let values = [
+            data::Void::default().to_value(),
+            value::Revealed::with_amount(42, &mut thread_rng()).to_value(),
+            data.to_value(),
+            attachment("text/plain").to_value(),
+        ];
+        assert_eq!(values[0], StateValue::Declarative);
+        assert_eq!(values[1], StateValue::Fungible(42));
+        assert_eq!(
+            values[2],
+            StateValue::Data(data.strict_serialize().unwrap())
+        );
+        assert_eq!(values[3], StateValue::Attachment {
+            id: attachment("text/plain").id,
+            mime: s!("text/plain")
+        });
+
+        for (value, kind) in values.iter().zip([
+            StateKind::Declarative,
+            StateKind::Fungible,
+            StateKind::Data,
+            StateKind::Attachment,
+        ]) {
+            assert_eq!(value.kind(), kind);
+            assert!(!value.is_confidential());
+            let concealed = value.conceal();
+            assert_eq!(concealed.kind(), kind);
+            assert_eq!(concealed.is_confidential(), kind != StateKind::Declarative);
+            assert_eq!(concealed.conceal(), concealed);
+
+            for value in [value.clone(), concealed] {
+                let serialized = value.strict_serialize().unwrap();
+                assert_eq!(StateValue::strict_deserialize(serialized).unwrap(), value);
+            }
+        }
+        assert_eq!(
+            StateValue::Concealed(StateKind::Data)
+                .strict_serialize()
+                .unwrap(),
+            vec![CONCEALED_STATE_TAG, StateKind::Data as u8]
+        );
+        assert!(StateValue::strict_deserialize([0x04]).is_err());
+        assert!(StateValue::strict_deserialize([CONCEALED_STATE_TAG, 0x04]).is_err());
+    }
+
+    #[test]
+    fn test_data_attachment_state() {
+        let seal = seal::Revealed::from(OutPoint::new(Txid::from_inner([1u8; 32]), 0));
+        let data = data::Revealed::Bytes(vec![1, 2, 3]);
+        let cases = [
+            (
+                AssignmentVec::NonFungible(vec![Assignment::Revealed {
+                    seal_definition: seal,
+                    assigned_state: data.clone(),
+                }]),
+                data.to_value(),
+            ),
+            (
+                AssignmentVec::Attachment(vec![Assignment::Revealed {
+                    seal_definition: seal,
+                    assigned_state: attachment("image/png"),
+                }]),
+                attachment("image/png").to_value(),
+            ),
+        ];
+        for (assignments, value) in cases {
+            let genesis = Genesis::with(
+                SchemaId::default(),
+                Chain::Testnet3,
+                empty!(),
+                OwnedRights::from_inner(bmap! { 1 => assignments }),
+                empty!(),
+            );
+            let outpoint = TypedOutpoint::new(genesis.node_id(), 1, 0);
+            let original = ContractState::with_genesis(&genesis);
+            assert_eq!(original.state_values(), bmap! { outpoint => value.clone() });
+            assert_eq!(
+                original.attachments().count(),
+                (value.kind() == StateKind::Attachment) as usize
+            );
+
+            // Data and attachments are concealed keeping their kinds
+            let mut state = original.clone();
+            assert_eq!(state.conceal_state_except(&[]), 1);
+            assert_eq!(state.state_values(), bmap! { outpoint => value.conceal() });
+            assert!(state.owned_data.values().flatten().next().is_none());
+            assert!(state.attachments().next().is_none());
+
+            assert_eq!(state.merge_reveal(&original), 1);
+            assert_eq!(state.state_values(), original.state_values());
+            assert_eq!(state.attachments().count(), original.attachments().count());
+        }
+    }
+
+    #[test]
+    fn test_legacy_decoding() {
+        fn legacy<State: StateAtom>(
+            map: &BTreeMap<OwnedRightType, Vec<AssignedState<State>>>,
+        ) -> BTreeMap<OwnedRightType, Vec<LegacyAssignedState<State>>> {
+            map.iter()
+                .map(|(ty, items)| {
+                    let items = items
+                        .iter()
+                        .map(|assigned| LegacyAssignedState {
+                            seal: assigned.seal,
+                            state: assigned.state.clone(),
+                            outpoint: assigned.outpoint,
+                        })
+                        .collect();
+                    (*ty, items)
+                })
+                .collect()
+        }
+
+        let state = display_fixture();
+        let mut encoded = vec![];
+        state.contract_id.strict_encode(&mut encoded).unwrap();
+        state.metadata.strict_encode(&mut encoded).unwrap();
+        legacy(&state.owned_rights)
+            .strict_encode(&mut encoded)
+            .unwrap();
+        legacy(&state.owned_values)
+            .strict_encode(&mut encoded)
+            .unwrap();
+        legacy(&state.owned_data)
+            .strict_encode(&mut encoded)
+            .unwrap();
+        legacy(&state.owned_attachments)
+            .strict_encode(&mut encoded)
+            .unwrap();
+
+        // State of the unversioned layout keeps the assignments, but not the
+        // applied nodes
+        let decoded = ContractState::strict_deserialize(&encoded).unwrap();
+        assert_eq!(decoded.contract_id, state.contract_id);
+        assert_eq!(decoded.metadata, state.metadata);
+        assert_eq!(decoded.owned_rights, state.owned_rights);
+        assert_eq!(decoded.owned_values, state.owned_values);
+        assert!(decoded.concealed.is_empty());
+        assert!(decoded.nodes.is_empty());
+        assert!(decoded.history.is_empty());
+
+        // Decoded state is encoded with the current version
+        let reencoded = decoded.strict_serialize().unwrap();
+        assert_eq!(reencoded[..8], CONTRACT_STATE_MAGIC);
+        assert_eq!(reencoded[8], CONTRACT_STATE_VERSION);
+        assert_eq!(
+            ContractState::strict_deserialize(reencoded).unwrap(),
+            decoded
+        );
+
+        let mut unsupported = state.strict_serialize().unwrap();
+        unsupported[8] = CONTRACT_STATE_VERSION + 1;
+        assert!(ContractState::strict_deserialize(&unsupported).is_err());
+        unsupported[8] = 0;
+        assert!(ContractState::strict_deserialize(&unsupported).is_err());
+    }
+
+    #[test]
+    fn test_decode_version_1() {
+        let mut state = display_fixture();
+        let seal = seal::Revealed::from(OutPoint::new(Txid::from_inner([2u8; 32]), 3));
+        state.spent = bmap! { TypedOutpoint::new(node_id(1), 2, 0) => node_id(2) };
+        state.seal_commitments =
+            bmap! { TypedOutpoint::new(node_id(1), 3, 1) => seal.commit_conceal() };
+
+        let concealed = state
+            .concealed
+            .iter()
+            .map(|(ty, items)| {
+                let items = items
+                    .iter()
+                    .map(|item| LegacyConcealedAssignment {
+                        kind: item.kind,
+                        seal: item.seal,
+                        outpoint: item.outpoint,
+                        witness: item.witness,
+                    })
+                    .collect::<Vec<_>>();
+                (*ty, items)
+            })
+            .collect::<BTreeMap<_, _>>();
+        // Spent output without known assignments is dropped
+        let spent = bmap! {
+            NodeOutpoint::new(node_id(1), 0) => node_id(2),
+            NodeOutpoint::new(node_id(1), 5) => node_id(2)
+        };
+        let seal_commitments = bmap! { NodeOutpoint::new(node_id(1), 1) => seal.commit_conceal() };
+        let mut encoded = CONTRACT_STATE_MAGIC.to_vec();
+        1u8.strict_encode(&mut encoded).unwrap();
+        state.contract_id.strict_encode(&mut encoded).unwrap();
+        state.metadata.strict_encode(&mut encoded).unwrap();
+        state.owned_rights.strict_encode(&mut encoded).unwrap();
+        state.owned_values.strict_encode(&mut encoded).unwrap();
+        state.owned_data.strict_encode(&mut encoded).unwrap();
+        state.owned_attachments.strict_encode(&mut encoded).unwrap();
+        concealed.strict_encode(&mut encoded).unwrap();
+        state.nodes.strict_encode(&mut encoded).unwrap();
+        state.history.strict_encode(&mut encoded).unwrap();
+        state.witness_index.strict_encode(&mut encoded).unwrap();
+        spent.strict_encode(&mut encoded).unwrap();
+        seal_commitments.strict_encode(&mut encoded).unwrap();
+
+        // Spent outputs and seal commitments are attributed to the types of
+        // the assignments at the same node outputs
+        let decoded = ContractState::strict_deserialize(&encoded).unwrap();
+        assert_eq!(decoded, state);
+        assert!(decoded.is_spent(&TypedOutpoint::new(node_id(1), 2, 0)));
+        assert!(!decoded.is_spent(&TypedOutpoint::new(node_id(1), 3, 0)));
     }
 }
@@ -0,0 +1,233 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Deterministic fixture objects together with their canonical strict encoding
+//! and commitment ids, for checking interoperability with other RGB
+//! implementations.
+//!
+//! Fixtures do not use any randomness: seal and amount blinding factors are
+//! constants. Their vectors are checked against the golden files in
+//! `tests/vectors`, such that any change of the strict encoding layout or of
+//! the tagged hash midstates fails the tests. Ids are given as hex of their
+//! raw bytes (which is their strict encoding) rather than as display strings.
+
+use std::fmt::{self, Display, Formatter};
+
+use amplify::Wrapper;
+use bitcoin::hashes::hex::ToHex;
+use bitcoin::hashes::Hash;
+use bitcoin::{OutPoint, Txid};
+use bp::dbc::Proof;
+use commit_verify::lnpbp4::{self, MerkleBlock, MerkleTree, MultiSource};
+use commit_verify::{CommitConceal, CommitVerify};
+use lnpbp::chain::Chain;
+use rgb_core::{secp256k1zkp, value};
+use strict_encoding::StrictEncode;
+
+use crate::fungible::schema::{FieldType, OwnedRightType, TransitionType};
+use crate::fungible::BlindedSeal;
+use crate::{
+    data, seal, Anchor, Assignment, AssignmentVec, ContractId, Disclosure, Genesis, Metadata, Node,
+    NodeId, OwnedRights, ParentOwnedRights, Schema, SchemaId, SealEndpoint, StateTransfer,
+    Transition, TransitionBundle,
+};
+
+/// Amount allocated by the fixture genesis and kept by the fixture transfers
+pub const FIXTURE_AMOUNT: u64 = 1000;
+
+/// UNIX timestamp of the fixture asset issue
+pub const FIXTURE_TIMESTAMP: i64 = 1_600_000_000;
+
+/// Seal with the blinding factor equal to its number, closed over the first
+/// output of a transaction with all txid bytes equal to the number
+pub fn fixture_seal(no: u8) -> seal::Revealed {
+    let outpoint = OutPoint::new(Txid::from_inner([no; 32]), 0);
+    let (_, revealed) = BlindedSeal::blind_with(outpoint, no as u64);
+    revealed
+}
+
+/// Assets assignment of [`FIXTURE_AMOUNT`] to the seal [`fixture_seal`] with
+/// the number `no`. The unit blinding factor keeps the fixture nodes balanced.
+fn assets(no: u8) -> OwnedRights {
+    let assignment = Assignment::Revealed {
+        seal_definition: fixture_seal(no),
+        assigned_state: value::Revealed {
+            value: FIXTURE_AMOUNT,
+            blinding: secp256k1zkp::key::ONE_KEY.into(),
+        },
+    };
+    OwnedRights::from_inner(bmap! {
+        u16::from(OwnedRightType::Assets) => AssignmentVec::Fungible(vec![assignment])
+    })
+}
+
+/// Minimal disclosure, which does not contain any data
+pub fn disclosure() -> Disclosure { Disclosure::default() }
+
+/// Genesis of RGB20 fungible asset allocating [`FIXTURE_AMOUNT`] to the seal
+/// number 1
+pub fn genesis() -> Genesis {
+    let metadata = bmap! {
+        u16::from(FieldType::Ticker) => vec![data::Revealed::String(s!("TCKR"))],
+        u16::from(FieldType::Name) => vec![data::Revealed::String(s!("Test asset"))],
+        u16::from(FieldType::Precision) => vec![data::Revealed::U8(8)],
+        u16::from(FieldType::IssuedSupply) => vec![data::Revealed::U64(FIXTURE_AMOUNT)],
+        u16::from(FieldType::Timestamp) => vec![data::Revealed::I64(FIXTURE_TIMESTAMP)]
+    };
+    Genesis::with(
+        SchemaId::default(),
+        Chain::Testnet3,
+        Metadata::from_inner(metadata),
+        assets(1),
+        empty!(),
+    )
+}
+
+/// Transfer of the whole allocation from the output of the `parent` node to
+/// the seal number `no`
+fn transition(parent: NodeId, no: u8) -> Transition {
+    let assets_type = u16::from(OwnedRightType::Assets);
+    let parent_owned_rights = bmap! { parent => bmap! { assets_type => vec![0u16] } };
+    Transition::with(
+        u16::from(TransitionType::Transfer),
+        empty!(),
+        empty!(),
+        assets(no),
+        empty!(),
+        ParentOwnedRights::from_inner(parent_owned_rights),
+    )
+}
+
+/// Anchor with a fixed witness txid committing to the `bundle` under the
+/// contract protocol
+fn anchor(contract_id: ContractId, bundle: &TransitionBundle) -> Anchor<lnpbp4::MerkleProof> {
+    let protocol_id = lnpbp4::ProtocolId::from(contract_id);
+    let message = lnpbp4::Message::from_inner(Hash::into_inner(bundle.bundle_id()));
+    let source = MultiSource {
+        min_depth: 3,
+        messages: bmap! { protocol_id => message },
+        static_entropy: Some(0),
+    };
+    let anchor = Anchor {
+        txid: Txid::from_inner([0xFF; 32]),
+        lnpbp4_proof: MerkleBlock::from(MerkleTree::commit(&source)),
+        dbc_proof: Proof::OpretFirst,
+    };
+    anchor
+        .to_merkle_proof(protocol_id)
+        .expect("anchor commits to the contract")
+}
+
+/// State transfer of the [`genesis`] allocation with a chain of two
+/// transitions, each in its own bundle, ending at the concealed seal number 3
+pub fn transfer() -> StateTransfer {
+    let genesis = genesis();
+    let contract_id = genesis.contract_id();
+    let first = transition(genesis.node_id(), 2);
+    let second = transition(first.node_id(), 3);
+    let anchored_bundles = [first, second]
+        .into_iter()
+        .map(|transition| {
+            let bundle = TransitionBundle::from(bmap! { transition => bset![0u16] });
+            (anchor(contract_id, &bundle), bundle)
+        })
+        .collect::<Vec<_>>();
+    let (_, last) = &anchored_bundles[1];
+    let endpoint = SealEndpoint::ConcealedUtxo(fixture_seal(3).commit_conceal());
+    StateTransfer::with(
+        Schema::default(),
+        None,
+        genesis,
+        vec![(last.bundle_id(), endpoint)],
+        anchored_bundles
+            .try_into()
+            .expect("two bundles fit into the consignment"),
+        empty!(),
+    )
+}
+
+/// Canonical strict encoding of a fixture object together with its
+/// commitment ids
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct TestVector {
+    /// Name of the fixture object
+    pub name: &'static str,
+    /// Strict-encoded fixture object
+    pub data: Vec<u8>,
+    /// Names and hex-encoded values of the commitment ids
+    pub ids: Vec<(&'static str, String)>,
+}
+
+impl TestVector {
+    fn with(name: &'static str, object: &impl StrictEncode) -> TestVector {
+        TestVector {
+            name,
+            data: strict_serialize(object),
+            ids: vec![],
+        }
+    }
+
+    fn id(mut self, name: &'static str, id: &impl StrictEncode) -> TestVector {
+        self.ids.push((name, strict_serialize(id).to_hex()));
+        self
+    }
+}
+
+/// Renders the vector in the format of the golden files: a line with the hex
+/// of the strict-encoded data followed by a line per each id
+impl Display for TestVector {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "data: {}", self.data.to_hex())?;
+        for (name, id) in &self.ids {
+            writeln!(f, "{}: {}", name, id)?;
+        }
+        Ok(())
+    }
+}
+
+fn strict_serialize(object: &impl StrictEncode) -> Vec<u8> {
+    object
+        .strict_serialize()
+        .expect("in-memory encoding of the fixture objects does not fail")
+}
+
+/// Vector of the [`disclosure`] fixture with its id and sig hash
+pub fn disclosure_vector() -> TestVector {
+    let disclosure = disclosure();
+    TestVector::with("disclosure", &disclosure)
+        .id("disclosure_id", &disclosure.id())
+        .id("sig_hash", &disclosure.sig_hash())
+}
+
+/// Vector of the [`genesis`] fixture with its node and contract ids
+pub fn genesis_vector() -> TestVector {
+    let genesis = genesis();
+    TestVector::with("genesis", &genesis)
+        .id("node_id", &genesis.node_id())
+        .id("contract_id", &genesis.contract_id())
+}
+
+/// Vector of the [`transfer`] fixture with the consignment and bundle ids
+pub fn transfer_vector() -> TestVector {
+    let transfer = transfer();
+    let mut vector = TestVector::with("transfer", &transfer)
+        .id("consignment_id", &transfer.id())
+        .id("contract_id", &transfer.contract_id());
+    for (_, bundle) in transfer.anchored_bundles.iter() {
+        vector = vector.id("bundle_id", &bundle.bundle_id());
+    }
+    vector
+}
+
+/// Vectors of all fixture objects
+pub fn vectors() -> Vec<TestVector> {
+    vec![disclosure_vector(), genesis_vector(), transfer_vector()]
+}
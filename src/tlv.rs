@@ -0,0 +1,141 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Forward-compatible extension area of the strict-encoded containers.
+//!
+//! New optional fields are added to [`crate::Disclosure`] and
+//! [`crate::InmemConsignment`] as type-length-value records without changing
+//! their layout. Even record types are reserved for this library and must be
+//! understood by the reader: data with an unknown even record fail to decode.
+//! Odd record types may be ignored by the readers which do not know them; such
+//! records are kept and re-encoded verbatim.
+
+use std::collections::{btree_map, BTreeMap};
+use std::io;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer};
+use strict_encoding::{StrictDecode, StrictEncode};
+
+/// Even record types known to this version of the library
+pub const KNOWN_TLV_TYPES: &[u16] = &[];
+
+/// Errors of the TLV extension records
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum TlvError {
+    /// extension record of even type {0} must be understood by the reader,
+    /// but is not known to this version of the library
+    UnknownEvenType(u16),
+}
+
+/// Checks that the record type is either odd or known to the library
+#[inline]
+fn check_type(ty: u16) -> Result<(), TlvError> {
+    if ty % 2 == 0 && !KNOWN_TLV_TYPES.contains(&ty) {
+        return Err(TlvError::UnknownEvenType(ty));
+    }
+    Ok(())
+}
+
+/// Extension records of a container, ordered by their type. Records can be
+/// added only with [`TlvMap::insert`] which checks their type; decoding and
+/// deserialization check the record types in the same way.
+#[cfg_attr(feature = "serde", derive(Serialize), serde(crate = "serde_crate"))]
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default, StrictEncode)]
+pub struct TlvMap(BTreeMap<u16, Vec<u8>>);
+
+impl TlvMap {
+    /// Constructs empty extension area
+    #[inline]
+    pub fn new() -> TlvMap { TlvMap::default() }
+
+    /// Returns value of the record with the type `ty`, if present
+    #[inline]
+    pub fn get(&self, ty: u16) -> Option<&[u8]> { self.0.get(&ty).map(Vec::as_slice) }
+
+    /// Adds record of the type `ty`, returning the previous value of the
+    /// record. Fails for the unknown even record types, since data containing
+    /// them can't be read back.
+    pub fn insert(&mut self, ty: u16, value: Vec<u8>) -> Result<Option<Vec<u8>>, TlvError> {
+        check_type(ty)?;
+        Ok(self.0.insert(ty, value))
+    }
+
+    /// Removes record of the type `ty`, returning its value
+    #[inline]
+    pub fn remove(&mut self, ty: u16) -> Option<Vec<u8>> { self.0.remove(&ty) }
+
+    /// Returns number of the records
+    #[inline]
+    pub fn len(&self) -> usize { self.0.len() }
+
+    /// Detects whether there are no records
+    #[inline]
+    pub fn is_empty(&self) -> bool { self.0.is_empty() }
+
+    /// Iterates over the records in the order of their types
+    #[inline]
+    pub fn iter(&self) -> btree_map::Iter<u16, Vec<u8>> { self.0.iter() }
+}
+
+impl StrictDecode for TlvMap {
+    fn strict_decode<D: io::Read>(d: D) -> Result<Self, strict_encoding::Error> {
+        let map = BTreeMap::<u16, Vec<u8>>::strict_decode(d)?;
+        for ty in map.keys() {
+            check_type(*ty)
+                .map_err(|err| strict_encoding::Error::DataIntegrityError(err.to_string()))?;
+        }
+        Ok(TlvMap(map))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for TlvMap {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where D: Deserializer<'de> {
+        let map = BTreeMap::<u16, Vec<u8>>::deserialize(deserializer)?;
+        for ty in map.keys() {
+            check_type(*ty).map_err(serde::de::Error::custom)?;
+        }
+        Ok(TlvMap(map))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_record_types() {
+        let mut tlv = TlvMap::new();
+        assert_eq!(tlv.insert(1, vec![1, 2, 3]), Ok(None));
+        assert_eq!(tlv.insert(1, vec![4]), Ok(Some(vec![1, 2, 3])));
+        assert_eq!(tlv.insert(2, vec![]), Err(TlvError::UnknownEvenType(2)));
+        assert_eq!(tlv.get(1), Some(&[4u8][..]));
+        assert_eq!(tlv.len(), 1);
+
+        let data = tlv.strict_serialize().unwrap();
+        assert_eq!(data, vec![1, 0, 1, 0, 1, 0, 4]);
+        assert_eq!(TlvMap::strict_deserialize(&data).unwrap(), tlv);
+        assert!(TlvMap::strict_deserialize(&[1, 0, 2, 0, 1, 0, 4]).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_deserialize_types() {
+        let mut tlv = TlvMap::new();
+        tlv.insert(3, vec![1, 2]).unwrap();
+        let json = serde_json::to_string(&tlv).unwrap();
+        assert_eq!(serde_json::from_str::<TlvMap>(&json).unwrap(), tlv);
+        assert!(serde_json::from_str::<TlvMap>(r#"{"2":[1]}"#).is_err());
+    }
+}
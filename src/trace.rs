@@ -0,0 +1,137 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Macros emitting `tracing` events when the `tracing` feature is enabled and
+//! expanding into nothing otherwise.
+//!
+//! Spans are attached to the long-running operations with
+//! `#[cfg_attr(feature = "tracing", tracing::instrument(...))]`; their
+//! durations are measured by the subscriber from the span enter and exit
+//! times.
+
+/// Emits debug-level event for a single processed item of a long-running
+/// operation
+macro_rules! debug_event {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        {
+            ::tracing::debug!($($arg)*);
+        }
+    };
+}
+
+/// Emits warn-level event for an anomaly which did not prevent the operation
+/// from completing
+macro_rules! warn_event {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        {
+            ::tracing::warn!($($arg)*);
+        }
+    };
+}
+
+#[cfg(all(test, feature = "tracing"))]
+pub(crate) mod test {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    use crate::stash::Stash;
+    use crate::verify::test::consignment;
+    use crate::MemStash;
+
+    /// Names of the created spans together with the names of their parents
+    pub(crate) type SpanNames = Vec<(&'static str, Option<&'static str>)>;
+
+    type SpanLog = Arc<Mutex<SpanNames>>;
+
+    /// Subscriber recording names of the created spans together with the
+    /// names of their parent spans
+    #[derive(Default)]
+    struct SpanRecorder {
+        last_id: AtomicU64,
+        names: Mutex<Vec<&'static str>>,
+        entered: Mutex<Vec<u64>>,
+        log: SpanLog,
+    }
+
+    impl Subscriber for SpanRecorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool { true }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let id = self.last_id.fetch_add(1, Ordering::SeqCst) + 1;
+            let mut names = self.names.lock().unwrap();
+            let parent = match span.parent() {
+                Some(parent) => Some(parent.into_u64()),
+                None if span.is_contextual() => self.entered.lock().unwrap().last().copied(),
+                None => None,
+            };
+            let parent = parent.map(|parent| names[parent as usize - 1]);
+            let name = span.metadata().name();
+            names.push(name);
+            self.log.lock().unwrap().push((name, parent));
+            Id::from_u64(id)
+        }
+
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, _: &Event<'_>) {}
+
+        fn enter(&self, span: &Id) {
+            self.entered.lock().unwrap().push(span.into_u64());
+        }
+
+        fn exit(&self, _: &Id) {
+            self.entered.lock().unwrap().pop();
+        }
+    }
+
+    /// Runs `f` recording the spans it creates
+    pub(crate) fn record_spans(f: impl FnOnce()) -> SpanNames {
+        let recorder = SpanRecorder::default();
+        let log = recorder.log.clone();
+        tracing::subscriber::with_default(recorder, f);
+        let names = log.lock().unwrap().clone();
+        names
+    }
+
+    #[test]
+    fn test_accept_spans() {
+        let consignment = consignment(2);
+        let spans = record_spans(|| {
+            let mut stash = MemStash::new();
+            stash.accept(&consignment, &[]).unwrap();
+        });
+        assert_eq!(spans, vec![
+            ("accept", None),
+            ("with_consignment", Some("accept")),
+            ("merge", Some("accept"))
+        ]);
+    }
+
+    #[test]
+    fn test_verify_spans() {
+        let consignment = consignment(3);
+        let spans = record_spans(|| assert!(consignment.verify_bundles().is_valid()));
+        assert_eq!(spans, vec![
+            ("verify_bundles", None),
+            ("bundle_levels", Some("verify_bundles")),
+            ("verify_level", Some("verify_bundles")),
+            ("verify_level", Some("verify_bundles")),
+            ("verify_level", Some("verify_bundles"))
+        ]);
+    }
+}
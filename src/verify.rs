@@ -0,0 +1,666 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Structural verification of the state transition bundles against the
+//! contract schema.
+//!
+//! Consignment bundles are verified in topological order, level by level:
+//! bundles of each level spend outputs only of the genesis, state extensions
+//! and bundles of the previous levels, so a bundle spending outputs of a
+//! failed bundle is reported as failed as well. Bundles within a level are
+//! independent, so with the `rayon` feature they are verified in parallel.
+//! The failures are always reported in the order of the bundles, making
+//! parallel reports identical to the serial ones. Seal closings and anchor
+//! commitments are not checked here; this remains the task of the
+//! consignment validation by [`rgb_core::Validator`].
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use amplify::Wrapper;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+use rgb_core::schema::{OwnedRightType, TransitionType};
+
+use crate::{
+    BundleId, ConsignmentType, ContractId, Disclosure, Extension, InmemConsignment, Node, NodeId,
+    NodeOutpoint, Schema, Transition, TransitionBundle,
+};
+
+/// Failures detected by the bundle verification
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Display, Error)]
+#[derive(StrictEncode, StrictDecode)]
+#[display(doc_comments)]
+pub enum BundleFailure {
+    /// bundle {0} belongs to the contract {1}, which schema is not known
+    UnknownContract(BundleId, ContractId),
+
+    /// transition {0} has type {1}, which is not defined by the schema
+    UnknownTransitionType(NodeId, TransitionType),
+
+    /// transition {0} spends outputs of the node {1}, which is not present
+    UnknownParent(NodeId, NodeId),
+
+    /// transition {0} spends non-existing output {1}
+    AbsentParentOutput(NodeId, NodeOutpoint),
+
+    /// transition {0} closes seals of the owned right type {1}, which is not
+    /// allowed by the schema
+    ClosesNotAllowed(NodeId, OwnedRightType),
+
+    /// transition {0} assigns owned right type {1}, which is not allowed by
+    /// the schema
+    OwnedRightNotAllowed(NodeId, OwnedRightType),
+    /// transition {0} spends outputs of the node {1}, which has failed the
+    /// verification
+    InvalidParent(NodeId, NodeId),
+}
+
+/// Report of the bundle verification
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+#[derive(StrictEncode, StrictDecode)]
+pub struct BundleReport {
+    /// Detected failures, in the order of the verified bundles
+    pub failures: Vec<BundleFailure>,
+}
+
+impl BundleReport {
+    /// Detects whether all the bundles have passed the verification
+    #[inline]
+    pub fn is_valid(&self) -> bool { self.failures.is_empty() }
+}
+
+impl FromIterator<Vec<BundleFailure>> for BundleReport {
+    fn from_iter<I: IntoIterator<Item = Vec<BundleFailure>>>(iter: I) -> Self {
+        BundleReport {
+            failures: iter.into_iter().flatten().collect(),
+        }
+    }
+}
+
+/// Number of the outputs of each owned right type defined by the nodes
+type OutputIndex = BTreeMap<NodeId, BTreeMap<OwnedRightType, usize>>;
+
+fn index_node(index: &mut OutputIndex, node: &impl Node) {
+    let outputs = node
+        .owned_rights()
+        .iter()
+        .map(|(ty, assignments)| (*ty, assignments.len()))
+        .collect();
+    index.insert(node.node_id(), outputs);
+}
+
+/// Nodes known to the verification of the consignment bundles
+struct KnownNodes<'nodes> {
+    /// Outputs of all the consignment nodes
+    outputs: &'nodes OutputIndex,
+
+    /// Nodes which have passed the verification; genesis and state extensions
+    /// are not verified and are always present
+    valid: &'nodes BTreeSet<NodeId>,
+}
+
+/// Verifies all known transitions of the `bundle` against the `schema`. If the
+/// `known` nodes are provided, also checks that the spent parent outputs exist
+/// and that the parents have passed the verification.
+fn verify_bundle(
+    schema: &Schema,
+    known: Option<&KnownNodes>,
+    bundle: &TransitionBundle,
+) -> Vec<BundleFailure> {
+    let mut failures = vec![];
+    for transition in bundle.known_transitions() {
+        let node_id = transition.node_id();
+        let transition_type = transition.transition_type();
+        let transition_schema = match schema.transitions.get(&transition_type) {
+            Some(transition_schema) => transition_schema,
+            None => {
+                let failure = BundleFailure::UnknownTransitionType(node_id, transition_type);
+                failures.push(failure);
+                continue;
+            }
+        };
+
+        for (parent_id, spent) in transition.parent_owned_rights().as_inner() {
+            let parent = match known.map(|known| (known.outputs.get(parent_id), known)) {
+                Some((Some(parent), known)) => {
+                    if !known.valid.contains(parent_id) {
+                        failures.push(BundleFailure::InvalidParent(node_id, *parent_id));
+                    }
+                    Some(parent)
+                }
+                Some((None, _)) => {
+                    failures.push(BundleFailure::UnknownParent(node_id, *parent_id));
+                    None
+                }
+                None => None,
+            };
+            for (ty, output_nos) in spent {
+                if !transition_schema.closes.contains_key(ty) {
+                    failures.push(BundleFailure::ClosesNotAllowed(node_id, *ty));
+                }
+                let count = match parent {
+                    Some(parent) => parent.get(ty).copied().unwrap_or_default(),
+                    None => continue,
+                };
+                for output_no in output_nos.iter().filter(|no| usize::from(**no) >= count) {
+                    let outpoint = NodeOutpoint::new(*parent_id, *output_no);
+                    failures.push(BundleFailure::AbsentParentOutput(node_id, outpoint));
+                }
+            }
+        }
+
+        for (ty, _) in transition.owned_rights().iter() {
+            if !transition_schema.owned_rights.contains_key(ty) {
+                failures.push(BundleFailure::OwnedRightNotAllowed(node_id, *ty));
+            }
+        }
+    }
+    debug_event!(
+        bundle_id = %bundle.bundle_id(),
+        failures = failures.len(),
+        "bundle verified"
+    );
+    failures
+}
+
+impl<T> InmemConsignment<T>
+where T: ConsignmentType
+{
+    fn output_index(&self) -> OutputIndex {
+        let mut index = OutputIndex::new();
+        index_node(&mut index, &self.genesis);
+        for extension in self.state_extensions.iter() {
+            index_node(&mut index, extension);
+        }
+        for transition in self
+            .anchored_bundles
+            .iter()
+            .flat_map(|(_, bundle)| bundle.known_transitions())
+        {
+            index_node(&mut index, transition);
+        }
+        index
+    }
+
+    /// Groups indexes of the consignment bundles by their topological levels.
+    /// Bundles of each level spend outputs only of the genesis, state
+    /// extensions and bundles of the previous levels; bundles with cyclic
+    /// dependencies form the last level.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(bundles = self.anchored_bundles.len()))
+    )]
+    pub(crate) fn bundle_levels(&self) -> Vec<Vec<usize>> {
+        let bundles = self
+            .anchored_bundles
+            .iter()
+            .map(|(_, bundle)| bundle)
+            .collect::<Vec<_>>();
+        let owners = bundles
+            .iter()
+            .enumerate()
+            .flat_map(|(no, bundle)| {
+                bundle
+                    .known_transitions()
+                    .map(move |transition| (transition.node_id(), no))
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        let mut pending = vec![0usize; bundles.len()];
+        let mut children = vec![BTreeSet::<usize>::new(); bundles.len()];
+        for (no, bundle) in bundles.iter().enumerate() {
+            let parents = bundle
+                .known_transitions()
+                .flat_map(|transition| transition.parent_owned_rights().as_inner().keys())
+                .filter_map(|parent_id| owners.get(parent_id).copied())
+                .filter(|parent| *parent != no)
+                .collect::<BTreeSet<_>>();
+            pending[no] = parents.len();
+            for parent in parents {
+                children[parent].insert(no);
+            }
+        }
+
+        let mut levels = vec![];
+        let mut level = (0..bundles.len())
+            .filter(|no| pending[*no] == 0)
+            .collect::<Vec<_>>();
+        let mut count = 0usize;
+        while !level.is_empty() {
+            let mut next = vec![];
+            for no in &level {
+                for child in &children[*no] {
+                    pending[*child] -= 1;
+                    if pending[*child] == 0 {
+                        next.push(*child);
+                    }
+                }
+            }
+            next.sort_unstable();
+            count += level.len();
+            levels.push(level);
+            level = next;
+        }
+        if count < bundles.len() {
+            warn_event!(
+                skipped = bundles.len() - count,
+                "bundles with cyclic dependencies are verified last"
+            );
+            levels.push((0..bundles.len()).filter(|no| pending[*no] > 0).collect());
+        }
+        levels
+    }
+
+    /// Verifies the consignment bundles level by level, running verification
+    /// of the bundles of each level with `verify_level`, which must return
+    /// failures in the order of the provided bundles
+    fn verify_levels(
+        &self,
+        verify_level: impl Fn(&[&TransitionBundle], &KnownNodes) -> Vec<Vec<BundleFailure>>,
+    ) -> BundleReport {
+        let outputs = self.output_index();
+        let mut valid = self
+            .state_extensions
+            .iter()
+            .map(Extension::node_id)
+            .chain(Some(self.genesis.node_id()))
+            .collect::<BTreeSet<_>>();
+        let bundles = self
+            .anchored_bundles
+            .iter()
+            .map(|(_, bundle)| bundle)
+            .collect::<Vec<_>>();
+
+        let mut failures = vec![vec![]; bundles.len()];
+        for (_depth, level) in self.bundle_levels().into_iter().enumerate() {
+            #[cfg(feature = "tracing")]
+            let _span = tracing::debug_span!("verify_level", depth = _depth, bundles = level.len())
+                .entered();
+            let level_bundles = level.iter().map(|no| bundles[*no]).collect::<Vec<_>>();
+            let known = KnownNodes {
+                outputs: &outputs,
+                valid: &valid,
+            };
+            let level_failures = verify_level(&level_bundles, &known);
+            for (no, bundle_failures) in level.into_iter().zip(level_failures) {
+                if bundle_failures.is_empty() {
+                    valid.extend(bundles[no].known_transitions().map(Transition::node_id));
+                }
+                failures[no] = bundle_failures;
+            }
+        }
+        failures.into_iter().collect()
+    }
+
+    /// Verifies the structure of all the consignment bundles against the
+    /// consignment schema in topological order
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(contract_id = %self.contract_id(), bundles = self.anchored_bundles.len())
+        )
+    )]
+    pub fn verify_bundles(&self) -> BundleReport {
+        self.verify_levels(|bundles, known| {
+            bundles
+                .iter()
+                .map(|bundle| verify_bundle(&self.schema, Some(known), bundle))
+                .collect()
+        })
+    }
+
+    /// Verifies the structure of the consignment bundles like
+    /// [`InmemConsignment::verify_bundles`], processing bundles of each
+    /// topological level in parallel
+    #[cfg(feature = "rayon")]
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(contract_id = %self.contract_id(), bundles = self.anchored_bundles.len())
+        )
+    )]
+    pub fn par_verify_bundles(&self) -> BundleReport {
+        self.verify_levels(|bundles, known| {
+            bundles
+                .par_iter()
+                .map(|bundle| verify_bundle(&self.schema, Some(known), bundle))
+                .collect()
+        })
+    }
+}
+
+impl Disclosure {
+    fn contract_bundles(&self) -> Vec<(ContractId, &TransitionBundle)> {
+        self.anchored_bundles()
+            .values()
+            .flat_map(|(_, bundles)| bundles.iter())
+            .map(|(contract_id, bundle)| (*contract_id, bundle))
+            .collect()
+    }
+
+    /// Verifies the structure of all the disclosure bundles against the
+    /// `schemata` of their contracts. Since the disclosure does not contain
+    /// the parent nodes, presence of the spent outputs is not checked.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(anchors = self.anchored_bundles().len())
+        )
+    )]
+    pub fn verify_bundles(&self, schemata: &BTreeMap<ContractId, Schema>) -> BundleReport {
+        self.contract_bundles()
+            .into_iter()
+            .map(|(contract_id, bundle)| verify_contract_bundle(schemata, contract_id, bundle))
+            .collect()
+    }
+
+    /// Verifies the structure of the disclosure bundles like
+    /// [`Disclosure::verify_bundles`], processing bundles in parallel
+    #[cfg(feature = "rayon")]
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(anchors = self.anchored_bundles().len())
+        )
+    )]
+    pub fn par_verify_bundles(&self, schemata: &BTreeMap<ContractId, Schema>) -> BundleReport {
+        let failures = self
+            .contract_bundles()
+            .into_par_iter()
+            .map(|(contract_id, bundle)| verify_contract_bundle(schemata, contract_id, bundle))
+            .collect::<Vec<_>>();
+        failures.into_iter().collect()
+    }
+}
+
+fn verify_contract_bundle(
+    schemata: &BTreeMap<ContractId, Schema>,
+    contract_id: ContractId,
+    bundle: &TransitionBundle,
+) -> Vec<BundleFailure> {
+    match schemata.get(&contract_id) {
+        Some(schema) => verify_bundle(schema, None, bundle),
+        None => vec![BundleFailure::UnknownContract(bundle.bundle_id(), contract_id)],
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use bitcoin::hashes::Hash;
+    use bitcoin::secp256k1::rand::rngs::StdRng;
+    use bitcoin::secp256k1::rand::SeedableRng;
+    use bitcoin::{OutPoint, Txid};
+    use bp::dbc::Proof;
+    use commit_verify::lnpbp4::{self, MerkleBlock, MerkleTree, MultiSource};
+    use commit_verify::CommitVerify;
+    use lnpbp::chain::Chain;
+    use rgb_core::schema::{Occurrences, TransitionSchema};
+    use rgb_core::value;
+    use strict_encoding::StrictEncode;
+
+    use super::*;
+    use crate::{
+        seal, Anchor, Assignment, AssignmentVec, Genesis, OwnedRights, ParentOwnedRights,
+        StateTransfer, Transition,
+    };
+
+    const TRANSFER: TransitionType = 1;
+    const ASSETS: OwnedRightType = 1;
+
+    /// Constructs assignment with blinding factor generated from the `seed`,
+    /// keeping the fixtures deterministic
+    fn assignments(seed: u64) -> OwnedRights {
+        let seal = seal::Revealed::from(OutPoint::new(Txid::from_inner([1u8; 32]), 0));
+        let mut rng = StdRng::seed_from_u64(seed);
+        let assignment = Assignment::Revealed {
+            seal_definition: seal,
+            assigned_state: value::Revealed::with_amount(1, &mut rng),
+        };
+        OwnedRights::from_inner(bmap! { ASSETS => AssignmentVec::Fungible(vec![assignment]) })
+    }
+
+    fn transition(parent: NodeId, output_no: u16) -> Transition {
+        let parent_owned_rights = bmap! { parent => bmap! { ASSETS => vec![output_no] } };
+        Transition::with(
+            TRANSFER,
+            empty!(),
+            empty!(),
+            assignments(0),
+            empty!(),
+            ParentOwnedRights::from_inner(parent_owned_rights),
+        )
+    }
+
+    fn anchor(contract_id: ContractId) -> Anchor<MerkleBlock> {
+        anchor_with(contract_id, lnpbp4::Message::from_inner([0u8; 32]))
+    }
+
+    /// Constructs anchor committing to the `message` under the contract
+    /// protocol id
+    fn anchor_with(contract_id: ContractId, message: lnpbp4::Message) -> Anchor<MerkleBlock> {
+        let protocol_id = lnpbp4::ProtocolId::from(contract_id);
+        let source = MultiSource {
+            min_depth: 3,
+            messages: bmap! { protocol_id => message },
+            static_entropy: None,
+        };
+        Anchor {
+            txid: Txid::from_inner([2u8; 32]),
+            lnpbp4_proof: MerkleBlock::from(MerkleTree::commit(&source)),
+            dbc_proof: Proof::OpretFirst,
+        }
+    }
+
+    /// Constructs consignment with a chain of `count` transfers, each spending
+    /// the first output of the previous one. Each bundle is committed with its
+    /// own anchor. Consignments with different `count` belong to different
+    /// contracts.
+    pub(crate) fn consignment(count: usize) -> StateTransfer {
+        let mut schema = Schema::default();
+        schema.transitions.insert(
+            TRANSFER,
+            TransitionSchema {
+                closes: bmap! { ASSETS => Occurrences::NoneOrMore },
+                owned_rights: bmap! { ASSETS => Occurrences::NoneOrMore },
+                ..Default::default()
+            },
+        );
+        let genesis = Genesis::with(
+            schema.schema_id(),
+            Chain::Testnet3,
+            empty!(),
+            assignments(count as u64),
+            empty!(),
+        );
+        let contract_id = genesis.contract_id();
+        let mut parent = genesis.node_id();
+        let mut anchored_bundles = vec![];
+        for _ in 0..count {
+            let transition = transition(parent, 0);
+            parent = transition.node_id();
+            let bundle = TransitionBundle::from(bmap! { transition => bset![0u16] });
+            let message = lnpbp4::Message::from_inner(Hash::into_inner(bundle.bundle_id()));
+            let anchor = anchor_with(contract_id, message)
+                .to_merkle_proof(lnpbp4::ProtocolId::from(contract_id))
+                .unwrap();
+            anchored_bundles.push((anchor, bundle));
+        }
+        StateTransfer::with(
+            schema,
+            None,
+            genesis,
+            empty!(),
+            anchored_bundles.try_into().unwrap(),
+            empty!(),
+        )
+    }
+
+    #[test]
+    fn test_valid_bundles() {
+        let consignment = consignment(10);
+        assert!(consignment.verify_bundles().is_valid());
+    }
+
+    #[test]
+    fn test_bundle_failures() {
+        let mut consignment = consignment(2);
+        let unknown = NodeId::from_inner(Hash::from_inner([3u8; 32]));
+        let genesis_id = consignment.genesis.node_id();
+        let mut absent = transition(genesis_id, 1);
+        absent
+            .owned_rights_mut()
+            .insert(2, AssignmentVec::Declarative(vec![]));
+        let orphan = transition(unknown, 0);
+        let anchor = consignment
+            .anchored_bundles
+            .iter()
+            .next()
+            .unwrap()
+            .0
+            .clone();
+        let bundle = TransitionBundle::from(bmap! {
+            absent.clone() => bset![0u16],
+            orphan.clone() => bset![1u16]
+        });
+        let mut anchored_bundles = consignment
+            .anchored_bundles
+            .iter()
+            .cloned()
+            .collect::<Vec<_>>();
+        anchored_bundles.push((anchor, bundle));
+        consignment.anchored_bundles = anchored_bundles.try_into().unwrap();
+
+        let absent_output = NodeOutpoint::new(genesis_id, 1);
+        let mut expected = vec![
+            BundleFailure::AbsentParentOutput(absent.node_id(), absent_output),
+            BundleFailure::OwnedRightNotAllowed(absent.node_id(), 2),
+            BundleFailure::UnknownParent(orphan.node_id(), unknown),
+        ];
+        // Transitions inside the bundle are ordered by their ids
+        if orphan < absent {
+            expected.rotate_left(2);
+        }
+        assert_eq!(consignment.verify_bundles().failures, expected);
+    }
+
+    #[test]
+    fn test_bundle_levels() {
+        let mut consignment = consignment(3);
+        assert_eq!(consignment.bundle_levels(), vec![vec![0], vec![1], vec![2]]);
+        assert!(consignment.verify_bundles().is_valid());
+
+        // Bundle spending outputs of a failed bundle fails as well, even if it
+        // precedes its parent in the consignment
+        let genesis_id = consignment.genesis.node_id();
+        let mut invalid = transition(genesis_id, 0);
+        invalid
+            .owned_rights_mut()
+            .insert(2, AssignmentVec::Declarative(vec![]));
+        let child = transition(invalid.node_id(), 0);
+        let mut anchored_bundles = consignment
+            .anchored_bundles
+            .iter()
+            .cloned()
+            .collect::<Vec<_>>();
+        let anchor = anchored_bundles[0].0.clone();
+        for transition in [child.clone(), invalid.clone()] {
+            let bundle = TransitionBundle::from(bmap! { transition => bset![0u16] });
+            anchored_bundles.push((anchor.clone(), bundle));
+        }
+        consignment.anchored_bundles = anchored_bundles.try_into().unwrap();
+        assert_eq!(
+            consignment.bundle_levels(),
+            vec![vec![0, 4], vec![1, 3], vec![2]]
+        );
+        assert_eq!(consignment.verify_bundles().failures, vec![
+            BundleFailure::InvalidParent(child.node_id(), invalid.node_id()),
+            BundleFailure::OwnedRightNotAllowed(invalid.node_id(), 2),
+        ]);
+    }
+
+    #[test]
+    fn test_disclosure_schemata() {
+        let consignment = consignment(3);
+        let contract_id = consignment.contract_id();
+        let bundles = consignment
+            .anchored_bundles
+            .iter()
+            .map(|(_, bundle)| bundle.clone())
+            .collect::<Vec<_>>();
+        let mut disclosure = Disclosure::default();
+        disclosure
+            .insert_anchored_bundles(
+                anchor(contract_id),
+                bmap! {
+                    contract_id => bundles[0].clone()
+                },
+            )
+            .unwrap();
+
+        assert_eq!(
+            disclosure.verify_bundles(&empty!()).failures,
+            vec![BundleFailure::UnknownContract(bundles[0].bundle_id(), contract_id)]
+        );
+        let schemata = bmap! { contract_id => consignment.schema.clone() };
+        assert!(disclosure.verify_bundles(&schemata).is_valid());
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_parallel_identity() {
+        let mut consignment = consignment(200);
+        // Make every transition fail to get a long list of failures, whose
+        // order must be preserved by the parallel verification
+        let transfer = consignment.schema.transitions.get_mut(&TRANSFER).unwrap();
+        transfer.closes.clear();
+        let serial = consignment.verify_bundles();
+        let parallel = consignment.par_verify_bundles();
+        // Each transition but the first also spends an invalid parent
+        assert_eq!(serial.failures.len(), 399);
+        assert_eq!(
+            serial.strict_serialize().unwrap(),
+            parallel.strict_serialize().unwrap()
+        );
+
+        let contract_id = consignment.contract_id();
+        let mut disclosure = Disclosure::default();
+        for (no, (_, bundle)) in consignment.anchored_bundles.iter().enumerate() {
+            let contract_id = if no % 2 == 0 { ContractId::default() } else { contract_id };
+            disclosure
+                .insert_anchored_bundles(
+                    anchor(contract_id),
+                    bmap! {
+                        contract_id => bundle.clone()
+                    },
+                )
+                .unwrap();
+        }
+        let schemata = bmap! { contract_id => consignment.schema.clone() };
+        assert_eq!(
+            disclosure
+                .verify_bundles(&schemata)
+                .strict_serialize()
+                .unwrap(),
+            disclosure
+                .par_verify_bundles(&schemata)
+                .strict_serialize()
+                .unwrap()
+        );
+    }
+}
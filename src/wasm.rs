@@ -0,0 +1,38 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! JavaScript bindings for parsing consignments in the browser wallets.
+//!
+//! Consignments are passed as strict-encoded byte arrays; decoding errors are
+//! returned as JavaScript strings.
+
+use strict_encoding::StrictDecode;
+use wasm_bindgen::prelude::*;
+
+use crate::StateTransfer;
+
+fn decode(data: &[u8]) -> Result<StateTransfer, JsValue> {
+    StateTransfer::strict_decode(data).map_err(|err| JsValue::from_str(&err.to_string()))
+}
+
+/// Decodes strict-encoded state transfer consignment, returning its
+/// [`crate::ConsignmentId`] string
+#[wasm_bindgen(js_name = consignmentId)]
+pub fn consignment_id(data: &[u8]) -> Result<String, JsValue> {
+    decode(data).map(|consignment| consignment.id().to_string())
+}
+
+/// Decodes strict-encoded state transfer consignment, returning id of the
+/// contract it belongs to
+#[wasm_bindgen(js_name = consignmentContractId)]
+pub fn consignment_contract_id(data: &[u8]) -> Result<String, JsValue> {
+    decode(data).map(|consignment| consignment.contract_id().to_string())
+}
@@ -0,0 +1,49 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Checks [`ElectrumResolver`] against a running Electrum server. The test is
+//! skipped unless `RGB_ELECTRUM_URL` points to the server (like
+//! `tcp://localhost:50001` for a local electrs) and `RGB_ELECTRUM_TXID` is a
+//! mined transaction known to it.
+
+#![cfg(all(feature = "electrum", not(target_arch = "wasm32")))]
+
+use std::env;
+use std::str::FromStr;
+
+use bitcoin::Txid;
+use rgb::validation::ResolveTx;
+use rgb::{ElectrumResolver, ResolveWitness};
+
+#[test]
+fn test_electrum_resolver() {
+    let (url, txid) = match (env::var("RGB_ELECTRUM_URL"), env::var("RGB_ELECTRUM_TXID")) {
+        (Ok(url), Ok(txid)) => (
+            url,
+            Txid::from_str(&txid).expect("invalid RGB_ELECTRUM_TXID"),
+        ),
+        _ => {
+            eprintln!("RGB_ELECTRUM_URL or RGB_ELECTRUM_TXID is not set, skipping");
+            return;
+        }
+    };
+    let mut resolver = ElectrumResolver::with_url(&url).expect("unable to connect");
+
+    let tx = resolver
+        .resolve_tx(txid)
+        .expect("transaction is not resolved");
+    assert_eq!(tx.txid(), txid);
+    let height = resolver.resolve_height(txid).unwrap();
+    assert!(matches!(height, Some(height) if height > 0));
+
+    let unknown = Txid::from_str(&"00".repeat(32)).unwrap();
+    assert_eq!(resolver.resolve_tx(unknown).unwrap_err().txid, unknown);
+}
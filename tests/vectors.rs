@@ -0,0 +1,81 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Checks fixture vectors against the golden files in `tests/vectors`. After
+//! an intended change of the encoding layout the files are regenerated with
+//! `RGB_UPDATE_VECTORS=1 cargo test --features test_vectors --test vectors`.
+
+#![cfg(feature = "test_vectors")]
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use rgb::test_vectors::{self, TestVector};
+use rgb::{Disclosure, Genesis, StateTransfer};
+use strict_encoding::StrictDecode;
+
+const UPDATE_VAR: &str = "RGB_UPDATE_VECTORS";
+
+fn golden_path(vector: &TestVector) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("vectors")
+        .join(format!("{}.txt", vector.name))
+}
+
+#[test]
+fn test_golden_files() {
+    for vector in test_vectors::vectors() {
+        let path = golden_path(&vector);
+        if env::var_os(UPDATE_VAR).is_some() {
+            fs::write(&path, vector.to_string()).unwrap();
+            continue;
+        }
+        let golden = fs::read_to_string(&path).unwrap_or_else(|err| {
+            panic!(
+                "golden file {} can't be read ({}); generate it by running the test with {}=1",
+                path.display(),
+                err,
+                UPDATE_VAR
+            )
+        });
+        assert_eq!(
+            vector.to_string(),
+            golden,
+            "vector `{}` does not match the golden file; encoding layout or commitment ids have \
+             changed",
+            vector.name
+        );
+    }
+}
+
+#[test]
+fn test_determinism() { assert_eq!(test_vectors::vectors(), test_vectors::vectors()); }
+
+#[test]
+fn test_roundtrip() {
+    let data = test_vectors::disclosure_vector().data;
+    assert_eq!(
+        Disclosure::strict_deserialize(data).unwrap(),
+        test_vectors::disclosure()
+    );
+    let data = test_vectors::genesis_vector().data;
+    assert_eq!(
+        Genesis::strict_deserialize(data).unwrap(),
+        test_vectors::genesis()
+    );
+    let data = test_vectors::transfer_vector().data;
+    assert_eq!(
+        StateTransfer::strict_deserialize(data).unwrap(),
+        test_vectors::transfer()
+    );
+}
@@ -0,0 +1,62 @@
+// RGB Standard Library: high-level API to RGB smart contracts.
+// Written in 2019-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright
+// and related and neighboring rights to this software to the public domain
+// worldwide. This software is distributed without any warranty.
+//
+// You should have received a copy of the MIT License along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Smoke tests for the `wasm32-unknown-unknown` target, run with
+//! `wasm-pack test --node -- --no-default-features --features wasm`.
+
+#![cfg(all(target_arch = "wasm32", feature = "wasm"))]
+
+use bitcoin::OutPoint;
+use commit_verify::CommitConceal;
+use lnpbp::chain::Chain;
+use rgb::fungible::BlindedSeal;
+use rgb::{Genesis, Schema, SchemaId, StateTransfer};
+use strict_encoding::StrictEncode;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+fn transfer() -> StateTransfer {
+    let genesis = Genesis::with(
+        SchemaId::default(),
+        Chain::Testnet3,
+        Default::default(),
+        Default::default(),
+        Default::default(),
+    );
+    StateTransfer::with(
+        Schema::default(),
+        None,
+        genesis,
+        Default::default(),
+        Default::default(),
+        Default::default(),
+    )
+}
+
+#[wasm_bindgen_test]
+fn consignment_id() {
+    let transfer = transfer();
+    let data = transfer.strict_serialize().unwrap();
+    assert_eq!(
+        rgb::wasm::consignment_id(&data).unwrap(),
+        transfer.id().to_string()
+    );
+    assert_eq!(
+        rgb::wasm::consignment_contract_id(&data).unwrap(),
+        transfer.contract_id().to_string()
+    );
+    assert!(rgb::wasm::consignment_id(&data[1..]).is_err());
+}
+
+#[wasm_bindgen_test]
+fn blinding_entropy() {
+    let (confidential, revealed) = BlindedSeal::blind(OutPoint::default());
+    assert_eq!(revealed.commit_conceal(), confidential);
+}